@@ -0,0 +1,111 @@
+// Temporal accumulation. While the view zooms steadily, every frame samples slightly other
+// points than the frame before. Blending the previous frame, moved and scaled to the new view,
+// into the new frame averages these samples, which reduces noise and aliasing without
+// rendering more samples per frame.
+
+use crate::mandel_image::Mapping;
+
+/// The weight of the previous frames in an accumulated frame
+pub const HISTORY_WEIGHT: f64 = 0.5;
+// Frames whose scales differ more than this factor are not blended, because the
+// previous frame would be too coarse
+const MAX_SCALE_RATIO: f64 = 2.0;
+
+/// A frame in the RGB24 format of cairo, with the view that it shows
+pub struct Frame<'a> {
+    pub data: &'a [u8],
+    pub stride: usize,
+    pub mapping: &'a Mapping,
+}
+
+// For every pixel column or row of the new view, the column or row of the previous view
+// that shows the same coordinate, if any
+fn reproject(n: usize, to_prev: impl Fn(f64) -> f64, prev_n: usize) -> Vec<Option<usize>> {
+    (0..n)
+        .map(|i| {
+            let p = to_prev(i as f64 + 0.5).floor();
+            (0.0 <= p && p < prev_n as f64).then_some(p as usize)
+        })
+        .collect()
+}
+
+/// Blend the previous frame into a new frame with the given mapping, with weight for the
+/// previous frame. Pixels of the new view that the previous frame does not show are kept.
+/// Frames of another fractal or iteration depth are not blended.
+pub fn accumulate(prev: &Frame, data: &mut [u8], stride: usize, mapping: &Mapping, weight: f64) {
+    let pm = prev.mapping;
+    let ratio = pm.scale / mapping.scale;
+    if pm.fractal != mapping.fractal
+        || pm.iteration_depth != mapping.iteration_depth
+        || !(1.0 / MAX_SCALE_RATIO..=MAX_SCALE_RATIO).contains(&ratio)
+    {
+        return;
+    }
+    // A window coordinate of the new view, converted to mandelbrot space and then to a
+    // window coordinate of the previous view
+    let cols = reproject(
+        mapping.win_width,
+        |wx| {
+            let x = mapping.cx + (wx - mapping.win_width as f64 / 2.0) * mapping.scale;
+            (x - pm.cx) / pm.scale + pm.win_width as f64 / 2.0
+        },
+        pm.win_width,
+    );
+    let rows = reproject(
+        mapping.win_height,
+        |wy| {
+            let y = mapping.cy - (wy - mapping.win_height as f64 / 2.0) * mapping.scale;
+            (pm.cy - y) / pm.scale + pm.win_height as f64 / 2.0
+        },
+        pm.win_height,
+    );
+    for (wy, row) in rows.iter().enumerate() {
+        let Some(py) = *row else { continue };
+        let line = &mut data[wy * stride..wy * stride + 4 * mapping.win_width];
+        let prev_line = &prev.data[py * prev.stride..];
+        for (wx, col) in cols.iter().enumerate() {
+            let Some(px) = *col else { continue };
+            // The fourth byte of a pixel is unused
+            for c in 0..3 {
+                let old = prev_line[4 * px + c] as f64;
+                let new = &mut line[4 * wx + c];
+                *new = (*new as f64 + weight * (old - *new as f64)).round() as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_blend_where_they_overlap() {
+        let mapping = Mapping::new_for_size(4);
+        let prev_data = vec![200u8; 4 * 4 * 4];
+        let prev = Frame {
+            data: &prev_data,
+            stride: 16,
+            mapping: &mapping,
+        };
+        let mut data = vec![100u8; 4 * 4 * 4];
+        accumulate(&prev, &mut data, 16, &mapping, 0.5);
+        assert!(data.chunks(4).all(|p| p[..3] == [150, 150, 150]));
+
+        // Moved by two pixels, so half of the new view is not in the previous one
+        let mut moved = mapping.clone();
+        moved.cx += 2.0 * mapping.scale;
+        let mut data = vec![100u8; 4 * 4 * 4];
+        accumulate(&prev, &mut data, 16, &moved, 0.5);
+        for row in data.chunks(16) {
+            assert_eq!(row[0], 150);
+            assert_eq!(row[12], 100);
+        }
+
+        let mut deeper = mapping.clone();
+        deeper.iteration_depth += 1;
+        let mut data = vec![100u8; 4 * 4 * 4];
+        accumulate(&prev, &mut data, 16, &deeper, 0.5);
+        assert!(data.iter().all(|&b| b == 100));
+    }
+}
@@ -0,0 +1,442 @@
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+use scoped_threadpool::Pool;
+
+use crate::colorings::Coloring;
+use crate::export::export_mapping;
+use crate::fractal::FractalParams;
+use crate::location::Location;
+use crate::mandel_image::{
+    continue_iteration, iteration_start, make_mandel_image, new_pool, smooth_escape, CancelToken,
+    Mapping, WinToMandel,
+};
+use crate::png::{rgb24_to_rgb, write_rgb24_png};
+use crate::stride_for_width;
+
+// The iteration state of one pixel
+#[derive(Clone, Copy)]
+struct PixelState {
+    z: (f64, f64),
+    c: (f64, f64),
+    iterations: u32,
+    escaped: bool,
+}
+
+/// The iteration state of all pixels of a view. The iteration depth can be increased
+/// step by step, continuing where the previous step stopped.
+pub struct DeepeningBuffer {
+    mapping: Mapping,
+    pixels: Vec<PixelState>,
+    depth: u32,
+}
+
+// Continue the iterations of a number of pixels to the given depth
+fn deepen_pixels(pixels: &mut [PixelState], fractal: &FractalParams, depth: u32) {
+    for p in pixels.iter_mut().filter(|p| !p.escaped) {
+        p.escaped = continue_iteration(fractal, &mut p.z, p.c, &mut p.iterations, depth);
+    }
+}
+
+impl DeepeningBuffer {
+    /// Start the iteration for all pixels of the mapping. Its iteration depth is ignored.
+    pub fn new(mapping: &Mapping) -> DeepeningBuffer {
+        let converter = WinToMandel::from_mapping(mapping);
+        let mut pixels = Vec::with_capacity(mapping.win_width * mapping.win_height);
+        for wy in 0..mapping.win_height {
+            let y = converter.cvt_y(wy);
+            for wx in 0..mapping.win_width {
+                let (z, c) = iteration_start(&mapping.fractal, converter.cvt_x(wx), y);
+                pixels.push(PixelState {
+                    z,
+                    c,
+                    iterations: 0,
+                    escaped: false,
+                });
+            }
+        }
+        DeepeningBuffer {
+            mapping: mapping.clone(),
+            pixels,
+            depth: 0,
+        }
+    }
+
+    /// The iteration depth that has been reached
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Increase the iteration depth. Only the pixels that did not escape yet are iterated further.
+    /// Returns false if the computation was cancelled.
+    pub fn deepen(&mut self, depth: u32, pool: &mut Option<Pool>, cancel: &CancelToken) -> bool {
+        if depth <= self.depth {
+            return true;
+        }
+        let w = self.mapping.win_width.max(1);
+        let fractal = &self.mapping.fractal;
+        match pool {
+            None => {
+                for row in self.pixels.chunks_mut(w) {
+                    if cancel.is_cancelled() {
+                        return false;
+                    }
+                    deepen_pixels(row, fractal, depth);
+                }
+            }
+            Some(pool) => {
+                let rows_per_part = self
+                    .mapping
+                    .win_height
+                    .div_ceil(pool.thread_count() as usize);
+                pool.scoped(|scope| {
+                    for part in self.pixels.chunks_mut(w * rows_per_part.max(1)) {
+                        scope.execute(move || {
+                            for row in part.chunks_mut(w) {
+                                if cancel.is_cancelled() {
+                                    return;
+                                }
+                                deepen_pixels(row, fractal, depth);
+                            }
+                        });
+                    }
+                });
+                if cancel.is_cancelled() {
+                    return false;
+                }
+            }
+        }
+        self.depth = depth;
+        true
+    }
+
+    /// Make an image of the current state. The colors are computed as if the maximum value
+    /// is palette_max, so the palette stays the same when the depth increases.
+    /// Pixels that did not escape yet get the color of palette_max.
+    pub fn image(&self, col_producer: &dyn Coloring, palette_max: u32) -> Option<(Vec<u8>, i32)> {
+        let stride = stride_for_width(self.mapping.win_width)?;
+        let (w, ustride) = (self.mapping.win_width, stride as usize);
+        let for_histogram;
+        let col_producer = if col_producer.needs_histogram() {
+            // The pixels that did not escape yet count as palette_max
+            let mut histogram = vec![0; palette_max as usize + 1];
+            for p in &self.pixels {
+                let v = if p.escaped { p.iterations } else { palette_max };
+                histogram[v.min(palette_max) as usize] += 1;
+            }
+            for_histogram = col_producer.for_histogram(&histogram);
+            for_histogram.as_deref().unwrap_or(col_producer)
+        } else {
+            col_producer
+        };
+        let mut data = vec![0; self.mapping.win_height * ustride];
+        for (row, line) in self.pixels.chunks(w.max(1)).zip(data.chunks_mut(ustride)) {
+            for (p, px) in row.iter().zip(line.chunks_exact_mut(4)) {
+                let color = if !p.escaped {
+                    col_producer.get_color(palette_max, palette_max)
+                } else if col_producer.is_smooth() {
+                    let v = smooth_escape(&self.mapping.fractal, p.iterations, p.z);
+                    col_producer.get_smooth_color(v, palette_max)
+                } else {
+                    col_producer.get_color(p.iterations, palette_max)
+                };
+                px.copy_from_slice(&color.to_ne_bytes());
+            }
+        }
+        Some((data, stride))
+    }
+}
+
+/// The iteration depths of the frames of a depth animation: from first to last,
+/// with a constant ratio between successive depths and every depth larger than the previous one
+pub fn depth_schedule(first: u32, last: u32, frames: usize) -> Vec<u32> {
+    let first = first.max(1);
+    let last = last.max(first);
+    if frames <= 1 {
+        return vec![last];
+    }
+    let ratio = (last as f64 / first as f64).powf(1.0 / (frames - 1) as f64);
+    let mut depths: Vec<u32> = Vec::with_capacity(frames);
+    for i in 0..frames {
+        let depth = (first as f64 * ratio.powi(i as i32)).round() as u32;
+        let depth = match depths.last() {
+            Some(&prev) => depth.max(prev + 1),
+            None => depth,
+        };
+        depths.push(depth);
+    }
+    depths
+}
+
+/// The file name of frame i of an animation
+pub fn frame_path(dir: &Path, i: usize) -> PathBuf {
+    dir.join(format!("frame-{:04}.png", i))
+}
+
+/// Write the frames of an animation of the view of mapping, in which the iteration depth
+/// increases according to depths, as numbered PNG files in dir. progress is called after every frame
+/// with the number of frames that have been written.
+/// Returns the number of frames that were written, which is less than requested when cancelled.
+pub fn export_depth_animation(
+    mapping: &Mapping,
+    col_producer: &dyn Coloring,
+    depths: &[u32],
+    dir: &Path,
+    cancel: &CancelToken,
+    progress: impl Fn(usize),
+) -> io::Result<usize> {
+    fs::create_dir_all(dir)?;
+    let palette_max = depths
+        .iter()
+        .copied()
+        .max()
+        .unwrap_or(mapping.iteration_depth);
+    let mut buffer = DeepeningBuffer::new(mapping);
+    let mut location = Location::new(mapping, col_producer.name());
+    let mut pool = new_pool();
+    for (i, &depth) in depths.iter().enumerate() {
+        if !buffer.deepen(depth, &mut pool, cancel) {
+            return Ok(i);
+        }
+        let (data, stride) = buffer
+            .image(col_producer, palette_max)
+            .ok_or_else(|| io::Error::other("invalid image size"))?;
+        location.iteration_depth = depth;
+        let out = BufWriter::new(fs::File::create(frame_path(dir, i))?);
+        write_rgb24_png(
+            out,
+            &data,
+            mapping.win_width,
+            mapping.win_height,
+            stride as usize,
+            &location.params(),
+        )?;
+        progress(i + 1);
+    }
+    Ok(depths.len())
+}
+
+/// A view of a zoom animation
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keyframe {
+    pub location: Location,
+    /// The number of frames that repeat the view before the animation moves on
+    pub hold: usize,
+    /// The number of frames from this view to the next keyframe
+    pub frames: usize,
+}
+
+/// The view at fraction t of the way from `from` to `to`. The scale changes exponentially, so
+/// the zoom speed is constant, and the center moves such that points move on the screen with
+/// a constant speed. The iteration depth changes geometrically. The fractal is that of from.
+pub fn interpolate_location(from: &Location, to: &Location, t: f64) -> Location {
+    let ratio = to.scale / from.scale;
+    let scale = from.scale * ratio.powf(t);
+    // The part of the way that the center has moved: the integral of 1 / scale, normalized
+    let f = if (ratio - 1.0).abs() < 1e-9 {
+        t
+    } else {
+        (1.0 - ratio.powf(-t)) / (1.0 - 1.0 / ratio)
+    };
+    let depths = (
+        from.iteration_depth.max(1) as f64,
+        to.iteration_depth.max(1) as f64,
+    );
+    Location {
+        cx: from.cx + (to.cx - from.cx) * f,
+        cy: from.cy + (to.cy - from.cy) * f,
+        scale,
+        iteration_depth: (depths.0 * (depths.1 / depths.0).powf(t)).round() as u32,
+        ..from.clone()
+    }
+}
+
+/// The views of all frames of an animation through the keyframes, ending with the last keyframe.
+/// Every keyframe is held for its hold frames first. The frames of the last keyframe are not
+/// used.
+pub fn zoom_schedule(keyframes: &[Keyframe]) -> Vec<Location> {
+    let mut views = Vec::new();
+    for pair in keyframes.windows(2) {
+        let (from, to) = (&pair[0], &pair[1]);
+        views.extend(std::iter::repeat_n(from.location.clone(), from.hold));
+        for i in 0..from.frames {
+            let t = i as f64 / from.frames as f64;
+            views.push(interpolate_location(&from.location, &to.location, t));
+        }
+    }
+    if let Some(last) = keyframes.last() {
+        views.extend(std::iter::repeat_n(last.location.clone(), last.hold + 1));
+    }
+    views
+}
+
+/// Where the frames of an animation go
+pub enum AnimationOutput {
+    /// Numbered PNG files in a directory, see frame_path
+    Frames(PathBuf),
+    /// A video file that ffmpeg encodes, with the given number of frames per second
+    Video(PathBuf, u32),
+}
+
+/// A zoom animation to export
+pub struct ZoomAnimation {
+    pub views: Vec<Location>,
+    /// The size of the window in which the views were chosen. The frames show the same part
+    /// of the plane, whatever their size.
+    pub view_size: (usize, usize),
+    /// The size of the frames
+    pub size: (usize, usize),
+    pub coloring: Box<dyn Coloring>,
+}
+
+enum FrameSink<'a> {
+    Files(&'a Path),
+    Ffmpeg(Child),
+}
+
+// Start ffmpeg, reading raw RGB frames from its standard input
+fn start_ffmpeg(path: &Path, size: (usize, usize), fps: u32) -> io::Result<Child> {
+    Command::new("ffmpeg")
+        .args([
+            "-y",
+            "-loglevel",
+            "error",
+            "-f",
+            "rawvideo",
+            "-pix_fmt",
+            "rgb24",
+        ])
+        .args(["-s", &format!("{}x{}", size.0, size.1)])
+        .args(["-r", &fps.to_string(), "-i", "-", "-pix_fmt", "yuv420p"])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("could not start ffmpeg: {}", e)))
+}
+
+impl ZoomAnimation {
+    fn mapping(&self, view: &Location) -> Mapping {
+        let (w, h) = self.view_size;
+        export_mapping(
+            &view.mapping(w, h),
+            self.size.0,
+            self.size.1,
+            view.iteration_depth,
+        )
+    }
+
+    /// Render the frames one after the other with one worker pool, and write them to output.
+    /// progress is called after every frame with the number of frames that have been written.
+    /// Returns the number of frames that were written, which is less than requested when
+    /// cancelled.
+    pub fn export(
+        &self,
+        output: &AnimationOutput,
+        cancel: &CancelToken,
+        progress: impl Fn(usize),
+    ) -> io::Result<usize> {
+        let (width, height) = self.size;
+        let mut sink = match output {
+            AnimationOutput::Frames(dir) => {
+                fs::create_dir_all(dir)?;
+                FrameSink::Files(dir)
+            }
+            AnimationOutput::Video(path, fps) => {
+                FrameSink::Ffmpeg(start_ffmpeg(path, self.size, *fps)?)
+            }
+        };
+        let mut pool = new_pool();
+        let mut rgb = Vec::with_capacity(3 * width);
+        let mut written = 0;
+        for (i, view) in self.views.iter().enumerate() {
+            let mapping = self.mapping(view);
+            let Some((data, stride)) =
+                make_mandel_image(&mapping, &self.coloring, &mut pool, 1, cancel)
+            else {
+                break;
+            };
+            match &mut sink {
+                FrameSink::Files(dir) => {
+                    let location = Location::new(&mapping, self.coloring.name());
+                    let out = BufWriter::new(fs::File::create(frame_path(dir, i))?);
+                    write_rgb24_png(
+                        out,
+                        &data,
+                        width,
+                        height,
+                        stride as usize,
+                        &location.params(),
+                    )?;
+                }
+                FrameSink::Ffmpeg(child) => {
+                    let stdin = child
+                        .stdin
+                        .as_mut()
+                        .ok_or_else(|| io::Error::other("ffmpeg has no input"))?;
+                    for y in 0..height {
+                        rgb24_to_rgb(&data[y * stride as usize..], width, &mut rgb);
+                        stdin.write_all(&rgb)?;
+                    }
+                }
+            }
+            written = i + 1;
+            progress(written);
+        }
+        if let FrameSink::Ffmpeg(mut child) = sink {
+            // Closing the input ends the video
+            drop(child.stdin.take());
+            let status = child.wait()?;
+            if !status.success() {
+                return Err(io::Error::other(format!("ffmpeg failed: {}", status)));
+            }
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zooms_are_exponential_in_scale() {
+        let from = Location::new(&Mapping::new_for_size(600), "");
+        let to = Location {
+            cx: -0.75,
+            cy: 0.1,
+            scale: from.scale / 1e6,
+            iteration_depth: 1000,
+            ..from.clone()
+        };
+        let keyframes = [
+            Keyframe {
+                location: from.clone(),
+                hold: 0,
+                frames: 10,
+            },
+            Keyframe {
+                location: to.clone(),
+                hold: 0,
+                frames: 0,
+            },
+        ];
+        let views = zoom_schedule(&keyframes);
+        assert_eq!(views.len(), 11);
+        assert_eq!(views[0], from);
+        assert_eq!(views[10], to);
+        let mut held = keyframes.clone();
+        held[0].hold = 2;
+        held[1].hold = 3;
+        let held = zoom_schedule(&held);
+        assert_eq!(held.len(), 16);
+        assert!(held[..3].iter().all(|v| *v == from));
+        assert!(held[12..].iter().all(|v| *v == to));
+        // Halfway in time is halfway in zoom
+        assert!((views[5].scale / (from.scale / 1e3) - 1.0).abs() < 1e-9);
+        for pair in views.windows(2) {
+            assert!((pair[1].scale / pair[0].scale - 0.25118864).abs() < 1e-6);
+        }
+    }
+}
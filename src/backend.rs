@@ -0,0 +1,161 @@
+// The ways of computing the images of views. The progressive renderer, the exports and the
+// benchmark all render through a RenderBackend, so the backends can be chosen at run time and
+// measured against each other.
+
+use scoped_threadpool::Pool;
+
+use crate::colorings::Coloring;
+use crate::mandel_image::{
+    counted_mandel_image, iteration_buffer, CancelToken, IterationBuffer, Mapping, RenderStats,
+    StatsCounter,
+};
+use crate::mariani_silver::MARIANI_SILVER;
+
+/// A way of computing the images and the values of the pixels of views
+pub trait RenderBackend: Sync {
+    /// The name of the backend on the command line and in the settings
+    fn name(&self) -> &'static str;
+    /// The image of a view with one pixel per block x block square, like make_mandel_image,
+    /// and what it computed. None if the view is not valid or the render was cancelled.
+    fn render(
+        &self,
+        mapping: &Mapping,
+        coloring: &dyn Coloring,
+        pool: &mut Option<Pool>,
+        block: usize,
+        cancel: &CancelToken,
+    ) -> Option<(Vec<u8>, i32, RenderStats)>;
+    /// The values of every pixel of a view, like make_iteration_buffer
+    fn values(
+        &self,
+        mapping: &Mapping,
+        pool: &mut Option<Pool>,
+        cancel: &CancelToken,
+    ) -> Option<IterationBuffer>;
+}
+
+/// A backend that renders on the processor, on the calling thread or on the threads of the
+/// pool, and with or without the vector kernel for the quadratic Mandelbrot set
+pub struct CpuBackend {
+    name: &'static str,
+    threaded: bool,
+    vectorized: bool,
+}
+
+impl RenderBackend for CpuBackend {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn render(
+        &self,
+        mapping: &Mapping,
+        coloring: &dyn Coloring,
+        pool: &mut Option<Pool>,
+        block: usize,
+        cancel: &CancelToken,
+    ) -> Option<(Vec<u8>, i32, RenderStats)> {
+        let counter = StatsCounter::default();
+        let mut single = None;
+        let pool = if self.threaded { pool } else { &mut single };
+        let (data, stride) = counted_mandel_image(
+            mapping,
+            coloring,
+            pool,
+            block,
+            cancel,
+            &counter,
+            self.vectorized,
+        )?;
+        Some((data, stride, counter.stats()))
+    }
+
+    fn values(
+        &self,
+        mapping: &Mapping,
+        pool: &mut Option<Pool>,
+        cancel: &CancelToken,
+    ) -> Option<IterationBuffer> {
+        let mut single = None;
+        let pool = if self.threaded { pool } else { &mut single };
+        iteration_buffer(mapping, pool, cancel, self.vectorized)
+    }
+}
+
+/// One point at a time, on the calling thread: the reference for the other backends
+pub static SCALAR: CpuBackend = CpuBackend {
+    name: "scalar",
+    threaded: false,
+    vectorized: false,
+};
+
+/// One point at a time on every thread of the pool
+pub static THREADED: CpuBackend = CpuBackend {
+    name: "threaded",
+    threaded: true,
+    vectorized: false,
+};
+
+/// Several points at a time on every thread of the pool, with AVX if the processor has it
+pub static SIMD: CpuBackend = CpuBackend {
+    name: "simd",
+    threaded: true,
+    vectorized: true,
+};
+
+/// The backends that can be chosen, the default first. There is no GPU backend; it would be
+/// another implementation of RenderBackend in this list, and the device of a HybridBackend,
+/// which would then be in this list too.
+pub static BACKENDS: [&dyn RenderBackend; 4] = [&SIMD, &THREADED, &SCALAR, &MARIANI_SILVER];
+
+/// The backend that renders when none is chosen
+pub fn default_backend() -> &'static dyn RenderBackend {
+    BACKENDS[0]
+}
+
+/// The backend with a name, if there is one
+pub fn backend_by_name(name: &str) -> Option<&'static dyn RenderBackend> {
+    BACKENDS
+        .iter()
+        .copied()
+        .find(|backend| backend.name() == name)
+}
+
+/// The names of the backends, the default first
+pub fn backend_names() -> Vec<&'static str> {
+    BACKENDS.iter().map(|backend| backend.name()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colorings::ColorInfo;
+
+    #[test]
+    fn backends_render_the_same_image() {
+        let mut mapping = Mapping::new_for_size(37);
+        mapping.iteration_depth = 80;
+        let color_info = ColorInfo::new();
+        let coloring = color_info.scheme(0).as_ref();
+        let cancel = CancelToken::never();
+        let mut pool = Some(Pool::new(2));
+        let images: Vec<_> = BACKENDS
+            .iter()
+            .map(|backend| backend.render(&mapping, coloring, &mut pool, 1, &cancel))
+            .collect();
+        let (data, stride, stats) = images[0].as_ref().unwrap();
+        for (backend, image) in BACKENDS.iter().zip(&images).skip(1) {
+            let image = image.as_ref().unwrap();
+            assert_eq!(image.1, *stride);
+            assert_eq!(&image.0, data);
+            // Subdivision fills the interior of the set without iterating it
+            if backend.name() == MARIANI_SILVER.name() {
+                assert!(image.2.points < stats.points);
+            } else {
+                assert_eq!(&image.2, stats);
+            }
+        }
+        assert_eq!(backend_by_name("scalar").map(|b| b.name()), Some("scalar"));
+        assert!(backend_by_name("gpu").is_none());
+    }
+}
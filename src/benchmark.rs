@@ -0,0 +1,188 @@
+use std::time::Instant;
+
+use scoped_threadpool::Pool;
+
+use crate::backend::{default_backend, RenderBackend};
+use crate::colorings::ColorInfo;
+use crate::mandel_image::{CancelToken, Mapping, PREVIEW_ITER_DEPTH, PROGRESSIVE_BLOCKS};
+
+// The size and iteration depth of the view that is rendered to measure the speed
+const BENCH_SZ: usize = 300;
+const BENCH_ITER_DEPTH: u32 = 256;
+const BENCH_RUNS: usize = 3;
+// The number of pixels of the view for which the passes are chosen
+const REFERENCE_PIXELS: f64 = 600.0 * 600.0;
+// The longest time in seconds that the first progressive pass should take
+const FIRST_PASS_TIME: f64 = 0.04;
+// The longest time in seconds that a preview of PREVIEW_PIXELS should take
+const PREVIEW_TIME: f64 = 0.02;
+const PREVIEW_PIXELS: f64 = 150.0 * 150.0;
+const MAX_FIRST_BLOCK: usize = 16;
+/// The time in seconds that the first pass after a move should take, on any machine
+pub const FRAME_BUDGET: f64 = 0.03;
+// The weight of the newest pass in the moving average of the time per sample
+const FRAME_SMOOTHING: f64 = 0.3;
+/// Renders that are estimated to take more seconds than this are confirmed first
+pub const CONFIRM_SECONDS: f64 = 60.0;
+
+// The block sizes of passes that start at first_block and halve it until 1
+fn blocks_from(first_block: usize) -> Vec<usize> {
+    let mut blocks = Vec::new();
+    let mut block = first_block;
+    while block > 1 {
+        blocks.push(block);
+        block /= 2;
+    }
+    blocks.push(1);
+    blocks
+}
+
+/// Render a test view a few times and return the number of pixels per second of the fastest run
+pub fn measure_speed(pool: &mut Option<Pool>) -> f64 {
+    measure_backend_speed(default_backend(), pool)
+}
+
+/// Like measure_speed, for the renders of a backend
+pub fn measure_backend_speed(backend: &dyn RenderBackend, pool: &mut Option<Pool>) -> f64 {
+    let mut mapping = Mapping::new_for_size(BENCH_SZ);
+    mapping.iteration_depth = BENCH_ITER_DEPTH;
+    let color_info = ColorInfo::new();
+    let mut best = f64::INFINITY;
+    for _ in 0..BENCH_RUNS {
+        let start = Instant::now();
+        let _ = backend.render(
+            &mapping,
+            color_info.scheme(0).as_ref(),
+            pool,
+            1,
+            &CancelToken::never(),
+        );
+        best = best.min(start.elapsed().as_secs_f64());
+    }
+    (BENCH_SZ * BENCH_SZ) as f64 / best.max(1e-6)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Render settings that fit the speed of the machine
+pub struct Calibration {
+    /// The speed that the settings are based on, in pixels per second at an iteration depth of 256
+    pub pixels_per_second: f64,
+    /// The block sizes of the passes of the progressive renderer, ending with 1
+    pub progressive_blocks: Vec<usize>,
+    /// The maximum iteration depth of quick previews
+    pub preview_iter_depth: u32,
+}
+
+impl Calibration {
+    /// The settings that are used before the speed has been measured
+    pub fn defaults() -> Calibration {
+        Calibration {
+            pixels_per_second: 0.0,
+            progressive_blocks: PROGRESSIVE_BLOCKS.to_vec(),
+            preview_iter_depth: PREVIEW_ITER_DEPTH,
+        }
+    }
+
+    /// Choose the settings for a machine that renders pixels_per_second
+    pub fn for_speed(pixels_per_second: f64) -> Calibration {
+        if pixels_per_second.is_nan() || pixels_per_second <= 0.0 {
+            return Calibration::defaults();
+        }
+        // The first pass uses the smallest block size that is fast enough
+        let mut first_block = 1;
+        while first_block < MAX_FIRST_BLOCK
+            && REFERENCE_PIXELS / (first_block * first_block) as f64 / pixels_per_second
+                > FIRST_PASS_TIME
+        {
+            first_block *= 2;
+        }
+        let progressive_blocks = blocks_from(first_block);
+        // The time per pixel grows about linearly with the iteration depth
+        let preview_iter_depth = (pixels_per_second * PREVIEW_TIME / PREVIEW_PIXELS
+            * BENCH_ITER_DEPTH as f64)
+            .clamp(32.0, 1024.0) as u32;
+        Calibration {
+            pixels_per_second,
+            progressive_blocks,
+            preview_iter_depth,
+        }
+    }
+
+    /// The estimated time in seconds to render the given number of samples at an iteration
+    /// depth, or None if the speed has not been measured. Points in the set take the whole
+    /// depth, so views with much of the set take longer.
+    pub fn estimate_seconds(&self, samples: f64, iteration_depth: u32) -> Option<f64> {
+        if self.pixels_per_second <= 0.0 {
+            return None;
+        }
+        let per_sample = iteration_depth.max(1) as f64 / BENCH_ITER_DEPTH as f64;
+        Some(samples * per_sample / self.pixels_per_second)
+    }
+}
+
+/// A duration in seconds as e.g. "2 hours 5 minutes"
+pub fn format_duration(seconds: f64) -> String {
+    let minutes = (seconds / 60.0).round() as u64;
+    match minutes {
+        0 => format!("{:.0} seconds", seconds),
+        1 => "1 minute".to_string(),
+        2..=119 => format!("{} minutes", minutes),
+        _ => format!("{} hours {} minutes", minutes / 60, minutes % 60),
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// A moving average of the time per sample of recent passes. The calibration measures a test
+/// view once, while the time per sample of the current view depends on where it is and on
+/// its iteration depth. The recent passes tell how fast the current view renders.
+pub struct FrameTimes {
+    seconds_per_sample: Option<f64>,
+}
+
+impl FrameTimes {
+    /// Add a pass that rendered samples in the given number of seconds
+    pub fn record(&mut self, samples: usize, seconds: f64) {
+        if samples == 0 || !seconds.is_finite() {
+            return;
+        }
+        let new = seconds / samples as f64;
+        let average = match self.seconds_per_sample {
+            Some(average) => average + FRAME_SMOOTHING * (new - average),
+            None => new,
+        };
+        self.seconds_per_sample = Some(average);
+    }
+
+    /// The block sizes of the passes for a view of pixels, such that the first pass fits in
+    /// FRAME_BUDGET, or None if no pass was recorded yet
+    pub fn progressive_blocks(&self, pixels: usize) -> Option<Vec<usize>> {
+        let seconds_per_sample = self.seconds_per_sample?;
+        let mut first_block = 1;
+        while first_block < MAX_FIRST_BLOCK
+            && (pixels / (first_block * first_block)) as f64 * seconds_per_sample > FRAME_BUDGET
+        {
+            first_block *= 2;
+        }
+        Some(blocks_from(first_block))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slow_frames_lower_the_first_resolution() {
+        let pixels = 600 * 600;
+        let mut times = FrameTimes::default();
+        assert_eq!(times.progressive_blocks(pixels), None);
+        // A full pass in 10 ms fits the budget
+        times.record(pixels, 0.01);
+        assert_eq!(times.progressive_blocks(pixels), Some(vec![1]));
+        // After a few passes of a second, the first pass gets blocks of 8 x 8 pixels
+        for _ in 0..10 {
+            times.record(pixels, 1.0);
+        }
+        assert_eq!(times.progressive_blocks(pixels), Some(vec![8, 4, 2, 1]));
+    }
+}
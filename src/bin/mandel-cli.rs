@@ -0,0 +1,287 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use mandelbrot::backend::{
+    backend_by_name, backend_names, default_backend, RenderBackend, BACKENDS,
+};
+use mandelbrot::benchmark::{measure_backend_speed, Calibration};
+use mandelbrot::colorings::ColorInfo;
+use mandelbrot::export::ExportJob;
+use mandelbrot::fixed::PreciseCenter;
+use mandelbrot::location::Location;
+use mandelbrot::mandel_image::{new_pool, CancelToken, Mapping};
+use mandelbrot::perturbation::{precision_exhausted, PRECISION_WARNING};
+use mandelbrot::plugins::{load_plugins, plugin_dir};
+use mandelbrot::png::read_png_text;
+
+const USAGE: &str = "usage:
+  mandel-cli render [-o FILE] [-s WIDTHxHEIGHT] [-a FACTOR] [--cx X] [--cy Y] [--zoom Z]
+                    [-i ITERATIONS] [-c COLORING] [-b BACKEND] [LINK]
+      render the location of a mandel:// link, or the start view, to a PNG file; the options
+      change the location, with --zoom the magnification of the start view, and -b chooses
+      the render backend: simd (the default), threaded, scalar or mariani-silver
+  mandel-cli batch FILE
+      render every line of FILE, which has the form: LINK OUTPUT [WIDTHxHEIGHT]
+  mandel-cli bench
+      measure the rendering speed of every backend and show the render settings for the default
+  mandel-cli convert INPUT
+      show the link of a PNG file made by mandelbrot, or the parameters of a link";
+
+// The directory with user data, as used by the GTK application
+fn user_data_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("share")),
+    }
+}
+
+const DEFAULT_SZ: (usize, usize) = (600, 600);
+const DEFAULT_OUTPUT: &str = "mandelbrot.png";
+// The width of the plane that the start view shows
+const START_WIDTH: f64 = 4.0;
+
+fn parse_size(s: &str) -> Result<(usize, usize), String> {
+    let (w, h) = s
+        .split_once('x')
+        .ok_or_else(|| format!("invalid size {}, expected WIDTHxHEIGHT", s))?;
+    match (w.parse(), h.parse()) {
+        (Ok(w), Ok(h)) if w > 0 && h > 0 => Ok((w, h)),
+        _ => Err(format!("invalid size {}", s)),
+    }
+}
+
+fn parse_location(link: &str) -> Result<Location, String> {
+    Location::from_uri(link).ok_or_else(|| format!("not a valid location: {}", link))
+}
+
+fn start_location() -> Location {
+    Location::new(&Mapping::new_for_size(DEFAULT_SZ.0), "")
+}
+
+// Render a location as a PNG file, with the location stored in text chunks.
+// The view keeps the scale of the location, so a larger size shows more. The image is
+// rendered in tiles, so large sizes do not need much memory.
+fn render(
+    location: &Location,
+    size: (usize, usize),
+    factor: usize,
+    backend: &'static dyn RenderBackend,
+    path: &Path,
+) -> Result<(), String> {
+    let color_info = ColorInfo::new();
+    let col_idx = color_info.index_of(&location.coloring).unwrap_or(0);
+    let job = ExportJob {
+        mapping: location.mapping(size.0, size.1),
+        coloring: color_info.scheme(col_idx).clone(),
+        factor,
+        backend,
+    };
+    if precision_exhausted(&job.mapping, job.coloring.as_ref()) {
+        eprintln!("warning: {}: {}", path.display(), PRECISION_WARNING);
+    }
+    let mut location = location.clone();
+    location.coloring = job.coloring.name().to_string();
+    let out = BufWriter::new(File::create(path).map_err(|e| e.to_string())?);
+    job.write_png(
+        out,
+        &location.params(),
+        &mut new_pool(),
+        &CancelToken::never(),
+        |_| (),
+    )
+    .and_then(|mut out| out.flush())
+    .map_err(|e| e.to_string())
+}
+
+fn parse_value<T: FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {} for {}", value, arg))
+}
+
+fn render_cmd(args: &[String]) -> Result<(), String> {
+    let mut output = DEFAULT_OUTPUT.to_string();
+    let mut size = DEFAULT_SZ;
+    let mut factor = 1;
+    let mut backend = default_backend();
+    let mut location = None;
+    let (mut cx, mut cy, mut zoom, mut iterations, mut coloring) = (None, None, None, None, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "-o" | "--output" => output = value()?.clone(),
+            "-s" | "--size" => size = parse_size(value()?)?,
+            "-a" | "--antialias" => {
+                factor = value()?
+                    .parse()
+                    .ok()
+                    .filter(|f| (1..=8).contains(f))
+                    .ok_or("the anti-aliasing factor must be 1 to 8")?
+            }
+            // The text is kept for the digits beyond those of f64
+            "--cx" => {
+                let text = value()?;
+                cx = Some((parse_value::<f64>(arg, text)?, text.clone()))
+            }
+            "--cy" => {
+                let text = value()?;
+                cy = Some((parse_value::<f64>(arg, text)?, text.clone()))
+            }
+            "--zoom" => {
+                zoom = Some(parse_value::<f64>(arg, value()?)?)
+                    .filter(|z| *z > 0.0 && z.is_finite())
+                    .map(Some)
+                    .ok_or("the zoom must be positive")?
+            }
+            "-i" | "--iterations" => iterations = Some(parse_value::<u32>(arg, value()?)?),
+            "-c" | "--coloring" => coloring = Some(value()?.clone()),
+            "-b" | "--backend" => {
+                let name = value()?;
+                backend = backend_by_name(name).ok_or_else(|| {
+                    format!(
+                        "unknown backend {}, expected one of {}",
+                        name,
+                        backend_names().join(", ")
+                    )
+                })?
+            }
+            _ if location.is_none() && !arg.starts_with('-') => {
+                location = Some(parse_location(arg)?)
+            }
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    let mut location = location.unwrap_or_else(start_location);
+    if cx.is_some() || cy.is_some() {
+        let (x, x_text) = cx.unwrap_or_else(|| (location.cx, location.cx.to_string()));
+        let (y, y_text) = cy.unwrap_or_else(|| (location.cy, location.cy.to_string()));
+        location.precise_center = PreciseCenter::from_text(&x_text, &y_text);
+        (location.cx, location.cy) = location
+            .precise_center
+            .as_ref()
+            .map_or((x, y), |center| center.approx());
+    }
+    if let Some(zoom) = zoom {
+        // Zoom 1 shows the start view, 4 units across the smaller side of the image
+        location.scale = START_WIDTH / (zoom * size.0.min(size.1) as f64);
+    }
+    location.iteration_depth = iterations.unwrap_or(location.iteration_depth);
+    if let Some(name) = coloring {
+        if ColorInfo::new().index_of(&name).is_none() {
+            return Err(format!("unknown coloring {}", name));
+        }
+        location.coloring = name;
+    }
+    render(&location, size, factor, backend, Path::new(&output))?;
+    println!("wrote {}", output);
+    Ok(())
+}
+
+fn batch_cmd(args: &[String]) -> Result<(), String> {
+    let [file] = args else {
+        return Err("batch needs one file".to_string());
+    };
+    let jobs = fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))?;
+    let mut failed = 0;
+    // Lines that are empty or start with # are skipped
+    for (n, line) in jobs.lines().enumerate() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() || words[0].starts_with('#') {
+            continue;
+        }
+        let result = match words[..] {
+            [link, output] => parse_location(link).and_then(|location| {
+                render(
+                    &location,
+                    DEFAULT_SZ,
+                    1,
+                    default_backend(),
+                    Path::new(output),
+                )
+            }),
+            [link, output, size] => parse_size(size).and_then(|size| {
+                parse_location(link).and_then(|location| {
+                    render(&location, size, 1, default_backend(), Path::new(output))
+                })
+            }),
+            _ => Err("expected LINK OUTPUT [WIDTHxHEIGHT]".to_string()),
+        };
+        match result {
+            Ok(()) => println!("wrote {}", words[1]),
+            Err(e) => {
+                eprintln!("{}:{}: {}", file, n + 1, e);
+                failed += 1;
+            }
+        }
+    }
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(format!("{} renders failed", failed))
+    }
+}
+
+fn bench_cmd() -> Result<(), String> {
+    let mut pool = new_pool();
+    for backend in BACKENDS {
+        let speed = measure_backend_speed(backend, &mut pool);
+        println!("{}: {:.0} pixels per second", backend.name(), speed);
+    }
+    let speed = measure_backend_speed(default_backend(), &mut pool);
+    let calibration = Calibration::for_speed(speed);
+    println!("progressive passes: {:?}", calibration.progressive_blocks);
+    println!(
+        "preview iteration depth: {}",
+        calibration.preview_iter_depth
+    );
+    Ok(())
+}
+
+fn convert_cmd(args: &[String]) -> Result<(), String> {
+    let [input] = args else {
+        return Err("convert needs one input".to_string());
+    };
+    if let Ok(location) = parse_location(input) {
+        for (key, value) in location.params() {
+            println!("{}={}", key, value);
+        }
+        return Ok(());
+    }
+    let file = File::open(input).map_err(|e| format!("{}: {}", input, e))?;
+    let text = read_png_text(file).map_err(|e| format!("{}: {}", input, e))?;
+    let location =
+        Location::from_png_text(&text).ok_or_else(|| format!("{} has no location", input))?;
+    println!("{}", location.to_uri());
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(dir) = user_data_dir() {
+        for message in load_plugins(&plugin_dir(&dir)) {
+            eprintln!("{}", message);
+        }
+    }
+    let result = match args.first().map(|s| s.as_str()) {
+        Some("render") => render_cmd(&args[1..]),
+        Some("batch") => batch_cmd(&args[1..]),
+        Some("bench") => bench_cmd(),
+        Some("convert") => convert_cmd(&args[1..]),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("mandel-cli: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
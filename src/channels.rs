@@ -0,0 +1,68 @@
+use async_channel::{Receiver, Sender, TrySendError};
+
+/// The capacity of the channel with render requests for the main view
+pub const REQUEST_CAPACITY: usize = 1;
+/// The capacity of the channel with rendered images for the main view. Its policy is Queue:
+/// tiles are drawn over the pass before them, so no pass may be dropped for a tile.
+pub const REPLY_CAPACITY: usize = 16;
+/// The number of export jobs that can wait
+pub const EXPORT_JOB_CAPACITY: usize = 4;
+/// The number of progress reports of an export that can wait
+pub const EXPORT_EVENT_CAPACITY: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+/// What happens to a message that is sent to a full channel
+pub enum Policy {
+    /// The oldest message in the channel is dropped. For messages that are replaced by newer ones,
+    /// like interactive render requests.
+    LatestWins,
+    /// The sender waits until there is room. For messages that must all arrive, like export jobs.
+    Queue,
+}
+
+/// The sending side of a bounded channel with a policy for when it is full
+pub struct PolicySender<T> {
+    sender: Sender<T>,
+    policy: Policy,
+}
+
+impl<T> Clone for PolicySender<T> {
+    fn clone(&self) -> Self {
+        PolicySender {
+            sender: self.sender.clone(),
+            policy: self.policy,
+        }
+    }
+}
+
+impl<T> PolicySender<T> {
+    pub fn policy(&self) -> Policy {
+        self.policy
+    }
+
+    /// Send a message, blocking the thread if the channel is full and the policy is Queue.
+    /// Returns false if the channel is closed.
+    pub fn send_blocking(&self, msg: T) -> bool {
+        match self.policy {
+            Policy::LatestWins => self.sender.force_send(msg).is_ok(),
+            Policy::Queue => self.sender.send_blocking(msg).is_ok(),
+        }
+    }
+
+    /// Send a message without blocking. With the Queue policy a full channel gives the
+    /// message back as the error.
+    pub fn try_send(&self, msg: T) -> Result<(), T> {
+        match self.policy {
+            Policy::LatestWins => self.sender.force_send(msg).map(|_| ()).map_err(|e| e.0),
+            Policy::Queue => self.sender.try_send(msg).map_err(|e| match e {
+                TrySendError::Full(msg) | TrySendError::Closed(msg) => msg,
+            }),
+        }
+    }
+}
+
+/// Make a bounded channel with the given capacity and policy
+pub fn channel<T>(capacity: usize, policy: Policy) -> (PolicySender<T>, Receiver<T>) {
+    let (sender, receiver) = async_channel::bounded(capacity.max(1));
+    (PolicySender { sender, policy }, receiver)
+}
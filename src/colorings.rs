@@ -1,14 +1,95 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
 use dyn_clone::DynClone;
+
+use crate::png::write_rgb24_png;
+use crate::report::json_string;
+
 pub trait Coloring: DynClone + Sync + Send {
     /// Get a color in GTK RGB-format, given the mandelbrot value
     /// and the maximum mandelbrot value
     fn get_color(&self, v: u32, max: u32) -> u32;
+    /// Get a color for a fractional mandelbrot value, as made by smooth_escape.
+    /// By default the fraction is ignored.
+    fn get_smooth_color(&self, v: f64, max: u32) -> u32 {
+        self.get_color(v as u32, max)
+    }
+    /// Whether the coloring uses the fraction of the mandelbrot value. If not,
+    /// the renderer does not need to compute it.
+    fn is_smooth(&self) -> bool {
+        false
+    }
+    /// Whether the coloring uses the estimated distance of escaped points to the set
+    fn uses_distance(&self) -> bool {
+        false
+    }
+    /// Get a color for an escaped point, given its estimated distance to the set in pixels.
+    /// By default the distance is ignored.
+    fn get_distance_color(&self, v: u32, max: u32, _distance: f64) -> u32 {
+        self.get_color(v, max)
+    }
+    /// Whether the colors depend on the distribution of the values in the image. The
+    /// renderer then collects a histogram first and colors with the result of for_histogram.
+    fn needs_histogram(&self) -> bool {
+        false
+    }
+    /// The coloring to use for an image in which histogram[v] pixels have value v
+    fn for_histogram(&self, _histogram: &[u32]) -> Option<Box<dyn Coloring>> {
+        None
+    }
+    /// Get a color for an escaped point after shifting and stretching the palette with the
+    /// parameters. By default the value is mapped with ColoringParams::apply.
+    fn get_param_color(&self, v: f64, max: u32, params: &ColoringParams) -> u32 {
+        if max as f64 <= v.floor() {
+            return self.get_smooth_color(v, max);
+        }
+        self.get_smooth_color(params.apply(v, max), max)
+    }
     /// Get a name for the coloring scheme, suitable for use in the UI
     fn name(&self) -> &str;
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// How the palette of a coloring is shifted and stretched: an escaped point with value v
+/// gets the color of value v * density + offset
+pub struct ColoringParams {
+    pub offset: f64,
+    pub density: f64,
+}
+
+impl ColoringParams {
+    /// The parameters that leave a coloring as it is
+    pub const IDENTITY: ColoringParams = ColoringParams {
+        offset: 0.0,
+        density: 1.0,
+    };
+
+    pub fn is_identity(&self) -> bool {
+        *self == ColoringParams::IDENTITY
+    }
+
+    /// The value whose color an escaped point with value v gets. The result wraps around
+    /// below max, so escaped points keep the colors of escaped points.
+    pub fn apply(&self, v: f64, max: u32) -> f64 {
+        (v * self.density + self.offset).rem_euclid(max.max(1) as f64)
+    }
+}
+
 dyn_clone::clone_trait_object!(Coloring);
 
+// Pick the color for value v from a palette that repeats
+fn cyclic(palette: &[u32], v: u32) -> u32 {
+    palette[v as usize % palette.len()]
+}
+
+const RGB18_PALETTE: [u32; 18] = [
+    0xff3f3f, 0xff7f3f, 0xffbf3f, 0xffff3f, 0xbfff3f, 0x7fff3f, 0x3fff3f, 0x3fff7f, 0x3fffbf,
+    0x3fffff, 0x3fbfff, 0x3f7fff, 0x3f3fff, 0x7f3fff, 0xbf3fff, 0xff3fff, 0xff3fbf, 0xff3f7f,
+];
+
 #[derive(Clone)]
 struct Rgb18 {}
 
@@ -17,27 +98,7 @@ impl Coloring for Rgb18 {
         if max <= v {
             return 0x000000;
         }
-        match v % 18 {
-            0 => 0xff3f3f,
-            1 => 0xff7f3f,
-            2 => 0xffbf3f,
-            3 => 0xffff3f,
-            4 => 0xbfff3f,
-            5 => 0x7fff3f,
-            6 => 0x3fff3f,
-            7 => 0x3fff7f,
-            8 => 0x3fffbf,
-            9 => 0x3fffff,
-            10 => 0x3fbfff,
-            11 => 0x3f7fff,
-            12 => 0x3f3fff,
-            13 => 0x7f3fff,
-            14 => 0xbf3fff,
-            15 => 0xff3fff,
-            16 => 0xff3fbf,
-            17 => 0xff3f7f,
-            _ => panic!("invalid remainder"),
-        }
+        cyclic(&RGB18_PALETTE, v)
     }
 
     fn name(&self) -> &'static str {
@@ -45,6 +106,11 @@ impl Coloring for Rgb18 {
     }
 }
 
+const RED_BLUE_PALETTE: [u32; 16] = [
+    0x000000, 0x400000, 0x800000, 0xc00000, 0xff0000, 0xff0040, 0xff0080, 0xff00c0, 0xff00ff,
+    0xc000ff, 0x8000ff, 0x4000ff, 0x0000ff, 0x0000c0, 0x000080, 0x000040,
+];
+
 #[derive(Clone)]
 struct RedBlue {}
 
@@ -53,25 +119,7 @@ impl Coloring for RedBlue {
         if max <= v {
             return 0x404040;
         }
-        match v % 16 {
-            0 => 0x000000,
-            1 => 0x400000,
-            2 => 0x800000,
-            3 => 0xc00000,
-            4 => 0xff0000,
-            5 => 0xff0040,
-            6 => 0xff0080,
-            7 => 0xff00c0,
-            8 => 0xff00ff,
-            9 => 0xc000ff,
-            10 => 0x8000ff,
-            11 => 0x4000ff,
-            12 => 0x0000ff,
-            13 => 0x0000c0,
-            14 => 0x000080,
-            15 => 0x000040,
-            _ => panic!("invalid remainder"),
-        }
+        cyclic(&RED_BLUE_PALETTE, v)
     }
 
     fn name(&self) -> &str {
@@ -79,6 +127,8 @@ impl Coloring for RedBlue {
     }
 }
 
+const RGB3_PALETTE: [u32; 3] = [0xff0000, 0x00ff00, 0x0000ff];
+
 #[derive(Clone)]
 struct RgbAlternating {}
 
@@ -87,12 +137,7 @@ impl Coloring for RgbAlternating {
         if max <= v {
             return 0x000000;
         }
-        match v % 3 {
-            0 => 0xff0000,
-            1 => 0x00ff00,
-            2 => 0x0000ff,
-            _ => panic!("invalid remainder"),
-        }
+        cyclic(&RGB3_PALETTE, v)
     }
 
     fn name(&self) -> &str {
@@ -137,18 +182,667 @@ impl Coloring for OldBlackWhite {
     }
 }
 
+// Mix two colors in GTK RGB-format, with weight f for the second color
+fn blend(c0: u32, c1: u32, f: f64) -> u32 {
+    let channel = |shift: u32| {
+        let v0 = (c0 >> shift & 0xff) as f64;
+        let v1 = (c1 >> shift & 0xff) as f64;
+        ((v0 + f * (v1 - v0)).round() as u32) << shift
+    };
+    channel(16) | channel(8) | channel(0)
+}
+
+#[derive(Clone)]
+/// A coloring that blends the colors of successive values of another coloring, so a
+/// fractional value gives a color between the colors of its neighbours and there are no bands
+struct Smooth {
+    inner: Box<dyn Coloring>,
+    name: String,
+}
+
+impl Smooth {
+    fn new(inner: Box<dyn Coloring>) -> Smooth {
+        let name = format!("smooth-{}", inner.name());
+        Smooth { inner, name }
+    }
+}
+
+impl Coloring for Smooth {
+    fn get_color(&self, v: u32, max: u32) -> u32 {
+        self.inner.get_color(v, max)
+    }
+
+    fn get_smooth_color(&self, v: f64, max: u32) -> u32 {
+        let v = v.max(0.0);
+        let base = v.floor() as u32;
+        if base >= max {
+            return self.inner.get_color(max, max);
+        }
+        // Escaped points never get the color of max
+        let next = (base + 1).min(max - 1);
+        blend(
+            self.inner.get_color(base, max),
+            self.inner.get_color(next, max),
+            v - v.floor(),
+        )
+    }
+
+    fn is_smooth(&self) -> bool {
+        true
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Clone)]
+/// A coloring that gives points in the set another color than the coloring it wraps
+pub struct InteriorColoring {
+    inner: Box<dyn Coloring>,
+    interior: u32,
+}
+
+impl InteriorColoring {
+    pub fn new(inner: Box<dyn Coloring>, interior: u32) -> InteriorColoring {
+        InteriorColoring {
+            inner,
+            interior: interior & 0xffffff,
+        }
+    }
+}
+
+impl Coloring for InteriorColoring {
+    fn get_color(&self, v: u32, max: u32) -> u32 {
+        if max <= v {
+            return self.interior;
+        }
+        self.inner.get_color(v, max)
+    }
+
+    fn get_smooth_color(&self, v: f64, max: u32) -> u32 {
+        if max as f64 <= v.floor() {
+            return self.interior;
+        }
+        self.inner.get_smooth_color(v, max)
+    }
+
+    fn is_smooth(&self) -> bool {
+        self.inner.is_smooth()
+    }
+
+    fn uses_distance(&self) -> bool {
+        self.inner.uses_distance()
+    }
+
+    fn get_distance_color(&self, v: u32, max: u32, distance: f64) -> u32 {
+        if max <= v {
+            return self.interior;
+        }
+        self.inner.get_distance_color(v, max, distance)
+    }
+
+    fn needs_histogram(&self) -> bool {
+        self.inner.needs_histogram()
+    }
+
+    fn for_histogram(&self, histogram: &[u32]) -> Option<Box<dyn Coloring>> {
+        let inner = self.inner.for_histogram(histogram)?;
+        Some(Box::new(InteriorColoring::new(inner, self.interior)))
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+}
+
+// The gradient of the equalized coloring: positions between 0 and 1 with their colors
+const EQUALIZED_GRADIENT: [(f64, u32); 6] = [
+    (0.0, 0x000764),
+    (0.16, 0x206bcb),
+    (0.42, 0xedffff),
+    (0.64, 0xffaa00),
+    (0.86, 0x000200),
+    (1.0, 0x000764),
+];
+
+// The color at position t of a gradient with stops sorted by position, which are at
+// or beyond 0 and 1
+fn gradient_at(stops: &[(f64, u32)], t: f64) -> u32 {
+    let t = t.clamp(0.0, 1.0);
+    for w in stops.windows(2) {
+        let ((t0, c0), (t1, c1)) = (w[0], w[1]);
+        if t <= t1 {
+            if t1 <= t0 {
+                return c1;
+            }
+            return blend(c0, c1, (t - t0) / (t1 - t0));
+        }
+    }
+    stops.last().map_or(0x000000, |&(_, c)| c)
+}
+
+fn gradient_color(t: f64) -> u32 {
+    gradient_at(&EQUALIZED_GRADIENT, t)
+}
+
+#[derive(Clone)]
+/// A coloring that spreads the gradient evenly over the pixels of the image: a value gets the
+/// color for the fraction of escaped pixels with a lower value. This gives a good contrast
+/// at every location and depth.
+struct Equalized {
+    // cdf[v] is the fraction of escaped pixels with a value below v, for v up to max
+    cdf: Arc<Vec<f64>>,
+}
+
+impl Equalized {
+    // The fraction of escaped pixels with a value below v. Without a histogram
+    // the values are spread evenly.
+    fn fraction(&self, v: u32, max: u32) -> f64 {
+        match self.cdf.get(v as usize) {
+            Some(&f) => f,
+            None => v as f64 / max.max(1) as f64,
+        }
+    }
+}
+
+impl Coloring for Equalized {
+    fn get_color(&self, v: u32, max: u32) -> u32 {
+        if max <= v {
+            return 0x000000;
+        }
+        gradient_color(self.fraction(v, max))
+    }
+
+    fn get_smooth_color(&self, v: f64, max: u32) -> u32 {
+        let v = v.max(0.0);
+        let base = v.floor() as u32;
+        if max <= base {
+            return 0x000000;
+        }
+        let (f0, f1) = (self.fraction(base, max), self.fraction(base + 1, max));
+        gradient_color(f0 + (v - v.floor()) * (f1 - f0))
+    }
+
+    fn is_smooth(&self) -> bool {
+        true
+    }
+
+    fn needs_histogram(&self) -> bool {
+        true
+    }
+
+    fn for_histogram(&self, histogram: &[u32]) -> Option<Box<dyn Coloring>> {
+        // The last entry counts the pixels that did not escape
+        let escaped = &histogram[..histogram.len().saturating_sub(1)];
+        let total: u64 = escaped.iter().map(|&n| n as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut cdf = Vec::with_capacity(histogram.len());
+        let mut below = 0;
+        for &n in escaped {
+            cdf.push(below as f64 / total as f64);
+            below += n as u64;
+        }
+        cdf.push(1.0);
+        Some(Box::new(Equalized { cdf: Arc::new(cdf) }))
+    }
+
+    fn name(&self) -> &str {
+        "equalized"
+    }
+}
+
+// The distance in pixels from which the distance coloring is white
+const DISTANCE_WHITE: f64 = 4.0;
+
+fn gray(level: f64) -> u32 {
+    let level = (255.0 * level.clamp(0.0, 1.0)).round() as u32;
+    level << 16 | level << 8 | level
+}
+
+#[derive(Clone)]
+/// A coloring that shades by the distance to the boundary of the set: the boundary and
+/// its thinnest filaments are dark and the color gets lighter further away
+struct Distance {}
+
+impl Coloring for Distance {
+    fn get_color(&self, v: u32, max: u32) -> u32 {
+        // Without a distance estimate the iteration count gives the shade
+        if max <= v {
+            return 0x000000;
+        }
+        gray((v as f64 / max as f64).sqrt())
+    }
+
+    fn uses_distance(&self) -> bool {
+        true
+    }
+
+    fn get_distance_color(&self, v: u32, max: u32, distance: f64) -> u32 {
+        if max <= v {
+            return 0x000000;
+        }
+        if distance.is_nan() {
+            return self.get_color(v, max);
+        }
+        gray((distance / DISTANCE_WHITE).sqrt())
+    }
+
+    fn name(&self) -> &str {
+        "distance"
+    }
+}
+
+#[derive(Clone)]
+/// A coloring with a shifted and stretched palette
+pub struct ParamColoring {
+    inner: Box<dyn Coloring>,
+    params: ColoringParams,
+}
+
+impl ParamColoring {
+    pub fn new(inner: Box<dyn Coloring>, params: ColoringParams) -> ParamColoring {
+        ParamColoring { inner, params }
+    }
+}
+
+impl Coloring for ParamColoring {
+    fn get_color(&self, v: u32, max: u32) -> u32 {
+        self.get_smooth_color(v as f64, max)
+    }
+
+    fn get_smooth_color(&self, v: f64, max: u32) -> u32 {
+        self.inner.get_param_color(v, max, &self.params)
+    }
+
+    fn is_smooth(&self) -> bool {
+        self.inner.is_smooth()
+    }
+
+    fn uses_distance(&self) -> bool {
+        self.inner.uses_distance()
+    }
+
+    fn get_distance_color(&self, v: u32, max: u32, distance: f64) -> u32 {
+        if max <= v {
+            return self.inner.get_distance_color(v, max, distance);
+        }
+        let v = self.params.apply(v as f64, max) as u32;
+        self.inner.get_distance_color(v, max, distance)
+    }
+
+    fn needs_histogram(&self) -> bool {
+        self.inner.needs_histogram()
+    }
+
+    fn for_histogram(&self, histogram: &[u32]) -> Option<Box<dyn Coloring>> {
+        let inner = self.inner.for_histogram(histogram)?;
+        Some(Box::new(ParamColoring::new(inner, self.params)))
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+}
+
+/// The number of values over which a gradient coloring runs through its stops once
+pub const GRADIENT_PERIOD: u32 = 32;
+
+#[derive(Clone)]
+/// A coloring with a gradient of user-defined color stops, which repeats every
+/// GRADIENT_PERIOD values. After the last stop the gradient returns to the first.
+pub struct GradientColoring {
+    name: String,
+    stops: Vec<(f64, u32)>,
+    // The stops with the last stop before 0 and the first beyond 1, so the gradient is cyclic
+    closed: Vec<(f64, u32)>,
+}
+
+impl GradientColoring {
+    /// Make a gradient from stops: positions between 0 and 1 with colors in GTK RGB-format.
+    /// Without stops the gradient is black.
+    pub fn new(name: &str, stops: &[(f64, u32)]) -> GradientColoring {
+        let mut stops: Vec<(f64, u32)> = stops
+            .iter()
+            .map(|&(t, c)| (t.clamp(0.0, 1.0), c & 0xffffff))
+            .collect();
+        if stops.is_empty() {
+            stops.push((0.0, 0x000000));
+        }
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let (first, last) = (stops[0], stops[stops.len() - 1]);
+        let mut closed = Vec::with_capacity(stops.len() + 2);
+        closed.push((last.0 - 1.0, last.1));
+        closed.extend_from_slice(&stops);
+        closed.push((first.0 + 1.0, first.1));
+        GradientColoring {
+            name: name.to_string(),
+            stops,
+            closed,
+        }
+    }
+
+    /// The stops, sorted by position
+    pub fn stops(&self) -> &[(f64, u32)] {
+        &self.stops
+    }
+
+    /// The color at position t of one period, with t between 0 and 1
+    pub fn color_at(&self, t: f64) -> u32 {
+        gradient_at(&self.closed, t)
+    }
+}
+
+impl Coloring for GradientColoring {
+    fn get_color(&self, v: u32, max: u32) -> u32 {
+        self.get_smooth_color(v as f64, max)
+    }
+
+    fn get_smooth_color(&self, v: f64, max: u32) -> u32 {
+        let v = v.max(0.0);
+        if max as f64 <= v.floor() {
+            return 0x000000;
+        }
+        let period = GRADIENT_PERIOD as f64;
+        self.color_at(v % period / period)
+    }
+
+    fn is_smooth(&self) -> bool {
+        true
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Clone)]
+/// A coloring with a palette that repeats, e.g. one loaded from a file
+pub struct PaletteColoring {
+    name: String,
+    palette: Arc<Vec<u32>>,
+}
+
+impl PaletteColoring {
+    /// Make a coloring from colors in GTK RGB-format. Without colors the palette is black.
+    pub fn new(name: &str, colors: &[u32]) -> PaletteColoring {
+        let mut palette: Vec<u32> = colors.iter().map(|&c| c & 0xffffff).collect();
+        if palette.is_empty() {
+            palette.push(0x000000);
+        }
+        PaletteColoring {
+            name: name.to_string(),
+            palette: Arc::new(palette),
+        }
+    }
+
+    pub fn colors(&self) -> &[u32] {
+        &self.palette
+    }
+}
+
+impl Coloring for PaletteColoring {
+    fn get_color(&self, v: u32, max: u32) -> u32 {
+        if max <= v {
+            return 0x000000;
+        }
+        cyclic(&self.palette, v)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// The most colors that a Fractint palette has
+pub const MAP_COLORS: usize = 256;
+
+/// Read the colors of a Fractint .map file: a line per color with its red, green and blue
+/// value from 0 to 255. Text after the three values is a comment, and so are empty lines.
+pub fn parse_fractint_map(text: &str) -> Result<Vec<u32>, String> {
+    let mut colors = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let values: Vec<&str> = line.split_whitespace().take(3).collect();
+        if values.is_empty() {
+            continue;
+        }
+        if values.len() < 3 {
+            return Err(format!("line {}: expected red, green and blue", n + 1));
+        }
+        let mut color = 0;
+        for value in values {
+            let channel: u8 = value
+                .parse()
+                .map_err(|_| format!("line {}: {} is not a color value", n + 1, value))?;
+            color = color << 8 | channel as u32;
+        }
+        if colors.len() == MAP_COLORS {
+            return Err(format!("more than {} colors", MAP_COLORS));
+        }
+        colors.push(color);
+    }
+    if colors.is_empty() {
+        return Err("there are no colors".to_string());
+    }
+    Ok(colors)
+}
+
+/// Write colors in the format of a Fractint .map file
+pub fn fractint_map(colors: &[u32]) -> String {
+    colors
+        .iter()
+        .take(MAP_COLORS)
+        .map(|c| format!("{} {} {}\n", c >> 16 & 0xff, c >> 8 & 0xff, c & 0xff))
+        .collect()
+}
+
+/// Write a palette as a JSON document with its name and its colors as "#rrggbb" strings
+pub fn palette_json(name: &str, colors: &[u32]) -> String {
+    let colors: Vec<String> = colors.iter().map(|c| format!("\"#{:06x}\"", c)).collect();
+    format!(
+        "{{\n  \"name\": {},\n  \"colors\": [{}]\n}}\n",
+        json_string(name),
+        colors.join(", ")
+    )
+}
+
+/// Read the colors of a palette written by palette_json
+pub fn parse_palette_json(text: &str) -> Result<Vec<u32>, String> {
+    let start = text.find("\"colors\"").ok_or("there are no colors")?;
+    let list = &text[start..];
+    let (open, close) = match (list.find('['), list.find(']')) {
+        (Some(open), Some(close)) if open < close => (open, close),
+        _ => return Err("the colors are not a list".to_string()),
+    };
+    let mut colors = Vec::new();
+    for item in list[open + 1..close].split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+        let color = item
+            .strip_prefix("\"#")
+            .and_then(|s| s.strip_suffix('"'))
+            .filter(|hex| hex.len() == 6)
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| format!("{} is not a color like \"#rrggbb\"", item))?;
+        colors.push(color);
+    }
+    if colors.is_empty() {
+        return Err("there are no colors".to_string());
+    }
+    Ok(colors)
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()) == Some("json")
+}
+
+/// Load a palette file as a coloring named after the file: a JSON file made by save_palette
+/// gives e.g. palette-sunset, any other file is read as a Fractint .map file, e.g. map-volcano
+pub fn load_palette(path: &Path) -> io::Result<PaletteColoring> {
+    let prefix = if is_json(path) { "palette" } else { "map" };
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy())
+        .unwrap_or_default();
+    load_palette_as(path, &format!("{}-{}", prefix, stem))
+}
+
+/// Load a palette like load_palette, with the given name
+pub fn load_palette_as(path: &Path, name: &str) -> io::Result<PaletteColoring> {
+    let text = fs::read_to_string(path)?;
+    let colors = if is_json(path) {
+        parse_palette_json(&text)
+    } else {
+        parse_fractint_map(&text)
+    };
+    let colors = colors.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(PaletteColoring::new(name, &colors))
+}
+
+/// The number of colors of a palette that is saved
+pub const PALETTE_SAMPLES: u32 = MAP_COLORS as u32;
+
+/// The colors that a coloring gives to the values 0 to PALETTE_SAMPLES - 1 of points that
+/// escape, at an iteration depth of PALETTE_SAMPLES
+pub fn sample_palette(coloring: &dyn Coloring) -> Vec<u32> {
+    (0..PALETTE_SAMPLES)
+        .map(|v| coloring.get_smooth_color(v as f64, PALETTE_SAMPLES) & 0xffffff)
+        .collect()
+}
+
+/// The size of a palette strip image, and the number of values that it shows
+pub const STRIP_WIDTH: usize = 1024;
+pub const STRIP_HEIGHT: usize = 80;
+pub const STRIP_VALUES: u32 = 128;
+// The tick marks below the colors: a short one every TICK_STEP values, a long one
+// every MAJOR_TICK_STEP values
+const TICK_AREA: usize = 16;
+const TICK_STEP: u32 = 8;
+const MAJOR_TICK_STEP: u32 = 32;
+
+/// An image of the colors of the values 0 to `values` of a coloring, from left to right,
+/// in the RGB24 format. Below the colors there are tick marks at multiples of the values.
+pub fn palette_strip(coloring: &dyn Coloring, values: u32, width: usize, height: usize) -> Vec<u8> {
+    let colors_height = height.saturating_sub(TICK_AREA);
+    let value_at = |x: usize| x as f64 * values as f64 / width.max(1) as f64;
+    // Escaped points never reach the iteration depth, so the interior color is not shown
+    let max = values + 1;
+    let colors: Vec<u32> = (0..width)
+        .map(|x| coloring.get_smooth_color(value_at(x), max) & 0xffffff)
+        .collect();
+    // The tick for value v is in the first column whose value is at least v
+    let tick_len = |x: usize| {
+        let v = value_at(x).floor() as u32;
+        let first = x == 0 || value_at(x - 1).floor() as u32 != v;
+        if !first {
+            0
+        } else if v.is_multiple_of(MAJOR_TICK_STEP) {
+            TICK_AREA * 3 / 4
+        } else if v.is_multiple_of(TICK_STEP) {
+            TICK_AREA / 3
+        } else {
+            0
+        }
+    };
+    let ticks: Vec<usize> = (0..width).map(tick_len).collect();
+    let mut data = Vec::with_capacity(4 * width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let color = if y < colors_height {
+                colors[x]
+            } else if y - colors_height < ticks[x] {
+                0x000000
+            } else {
+                0xffffff
+            };
+            data.extend_from_slice(&color.to_ne_bytes());
+        }
+    }
+    data
+}
+
+/// Save the palette strip of a coloring as a PNG file
+pub fn save_palette_strip(coloring: &dyn Coloring, path: &Path) -> io::Result<()> {
+    let data = palette_strip(coloring, STRIP_VALUES, STRIP_WIDTH, STRIP_HEIGHT);
+    let text = [
+        ("coloring", coloring.name().to_string()),
+        ("values", STRIP_VALUES.to_string()),
+        ("ticks", format!("every {} values", TICK_STEP)),
+    ];
+    let out = BufWriter::new(File::create(path)?);
+    write_rgb24_png(
+        out,
+        &data,
+        STRIP_WIDTH,
+        STRIP_HEIGHT,
+        4 * STRIP_WIDTH,
+        &text,
+    )?;
+    Ok(())
+}
+
+/// Save the sampled palette of a coloring: as JSON if the file name ends in .json,
+/// otherwise as a Fractint .map file
+pub fn save_palette(coloring: &dyn Coloring, path: &Path) -> io::Result<()> {
+    let colors = sample_palette(coloring);
+    let text = if is_json(path) {
+        palette_json(coloring.name(), &colors)
+    } else {
+        fractint_map(&colors)
+    };
+    fs::write(path, text)
+}
+
+fn equalized() -> Box<dyn Coloring> {
+    Box::new(Equalized {
+        cdf: Arc::new(Vec::new()),
+    })
+}
+
+/// The colorings that are offered, in the order of the UI. A new coloring only needs
+/// a constructor here.
+const REGISTRY: &[fn() -> Box<dyn Coloring>] = &[
+    || Box::new(Rgb18 {}),
+    || Box::new(RgbAlternating {}),
+    || Box::new(RedBlue {}),
+    || Box::new(BlackWhite {}),
+    || Box::new(OldBlackWhite {}),
+    || Box::new(Smooth::new(Box::new(Rgb18 {}))),
+    || Box::new(Smooth::new(Box::new(RgbAlternating {}))),
+    || Box::new(Smooth::new(Box::new(RedBlue {}))),
+    equalized,
+    || Box::new(Distance {}),
+];
+
+// Colorings that were registered while running, e.g. by plugins
+static REGISTERED: Mutex<Vec<Box<dyn Coloring>>> = Mutex::new(Vec::new());
+
+/// Add a coloring to the colorings of every ColorInfo that is made from now on.
+/// A registered coloring with the same name is replaced.
+pub fn register_coloring(coloring: Box<dyn Coloring>) {
+    let mut registered = REGISTERED.lock().unwrap();
+    registered.retain(|c| c.name() != coloring.name());
+    registered.push(coloring);
+}
+
 fn all_colorings() -> Vec<Box<dyn Coloring>> {
-    vec![
-        Box::new(Rgb18 {}),
-        Box::new(RgbAlternating {}),
-        Box::new(RedBlue {}),
-        Box::new(BlackWhite {}),
-        Box::new(OldBlackWhite {}),
-    ]
+    let mut colorings: Vec<Box<dyn Coloring>> = REGISTRY.iter().map(|make| make()).collect();
+    for coloring in REGISTERED.lock().unwrap().iter() {
+        if !colorings.iter().any(|c| c.name() == coloring.name()) {
+            colorings.push(coloring.clone());
+        }
+    }
+    colorings
 }
 
 pub struct ColorInfo {
     colorings: Vec<Box<dyn Coloring>>,
+    // The number of colorings that are there from the start
+    builtin: usize,
 }
 
 pub struct NameIter<'a> {
@@ -168,8 +862,10 @@ impl<'a> Iterator for NameIter<'a> {
 
 impl ColorInfo {
     pub fn new() -> ColorInfo {
+        let colorings = all_colorings();
         ColorInfo {
-            colorings: all_colorings(),
+            builtin: colorings.len(),
+            colorings,
         }
     }
 
@@ -179,9 +875,224 @@ impl ColorInfo {
     pub fn scheme(&self, i: usize) -> &Box<dyn Coloring> {
         &self.colorings[i]
     }
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.colorings.iter().position(|clr| clr.name() == name)
+    }
+    /// Add a coloring and return its index. A coloring with the same name is replaced.
+    pub fn add(&mut self, coloring: Box<dyn Coloring>) -> usize {
+        match self.index_of(coloring.name()) {
+            Some(idx) => {
+                self.colorings[idx] = coloring;
+                idx
+            }
+            None => {
+                self.colorings.push(coloring);
+                self.colorings.len() - 1
+            }
+        }
+    }
+    /// The colorings that were added after the start, e.g. loaded palettes
+    pub fn added(&self) -> &[Box<dyn Coloring>] {
+        &self.colorings[self.builtin..]
+    }
     pub fn names_iter(&self) -> NameIter {
         NameIter {
             iter: self.colorings.iter(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_VALUES: [u32; 6] = [0, 1, 2, 17, 100, 1000];
+
+    fn check(col: &dyn Coloring, color: u32, v: u32) {
+        assert_eq!(
+            color >> 24,
+            0,
+            "{} gives {:#x} for {}",
+            col.name(),
+            color,
+            v
+        );
+    }
+
+    // Every value from 0 to max, with fractions and distances, gives a valid color
+    fn sweep(col: &dyn Coloring, max: u32) {
+        for v in 0..=max {
+            check(col, col.get_color(v, max), v);
+            for frac in [0.0, 0.25, 0.5, 0.999] {
+                check(col, col.get_smooth_color(v as f64 + frac, max), v);
+            }
+            for distance in [0.0, 0.3, 2.0, 1e9, f64::INFINITY, f64::NAN] {
+                check(col, col.get_distance_color(v, max, distance), v);
+            }
+        }
+    }
+
+    #[test]
+    fn colorings_are_total() {
+        for col in all_colorings() {
+            for max in MAX_VALUES {
+                sweep(col.as_ref(), max);
+                if col.needs_histogram() {
+                    let mut histogram = vec![1; max as usize + 1];
+                    histogram[0] = 0;
+                    if let Some(col) = col.for_histogram(&histogram) {
+                        sweep(col.as_ref(), max);
+                    }
+                    assert!(col.for_histogram(&vec![0; max as usize + 1]).is_none());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn interior_colors_replace_the_set() {
+        for col in all_colorings() {
+            let interior = InteriorColoring::new(col.clone(), 0x123456);
+            for max in MAX_VALUES {
+                sweep(&interior, max);
+                assert_eq!(interior.get_color(max, max), 0x123456);
+                assert_eq!(interior.get_smooth_color(max as f64, max), 0x123456);
+                if max > 0 {
+                    assert_eq!(
+                        interior.get_color(max - 1, max),
+                        col.get_color(max - 1, max)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn params_shift_and_stretch_the_palette() {
+        let rgb18 = Rgb18 {};
+        let shifted = ParamColoring::new(
+            Box::new(rgb18.clone()),
+            ColoringParams {
+                offset: 3.0,
+                density: 2.0,
+            },
+        );
+        assert_eq!(shifted.get_color(5, 100), rgb18.get_color(13, 100));
+        assert_eq!(shifted.get_color(100, 100), rgb18.get_color(100, 100));
+        for col in all_colorings() {
+            let identity = ParamColoring::new(col.clone(), ColoringParams::IDENTITY);
+            for max in MAX_VALUES {
+                sweep(&identity, max);
+                assert_eq!(
+                    identity.get_color(max / 2, max),
+                    col.get_color(max / 2, max)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn palette_strips_have_ticks() {
+        let rgb18 = Rgb18 {};
+        let (w, h) = (64, 20);
+        let strip = palette_strip(&rgb18, 16, w, h);
+        assert_eq!(strip.len(), 4 * w * h);
+        let pixel = |x: usize, y: usize| {
+            let i = 4 * (y * w + x);
+            u32::from_ne_bytes([strip[i], strip[i + 1], strip[i + 2], strip[i + 3]])
+        };
+        assert_eq!(pixel(0, 0), rgb18.get_color(0, 17));
+        assert_eq!(pixel(w - 1, 0), rgb18.get_color(15, 17));
+        let ticks = h - TICK_AREA;
+        // Ticks at the values 0 and 8, with 4 columns per value
+        assert_eq!(pixel(0, ticks), 0x000000);
+        assert_eq!(pixel(32, ticks), 0x000000);
+        assert_eq!(pixel(31, ticks), 0xffffff);
+        assert_eq!(pixel(4, ticks), 0xffffff);
+    }
+
+    #[test]
+    fn palettes_repeat() {
+        let rgb18 = Rgb18 {};
+        assert_eq!(rgb18.get_color(0, 100), rgb18.get_color(18, 100));
+        assert_eq!(rgb18.get_color(100, 100), 0x000000);
+    }
+
+    #[test]
+    fn gradients_are_total() {
+        let gradients = [
+            GradientColoring::new("none", &[]),
+            GradientColoring::new("one", &[(0.5, 0x123456)]),
+            GradientColoring::new("same", &[(0.3, 0xff0000), (0.3, 0x00ff00)]),
+            GradientColoring::new("wide", &[(-1.0, 0xffffffff), (2.0, 0x0000ff)]),
+        ];
+        for col in &gradients {
+            for max in MAX_VALUES {
+                sweep(col, max);
+            }
+        }
+    }
+
+    #[test]
+    fn gradients_are_cyclic() {
+        let col = GradientColoring::new("g", &[(0.25, 0xff0000), (0.75, 0x0000ff)]);
+        assert_eq!(col.color_at(0.25), 0xff0000);
+        assert_eq!(col.color_at(0.75), 0x0000ff);
+        assert_eq!(col.color_at(0.0), col.color_at(1.0));
+        assert_eq!(col.get_color(0, 100), col.get_color(GRADIENT_PERIOD, 100));
+    }
+
+    #[test]
+    fn fractint_maps_are_read() {
+        let map = "0 0 0 black\n\n255 128 1\n  10 20 30  comment with 1 2 3\n";
+        assert_eq!(
+            parse_fractint_map(map),
+            Ok(vec![0x000000, 0xff8001, 0x0a141e])
+        );
+        assert!(parse_fractint_map("").is_err());
+        assert!(parse_fractint_map("1 2\n").is_err());
+        assert!(parse_fractint_map("1 2 256\n").is_err());
+        assert!(parse_fractint_map(&"1 2 3\n".repeat(MAP_COLORS + 1)).is_err());
+        let col = PaletteColoring::new("map", &parse_fractint_map(map).unwrap());
+        for max in MAX_VALUES {
+            sweep(&col, max);
+        }
+        assert_eq!(col.get_color(1, 100), col.get_color(4, 100));
+    }
+
+    #[test]
+    fn saved_palettes_load_again() {
+        for col in all_colorings() {
+            let colors = sample_palette(col.as_ref());
+            assert_eq!(colors.len(), PALETTE_SAMPLES as usize);
+            assert_eq!(
+                parse_fractint_map(&fractint_map(&colors)),
+                Ok(colors.clone())
+            );
+            let json = palette_json(col.name(), &colors);
+            assert_eq!(parse_palette_json(&json), Ok(colors));
+        }
+        assert!(parse_palette_json("{\"colors\": []}").is_err());
+        assert!(parse_palette_json("{\"colors\": [\"#12345\"]}").is_err());
+    }
+
+    #[test]
+    fn added_colorings_replace_by_name() {
+        let mut info = ColorInfo::new();
+        let n = info.len();
+        let idx = info.add(Box::new(GradientColoring::new("g", &[(0.0, 0xff0000)])));
+        assert_eq!((idx, info.len()), (n, n + 1));
+        let idx = info.add(Box::new(GradientColoring::new("g", &[(0.0, 0x00ff00)])));
+        assert_eq!((idx, info.len()), (n, n + 1));
+        assert_eq!(info.scheme(idx).get_color(0, 100), 0x00ff00);
+    }
+
+    #[test]
+    fn names_are_unique() {
+        let info = ColorInfo::new();
+        let names: Vec<&str> = info.names_iter().collect();
+        for (i, name) in names.iter().enumerate() {
+            assert_eq!(info.index_of(name), Some(i));
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use crate::colorings::Coloring;
+
+/// The number of distinct values of a color channel
+const CHANNEL_LEN: usize = 256;
+
+#[derive(Clone)]
+/// A transfer curve for one color channel. The curve goes through a
+/// number of control points (input value, output value), sorted on the
+/// input value. The first and last control points are always at input
+/// values 0 and 255. Between control points a monotone cubic is used.
+pub struct Curve {
+    points: Vec<(u8, u8)>,
+    lut: [u8; CHANNEL_LEN],
+}
+
+impl Curve {
+    pub fn identity() -> Curve {
+        let mut curve = Curve {
+            points: vec![(0, 0), (255, 255)],
+            lut: [0; CHANNEL_LEN],
+        };
+        curve.update_lut();
+        curve
+    }
+    pub fn points(&self) -> &[(u8, u8)] {
+        self.points.as_slice()
+    }
+    pub fn is_identity(&self) -> bool {
+        self.lut.iter().enumerate().all(|(i, v)| i == *v as usize)
+    }
+    pub fn apply(&self, v: u8) -> u8 {
+        self.lut[v as usize]
+    }
+    /// Add a control point and return its index.
+    /// An existing point with the same input value is replaced.
+    pub fn add_point(&mut self, x: u8, y: u8) -> usize {
+        let idx = match self.points.binary_search_by_key(&x, |p| p.0) {
+            Ok(idx) => {
+                self.points[idx].1 = y;
+                idx
+            }
+            Err(idx) => {
+                self.points.insert(idx, (x, y));
+                idx
+            }
+        };
+        self.update_lut();
+        idx
+    }
+    /// Move control point `idx`. The end points can only move vertically and
+    /// the other points can not pass their neighbours.
+    pub fn move_point(&mut self, idx: usize, x: u8, y: u8) {
+        let last = self.points.len() - 1;
+        let x = if idx == 0 {
+            0
+        } else if idx == last {
+            255
+        } else {
+            x.clamp(self.points[idx - 1].0 + 1, self.points[idx + 1].0 - 1)
+        };
+        self.points[idx] = (x, y);
+        self.update_lut();
+    }
+    /// Remove control point `idx`. The end points can not be removed.
+    pub fn remove_point(&mut self, idx: usize) {
+        if 0 < idx && idx < self.points.len() - 1 {
+            self.points.remove(idx);
+            self.update_lut();
+        }
+    }
+    // Fill the lookup table with a monotone cubic (Fritsch-Carlson) through the points
+    fn update_lut(&mut self) {
+        let pts: Vec<(f64, f64)> = self
+            .points
+            .iter()
+            .map(|&(x, y)| (x as f64, y as f64))
+            .collect();
+        let n = pts.len();
+        let slopes: Vec<f64> = pts
+            .windows(2)
+            .map(|w| (w[1].1 - w[0].1) / (w[1].0 - w[0].0))
+            .collect();
+        let mut tangents = vec![0.0; n];
+        tangents[0] = slopes[0];
+        tangents[n - 1] = slopes[n - 2];
+        for i in 1..n - 1 {
+            if slopes[i - 1] * slopes[i] > 0.0 {
+                tangents[i] = (slopes[i - 1] + slopes[i]) / 2.0;
+            }
+        }
+        for i in 0..n - 1 {
+            if slopes[i] == 0.0 {
+                tangents[i] = 0.0;
+                tangents[i + 1] = 0.0;
+            } else {
+                let a = tangents[i] / slopes[i];
+                let b = tangents[i + 1] / slopes[i];
+                let s = a * a + b * b;
+                if s > 9.0 {
+                    let t = 3.0 / s.sqrt();
+                    tangents[i] = t * a * slopes[i];
+                    tangents[i + 1] = t * b * slopes[i];
+                }
+            }
+        }
+        let mut seg = 0;
+        for x in 0..CHANNEL_LEN {
+            let xf = x as f64;
+            while seg < n - 2 && pts[seg + 1].0 < xf {
+                seg += 1;
+            }
+            let (x0, y0) = pts[seg];
+            let (x1, y1) = pts[seg + 1];
+            let h = x1 - x0;
+            let t = (xf - x0) / h;
+            let (t2, t3) = (t * t, t * t * t);
+            let y = (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+                + (t3 - 2.0 * t2 + t) * h * tangents[seg]
+                + (-2.0 * t3 + 3.0 * t2) * y1
+                + (t3 - t2) * h * tangents[seg + 1];
+            self.lut[x] = y.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+#[derive(Clone)]
+/// Transfer curves for the red, green and blue channel of a color
+pub struct TransferCurves {
+    channels: [Curve; 3],
+}
+
+impl TransferCurves {
+    pub const CHANNEL_NAMES: [&'static str; 3] = ["red", "green", "blue"];
+
+    pub fn identity() -> TransferCurves {
+        TransferCurves {
+            channels: [Curve::identity(), Curve::identity(), Curve::identity()],
+        }
+    }
+    pub fn is_identity(&self) -> bool {
+        self.channels.iter().all(|c| c.is_identity())
+    }
+    pub fn channel(&self, i: usize) -> &Curve {
+        &self.channels[i]
+    }
+    pub fn channel_mut(&mut self, i: usize) -> &mut Curve {
+        &mut self.channels[i]
+    }
+    /// Apply the curves to a color in GTK RGB-format
+    pub fn apply(&self, color: u32) -> u32 {
+        let r = self.channels[0].apply((color >> 16) as u8) as u32;
+        let g = self.channels[1].apply((color >> 8) as u8) as u32;
+        let b = self.channels[2].apply(color as u8) as u32;
+        (r << 16) | (g << 8) | b
+    }
+}
+
+#[derive(Clone)]
+/// A coloring that passes the colors of another coloring through transfer curves
+pub struct CurvedColoring {
+    inner: Box<dyn Coloring>,
+    curves: TransferCurves,
+}
+
+impl CurvedColoring {
+    pub fn new(inner: Box<dyn Coloring>, curves: TransferCurves) -> CurvedColoring {
+        CurvedColoring { inner, curves }
+    }
+}
+
+impl Coloring for CurvedColoring {
+    fn get_color(&self, v: u32, max: u32) -> u32 {
+        self.curves.apply(self.inner.get_color(v, max))
+    }
+
+    fn get_smooth_color(&self, v: f64, max: u32) -> u32 {
+        self.curves.apply(self.inner.get_smooth_color(v, max))
+    }
+
+    fn is_smooth(&self) -> bool {
+        self.inner.is_smooth()
+    }
+
+    fn uses_distance(&self) -> bool {
+        self.inner.uses_distance()
+    }
+
+    fn get_distance_color(&self, v: u32, max: u32, distance: f64) -> u32 {
+        self.curves
+            .apply(self.inner.get_distance_color(v, max, distance))
+    }
+
+    fn needs_histogram(&self) -> bool {
+        self.inner.needs_histogram()
+    }
+
+    fn for_histogram(&self, histogram: &[u32]) -> Option<Box<dyn Coloring>> {
+        let inner = self.inner.for_histogram(histogram)?;
+        Some(Box::new(CurvedColoring::new(inner, self.curves.clone())))
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+}
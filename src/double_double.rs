@@ -0,0 +1,107 @@
+// Double-double numbers: the unevaluated sum of two f64, hi + lo with |lo| at most half an ulp
+// of hi, which gives 106 bits of precision. The operations are the error-free transformations
+// of Dekker and Knuth, so they cost a few f64 operations each, far less than the limbs of a
+// Fixed number of the same precision.
+
+use std::ops::{Add, Mul, Sub};
+
+use crate::fixed::Fixed;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DoubleDouble {
+    hi: f64,
+    lo: f64,
+}
+
+// a + b as the rounded sum and its rounding error
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+// a + b as the rounded sum and its rounding error, for |a| >= |b|
+fn quick_two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    (s, b - (s - a))
+}
+
+impl DoubleDouble {
+    pub const ZERO: DoubleDouble = DoubleDouble { hi: 0.0, lo: 0.0 };
+
+    /// The nearest double-double of a fixed point number
+    pub fn from_fixed(v: &Fixed) -> DoubleDouble {
+        let hi = v.to_f64();
+        let lo = v.sub(&Fixed::from_f64(hi, v.fraction_limbs())).to_f64();
+        DoubleDouble { hi, lo }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.hi + self.lo
+    }
+
+    /// Twice the number, which is exact
+    pub fn double(self) -> DoubleDouble {
+        DoubleDouble {
+            hi: 2.0 * self.hi,
+            lo: 2.0 * self.lo,
+        }
+    }
+}
+
+impl Add for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn add(self, other: DoubleDouble) -> DoubleDouble {
+        let (s, e) = two_sum(self.hi, other.hi);
+        let (t, f) = two_sum(self.lo, other.lo);
+        let (s, e) = quick_two_sum(s, e + t);
+        let (hi, lo) = quick_two_sum(s, e + f);
+        DoubleDouble { hi, lo }
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn sub(self, other: DoubleDouble) -> DoubleDouble {
+        self + DoubleDouble {
+            hi: -other.hi,
+            lo: -other.lo,
+        }
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn mul(self, other: DoubleDouble) -> DoubleDouble {
+        let p = self.hi * other.hi;
+        // The fused multiply-add gives the rounding error of the product exactly
+        let e = self.hi.mul_add(other.hi, -p);
+        let e = e + (self.hi * other.lo + self.lo * other.hi);
+        let (hi, lo) = quick_two_sum(p, e);
+        DoubleDouble { hi, lo }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn double_double_keeps_the_low_bits() {
+        let x = Fixed::parse("-0.743643887037158704752191506114774").unwrap();
+        let y = Fixed::parse("0.131825904205311970493132056385139").unwrap();
+        let (dx, dy) = (DoubleDouble::from_fixed(&x), DoubleDouble::from_fixed(&y));
+        // Sums and products agree with the fixed point ones far beyond f64
+        let exact = x.mul(&y).add(&x).sub(&y).with_limbs(4);
+        let dd = dx * dy + dx - dy;
+        let error = exact
+            .sub(&Fixed::from_f64(dd.hi, 4))
+            .sub(&Fixed::from_f64(dd.lo, 4));
+        assert!(error.to_f64().abs() < 1e-30, "{}", error.to_f64());
+        assert_ne!(dd.lo, 0.0);
+        assert_eq!(dx.double().to_f64(), 2.0 * dx.to_f64());
+    }
+}
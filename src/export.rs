@@ -0,0 +1,123 @@
+// Rendering of large images for export. The image is rendered in tiles that are written to
+// the PNG file as soon as they are done, so the whole image is never in memory.
+
+use std::io::{self, Write};
+
+use scoped_threadpool::Pool;
+
+use crate::backend::RenderBackend;
+use crate::colorings::Coloring;
+use crate::mandel_image::{sample_histogram, supersampled_image, tiles, CancelToken, Mapping};
+use crate::png::{rgb24_to_rgb, ColorType, PngEncoder};
+
+/// The mapping for an image of width x height pixels that shows all of the view, with the
+/// center of the view in its center. A different aspect ratio adds space at two sides.
+pub fn export_mapping(
+    view: &Mapping,
+    width: usize,
+    height: usize,
+    iteration_depth: u32,
+) -> Mapping {
+    let scale_x = view.scale * view.win_width as f64 / width.max(1) as f64;
+    let scale_y = view.scale * view.win_height as f64 / height.max(1) as f64;
+    Mapping {
+        scale: scale_x.max(scale_y),
+        iteration_depth,
+        win_width: width,
+        win_height: height,
+        ..view.clone()
+    }
+}
+
+/// The coloring for the tiles of an image with the given mapping. A coloring that depends on
+/// the histogram gets that of the whole image, not that of a tile. None if cancelled.
+pub fn tile_coloring(
+    mapping: &Mapping,
+    coloring: Box<dyn Coloring>,
+    pool: &mut Option<Pool>,
+    cancel: &CancelToken,
+) -> Option<Box<dyn Coloring>> {
+    if !coloring.needs_histogram() {
+        return Some(coloring);
+    }
+    let histogram = sample_histogram(mapping, pool, cancel)?;
+    Some(coloring.for_histogram(&histogram).unwrap_or(coloring))
+}
+
+/// An image to export
+pub struct ExportJob {
+    pub mapping: Mapping,
+    pub coloring: Box<dyn Coloring>,
+    /// Every pixel is the average of factor x factor samples
+    pub factor: usize,
+    pub backend: &'static dyn RenderBackend,
+}
+
+impl ExportJob {
+    /// Render the image as an RGB PNG file, without slope shading. Only one tile of the image
+    /// is in memory at a time. progress is called with the fraction of the image that is done
+    /// after every tile. A cancelled export gives an
+    /// error of kind Interrupted.
+    pub fn write_png<W: Write>(
+        &self,
+        out: W,
+        text: &[(&str, String)],
+        pool: &mut Option<Pool>,
+        cancel: &CancelToken,
+        mut progress: impl FnMut(f64),
+    ) -> io::Result<W> {
+        let mapping = &self.mapping;
+        if !mapping.is_valid() {
+            return Err(io::Error::other("invalid image size"));
+        }
+        let cancelled = || io::Error::new(io::ErrorKind::Interrupted, "the export was cancelled");
+        let coloring =
+            &tile_coloring(mapping, self.coloring.clone(), pool, cancel).ok_or_else(cancelled)?;
+        let (width, height) = (mapping.win_width, mapping.win_height);
+        let mut encoder = PngEncoder::new(out, width, height, ColorType::Rgb, text)?;
+        let mut rgb = Vec::with_capacity(3 * width);
+        for (first_row, tile) in tiles(mapping, self.factor) {
+            let (data, stride, _) = supersampled_image(
+                self.backend,
+                &tile,
+                coloring.as_ref(),
+                pool,
+                self.factor,
+                cancel,
+            )
+            .ok_or_else(cancelled)?;
+            for y in 0..tile.win_height {
+                rgb24_to_rgb(&data[y * stride as usize..], width, &mut rgb);
+                encoder.write_row(&rgb)?;
+            }
+            progress((first_row + tile.win_height) as f64 / height as f64);
+        }
+        encoder.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mandel_image::{WinToMandel, TILE_BYTES};
+
+    #[test]
+    fn tiles_continue_the_image() {
+        let view = Mapping::new_for_size(600);
+        let mapping = export_mapping(&view, 20000, 250, 100);
+        // The whole view fits, and the larger side is filled
+        assert!(mapping.scale * 20000.0 >= view.scale * 600.0);
+        assert!((mapping.scale * 250.0 - view.scale * 600.0).abs() < 1e-12);
+        let full = WinToMandel::from_mapping(&mapping);
+        let tiles: Vec<(usize, Mapping)> = tiles(&mapping, 4).collect();
+        assert_eq!(tiles.iter().map(|(_, t)| t.win_height).sum::<usize>(), 250);
+        let (first_row, tile) = &tiles[1];
+        assert!(4 * 20000 * 16 * tile.win_height <= TILE_BYTES);
+        let tile = WinToMandel::from_mapping(tile);
+        for (wx, wy) in [(0, 0), (19999, 20), (500, 10)] {
+            let (x0, y0) = full.cvt(wx, wy + first_row);
+            let (x1, y1) = tile.cvt(wx, wy);
+            assert!((x0 - x1).abs() < 1e-12 && (y0 - y1).abs() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,349 @@
+// Numbers with more precision than f64, for the centers of views that are deeper than f64
+// can tell pixels apart. There is no multi-precision crate among the dependencies (rug
+// needs GMP, a C library), and the coordinates of views are small, so a number is a fixed
+// point binary fraction: an integer part of 32 bits and as many 32 bit limbs after the
+// binary point as the scale of the view needs. Only what the reference orbits of deep views
+// need is there: addition, subtraction, multiplication and decimal text.
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+// The number of bits of precision that a deep view gets beyond the size of its pixels, for
+// the rounding of the reference orbit
+const GUARD_BITS: f64 = 64.0;
+
+#[derive(Clone, Debug)]
+/// A signed fixed point number. The limbs are little endian: the last limb is the integer
+/// part, the others are the fraction.
+pub struct Fixed {
+    negative: bool,
+    limbs: Vec<u32>,
+}
+
+/// The number of limbs after the binary point for a view with pixels of size scale
+pub fn limbs_for_scale(scale: f64) -> usize {
+    ((GUARD_BITS - scale.log2()) / 32.0).ceil().max(2.0) as usize
+}
+
+// Compare magnitudes of the same number of limbs
+fn cmp_magnitude(a: &[u32], b: &[u32]) -> std::cmp::Ordering {
+    a.iter().rev().cmp(b.iter().rev())
+}
+
+impl Fixed {
+    /// Zero with fraction limbs after the binary point
+    pub fn zero(fraction: usize) -> Fixed {
+        Fixed {
+            negative: false,
+            limbs: vec![0; fraction + 1],
+        }
+    }
+
+    /// The value of v with fraction limbs after the binary point. Bits of v below the last
+    /// limb are cut off. v must be smaller than 2^32.
+    pub fn from_f64(v: f64, fraction: usize) -> Fixed {
+        let mut n = Fixed::zero(fraction);
+        let mut rest = v.abs();
+        for limb in n.limbs.iter_mut().rev() {
+            // Multiplying by a power of two and taking the integer part off are exact
+            let part = rest.floor().min(u32::MAX as f64);
+            *limb = part as u32;
+            rest = (rest - part) * 4294967296.0;
+        }
+        n.negative = v < 0.0 && !n.is_zero();
+        n
+    }
+
+    /// The nearest f64, up to the rounding of the last bit
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self
+            .limbs
+            .iter()
+            .fold(0.0, |v, &limb| v / 4294967296.0 + limb as f64);
+        if self.negative {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    /// The number of limbs after the binary point
+    pub fn fraction_limbs(&self) -> usize {
+        self.limbs.len() - 1
+    }
+
+    fn is_zero(&self) -> bool {
+        self.limbs.iter().all(|&limb| limb == 0)
+    }
+
+    /// The same value with fraction limbs after the binary point, rounded to the nearest
+    pub fn with_limbs(&self, fraction: usize) -> Fixed {
+        let mut n = Fixed::zero(fraction);
+        let len = self.limbs.len();
+        if fraction + 1 >= len {
+            n.limbs[fraction + 1 - len..].copy_from_slice(&self.limbs);
+        } else {
+            let cut = len - fraction - 1;
+            n.limbs.copy_from_slice(&self.limbs[cut..]);
+            if self.limbs[cut - 1] & 0x8000_0000 != 0 {
+                n.add_ulp();
+            }
+        }
+        n.negative = self.negative && !n.is_zero();
+        n
+    }
+
+    // Add one to the magnitude in the last limb
+    fn add_ulp(&mut self) {
+        for limb in self.limbs.iter_mut() {
+            let (sum, carry) = limb.overflowing_add(1);
+            *limb = sum;
+            if !carry {
+                break;
+            }
+        }
+    }
+
+    // Both numbers with the larger number of limbs
+    fn aligned(&self, other: &Fixed) -> (Fixed, Fixed) {
+        let fraction = self.fraction_limbs().max(other.fraction_limbs());
+        (self.with_limbs(fraction), other.with_limbs(fraction))
+    }
+
+    /// The sum, with the precision of the more precise of the two
+    pub fn add(&self, other: &Fixed) -> Fixed {
+        let (a, b) = self.aligned(other);
+        if a.negative == b.negative {
+            let mut limbs = Vec::with_capacity(a.limbs.len());
+            let mut carry = 0;
+            for (x, y) in a.limbs.iter().zip(&b.limbs) {
+                let sum = *x as u64 + *y as u64 + carry;
+                limbs.push(sum as u32);
+                carry = sum >> 32;
+            }
+            return Fixed {
+                negative: a.negative,
+                limbs,
+            };
+        }
+        // Subtract the smaller magnitude from the larger, which gives the sign
+        let (large, small) = match cmp_magnitude(&a.limbs, &b.limbs) {
+            std::cmp::Ordering::Less => (b, a),
+            _ => (a, b),
+        };
+        let mut limbs = Vec::with_capacity(large.limbs.len());
+        let mut borrow = 0;
+        for (x, y) in large.limbs.iter().zip(&small.limbs) {
+            let (diff, b1) = x.overflowing_sub(*y);
+            let (diff, b2) = diff.overflowing_sub(borrow);
+            limbs.push(diff);
+            borrow = (b1 || b2) as u32;
+        }
+        let mut n = Fixed {
+            negative: large.negative,
+            limbs,
+        };
+        n.negative &= !n.is_zero();
+        n
+    }
+
+    /// The difference, with the precision of the more precise of the two
+    pub fn sub(&self, other: &Fixed) -> Fixed {
+        let mut negated = other.clone();
+        negated.negative = !other.negative && !other.is_zero();
+        self.add(&negated)
+    }
+
+    /// The product, with the precision of the more precise of the two. The bits below the
+    /// last limb are cut off.
+    pub fn mul(&self, other: &Fixed) -> Fixed {
+        let (a, b) = self.aligned(other);
+        let n = a.limbs.len();
+        let mut product = vec![0u32; 2 * n];
+        for (i, &x) in a.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &y) in b.limbs.iter().enumerate() {
+                let t = x as u64 * y as u64 + product[i + j] as u64 + carry;
+                product[i + j] = t as u32;
+                carry = t >> 32;
+            }
+            product[i + n] = carry as u32;
+        }
+        let mut m = Fixed {
+            negative: a.negative != b.negative,
+            limbs: product[n - 1..2 * n - 1].to_vec(),
+        };
+        m.negative &= !m.is_zero();
+        m
+    }
+
+    /// The value of a decimal like -0.743643887037158704752191506114774, without an exponent.
+    /// The precision follows the number of digits.
+    pub fn parse(text: &str) -> Option<Fixed> {
+        let text = text.trim();
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let valid = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if integer.is_empty() && fraction.is_empty() || !valid(integer) || !valid(fraction) {
+            return None;
+        }
+        let integer: u32 = if integer.is_empty() {
+            0
+        } else {
+            integer.parse().ok()?
+        };
+        // A digit needs log2(10) bits, and one guard limb keeps the rounding exact
+        let limbs = fraction.len() * 10 / 3 / 32 + 1;
+        let mut n = Fixed::zero(limbs + 1);
+        for digit in fraction.bytes().rev() {
+            *n.limbs.last_mut()? = (digit - b'0') as u32;
+            n.div_small(10);
+        }
+        *n.limbs.last_mut()? = integer;
+        let mut n = n.with_limbs(limbs);
+        n.negative = negative && !n.is_zero();
+        Some(n)
+    }
+
+    // Divide the magnitude by d, cutting off the remainder
+    fn div_small(&mut self, d: u32) {
+        let mut rem = 0u64;
+        for limb in self.limbs.iter_mut().rev() {
+            let cur = rem << 32 | *limb as u64;
+            *limb = (cur / d as u64) as u32;
+            rem = cur % d as u64;
+        }
+    }
+
+    // The limbs without the trailing zeros of the fraction, which do not change the value
+    fn significant(&self) -> &[u32] {
+        let zeros = self.limbs.iter().take_while(|&&limb| limb == 0).count();
+        &self.limbs[zeros.min(self.limbs.len() - 1)..]
+    }
+}
+
+impl PartialEq for Fixed {
+    fn eq(&self, other: &Fixed) -> bool {
+        self.negative == other.negative && self.significant() == other.significant()
+    }
+}
+
+impl Hash for Fixed {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.negative.hash(state);
+        self.significant().hash(state);
+    }
+}
+
+impl fmt::Display for Fixed {
+    /// All decimals of the number, which a binary fraction has a finite number of
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let last = self.limbs.len() - 1;
+        let sign = if self.negative { "-" } else { "" };
+        write!(f, "{}{}", sign, self.limbs[last])?;
+        let mut fraction = self.significant()[..self.significant().len() - 1].to_vec();
+        if fraction.is_empty() {
+            return Ok(());
+        }
+        f.write_str(".")?;
+        while fraction.iter().any(|&limb| limb != 0) {
+            let mut carry = 0u64;
+            for limb in fraction.iter_mut() {
+                let t = *limb as u64 * 10 + carry;
+                *limb = t as u32;
+                carry = t >> 32;
+            }
+            write!(f, "{}", carry)?;
+        }
+        Ok(())
+    }
+}
+
+// The number of significant digits that tell all f64 values apart
+const F64_DIGITS: usize = 17;
+
+// The number of digits of a decimal without the leading and trailing zeros
+fn significant_digits(text: &str) -> usize {
+    let digits: String = text.chars().filter(|c| c.is_ascii_digit()).collect();
+    digits.trim_matches('0').len()
+}
+
+#[derive(Clone, PartialEq, Hash, Debug)]
+/// The center of a deep view with more precision than f64. The cx and cy of the view stay the
+/// nearest f64 and can be changed as before: the view is centered at x + (cx - x as f64),
+/// y + (cy - y as f64), so a change of cx moves the center by just that much.
+pub struct PreciseCenter {
+    pub x: Fixed,
+    pub y: Fixed,
+}
+
+impl PreciseCenter {
+    /// The center as f64, which the view gets as cx and cy
+    pub fn approx(&self) -> (f64, f64) {
+        (self.x.to_f64(), self.y.to_f64())
+    }
+
+    /// The precise center of a view with center (cx, cy)
+    pub fn at(&self, cx: f64, cy: f64) -> PreciseCenter {
+        let (ax, ay) = self.approx();
+        self.moved(cx - ax, cy - ay)
+    }
+
+    /// The center moved by (dx, dy), without losing the bits of the move below those of the
+    /// f64 center
+    pub fn moved(&self, dx: f64, dy: f64) -> PreciseCenter {
+        if dx == 0.0 && dy == 0.0 {
+            return self.clone();
+        }
+        let fraction = self
+            .x
+            .fraction_limbs()
+            .max(limbs_for_scale(dx.abs().max(dy.abs())));
+        PreciseCenter {
+            x: self.x.add(&Fixed::from_f64(dx, fraction)),
+            y: self.y.add(&Fixed::from_f64(dy, fraction)),
+        }
+    }
+
+    /// The precise center for the decimals of cx and cy, if they have more digits than an f64
+    /// holds
+    pub fn from_text(cx: &str, cy: &str) -> Option<PreciseCenter> {
+        if significant_digits(cx) <= F64_DIGITS && significant_digits(cy) <= F64_DIGITS {
+            return None;
+        }
+        Some(PreciseCenter {
+            x: Fixed::parse(cx)?,
+            y: Fixed::parse(cy)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_point_arithmetic_is_exact() {
+        let a = Fixed::from_f64(-0.75, 3);
+        let b = Fixed::from_f64(0.1, 3);
+        assert_eq!(a.add(&b).to_f64(), -0.65);
+        assert_eq!(b.sub(&a).to_f64(), 0.85);
+        assert_eq!(a.mul(&a).to_f64(), 0.5625);
+        assert_eq!(a.mul(&b).to_f64(), -0.75 * 0.1);
+        // A difference far below the precision of f64 is kept
+        let tiny = Fixed::from_f64(1e-30, 6);
+        let moved = a.add(&tiny);
+        assert_eq!(moved.to_f64(), -0.75);
+        assert!((moved.sub(&a).to_f64() / 1e-30 - 1.0).abs() < 1e-15);
+        let text = "-0.743643887037158704752191506114774";
+        let x = Fixed::parse(text).unwrap();
+        assert_eq!(x.to_f64(), -0.7436438870371587);
+        assert_eq!(Fixed::parse(&x.to_string()), Some(x.clone()));
+        assert!(x.to_string().starts_with(text));
+        assert_eq!(Fixed::parse("1.5").unwrap().to_string(), "1.5");
+        assert_eq!(Fixed::parse("1e-5"), None);
+    }
+}
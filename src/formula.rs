@@ -0,0 +1,655 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::sync::{Arc, RwLock};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+/// A complex number
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub const fn new(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+    pub fn norm_sqr(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+    fn abs(self) -> f64 {
+        self.norm_sqr().sqrt()
+    }
+    fn exp(self) -> Complex {
+        let r = self.re.exp();
+        Complex::new(r * self.im.cos(), r * self.im.sin())
+    }
+    fn ln(self) -> Complex {
+        Complex::new(self.abs().ln(), self.im.atan2(self.re))
+    }
+    fn sqrt(self) -> Complex {
+        let r = self.abs().sqrt();
+        let theta = self.im.atan2(self.re) / 2.0;
+        Complex::new(r * theta.cos(), r * theta.sin())
+    }
+    fn sin(self) -> Complex {
+        Complex::new(
+            self.re.sin() * self.im.cosh(),
+            self.re.cos() * self.im.sinh(),
+        )
+    }
+    fn cos(self) -> Complex {
+        Complex::new(
+            self.re.cos() * self.im.cosh(),
+            -self.re.sin() * self.im.sinh(),
+        )
+    }
+    fn sinh(self) -> Complex {
+        Complex::new(
+            self.re.sinh() * self.im.cos(),
+            self.re.cosh() * self.im.sin(),
+        )
+    }
+    fn cosh(self) -> Complex {
+        Complex::new(
+            self.re.cosh() * self.im.cos(),
+            self.re.sinh() * self.im.sin(),
+        )
+    }
+    // 1/z, which is infinite for 0 as for real numbers
+    fn recip(self) -> Complex {
+        let d = self.norm_sqr();
+        if d == 0.0 {
+            return Complex::new(f64::INFINITY, 0.0);
+        }
+        Complex::new(self.re / d, -self.im / d)
+    }
+    fn powi(self, n: i32) -> Complex {
+        let mut result = Complex::new(1.0, 0.0);
+        for _ in 0..n.unsigned_abs() {
+            result = result * self;
+        }
+        if n < 0 {
+            result.recip()
+        } else {
+            result
+        }
+    }
+    fn pow(self, w: Complex) -> Complex {
+        if self.re == 0.0 && self.im == 0.0 {
+            return self;
+        }
+        (w * self.ln()).exp()
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, o: Complex) -> Complex {
+        Complex::new(self.re + o.re, self.im + o.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, o: Complex) -> Complex {
+        Complex::new(self.re - o.re, self.im - o.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, o: Complex) -> Complex {
+        Complex::new(
+            self.re * o.re - self.im * o.im,
+            self.re * o.im + self.im * o.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+    fn div(self, o: Complex) -> Complex {
+        let d = o.re * o.re + o.im * o.im;
+        Complex::new(
+            (self.re * o.re + self.im * o.im) / d,
+            (self.im * o.re - self.re * o.im) / d,
+        )
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+    fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+/// An error in a formula, at a character position
+pub struct FormulaError {
+    pub pos: usize,
+    pub message: String,
+}
+
+impl fmt::Display for FormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.pos + 1)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Token {
+    Num(f64),
+    Name(usize, usize),
+    Op(char),
+    End,
+}
+
+type Function = fn(Complex) -> Complex;
+
+// The functions of one complex argument that can be used in a formula
+const FUNCTIONS: [(&str, Function); 12] = [
+    ("sin", Complex::sin),
+    ("cos", Complex::cos),
+    ("tan", |z| z.sin() / z.cos()),
+    ("sinh", Complex::sinh),
+    ("cosh", Complex::cosh),
+    ("exp", Complex::exp),
+    ("log", Complex::ln),
+    ("sqrt", Complex::sqrt),
+    ("conj", |z| Complex::new(z.re, -z.im)),
+    ("abs", |z| Complex::new(z.abs(), 0.0)),
+    ("re", |z| Complex::new(z.re, 0.0)),
+    ("im", |z| Complex::new(z.im, 0.0)),
+];
+
+// An expression tree. Evaluation is done by closures that are made from the tree.
+enum Expr {
+    Const(Complex),
+    Z,
+    C,
+    Neg(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+    // An integer power, computed by repeated multiplication
+    PowI(Box<Expr>, i32),
+    Call(Function, Box<Expr>),
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    token: Token,
+    token_pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(src: &'a str) -> Result<Parser<'a>, FormulaError> {
+        let mut parser = Parser {
+            src,
+            pos: 0,
+            token: Token::End,
+            token_pos: 0,
+        };
+        parser.advance()?;
+        Ok(parser)
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, FormulaError> {
+        Err(FormulaError {
+            pos: self.token_pos,
+            message: message.to_string(),
+        })
+    }
+
+    fn advance(&mut self) -> Result<(), FormulaError> {
+        let bytes = self.src.as_bytes();
+        while self.pos < bytes.len() && bytes[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        self.token_pos = self.pos;
+        if self.pos >= bytes.len() {
+            self.token = Token::End;
+            return Ok(());
+        }
+        let start = self.pos;
+        let b = bytes[self.pos];
+        if b.is_ascii_digit() || b == b'.' {
+            while self.pos < bytes.len()
+                && (bytes[self.pos].is_ascii_digit() || bytes[self.pos] == b'.')
+            {
+                self.pos += 1;
+            }
+            // An exponent, like 1e-3
+            if self.pos < bytes.len() && (bytes[self.pos] == b'e' || bytes[self.pos] == b'E') {
+                let mut p = self.pos + 1;
+                if p < bytes.len() && (bytes[p] == b'+' || bytes[p] == b'-') {
+                    p += 1;
+                }
+                if p < bytes.len() && bytes[p].is_ascii_digit() {
+                    while p < bytes.len() && bytes[p].is_ascii_digit() {
+                        p += 1;
+                    }
+                    self.pos = p;
+                }
+            }
+            match self.src[start..self.pos].parse::<f64>() {
+                Ok(v) => self.token = Token::Num(v),
+                Err(_) => return self.error("invalid number"),
+            }
+        } else if b.is_ascii_alphabetic() {
+            while self.pos < bytes.len() && bytes[self.pos].is_ascii_alphanumeric() {
+                self.pos += 1;
+            }
+            self.token = Token::Name(start, self.pos);
+        } else if b"+-*/^()=".contains(&b) {
+            self.pos += 1;
+            self.token = Token::Op(b as char);
+        } else {
+            return self.error("unexpected character");
+        }
+        Ok(())
+    }
+
+    fn expect(&mut self, op: char, message: &str) -> Result<(), FormulaError> {
+        if self.token != Token::Op(op) {
+            return self.error(message);
+        }
+        self.advance()
+    }
+
+    // formula := [ "z" "=" ] expr
+    fn formula(&mut self) -> Result<Expr, FormulaError> {
+        if let Token::Name(s, e) = self.token {
+            let rest = self.src[e..].trim_start();
+            if &self.src[s..e] == "z" && rest.starts_with('=') {
+                self.advance()?;
+                self.advance()?;
+            }
+        }
+        let expr = self.expr()?;
+        if self.token != Token::End {
+            return self.error("expected an operator");
+        }
+        Ok(expr)
+    }
+
+    // expr := term { ("+" | "-") term }
+    fn expr(&mut self) -> Result<Expr, FormulaError> {
+        let mut left = self.term()?;
+        while let Token::Op(op @ ('+' | '-')) = self.token {
+            self.advance()?;
+            let right = self.term()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    // term := unary { ("*" | "/") unary }
+    fn term(&mut self) -> Result<Expr, FormulaError> {
+        let mut left = self.unary()?;
+        while let Token::Op(op @ ('*' | '/')) = self.token {
+            self.advance()?;
+            let right = self.unary()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    // unary := "-" unary | power
+    fn unary(&mut self) -> Result<Expr, FormulaError> {
+        if self.token == Token::Op('-') {
+            self.advance()?;
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+
+    // power := primary [ "^" unary ]
+    fn power(&mut self) -> Result<Expr, FormulaError> {
+        let base = self.primary()?;
+        if self.token != Token::Op('^') {
+            return Ok(base);
+        }
+        self.advance()?;
+        // The exponent is folded first, so that e.g. -1 and (1+1) are integer powers too
+        let exponent = fold(self.unary()?);
+        if let Expr::Const(w) = exponent {
+            if w.im == 0.0 && w.re.fract() == 0.0 && (-64.0..=64.0).contains(&w.re) {
+                return Ok(Expr::PowI(Box::new(base), w.re as i32));
+            }
+        }
+        Ok(Expr::Binary('^', Box::new(base), Box::new(exponent)))
+    }
+
+    // primary := number | name | name "(" expr ")" | "(" expr ")"
+    fn primary(&mut self) -> Result<Expr, FormulaError> {
+        match self.token {
+            Token::Num(v) => {
+                self.advance()?;
+                Ok(Expr::Const(Complex::new(v, 0.0)))
+            }
+            Token::Op('(') => {
+                self.advance()?;
+                let expr = self.expr()?;
+                self.expect(')', "expected )")?;
+                Ok(expr)
+            }
+            Token::Name(s, e) => {
+                let name = &self.src[s..e];
+                let expr = match name {
+                    "z" => Expr::Z,
+                    "c" => Expr::C,
+                    "i" => Expr::Const(Complex::new(0.0, 1.0)),
+                    "pi" => Expr::Const(Complex::new(std::f64::consts::PI, 0.0)),
+                    "e" => Expr::Const(Complex::new(std::f64::consts::E, 0.0)),
+                    _ => match FUNCTIONS.iter().find(|f| f.0 == name) {
+                        Some(&(_, fun)) => {
+                            self.advance()?;
+                            self.expect('(', "expected (")?;
+                            let arg = self.expr()?;
+                            self.expect(')', "expected )")?;
+                            return Ok(Expr::Call(fun, Box::new(arg)));
+                        }
+                        None => return self.error(&format!("unknown name {}", name)),
+                    },
+                };
+                self.advance()?;
+                Ok(expr)
+            }
+            Token::End => self.error("unexpected end of formula"),
+            Token::Op(_) => self.error("expected a value"),
+        }
+    }
+}
+
+type Eval = Box<dyn Fn(Complex, Complex) -> Complex + Send + Sync>;
+
+// Replace every sub expression without z and c by its value
+fn fold(expr: Expr) -> Expr {
+    let value = |e: &Expr| match e {
+        Expr::Const(v) => Some(*v),
+        _ => None,
+    };
+    match expr {
+        Expr::Neg(a) => {
+            let a = fold(*a);
+            match value(&a) {
+                Some(v) => Expr::Const(-v),
+                None => Expr::Neg(Box::new(a)),
+            }
+        }
+        Expr::Binary(op, a, b) => {
+            let (a, b) = (fold(*a), fold(*b));
+            match (value(&a), value(&b)) {
+                (Some(x), Some(y)) => Expr::Const(binary(op)(x, y)),
+                _ => Expr::Binary(op, Box::new(a), Box::new(b)),
+            }
+        }
+        Expr::PowI(a, n) => {
+            let a = fold(*a);
+            match value(&a) {
+                Some(v) => Expr::Const(v.powi(n)),
+                None => Expr::PowI(Box::new(a), n),
+            }
+        }
+        Expr::Call(fun, a) => {
+            let a = fold(*a);
+            match value(&a) {
+                Some(v) => Expr::Const(fun(v)),
+                None => Expr::Call(fun, Box::new(a)),
+            }
+        }
+        e => e,
+    }
+}
+
+fn binary(op: char) -> fn(Complex, Complex) -> Complex {
+    match op {
+        '+' => Complex::add,
+        '-' => Complex::sub,
+        '*' => Complex::mul,
+        '/' => Complex::div,
+        _ => Complex::pow,
+    }
+}
+
+// Turn the tree into nested closures
+fn compile(expr: Expr) -> Eval {
+    match expr {
+        Expr::Const(v) => Box::new(move |_z, _c| v),
+        Expr::Z => Box::new(|z, _c| z),
+        Expr::C => Box::new(|_z, c| c),
+        Expr::Neg(a) => {
+            let a = compile(*a);
+            Box::new(move |z, c| -a(z, c))
+        }
+        Expr::PowI(a, 2) => {
+            let a = compile(*a);
+            Box::new(move |z, c| {
+                let v = a(z, c);
+                v * v
+            })
+        }
+        Expr::PowI(a, n) => {
+            let a = compile(*a);
+            Box::new(move |z, c| a(z, c).powi(n))
+        }
+        Expr::Binary(op, a, b) => {
+            let (a, b, f) = (compile(*a), compile(*b), binary(op));
+            Box::new(move |z, c| f(a(z, c), b(z, c)))
+        }
+        Expr::Call(fun, a) => {
+            let a = compile(*a);
+            Box::new(move |z, c| fun(a(z, c)))
+        }
+    }
+}
+
+#[derive(Clone)]
+/// A user-defined iteration z -> f(z, c), like "z = z^2 + c*sin(z)"
+pub struct Formula {
+    source: String,
+    eval: Arc<Eval>,
+}
+
+/// The first character of the name of a named formula, e.g. @burning-ship
+pub const NAMED_PREFIX: char = '@';
+
+// Formulas that are not written in the expression language but registered by name
+static NAMED_FORMULAS: RwLock<Vec<(String, Arc<Eval>)>> = RwLock::new(Vec::new());
+
+/// Register a formula that is computed by `step` instead of an expression. It is used with
+/// the name after NAMED_PREFIX. A formula with the same name is replaced.
+pub fn register_formula(
+    name: &str,
+    step: impl Fn(Complex, Complex) -> Complex + Send + Sync + 'static,
+) {
+    let mut named = NAMED_FORMULAS.write().unwrap();
+    named.retain(|(n, _)| n != name);
+    named.push((name.to_string(), Arc::new(Box::new(step))));
+}
+
+/// The names of the registered formulas, including NAMED_PREFIX
+pub fn named_formulas() -> Vec<String> {
+    NAMED_FORMULAS
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(name, _)| format!("{}{}", NAMED_PREFIX, name))
+        .collect()
+}
+
+impl Formula {
+    pub fn parse(source: &str) -> Result<Formula, FormulaError> {
+        if let Some(name) = source.trim().strip_prefix(NAMED_PREFIX) {
+            let named = NAMED_FORMULAS.read().unwrap();
+            let (_, eval) = named
+                .iter()
+                .find(|(n, _)| n == name)
+                .ok_or_else(|| FormulaError {
+                    pos: 0,
+                    message: format!("unknown formula {}", name),
+                })?;
+            return Ok(Formula {
+                source: source.trim().to_string(),
+                eval: eval.clone(),
+            });
+        }
+        let expr = Parser::new(source)?.formula()?;
+        Ok(Formula {
+            source: source.trim().to_string(),
+            eval: Arc::new(compile(fold(expr))),
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Compute the next value of z
+    pub fn step(&self, z: Complex, c: Complex) -> Complex {
+        (self.eval)(z, c)
+    }
+}
+
+impl PartialEq for Formula {
+    fn eq(&self, other: &Formula) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Hash for Formula {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.source.hash(state);
+    }
+}
+
+impl fmt::Debug for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Formula({:?})", self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The value of a formula for real z and c
+    fn eval(source: &str, z: f64, c: f64) -> Complex {
+        Formula::parse(source)
+            .unwrap()
+            .step(Complex::new(z, 0.0), Complex::new(c, 0.0))
+    }
+
+    fn error_pos(source: &str) -> usize {
+        Formula::parse(source).unwrap_err().pos
+    }
+
+    #[test]
+    fn operators_have_precedence() {
+        assert_eq!(eval("z + c*2", 1.0, 3.0), Complex::new(7.0, 0.0));
+        assert_eq!(eval("2*z^2 - c/2", 3.0, 4.0), Complex::new(16.0, 0.0));
+        assert_eq!(eval("(z + c)*2", 1.0, 3.0), Complex::new(8.0, 0.0));
+        // ^ is right associative: 2^(3^2), not (2^3)^2
+        assert_eq!(eval("2^3^2", 0.0, 0.0), Complex::new(512.0, 0.0));
+        assert_eq!(eval("z^2^2", 3.0, 0.0), Complex::new(81.0, 0.0));
+    }
+
+    #[test]
+    fn unary_minus_binds_less_than_power() {
+        assert_eq!(eval("-z^2", 3.0, 0.0), Complex::new(-9.0, 0.0));
+        assert_eq!(eval("(-z)^2", 3.0, 0.0), Complex::new(9.0, 0.0));
+        assert_eq!(eval("z^-1", 4.0, 0.0), Complex::new(0.25, 0.0));
+        assert_eq!(eval("c - -z", 1.0, 2.0), Complex::new(3.0, 0.0));
+    }
+
+    #[test]
+    fn the_z_prefix_is_optional() {
+        let with = Formula::parse("z = z^2 + c").unwrap();
+        let without = Formula::parse("z^2 + c").unwrap();
+        let (z, c) = (Complex::new(0.5, -1.0), Complex::new(-0.3, 0.2));
+        assert_eq!(with.step(z, c), without.step(z, c));
+        assert_eq!(with.source(), "z = z^2 + c");
+        // z alone is not a prefix
+        assert_eq!(eval("z", 2.0, 0.0), Complex::new(2.0, 0.0));
+        assert_eq!(error_pos("z = = z"), 4);
+    }
+
+    #[test]
+    fn an_exponent_is_not_the_constant_e() {
+        assert_eq!(eval("1e-3", 0.0, 0.0), Complex::new(1e-3, 0.0));
+        assert_eq!(eval("2E2", 0.0, 0.0), Complex::new(200.0, 0.0));
+        assert_eq!(eval("e", 0.0, 0.0), Complex::new(std::f64::consts::E, 0.0));
+        assert_eq!(
+            eval("e-3", 0.0, 0.0),
+            Complex::new(std::f64::consts::E - 3.0, 0.0)
+        );
+        // Without digits after it, the e is a name, and a number followed by a name is an error
+        assert_eq!(error_pos("2e"), 1);
+    }
+
+    #[test]
+    fn errors_have_the_position_of_their_token() {
+        let error = Formula::parse("z^").unwrap_err();
+        assert_eq!(error.pos, 2);
+        assert_eq!(error.message, "unexpected end of formula");
+        let error = Formula::parse("sin z").unwrap_err();
+        assert_eq!(error.pos, 4);
+        assert_eq!(error.message, "expected (");
+        let error = Formula::parse("z + $").unwrap_err();
+        assert_eq!(error.pos, 4);
+        assert_eq!(error.message, "unexpected character");
+        // Positions are shown counting from 1
+        assert_eq!(error.to_string(), "unexpected character at position 5");
+        assert_eq!(error_pos("foo(z)"), 0);
+        assert_eq!(error_pos("(z + c"), 6);
+    }
+
+    #[test]
+    fn constants_are_folded() {
+        let folded = fold(
+            Parser::new("2*pi + sin(0) - i^2")
+                .unwrap()
+                .formula()
+                .unwrap(),
+        );
+        let Expr::Const(v) = folded else {
+            panic!("not folded");
+        };
+        assert!((v.re - (2.0 * std::f64::consts::PI + 1.0)).abs() < 1e-12 && v.im.abs() < 1e-12);
+        // Only the parts without z and c
+        let folded = fold(Parser::new("z*(2 + 3)").unwrap().formula().unwrap());
+        let Expr::Binary('*', z, five) = folded else {
+            panic!("not a product");
+        };
+        assert!(matches!(*z, Expr::Z));
+        assert!(matches!(*five, Expr::Const(v) if v == Complex::new(5.0, 0.0)));
+    }
+
+    #[test]
+    fn zero_to_the_power_zero() {
+        let zero = Complex::new(0.0, 0.0);
+        // The general power is 0 for base 0, but the integer power is an empty product
+        assert_eq!(zero.pow(zero), zero);
+        assert_eq!(zero.powi(0), Complex::new(1.0, 0.0));
+        // A constant integer exponent is a PowI, any other exponent is a general power
+        assert_eq!(eval("z^0", 0.0, 0.0), Complex::new(1.0, 0.0));
+        assert_eq!(eval("z^c", 0.0, 0.0), zero);
+        // also a negative one, which is infinite for 0
+        assert_eq!(eval("z^-1", 0.0, 0.0), Complex::new(f64::INFINITY, 0.0));
+    }
+
+    #[test]
+    fn exponents_are_folded_into_integer_powers() {
+        for (source, n) in [("z^-1", -1), ("z^(1+1)", 2), ("z^2^2", 4), ("z^(8/2)", 4)] {
+            let expr = Parser::new(source).unwrap().formula().unwrap();
+            assert!(matches!(expr, Expr::PowI(_, m) if m == n), "{}", source);
+        }
+        // Exponents that are not integers, or too large, are general powers
+        for source in ["z^0.5", "z^i", "z^100", "z^c"] {
+            let expr = Parser::new(source).unwrap().formula().unwrap();
+            assert!(matches!(expr, Expr::Binary('^', _, _)), "{}", source);
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use crate::formula::Formula;
+use crate::newton::{Polynomial, DEFAULT_POLYNOMIAL};
+
+/// The formula that the Formula type starts with
+pub const DEFAULT_FORMULA: &str = "z^2 + c";
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+/// The kinds of fractals that can be rendered
+pub enum FractalType {
+    /// z -> z^d + c, with z starting at 0 and c the point
+    Mandelbrot,
+    /// z -> z^d + c, with z starting at the point and c a constant
+    Julia,
+    /// z -> conj(z)^d + c, with z starting at 0 and c the point. Also known as the Mandelbar set.
+    Tricorn,
+    /// A formula entered by the user, with z starting at 0 and c the point
+    Formula,
+    /// Newton's method for the roots of a polynomial, with z starting at the point
+    Newton,
+}
+
+impl FractalType {
+    pub const ALL: [FractalType; 5] = [
+        FractalType::Mandelbrot,
+        FractalType::Julia,
+        FractalType::Tricorn,
+        FractalType::Formula,
+        FractalType::Newton,
+    ];
+
+    /// Get a name for the fractal type, suitable for use in the UI
+    pub fn name(&self) -> &'static str {
+        match self {
+            FractalType::Mandelbrot => "mandelbrot",
+            FractalType::Julia => "julia",
+            FractalType::Tricorn => "tricorn",
+            FractalType::Formula => "formula",
+            FractalType::Newton => "newton",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<FractalType> {
+        FractalType::ALL.into_iter().find(|t| t.name() == name)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+/// Parameters that determine which fractal is computed
+pub struct FractalParams {
+    pub fractal_type: FractalType,
+    /// The exponent d of z^d + c, at least 2
+    pub power: u32,
+    /// The real part of the constant c of a Julia set
+    pub julia_cx: f64,
+    /// The imaginary part of the constant c of a Julia set
+    pub julia_cy: f64,
+    /// The iteration of the Formula type
+    pub formula: Formula,
+    /// The polynomial of the Newton type
+    pub polynomial: Polynomial,
+}
+
+impl FractalParams {
+    pub fn mandelbrot() -> FractalParams {
+        FractalParams {
+            fractal_type: FractalType::Mandelbrot,
+            power: 2,
+            julia_cx: -0.8,
+            julia_cy: 0.156,
+            formula: Formula::parse(DEFAULT_FORMULA).expect("the default formula is valid"),
+            polynomial: Polynomial::parse(DEFAULT_POLYNOMIAL)
+                .expect("the default polynomial is valid"),
+        }
+    }
+}
@@ -0,0 +1,373 @@
+// GIMP gradient (.ggr) files. After the header lines "GIMP Gradient", an optional
+// "Name: ..." and the number of segments, every line describes a segment:
+//
+//     left middle right  r0 g0 b0 a0  r1 g1 b1 a1  blending coloring [left-type right-type]
+//
+// The positions and color components are between 0 and 1. The segment blends from the left
+// color to the right color, with the middle position where the blend is halfway. The alpha
+// components and the endpoint types, which refer to the colors of the GIMP toolbox, are ignored.
+
+use std::f64::consts::PI;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::colorings::{Coloring, GRADIENT_PERIOD};
+
+const EPSILON: f64 = 1e-10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// How a segment goes from its left to its right color
+pub enum Blending {
+    Linear,
+    Curved,
+    Sine,
+    SphereIncreasing,
+    SphereDecreasing,
+    Step,
+}
+
+impl Blending {
+    fn from_code(code: u32) -> Option<Blending> {
+        match code {
+            0 => Some(Blending::Linear),
+            1 => Some(Blending::Curved),
+            2 => Some(Blending::Sine),
+            3 => Some(Blending::SphereIncreasing),
+            4 => Some(Blending::SphereDecreasing),
+            5 => Some(Blending::Step),
+            _ => None,
+        }
+    }
+
+    // The weight of the right color at position pos of a segment from 0 to 1,
+    // of which the middle is at position middle
+    fn factor(&self, middle: f64, pos: f64) -> f64 {
+        let linear = || {
+            if pos <= middle {
+                if middle < EPSILON {
+                    0.0
+                } else {
+                    0.5 * pos / middle
+                }
+            } else if 1.0 - middle < EPSILON {
+                1.0
+            } else {
+                0.5 + 0.5 * (pos - middle) / (1.0 - middle)
+            }
+        };
+        match self {
+            Blending::Linear => linear(),
+            Blending::Curved => {
+                let middle = middle.clamp(EPSILON, 1.0 - EPSILON);
+                pos.powf(0.5f64.ln() / middle.ln())
+            }
+            Blending::Sine => ((-PI / 2.0 + PI * linear()).sin() + 1.0) / 2.0,
+            Blending::SphereIncreasing => {
+                let f = linear() - 1.0;
+                (1.0 - f * f).max(0.0).sqrt()
+            }
+            Blending::SphereDecreasing => {
+                let f = linear();
+                1.0 - (1.0 - f * f).max(0.0).sqrt()
+            }
+            Blending::Step => {
+                if pos >= middle {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// How the colors of a segment are mixed: per RGB component, or along the hue
+/// counterclockwise or clockwise
+pub enum ColorMode {
+    Rgb,
+    HsvCcw,
+    HsvCw,
+}
+
+impl ColorMode {
+    fn from_code(code: u32) -> Option<ColorMode> {
+        match code {
+            0 => Some(ColorMode::Rgb),
+            1 => Some(ColorMode::HsvCcw),
+            2 => Some(ColorMode::HsvCw),
+            _ => None,
+        }
+    }
+}
+
+type Rgb = [f64; 3];
+
+fn rgb_to_hsv([r, g, b]: Rgb) -> Rgb {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    let h = if delta < EPSILON {
+        0.0
+    } else if max == r {
+        ((g - b) / delta).rem_euclid(6.0) / 6.0
+    } else if max == g {
+        ((b - r) / delta + 2.0) / 6.0
+    } else {
+        ((r - g) / delta + 4.0) / 6.0
+    };
+    let s = if max < EPSILON { 0.0 } else { delta / max };
+    [h, s, max]
+}
+
+fn hsv_to_rgb([h, s, v]: Rgb) -> Rgb {
+    let h6 = h.rem_euclid(1.0) * 6.0;
+    let f = h6 - h6.floor();
+    let (p, q, t) = (v * (1.0 - s), v * (1.0 - s * f), v * (1.0 - s * (1.0 - f)));
+    match h6 as u32 {
+        0 => [v, t, p],
+        1 => [q, v, p],
+        2 => [p, v, t],
+        3 => [p, q, v],
+        4 => [t, p, v],
+        _ => [v, p, q],
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// A segment of a gradient
+pub struct Segment {
+    pub left: f64,
+    pub middle: f64,
+    pub right: f64,
+    pub left_color: Rgb,
+    pub right_color: Rgb,
+    pub blending: Blending,
+    pub color_mode: ColorMode,
+}
+
+impl Segment {
+    // The color at position t, which is inside the segment
+    fn color_at(&self, t: f64) -> Rgb {
+        let len = self.right - self.left;
+        let (middle, pos) = if len < EPSILON {
+            (0.5, 0.5)
+        } else {
+            ((self.middle - self.left) / len, (t - self.left) / len)
+        };
+        let f = self
+            .blending
+            .factor(middle.clamp(0.0, 1.0), pos.clamp(0.0, 1.0));
+        let mix = |a: Rgb, b: Rgb| [0, 1, 2].map(|i| a[i] + f * (b[i] - a[i]));
+        match self.color_mode {
+            ColorMode::Rgb => mix(self.left_color, self.right_color),
+            mode => {
+                let (hsv0, hsv1) = (rgb_to_hsv(self.left_color), rgb_to_hsv(self.right_color));
+                let [_, s, v] = mix(hsv0, hsv1);
+                let (h0, h1) = (hsv0[0], hsv1[0]);
+                let h = if mode == ColorMode::HsvCcw {
+                    h0 + (h1 - h0).rem_euclid(1.0) * f
+                } else {
+                    h0 - (h0 - h1).rem_euclid(1.0) * f
+                };
+                hsv_to_rgb([h, s, v])
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// A gradient that was read from a .ggr file
+pub struct GimpGradient {
+    pub name: String,
+    pub segments: Vec<Segment>,
+}
+
+fn parse_error(line: usize, message: &str) -> String {
+    format!("line {}: {}", line, message)
+}
+
+impl GimpGradient {
+    /// Read a gradient from the text of a .ggr file
+    pub fn parse(text: &str) -> Result<GimpGradient, String> {
+        let mut lines = text.lines().enumerate().map(|(n, l)| (n + 1, l.trim()));
+        match lines.next() {
+            Some((_, "GIMP Gradient")) => {}
+            _ => return Err("not a GIMP gradient".to_string()),
+        }
+        let (mut n, mut line) = lines.next().ok_or("the number of segments is missing")?;
+        let mut name = String::new();
+        if let Some(rest) = line.strip_prefix("Name:") {
+            name = rest.trim().to_string();
+            (n, line) = lines.next().ok_or("the number of segments is missing")?;
+        }
+        let count: usize = line
+            .parse()
+            .map_err(|_| parse_error(n, "expected the number of segments"))?;
+        if count == 0 {
+            return Err(parse_error(n, "there are no segments"));
+        }
+        let mut segments = Vec::with_capacity(count);
+        for _ in 0..count {
+            let (n, line) = lines.next().ok_or("there are fewer segments than stated")?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.len() < 13 {
+                return Err(parse_error(n, "a segment needs 13 values"));
+            }
+            let mut v = [0.0; 11];
+            for (v, word) in v.iter_mut().zip(&words) {
+                *v = word
+                    .parse()
+                    .ok()
+                    .filter(|v: &f64| v.is_finite())
+                    .ok_or_else(|| parse_error(n, &format!("{} is not a number", word)))?;
+            }
+            let code = |word: &str| word.parse::<u32>().ok();
+            let blending = code(words[11])
+                .and_then(Blending::from_code)
+                .ok_or_else(|| parse_error(n, "unknown blending function"))?;
+            let color_mode = code(words[12])
+                .and_then(ColorMode::from_code)
+                .ok_or_else(|| parse_error(n, "unknown coloring type"))?;
+            let unit = |v: f64| v.clamp(0.0, 1.0);
+            let (left, middle, right) = (unit(v[0]), unit(v[1]), unit(v[2]));
+            if !(left <= middle && middle <= right) {
+                return Err(parse_error(
+                    n,
+                    "the positions of the segment are not in order",
+                ));
+            }
+            segments.push(Segment {
+                left,
+                middle,
+                right,
+                left_color: [unit(v[3]), unit(v[4]), unit(v[5])],
+                right_color: [unit(v[7]), unit(v[8]), unit(v[9])],
+                blending,
+                color_mode,
+            });
+        }
+        Ok(GimpGradient { name, segments })
+    }
+
+    /// The color at position t between 0 and 1, in GTK RGB-format
+    pub fn color_at(&self, t: f64) -> u32 {
+        let t = t.clamp(0.0, 1.0);
+        let segment = self
+            .segments
+            .iter()
+            .find(|s| t <= s.right)
+            .or(self.segments.last());
+        let rgb = match segment {
+            Some(segment) => segment.color_at(t),
+            None => [0.0; 3],
+        };
+        let channel = |v: f64| (v * 255.0).round().clamp(0.0, 255.0) as u32;
+        channel(rgb[0]) << 16 | channel(rgb[1]) << 8 | channel(rgb[2])
+    }
+}
+
+#[derive(Clone)]
+/// A coloring with a GIMP gradient, which repeats every GRADIENT_PERIOD values
+pub struct GimpColoring {
+    name: String,
+    gradient: Arc<GimpGradient>,
+}
+
+impl GimpColoring {
+    pub fn new(name: &str, gradient: GimpGradient) -> GimpColoring {
+        GimpColoring {
+            name: name.to_string(),
+            gradient: Arc::new(gradient),
+        }
+    }
+}
+
+impl Coloring for GimpColoring {
+    fn get_color(&self, v: u32, max: u32) -> u32 {
+        self.get_smooth_color(v as f64, max)
+    }
+
+    fn get_smooth_color(&self, v: f64, max: u32) -> u32 {
+        let v = v.max(0.0);
+        if max as f64 <= v.floor() {
+            return 0x000000;
+        }
+        let period = GRADIENT_PERIOD as f64;
+        self.gradient.color_at(v % period / period)
+    }
+
+    fn is_smooth(&self) -> bool {
+        true
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Load a .ggr file as a coloring named after the gradient, e.g. ggr-Sunrise
+pub fn load_ggr(path: &Path) -> io::Result<GimpColoring> {
+    let text = fs::read_to_string(path)?;
+    let gradient =
+        GimpGradient::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let name = if gradient.name.is_empty() {
+        path.file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    } else {
+        gradient.name.clone()
+    };
+    Ok(GimpColoring::new(&format!("ggr-{}", name), gradient))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TWO_SEGMENTS: &str = "GIMP Gradient
+Name: Test
+2
+0.0 0.25 0.5 0 0 0 1 1 1 1 1 0 0
+0.5 0.75 1.0 1 0 0 1 0 0 1 1 2 1 0 0
+";
+
+    #[test]
+    fn gradients_are_read() {
+        let gradient = GimpGradient::parse(TWO_SEGMENTS).unwrap();
+        assert_eq!(gradient.name, "Test");
+        assert_eq!(gradient.segments.len(), 2);
+        assert_eq!(gradient.segments[1].blending, Blending::Sine);
+        assert_eq!(gradient.segments[1].color_mode, ColorMode::HsvCcw);
+        assert_eq!(gradient.color_at(0.0), 0x000000);
+        assert_eq!(gradient.color_at(0.25), 0x808080);
+        assert_eq!(gradient.color_at(0.5), 0xffffff);
+        assert_eq!(gradient.color_at(1.0), 0x0000ff);
+        // Counterclockwise from red to blue passes green
+        assert_eq!(gradient.color_at(0.75), 0x00ff00);
+    }
+
+    #[test]
+    fn invalid_gradients_are_rejected() {
+        assert!(GimpGradient::parse("").is_err());
+        assert!(GimpGradient::parse("GIMP Gradient\n1\n").is_err());
+        assert!(GimpGradient::parse("GIMP Gradient\n1\n0 0.5 1 0 0 0 1 1 1 1 1 9 0\n").is_err());
+        assert!(GimpGradient::parse("GIMP Gradient\n1\n0 0.5 x 0 0 0 1 1 1 1 1 0 0\n").is_err());
+        assert!(GimpGradient::parse("GIMP Gradient\n1\n0.5 0.2 1 0 0 0 1 1 1 1 1 0 0\n").is_err());
+    }
+
+    #[test]
+    fn blendings_end_at_the_colors() {
+        for code in 0..=5 {
+            let blending = Blending::from_code(code).unwrap();
+            for middle in [0.0, 0.3, 0.5, 0.9] {
+                let (f0, f1) = (blending.factor(middle, 0.0), blending.factor(middle, 1.0));
+                assert!((0.0..=1.0).contains(&f0) && (0.0..=1.0).contains(&f1));
+                assert!((f1 - 1.0).abs() < 1e-9, "{:?} {}", blending, middle);
+            }
+            assert!(blending.factor(0.5, 0.0).abs() < 1e-9);
+        }
+    }
+}
@@ -1,33 +1,414 @@
+mod animation;
+mod config;
+mod curves;
+mod export;
+mod gradient;
+mod hires;
+mod histogram;
+mod inspector;
+mod julia_preview;
+mod lighting;
+mod linked;
+mod minimap;
+mod overlay;
+mod preferences;
+mod preset_manager;
+mod print;
+mod random_history;
+mod recent;
+mod region;
+mod session;
 mod state;
+mod status_bar;
+mod tour;
+mod transition;
+mod user_presets;
+mod view_settings;
+mod workspace;
+mod zoom_animation;
 
+use crate::benchmark::{measure_speed, Calibration};
+use crate::channels::{channel, Policy, REPLY_CAPACITY, REQUEST_CAPACITY};
+use crate::colorings::{load_palette, save_palette, save_palette_strip, Coloring, ColoringParams};
+use crate::formula::{named_formulas, Formula};
+use crate::fractal::FractalType;
+use crate::ggr::load_ggr;
 use crate::image::Image;
-use crate::mandel_image::mandel_producer;
+use crate::location::Location;
+use crate::mandel_image::{
+    mandel_producer, new_pool, orbit, MandelToWin, RenderStats, WinToMandel,
+};
+use crate::newton::Polynomial;
+use crate::plugins::{load_plugins, plugin_dir};
+use crate::png::{read_png_text, rgb24_to_rgb};
 use crate::presets::Presets;
+use crate::random::{new_seed, random_location, random_palette};
+use crate::report::PassTiming;
 use crate::{MandelReply, IMG_FMT};
 use async_channel::Receiver;
 use gtk::ffi::GTK_INVALID_LIST_POSITION;
-use gtk::gdk::ffi::GDK_BUTTON_PRIMARY;
-use gtk::glib::clone;
+use gtk::gdk::ffi::{GDK_BUTTON_PRIMARY, GDK_BUTTON_SECONDARY};
 use gtk::glib::object::Cast;
+use gtk::glib::{clone, BoxedAnyObject};
 use gtk::{
-    gio, glib, prelude::*, Adjustment, Application, ApplicationWindow, Button, DrawingArea,
-    DropDown, GestureClick, Label, ListItem, ListView, Orientation, Scale, SignalListItemFactory,
-    SingleSelection, SpinButton, StringList, StringObject, Window,
+    gdk, gio, glib, prelude::*, Adjustment, Application, ApplicationWindow, Button, DrawingArea,
+    DropDown, EventControllerKey, EventControllerMotion, FileChooserAction, FileChooserNative,
+    FileFilter, GestureClick, GestureDrag, Label, ListBox, ListItem, ListView, MenuButton,
+    Orientation, Popover, ResponseType, Scale, ScrolledWindow, SignalListItemFactory,
+    SingleSelection, SpinButton, ToggleButton, Window,
 };
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
-use self::state::{postpone_redraw, State};
+use self::animation::DepthAnimationWindow;
+use self::config::Config;
+use self::curves::build_curves_window;
+use self::export::{
+    save_mask, save_screenshot, save_with_report, show_error, show_info, timestamp, write_view_png,
+};
+use self::gradient::{from_rgba, to_rgba, GradientWindow};
+use self::hires::HiResWindow;
+use self::histogram::HistogramPanel;
+use self::inspector::Inspector;
+use self::julia_preview::JuliaPreview;
+use self::lighting::LightingWindow;
+use self::linked::LinkedView;
+use self::minimap::Minimap;
+use self::preferences::{start_interior, PreferencesWindow};
+use self::preset_manager::PresetManager;
+use self::print::print_view;
+use self::random_history::{RandomEntry, RandomHistory, RandomResult};
+use self::recent::{thumbnail_texture, THUMB_SZ};
+use self::region::{RegionWindow, REGION_SZ};
+use self::session::Session;
+use self::state::{postpone_redraw, scale_for_zoom, zoom_for_scale, State, ZOOM_STEP};
+use self::status_bar::{coordinate_digits, StatusBar};
+use self::tour::Tour;
+use self::transition::{reduce_motion, Transition, ViewPoint};
+use self::user_presets::{
+    choose_preset_export, choose_preset_import, load_presets, location_preset, preset_items,
+    save_presets, show_preset_item,
+};
+use self::view_settings::ViewSettings;
+use self::workspace::Workspace;
+use self::zoom_animation::ZoomAnimationWindow;
 
 const APP_ID: &str = "nl.uu.gjgiezeman.mandelbrot";
 const WIN_SZ0: usize = 600;
+// The time between two frames of a dive and the zoom slider step per frame
+const DIVE_INTERVAL: Duration = Duration::from_millis(40);
+const DIVE_STEP: f64 = 0.5;
+// The time between two frames of color cycling and the offset step per frame
+const CYCLE_INTERVAL: Duration = Duration::from_millis(33);
+const CYCLE_STEP: f64 = 0.25;
+// With reduced motion, a dive and color cycling make one jump of this size instead
+const DIVE_JUMP: f64 = 25.0;
+const CYCLE_JUMP: f64 = 10.0;
+// The duration of the animation when the view jumps to a clicked point or a preset
+const JUMP_DURATION: Duration = Duration::from_millis(500);
+// After a drag that is faster than the minimum speed in pixels per second, the view glides on
+// with a speed that decays with the time constant in seconds until it is below the stop speed
+const GLIDE_MIN_SPEED: f64 = 300.0;
+const GLIDE_STOP_SPEED: f64 = 20.0;
+const GLIDE_TIME_CONSTANT: f64 = 0.3;
+const GLIDE_INTERVAL: Duration = Duration::from_millis(40);
+// In fullscreen mode, the controls show while the pointer is this close to the top edge
+const FULLSCREEN_EDGE: f64 = 4.0;
+// The smallest distance in pixels between the lines of the coordinate grid
+const GRID_MIN_SPACING: f64 = 80.0;
+// The steps of the keyboard navigation: the pan in pixels, or with Shift in fine pixels,
+// the zoom slider step and the factor of the iteration depth
+const KEY_PAN: f64 = 50.0;
+const KEY_PAN_FINE: f64 = 2.0;
+const KEY_ZOOM: f64 = 5.0;
+const KEY_DEPTH_FACTOR: f64 = 1.1;
+
+#[derive(Clone)]
+// The widgets that show the parameters of the view in the main window
+struct Controls {
+    // The center, zoom, iterations and coloring, which the widgets are bound to
+    settings: ViewSettings,
+    fractals: DropDown,
+    power_adj: Adjustment,
+    julia_cx_value: gtk::Entry,
+    julia_cy_value: gtk::Entry,
+    formula_value: gtk::Entry,
+    polynomial_value: gtk::Entry,
+    // The animation of a jump to another view that is running, with the view it goes to
+    jump: Rc<RefCell<Option<(Transition, ViewPoint)>>>,
+    // The number of the glide after a drag, which stops when the number changes
+    glide: Rc<Cell<u32>>,
+}
+
+impl Controls {
+    // Set the widgets to a location. The state follows the widgets.
+    fn show_location(&self, state: &Rc<RefCell<State>>, location: &Location) {
+        let col_idx = state.borrow().col_idx_of(&location.coloring);
+        let _late_redraw = postpone_redraw(state);
+        self.settings.set_cx(location.cx);
+        self.settings.set_cy(location.cy);
+        state
+            .borrow_mut()
+            .set_precise_center(location.precise_center.clone());
+        self.julia_cx_value
+            .set_text(&location.fractal.julia_cx.to_string());
+        self.julia_cy_value
+            .set_text(&location.fractal.julia_cy.to_string());
+        self.power_adj.set_value(location.fractal.power as f64);
+        self.formula_value
+            .set_text(location.fractal.formula.source());
+        self.polynomial_value
+            .set_text(&location.fractal.polynomial.to_string());
+        self.settings.set_zoom(zoom_for_scale(location.scale));
+        self.settings.set_iterations(location.iteration_depth);
+        if let Some(pos) = FractalType::ALL
+            .iter()
+            .position(|t| *t == location.fractal.fractal_type)
+        {
+            self.fractals.set_selected(pos as u32);
+        }
+        if let Some(col_idx) = col_idx {
+            self.settings.set_coloring(col_idx as u32);
+        }
+    }
+
+    fn view_point(&self, state: &State) -> ViewPoint {
+        ViewPoint {
+            cx: state.cx(),
+            cy: state.cy(),
+            zoom: self.settings.zoom(),
+        }
+    }
+
+    fn show_view_point(&self, state: &Rc<RefCell<State>>, vp: &ViewPoint) {
+        let _late_redraw = postpone_redraw(state);
+        self.settings.set_cx(vp.cx);
+        self.settings.set_cy(vp.cy);
+        self.settings.set_zoom(vp.zoom);
+    }
+
+    // Where the view goes to, which is where it is unless it jumps
+    fn jump_target(&self, state: &State) -> ViewPoint {
+        match &*self.jump.borrow() {
+            Some((_, to)) => *to,
+            None => self.view_point(state),
+        }
+    }
+
+    // Stop a jump or a glide of the view, e.g. because the user moves it otherwise
+    fn stop_motion(&self, state: &Rc<RefCell<State>>) {
+        self.glide.set(self.glide.get().wrapping_add(1));
+        if let Some((transition, _)) = self.jump.take() {
+            transition.stop();
+        }
+        state.borrow_mut().set_preview(false);
+    }
+
+    // Move the view to a point in a short animation. The frames before the last one are
+    // rendered with only the first pass. A jump or glide that is still running is stopped.
+    fn jump_to(&self, state: &Rc<RefCell<State>>, to: ViewPoint) {
+        self.stop_motion(state);
+        let from = self.view_point(&state.borrow());
+        let jump = self.jump.clone();
+        state.borrow_mut().set_preview(true);
+        let transition = Transition::start(
+            from,
+            to,
+            JUMP_DURATION,
+            clone!(@strong state, @strong self as controls => move |vp| {
+                controls.show_view_point(&state, vp);
+            }),
+            clone!(@strong state => move || {
+                jump.take();
+                state.borrow_mut().set_preview(false);
+            }),
+        );
+        *self.jump.borrow_mut() = Some((transition, to));
+    }
+}
 
-fn mandel_draw(state: &Rc<RefCell<State>>, ctxt: &gtk::cairo::Context) {
+// Draw the image in the center of the canvas, on the background. The image does not fit the
+// canvas before the first image of a new size arrives, e.g. while the window is resized.
+fn mandel_draw(state: &Rc<RefCell<State>>, ctxt: &gtk::cairo::Context, w: i32, h: i32) {
+    paint_background(&state.borrow(), ctxt);
     if let Some(img) = &state.borrow().img() {
-        ctxt.set_source_surface(img.surface(), 0.0, 0.0)
+        let x = ((w as f64 - img.width() as f64) / 2.0).floor();
+        let y = ((h as f64 - img.height() as f64) / 2.0).floor();
+        let (dx, dy) = state.borrow().image_offset();
+        ctxt.set_source_surface(img.surface(), x + dx, y + dy)
             .expect("Expected to be able to set source surface");
         ctxt.paint().unwrap();
     }
+    if state.borrow().show_breadcrumbs() {
+        let state = state.borrow();
+        let mapping = state.mapping();
+        let x = ((w as f64 - mapping.win_width as f64) / 2.0).floor();
+        let y = ((h as f64 - mapping.win_height as f64) / 2.0).floor();
+        for (wx, wy) in state.breadcrumbs_in_view() {
+            state.overlay_style().marker(ctxt, x + wx, y + wy);
+        }
+    }
+    if state.borrow().show_grid() {
+        let state = state.borrow();
+        let mapping = state.mapping();
+        let x = ((w as f64 - mapping.win_width as f64) / 2.0).floor();
+        let y = ((h as f64 - mapping.win_height as f64) / 2.0).floor();
+        draw_grid(ctxt, &state, x, y);
+    }
+    if let Some(orbit) = state.borrow().orbit() {
+        let state = state.borrow();
+        let mapping = state.mapping();
+        let x = ((w as f64 - mapping.win_width as f64) / 2.0).floor();
+        let y = ((h as f64 - mapping.win_height as f64) / 2.0).floor();
+        let to_win = MandelToWin::from_mapping(mapping);
+        for &(r, i) in &orbit.points {
+            let (wx, wy) = to_win.cvt(r, i);
+            ctxt.line_to(x + wx, y + wy);
+        }
+        state.overlay_style().stroke(ctxt);
+        if let Some(&(r, i)) = orbit.points.first() {
+            let (wx, wy) = to_win.cvt(r, i);
+            state.overlay_style().marker(ctxt, x + wx, y + wy);
+        }
+    }
+    if state.borrow().show_crosshair() {
+        let state = state.borrow();
+        let mapping = state.mapping();
+        let x = ((w as f64 - mapping.win_width as f64) / 2.0).floor();
+        let y = ((h as f64 - mapping.win_height as f64) / 2.0).floor();
+        draw_crosshair(
+            ctxt,
+            &state,
+            x + (mapping.win_width / 2) as f64,
+            y + (mapping.win_height / 2) as f64,
+        );
+    }
+    if state.borrow().show_stats() {
+        let state = state.borrow();
+        let mapping = state.mapping();
+        let x = ((w as f64 - mapping.win_width as f64) / 2.0).floor();
+        let y = ((h as f64 - mapping.win_height as f64) / 2.0).floor();
+        draw_stats(ctxt, &state, x, y);
+    }
+    if let Some((x0, y0, x1, y1)) = state.borrow().selection() {
+        let style = state.borrow().overlay_style().clone();
+        let (w, h) = ((x1 - x0).abs(), (y1 - y0).abs());
+        ctxt.rectangle(x0.min(x1), y0.min(y1), w, h);
+        style.stroke_dashed(ctxt);
+        let size = format!("{:.0} x {:.0}", w, h);
+        style.text(ctxt, x0.min(x1), y0.min(y1) - style.font_size / 2.0, &size);
+    }
+}
+
+fn paint_background(state: &State, ctxt: &gtk::cairo::Context) {
+    let background = state.background();
+    ctxt.set_source_rgb(
+        background.red() as f64,
+        background.green() as f64,
+        background.blue() as f64,
+    );
+    let _ = ctxt.paint();
+}
+
+// Draw the image of the compare view in the center of its canvas, like that of the main view
+fn compare_draw(state: &State, ctxt: &gtk::cairo::Context, w: i32, h: i32) {
+    paint_background(state, ctxt);
+    if let Some(img) = state.compare_img() {
+        let x = ((w as f64 - img.width() as f64) / 2.0).floor();
+        let y = ((h as f64 - img.height() as f64) / 2.0).floor();
+        if ctxt.set_source_surface(img.surface(), x, y).is_ok() {
+            let _ = ctxt.paint();
+        }
+    }
+}
+
+// Draw the axes and grid lines at a power of ten that keeps them at least GRID_MIN_SPACING
+// pixels apart, with their coordinates, over the image at (x, y)
+fn draw_grid(ctxt: &gtk::cairo::Context, state: &State, x: f64, y: f64) {
+    let mapping = state.mapping();
+    let style = state.overlay_style();
+    let decimals = (-(GRID_MIN_SPACING * mapping.scale).log10()).floor();
+    let spacing = 10f64.powf(-decimals);
+    let decimals = decimals.max(0.0) as usize;
+    let (w, h) = (mapping.win_width as f64, mapping.win_height as f64);
+    let to_win = MandelToWin::from_mapping(mapping);
+    let to_mandel = WinToMandel::from_mapping(mapping);
+    let (left, top) = to_mandel.cvt_f(0.0, 0.0);
+    let (right, bottom) = to_mandel.cvt_f(w, h);
+    let mut labels = Vec::new();
+    for i in ((left / spacing).ceil() as i64)..=((right / spacing).floor() as i64) {
+        let wx = x + to_win.cvt_x(i as f64 * spacing);
+        ctxt.move_to(wx, y);
+        ctxt.line_to(wx, y + h);
+        if i == 0 {
+            style.stroke(ctxt);
+        } else {
+            style.stroke_dashed(ctxt);
+        }
+        let label = format!("{:.*}", decimals, i as f64 * spacing);
+        labels.push((wx + 3.0, y + style.font_size + 2.0, label));
+    }
+    for i in ((bottom / spacing).ceil() as i64)..=((top / spacing).floor() as i64) {
+        let wy = y + to_win.cvt_y(i as f64 * spacing);
+        ctxt.move_to(x, wy);
+        ctxt.line_to(x + w, wy);
+        if i == 0 {
+            style.stroke(ctxt);
+        } else {
+            style.stroke_dashed(ctxt);
+        }
+        let label = format!("{:.*}i", decimals, i as f64 * spacing);
+        labels.push((x + 3.0, wy - 3.0, label));
+    }
+    // The labels are drawn last, so no line crosses them
+    for (lx, ly, label) in labels {
+        style.text(ctxt, lx, ly, &label);
+    }
+}
+
+// Write what the passes of the view computed in the corner at (x, y): the time they took,
+// the iterations and how many points of the last pass reached the iteration depth
+fn draw_stats(ctxt: &gtk::cairo::Context, state: &State, x: f64, y: f64) {
+    let style = state.overlay_style();
+    let seconds: f64 = state.passes().iter().map(|p| p.seconds).sum();
+    let mut total = RenderStats::default();
+    for stats in state.pass_stats() {
+        total.add(stats);
+    }
+    let last = state.pass_stats().last().copied().unwrap_or_default();
+    let per_second = if seconds > 0.0 {
+        total.iterations as f64 / seconds
+    } else {
+        0.0
+    };
+    let lines = [
+        format!("time: {:.3} s in {} passes", seconds, state.passes().len()),
+        format!("iterations: {}", total.iterations),
+        format!("speed: {:.3e} iterations/s", per_second),
+        format!("at max depth: {} of {} points", last.max_depth, last.points),
+    ];
+    for (i, line) in lines.iter().enumerate() {
+        let ly = y + (i + 1) as f64 * 1.5 * style.font_size;
+        style.text(ctxt, x + style.font_size / 2.0, ly, line);
+    }
+}
+
+// Mark the center of the view at (x, y) with a cross and its coordinates
+fn draw_crosshair(ctxt: &gtk::cairo::Context, state: &State, x: f64, y: f64) {
+    let style = state.overlay_style();
+    let (gap, arm) = (4.0, 4.0 + 3.0 * style.font_size / 2.0);
+    for (dx, dy) in [(1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0)] {
+        ctxt.move_to(x + gap * dx, y + gap * dy);
+        ctxt.line_to(x + arm * dx, y + arm * dy);
+    }
+    style.stroke(ctxt);
+    let digits = coordinate_digits(state.mapping().scale);
+    let text = format!("{:.*} {:+.*}i", digits, state.cx(), digits, state.cy());
+    style.text(ctxt, x + gap, y + gap + style.font_size, &text);
 }
 
 fn expect_float_value(e: &gtk::Entry) -> Option<f64> {
@@ -39,72 +420,280 @@ fn expect_float_value(e: &gtk::Entry) -> Option<f64> {
     }
 }
 
-fn color_changed(state: &mut State, dd: &DropDown) {
-    let sel = dd.selected();
-    if sel != GTK_INVALID_LIST_POSITION {
-        state.set_col_idx(sel as usize);
-    }
-}
-
-async fn new_image_handler(reply_receiver: Receiver<MandelReply>, state: Rc<RefCell<State>>) {
+async fn new_image_handler(
+    reply_receiver: Receiver<MandelReply>,
+    state: Rc<RefCell<State>>,
+    status: StatusBar,
+    histogram: HistogramPanel,
+) {
     while let Ok(reply) = reply_receiver.recv().await {
+        let mut state = state.borrow_mut();
+        let mut reply = match reply {
+            MandelReply::Pass(pass) => pass,
+            MandelReply::Tile(tile) => {
+                state.blit_tile(&tile);
+                continue;
+            }
+        };
+        if let Some(buffer) = reply.buffer.take() {
+            state.set_buffer(buffer);
+        }
+        state.accumulate_frame(&mut reply.data, reply.stride as usize, &reply.mapping);
         let img = Image::new(reply.data, IMG_FMT, reply.width, reply.height, reply.stride);
-        state.borrow_mut().set_img(img);
+        state.add_pass(
+            PassTiming {
+                block: reply.block,
+                seconds: reply.seconds,
+            },
+            reply.stats,
+        );
+        state.set_img(img);
+        status.update_view(&state);
+        histogram.update(&state);
     }
 }
 
+// Center the view on the clicked point. A double click also zooms in by a factor 2, or out
+// with Shift. The view moves there in a short animation.
 fn on_clicked(
     state: &Rc<RefCell<State>>,
     gesture: &GestureClick,
+    n_press: i32,
     wx: f64,
     wy: f64,
-    cx_value: &gtk::Entry,
-    cy_value: &gtk::Entry,
+    controls: &Controls,
 ) {
     gesture.set_state(gtk::EventSequenceState::Claimed);
+    if n_press == 1 {
+        state.borrow_mut().remember_location();
+        let (cx, cy) = state.borrow().win_to_mandel(wx, wy);
+        let zoom = controls.settings.zoom();
+        controls.jump_to(state, ViewPoint { cx, cy, zoom });
+    } else if n_press == 2 {
+        // The first click of the double click already chose the center
+        let mut to = controls.jump_target(&state.borrow());
+        let zoom_step = 2.0f64.ln() / ZOOM_STEP.ln();
+        let zoom_out = gesture
+            .current_event_state()
+            .contains(gdk::ModifierType::SHIFT_MASK);
+        to.zoom += if zoom_out { -zoom_step } else { zoom_step };
+        controls.jump_to(state, to);
+    }
+}
+
+// Draw the orbit of the clicked point over the image, and tell in the status bar how it ends
+fn show_orbit(state: &Rc<RefCell<State>>, status: &StatusBar, wx: f64, wy: f64) {
+    let (x, y) = state.borrow().win_to_mandel(wx, wy);
+    let orbit = {
+        let state = state.borrow();
+        let mapping = state.mapping();
+        orbit(&mapping.fractal, x, y, mapping.iteration_depth)
+    };
+    status.show_orbit(Some(&orbit));
+    state.borrow_mut().set_orbit(Some(orbit));
+}
+
+// Move the view by the distance that it was dragged. A drag so short that the click gesture
+// took it as a click leaves the view as it is.
+fn on_pan_end(state: &Rc<RefCell<State>>, dx: f64, dy: f64, settings: &ViewSettings) {
+    state.borrow_mut().set_pan(0.0, 0.0);
+    let click_distance = gtk::Settings::default().map_or(5, |s| s.gtk_double_click_distance());
+    if dx.hypot(dy) <= click_distance as f64 {
+        return;
+    }
     let _late_redraw = postpone_redraw(state);
-    let (new_cx, new_cy) = state.borrow().win_to_mandel(wx, wy);
-    cx_value.set_text(&new_cx.to_string());
-    cy_value.set_text(&new_cy.to_string());
+    let scale = state.borrow().mapping().scale;
+    // By whole pixels, so that the pixels that stay in view need not be computed again
+    let (dx, dy) = (dx.round(), dy.round());
+    move_view(state, settings, -dx * scale, dy * scale);
+}
+
+// Move the center of the view by (dx, dy) and show where it is in the settings
+fn move_view(state: &Rc<RefCell<State>>, settings: &ViewSettings, dx: f64, dy: f64) {
+    state.borrow_mut().move_center(dx, dy);
+    let (cx, cy) = {
+        let state = state.borrow();
+        (state.cx(), state.cy())
+    };
+    settings.set_cx(cx);
+    settings.set_cy(cy);
 }
 
-fn preset_ready(
+// Let the view glide on after a fast drag, like a map, with a speed in pixels per second
+// that decays. The frames are rendered with only the first pass.
+fn glide(state: &Rc<RefCell<State>>, controls: &Controls, vx: f64, vy: f64) {
+    if vx.hypot(vy) < GLIDE_MIN_SPEED || reduce_motion() {
+        return;
+    }
+    controls.stop_motion(state);
+    let glide = controls.glide.get();
+    let velocity = Cell::new((vx, vy));
+    let last_frame = Cell::new(Instant::now());
+    state.borrow_mut().set_preview(true);
+    glib::timeout_add_local(
+        GLIDE_INTERVAL,
+        clone!(@strong state, @strong controls => move || {
+            if controls.glide.get() != glide {
+                return glib::ControlFlow::Break;
+            }
+            let now = Instant::now();
+            let dt = now.duration_since(last_frame.replace(now)).as_secs_f64();
+            let decay = (-dt / GLIDE_TIME_CONSTANT).exp();
+            let (vx, vy) = velocity.get();
+            let (vx, vy) = (vx * decay, vy * decay);
+            if vx.hypot(vy) < GLIDE_STOP_SPEED {
+                controls.stop_motion(&state);
+                return glib::ControlFlow::Break;
+            }
+            velocity.set((vx, vy));
+            let scale = state.borrow().mapping().scale;
+            let _late_redraw = postpone_redraw(&state);
+            move_view(&state, &controls.settings, -vx * dt * scale, vy * dt * scale);
+            glib::ControlFlow::Continue
+        }),
+    );
+}
+
+fn on_select_update(state: &Rc<RefCell<State>>, gesture: &GestureDrag, dx: f64, dy: f64) {
+    if let Some((x0, y0)) = gesture.start_point() {
+        state
+            .borrow_mut()
+            .set_selection(Some((x0, y0, x0 + dx, y0 + dy)));
+    }
+}
+
+// Show the selected region in the region window. If the selection is so small
+// that it must have been a click, return the clicked position instead.
+fn on_select_end(
     state: &Rc<RefCell<State>>,
-    cx_value: &gtk::Entry,
-    cy_value: &gtk::Entry,
-    zoom_adj: &Adjustment,
-    iter_adj: &Adjustment,
-    presets: &Presets,
+    gesture: &GestureDrag,
+    dx: f64,
+    dy: f64,
+    region_window: &RegionWindow,
+) -> Option<(f64, f64)> {
+    state.borrow_mut().set_selection(None);
+    let (x0, y0) = gesture.start_point()?;
+    if dx.abs() < 4.0 && dy.abs() < 4.0 {
+        return Some((x0, y0));
+    }
+    let state = state.borrow();
+    let mapping = state
+        .mapping()
+        .for_region(x0, y0, x0 + dx, y0 + dy, REGION_SZ);
+    region_window.show_region(mapping, state.coloring());
+    None
+}
+
+// Use the clicked point as the constant of a Julia set
+fn pick_julia_c(
+    state: &Rc<RefCell<State>>,
+    wx: f64,
+    wy: f64,
+    fractals: &DropDown,
+    julia_cx_value: &gtk::Entry,
+    julia_cy_value: &gtk::Entry,
 ) {
-    let preset = state.borrow_mut().take_preset();
-    let _delayed_redraw = postpone_redraw(state);
-    if let Some(preset) = preset {
-        let preset = presets.get(preset as usize);
-        cx_value.set_text(&preset.cx().to_string());
-        cy_value.set_text(&preset.cy().to_string());
-        zoom_adj.set_value(preset.zoom());
-        iter_adj.set_value(preset.iter_depth());
+    let _late_redraw = postpone_redraw(state);
+    let (new_cx, new_cy) = state.borrow().win_to_mandel(wx, wy);
+    julia_cx_value.set_text(&new_cx.to_string());
+    julia_cy_value.set_text(&new_cy.to_string());
+    if let Some(pos) = FractalType::ALL
+        .iter()
+        .position(|t| *t == FractalType::Julia)
+    {
+        fractals.set_selected(pos as u32);
+    }
+}
+
+fn formula_changed(state: &mut State, e: &gtk::Entry, error_label: &Label) {
+    match Formula::parse(&e.text()) {
+        Ok(formula) => {
+            e.remove_css_class("error");
+            error_label.set_text("");
+            state.set_formula(formula);
+        }
+        Err(err) => {
+            e.add_css_class("error");
+            error_label.set_text(&err.to_string());
+        }
     }
 }
 
+fn polynomial_changed(state: &mut State, e: &gtk::Entry, error_label: &Label) {
+    match Polynomial::parse(&e.text()) {
+        Ok(polynomial) => {
+            e.remove_css_class("error");
+            error_label.set_text("");
+            state.set_polynomial(polynomial);
+        }
+        Err(err) => {
+            e.add_css_class("error");
+            error_label.set_text(&err);
+        }
+    }
+}
+
+fn fractal_changed(state: &mut State, dd: &DropDown) {
+    let sel = dd.selected();
+    if sel != GTK_INVALID_LIST_POSITION {
+        state.set_fractal_type(FractalType::ALL[sel as usize]);
+    }
+}
+
+// Jump to the chosen preset, if any. The iteration depth, the coloring and the fractal change
+// at once, and the view moves to the preset in a short animation.
+fn preset_ready(state: &Rc<RefCell<State>>, controls: &Controls, presets: &Presets) {
+    let Some(preset) = state.borrow_mut().take_preset() else {
+        return;
+    };
+    state.borrow_mut().remember_location();
+    let preset = presets.get(preset as usize);
+    let to = ViewPoint {
+        cx: preset.cx(),
+        cy: preset.cy(),
+        zoom: preset.zoom(),
+    };
+    let from = controls.view_point(&state.borrow());
+    {
+        let _delayed_redraw = postpone_redraw(state);
+        if let Some(location) = preset.location() {
+            let mut start = location.clone();
+            start.cx = from.cx;
+            start.cy = from.cy;
+            start.scale = scale_for_zoom(from.zoom);
+            controls.show_location(state, &start);
+        } else {
+            controls.settings.set_iterations(preset.iter_depth() as u32);
+        }
+    }
+    controls.jump_to(state, to);
+}
+
 fn preset_setup(_fac: &SignalListItemFactory, item: &ListItem) {
-    item.set_child(Some(&Label::new(None)));
+    let row = make_row_box();
+    row.set_spacing(10);
+    row.append(&gtk::Image::builder().pixel_size(THUMB_SZ as i32).build());
+    row.append(&Label::new(None));
+    item.set_child(Some(&row));
 }
 
 fn preset_bind(_fac: &SignalListItemFactory, item: &ListItem) {
-    if let Some(widget) = item.child() {
-        if let Some(obj) = item.item() {
-            if let Ok(str) = obj.downcast::<StringObject>() {
-                if let Ok(label) = widget.downcast::<Label>() {
-                    label.set_text(&str.string());
-                }
-            }
-        }
+    let (Some(row), Some(obj)) = (item.child(), item.item()) else {
+        return;
+    };
+    let image = row
+        .first_child()
+        .and_then(|w| w.downcast::<gtk::Image>().ok());
+    let label = row.last_child().and_then(|w| w.downcast::<Label>().ok());
+    if let (Some(image), Some(label), Ok(obj)) = (image, label, obj.downcast::<BoxedAnyObject>()) {
+        show_preset_item(item, &obj, &image, &label);
     }
 }
 
-fn build_preset_window(state: &Rc<RefCell<State>>, presets: &Presets) -> Window {
-    let preset_list = SingleSelection::new(Some(StringList::new(presets.names())));
+fn build_preset_window(state: &Rc<RefCell<State>>, presets: &Rc<RefCell<Presets>>) -> Window {
+    let items = gio::ListStore::new::<BoxedAnyObject>();
+    items.extend_from_slice(&preset_items(&presets.borrow()));
+    let preset_list = SingleSelection::new(Some(items.clone()));
     let factory = SignalListItemFactory::new();
     factory.connect_setup(preset_setup);
     factory.connect_bind(preset_bind);
@@ -115,6 +704,41 @@ fn build_preset_window(state: &Rc<RefCell<State>>, presets: &Presets) -> Window
         .margin_start(20)
         .margin_end(20)
         .build();
+    let name_entry = gtk::Entry::builder()
+        .placeholder_text("name")
+        .hexpand(true)
+        .build();
+    let add_btn = Button::builder()
+        .label("Add preset")
+        .tooltip_text("Save the current view as a preset")
+        .margin_start(10)
+        .build();
+    let add_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .margin_top(20)
+        .margin_start(20)
+        .margin_end(20)
+        .build();
+    add_box.append(&name_entry);
+    add_box.append(&add_btn);
+    let import_btn = Button::builder().label("Import...").build();
+    let export_btn = Button::builder()
+        .label("Export...")
+        .margin_start(10)
+        .build();
+    let manage_btn = Button::builder()
+        .label("Manage...")
+        .margin_start(10)
+        .build();
+    let file_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .margin_top(10)
+        .margin_start(20)
+        .margin_end(20)
+        .build();
+    file_box.append(&import_btn);
+    file_box.append(&export_btn);
+    file_box.append(&manage_btn);
     let cancel_btn = Button::builder().label("Cancel").build();
     let ok_btn = Button::builder().label("Apply").margin_start(10).build();
     let ready_box = gtk::Box::builder()
@@ -130,6 +754,8 @@ fn build_preset_window(state: &Rc<RefCell<State>>, presets: &Presets) -> Window
         .orientation(gtk::Orientation::Vertical)
         .build();
     content_box.append(&preset_view);
+    content_box.append(&add_box);
+    content_box.append(&file_box);
     content_box.append(&ready_box);
     let win = Window::builder()
         .title("Presets")
@@ -139,6 +765,33 @@ fn build_preset_window(state: &Rc<RefCell<State>>, presets: &Presets) -> Window
         .hide_on_close(true)
         .child(&content_box)
         .build();
+    add_btn.connect_clicked(clone!(@strong state, @strong presets, @strong items,
+            @strong preset_list, @weak name_entry => move |_| {
+        let mut presets = presets.borrow_mut();
+        let text = name_entry.text();
+        let name = match text.trim() {
+            "" => format!("Preset {}", presets.len() + 1),
+            name => name.to_string(),
+        };
+        let idx = presets.add(&name, location_preset(state.borrow().location()));
+        save_presets(&presets);
+        items.splice(0, items.n_items(), &preset_items(&presets));
+        preset_list.set_selected(idx as u32);
+        name_entry.set_text("");
+    }));
+    let refresh = clone!(@strong presets, @strong items => move || {
+        items.splice(0, items.n_items(), &preset_items(&presets.borrow()));
+    });
+    import_btn.connect_clicked(
+        clone!(@strong presets, @strong refresh, @weak win => move |_| {
+            choose_preset_import(&win, &presets, refresh.clone());
+        }),
+    );
+    let manager = PresetManager::new(&win, presets, refresh);
+    manage_btn.connect_clicked(move |_| manager.present());
+    export_btn.connect_clicked(clone!(@strong presets, @weak win => move |_| {
+        choose_preset_export(&win, &presets);
+    }));
     cancel_btn.connect_clicked(clone!(@weak win, @strong state => move |_| {
         state.borrow_mut().set_preset(None);
         win.set_visible(false);
@@ -151,6 +804,246 @@ fn build_preset_window(state: &Rc<RefCell<State>>, presets: &Presets) -> Window
     win
 }
 
+fn recent_label(location: &Location) -> String {
+    format!(
+        "{}: {} {:+}i\nzoom {:.0}, {} iterations, {}",
+        location.fractal.fractal_type.name(),
+        location.cx,
+        location.cy,
+        zoom_for_scale(location.scale),
+        location.iteration_depth,
+        location.coloring
+    )
+}
+
+// Fill the list with a row per recent location, most recent first
+fn fill_recent_list(state: &Rc<RefCell<State>>, list: &ListBox) {
+    while let Some(child) = list.first_child() {
+        list.remove(&child);
+    }
+    let n = state.borrow().recent().len();
+    for i in 0..n {
+        let location = state.borrow().recent().location(i).clone();
+        let image = gtk::Image::builder().pixel_size(THUMB_SZ as i32).build();
+        image.set_paintable(thumbnail_texture(&location, THUMB_SZ).as_ref());
+        let label = Label::builder()
+            .label(recent_label(&location))
+            .xalign(0.0)
+            .build();
+        let row = make_row_box();
+        row.set_spacing(10);
+        row.append(&image);
+        row.append(&label);
+        list.append(&row);
+    }
+}
+
+fn build_recent_button(state: &Rc<RefCell<State>>, controls: &Controls) -> MenuButton {
+    let list = ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .build();
+    list.set_placeholder(Some(&Label::new(Some("no recent locations"))));
+    let scroller = ScrolledWindow::builder()
+        .child(&list)
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .propagate_natural_height(true)
+        .max_content_height(400)
+        .build();
+    let popover = Popover::builder().child(&scroller).build();
+    popover.connect_show(clone!(@strong state, @weak list => move |_p| {
+        fill_recent_list(&state, &list);
+    }));
+    list.connect_row_activated(
+        clone!(@strong state, @strong controls, @weak popover => move |_l, row| {
+            popover.popdown();
+            let location = state.borrow().recent().location(row.index() as usize).clone();
+            state.borrow_mut().remember_location();
+            controls.show_location(&state, &location);
+        }),
+    );
+    MenuButton::builder()
+        .label("Recent")
+        .popover(&popover)
+        .margin_start(5)
+        .build()
+}
+
+// A strip with the colors of a palette, as wide as a thumbnail
+fn palette_strip(entry: &RandomEntry) -> DrawingArea {
+    let strip = DrawingArea::builder()
+        .content_width(THUMB_SZ as i32)
+        .content_height(THUMB_SZ as i32)
+        .build();
+    if let Some(gradient) = entry.gradient() {
+        strip.set_draw_func(move |_d, ctxt, w, h| {
+            for x in 0..w {
+                let rgba = to_rgba(gradient.color_at(x as f64 / w as f64));
+                ctxt.set_source_rgb(rgba.red() as f64, rgba.green() as f64, rgba.blue() as f64);
+                ctxt.rectangle(x as f64, 0.0, 1.0, h as f64);
+                let _ = ctxt.fill();
+            }
+        });
+    }
+    strip
+}
+
+// Fill the list with a row per randomized palette or location, most recent first
+fn fill_random_list(history: &Rc<RefCell<RandomHistory>>, list: &ListBox) {
+    while let Some(child) = list.first_child() {
+        list.remove(&child);
+    }
+    let history = history.borrow();
+    for i in 0..history.len() {
+        let entry = history.entry(i);
+        let row = make_row_box();
+        row.set_spacing(10);
+        let text = match &entry.result {
+            RandomResult::Palette(stops) => {
+                row.append(&palette_strip(entry));
+                format!("palette, seed {:016x}\n{} stops", entry.seed, stops.len())
+            }
+            RandomResult::Location(location) => {
+                let image = gtk::Image::builder().pixel_size(THUMB_SZ as i32).build();
+                image.set_paintable(thumbnail_texture(location, THUMB_SZ).as_ref());
+                row.append(&image);
+                format!(
+                    "location, seed {:016x}\n{}",
+                    entry.seed,
+                    recent_label(location)
+                )
+            }
+        };
+        row.append(&Label::builder().label(text).xalign(0.0).build());
+        list.append(&row);
+    }
+}
+
+// Show a palette as the coloring of the view
+fn use_random_palette(state: &Rc<RefCell<State>>, controls: &Controls, entry: &RandomEntry) {
+    if let Some(gradient) = entry.gradient() {
+        let idx = state.borrow_mut().add_coloring(Box::new(gradient));
+        controls.settings.set_coloring(idx as u32);
+    }
+}
+
+fn use_random_entry(state: &Rc<RefCell<State>>, controls: &Controls, entry: &RandomEntry) {
+    match &entry.result {
+        RandomResult::Palette(_) => use_random_palette(state, controls, entry),
+        RandomResult::Location(location) => {
+            state.borrow_mut().remember_location();
+            controls.show_location(state, location);
+        }
+    }
+}
+
+// A menu to randomize the palette or the location, with the history of what was randomized
+fn build_random_button(state: &Rc<RefCell<State>>, controls: &Controls) -> MenuButton {
+    let history = Rc::new(RefCell::new(RandomHistory::load()));
+    let palette_btn = Button::with_label("Random palette");
+    let location_btn = Button::with_label("Random location");
+    let buttons = make_row_box();
+    buttons.append(&palette_btn);
+    buttons.append(&location_btn);
+    let list = ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .build();
+    list.set_placeholder(Some(&Label::new(Some("nothing randomized yet"))));
+    let scroller = ScrolledWindow::builder()
+        .child(&list)
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .propagate_natural_height(true)
+        .max_content_height(400)
+        .build();
+    let content = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+    content.append(&buttons);
+    content.append(&scroller);
+    let popover = Popover::builder().child(&content).build();
+    popover.connect_show(clone!(@strong history, @weak list => move |_p| {
+        fill_random_list(&history, &list);
+    }));
+    palette_btn.connect_clicked(
+        clone!(@strong state, @strong controls, @strong history, @weak list => move |_b| {
+            let seed = new_seed();
+            let entry = RandomEntry::palette(seed, &random_palette(seed));
+            history.borrow_mut().add(entry.clone());
+            fill_random_list(&history, &list);
+            use_random_palette(&state, &controls, &entry);
+        }),
+    );
+    location_btn.connect_clicked(
+        clone!(@strong state, @strong controls, @strong history, @weak list => move |_b| {
+            let seed = new_seed();
+            let win_sz = state.borrow().mapping().win_width;
+            let entry = RandomEntry::location(seed, &random_location(seed, win_sz));
+            history.borrow_mut().add(entry.clone());
+            fill_random_list(&history, &list);
+            use_random_entry(&state, &controls, &entry);
+        }),
+    );
+    list.connect_row_activated(
+        clone!(@strong state, @strong controls, @strong history, @weak popover => move |_l, row| {
+            popover.popdown();
+            let entry = history.borrow().entry(row.index() as usize).clone();
+            use_random_entry(&state, &controls, &entry);
+        }),
+    );
+    MenuButton::builder()
+        .label("Random")
+        .popover(&popover)
+        .margin_start(5)
+        .build()
+}
+
+// Zoom in on the center, one step per frame, until the button is switched off or the zoom
+// reaches the end of its range. The frames are accumulated meanwhile.
+fn dive(
+    state: &Rc<RefCell<State>>,
+    controls: &Controls,
+    zoom_adj: &Adjustment,
+    btn: &ToggleButton,
+) {
+    state.borrow_mut().set_accumulate(true);
+    glib::timeout_add_local(
+        DIVE_INTERVAL,
+        clone!(@strong state, @strong controls, @weak zoom_adj, @weak btn =>
+            @default-return glib::ControlFlow::Break, move || {
+            if !btn.is_active() {
+                return glib::ControlFlow::Break;
+            }
+            let zoom = controls.settings.zoom() + DIVE_STEP;
+            if zoom >= zoom_adj.upper() {
+                btn.set_active(false);
+                return glib::ControlFlow::Break;
+            }
+            controls.settings.set_zoom(zoom);
+            glib::ControlFlow::Continue
+        }),
+    );
+}
+
+// Shift the colors a step per frame until the button is switched off. The frames are
+// colored from the values of the last render, so the colors wait for a render to finish.
+fn cycle_colors(state: &Rc<RefCell<State>>, btn: &ToggleButton) {
+    let phase = Rc::new(Cell::new(0.0));
+    glib::timeout_add_local(
+        CYCLE_INTERVAL,
+        clone!(@strong state, @weak btn => @default-return glib::ControlFlow::Break, move || {
+            if !btn.is_active() {
+                state.borrow_mut().set_cycle_phase(None);
+                return glib::ControlFlow::Break;
+            }
+            if !state.borrow().needs_buffer() {
+                phase.set(phase.get() + CYCLE_STEP);
+                state.borrow_mut().set_cycle_phase(Some(phase.get()));
+            }
+            glib::ControlFlow::Continue
+        }),
+    );
+}
+
 fn make_row_box() -> gtk::Box {
     gtk::Box::builder()
         .orientation(gtk::Orientation::Horizontal)
@@ -158,48 +1051,993 @@ fn make_row_box() -> gtk::Box {
         .build()
 }
 
-fn build_ui(app: &Application) {
-    let (req_sender, req_receiver) = async_channel::unbounded();
-    let (reply_sender, reply_receiver) = async_channel::bounded(1);
+fn add_screenshot_action(
+    app: &Application,
+    window: &ApplicationWindow,
+    state: &Rc<RefCell<State>>,
+) {
+    let config = Config::load();
+    let screenshot = gio::SimpleAction::new("screenshot", None);
+    screenshot.connect_activate(clone!(@strong state, @weak window => move |_a, _p| {
+        match save_screenshot(&state.borrow()) {
+            Ok(path) => show_info(&window, "Saved screenshot", &path.display().to_string()),
+            Err(e) => show_error(&window, "Could not save screenshot", e),
+        }
+    }));
+    window.add_action(&screenshot);
+    let accel = if gtk::accelerator_parse(&config.screenshot_accel).is_some() {
+        config.screenshot_accel
+    } else {
+        let defaults = Config::defaults();
+        show_error(
+            window,
+            "Invalid screenshot shortcut",
+            format!(
+                "{} is not a shortcut; {} is used instead",
+                config.screenshot_accel, defaults.screenshot_accel
+            ),
+        );
+        defaults.screenshot_accel
+    };
+    app.set_accels_for_action("win.screenshot", &[&accel]);
+}
+
+fn choose_image_file(window: &ApplicationWindow, state: &Rc<RefCell<State>>) {
+    let chooser = FileChooserNative::new(
+        Some("Save image"),
+        Some(window),
+        FileChooserAction::Save,
+        Some("Save"),
+        None,
+    );
+    let name = timestamp().unwrap_or_else(|_| "image".to_string());
+    chooser.set_current_name(&format!("mandelbrot-{}.png", name));
+    chooser.connect_response(
+        clone!(@strong state, @weak window => move |chooser, response| {
+            if response != ResponseType::Accept {
+                return;
+            }
+            if let Some(path) = chooser.file().and_then(|f| f.path()) {
+                match write_view_png(&state.borrow(), &path) {
+                    Ok(()) => show_info(&window, "Saved image", &path.display().to_string()),
+                    Err(e) => show_error(&window, "Could not save image", e),
+                }
+            }
+        }),
+    );
+    chooser.show();
+}
+
+// Read the view that is stored in the text chunks of a PNG file
+fn read_image_location(path: &Path) -> io::Result<Location> {
+    let text = read_png_text(BufReader::new(File::open(path)?))?;
+    Location::from_png_text(&text).ok_or_else(|| io::Error::other("the image has no view"))
+}
+
+// Let the user pick a PNG file that was saved by this application and show its view
+fn choose_open_image(window: &ApplicationWindow, state: &Rc<RefCell<State>>, controls: &Controls) {
+    let chooser = FileChooserNative::new(
+        Some("Open image"),
+        Some(window),
+        FileChooserAction::Open,
+        Some("Open"),
+        None,
+    );
+    let filter = FileFilter::new();
+    filter.set_name(Some("PNG images"));
+    filter.add_pattern("*.png");
+    chooser.add_filter(&filter);
+    chooser.connect_response(
+        clone!(@strong state, @strong controls, @weak window => move |chooser, response| {
+            if response != ResponseType::Accept {
+                return;
+            }
+            if let Some(path) = chooser.file().and_then(|f| f.path()) {
+                match read_image_location(&path) {
+                    Ok(location) => {
+                        state.borrow_mut().remember_location();
+                        controls.show_location(&state, &location);
+                    }
+                    Err(e) => show_error(&window, "Could not open image", e),
+                }
+            }
+        }),
+    );
+    chooser.show();
+}
+
+fn add_open_image_action(
+    app: &Application,
+    window: &ApplicationWindow,
+    state: &Rc<RefCell<State>>,
+    controls: &Controls,
+) {
+    let open_image = gio::SimpleAction::new("open-image", None);
+    open_image.connect_activate(
+        clone!(@strong state, @strong controls, @weak window => move |_a, _p| {
+            choose_open_image(&window, &state, &controls)
+        }),
+    );
+    window.add_action(&open_image);
+    app.set_accels_for_action("win.open-image", &["<Control>o"]);
+}
+
+fn add_save_image_action(
+    app: &Application,
+    window: &ApplicationWindow,
+    state: &Rc<RefCell<State>>,
+) {
+    let save_image = gio::SimpleAction::new("save-image", None);
+    save_image.connect_activate(clone!(@strong state, @weak window => move |_a, _p| {
+        choose_image_file(&window, &state)
+    }));
+    window.add_action(&save_image);
+    app.set_accels_for_action("win.save-image", &["<Control>s"]);
+}
+
+fn add_report_action(window: &ApplicationWindow, state: &Rc<RefCell<State>>) {
+    let export_report = gio::SimpleAction::new("export-report", None);
+    export_report.connect_activate(clone!(@strong state, @weak window => move |_a, _p| {
+        save_with_report(&window, &state.borrow());
+    }));
+    window.add_action(&export_report);
+}
+
+fn add_mask_action(window: &ApplicationWindow, state: &Rc<RefCell<State>>) {
+    // The parameter tells whether the mask has one bit per pixel
+    let export_mask = gio::SimpleAction::new("export-mask", Some(glib::VariantTy::BOOLEAN));
+    export_mask.connect_activate(clone!(@strong state, @weak window => move |_a, p| {
+        let bilevel = p.and_then(|p| p.get::<bool>()).unwrap_or(true);
+        save_mask(&window, &state.borrow(), bilevel);
+    }));
+    window.add_action(&export_mask);
+}
+
+// Let the user pick a palette or GIMP gradient file and show the view with it
+fn choose_palette(window: &ApplicationWindow, state: &Rc<RefCell<State>>, settings: &ViewSettings) {
+    let chooser = FileChooserNative::new(
+        Some("Load palette"),
+        Some(window),
+        FileChooserAction::Open,
+        Some("Load"),
+        None,
+    );
+    let filter = FileFilter::new();
+    filter.set_name(Some("Palettes and GIMP gradients"));
+    filter.add_pattern("*.map");
+    filter.add_pattern("*.json");
+    filter.add_pattern("*.ggr");
+    chooser.add_filter(&filter);
+    chooser.connect_response(
+        clone!(@strong state, @strong settings, @weak window => move |chooser, response| {
+            if response != ResponseType::Accept {
+                return;
+            }
+            if let Some(path) = chooser.file().and_then(|f| f.path()) {
+                let palette = match path.extension().and_then(|e| e.to_str()) {
+                    Some("ggr") => load_ggr(&path).map(|c| Box::new(c) as Box<dyn Coloring>),
+                    _ => load_palette(&path).map(|c| Box::new(c) as Box<dyn Coloring>),
+                };
+                match palette {
+                    Ok(palette) => {
+                        let idx = state.borrow_mut().add_coloring(palette);
+                        settings.set_coloring(idx as u32);
+                    }
+                    Err(e) => show_error(&window, "Could not load palette", e),
+                }
+            }
+        }),
+    );
+    chooser.show();
+}
+
+// Let the user save the colors of the current coloring as a .map or JSON file
+fn choose_palette_file(window: &ApplicationWindow, state: &Rc<RefCell<State>>) {
+    let chooser = FileChooserNative::new(
+        Some("Save palette"),
+        Some(window),
+        FileChooserAction::Save,
+        Some("Save"),
+        None,
+    );
+    let coloring = state.borrow().coloring();
+    chooser.set_current_name(&format!("{}.map", coloring.name()));
+    chooser.connect_response(clone!(@weak window => move |chooser, response| {
+        if response != ResponseType::Accept {
+            return;
+        }
+        if let Some(path) = chooser.file().and_then(|f| f.path()) {
+            match save_palette(coloring.as_ref(), &path) {
+                Ok(()) => show_info(&window, "Saved palette", &path.display().to_string()),
+                Err(e) => show_error(&window, "Could not save palette", e),
+            }
+        }
+    }));
+    chooser.show();
+}
+
+fn choose_strip_file(window: &ApplicationWindow, state: &Rc<RefCell<State>>) {
+    let chooser = FileChooserNative::new(
+        Some("Save palette strip"),
+        Some(window),
+        FileChooserAction::Save,
+        Some("Save"),
+        None,
+    );
+    let coloring = state.borrow().coloring();
+    chooser.set_current_name(&format!("{}-strip.png", coloring.name()));
+    chooser.connect_response(clone!(@weak window => move |chooser, response| {
+        if response != ResponseType::Accept {
+            return;
+        }
+        if let Some(path) = chooser.file().and_then(|f| f.path()) {
+            match save_palette_strip(coloring.as_ref(), &path) {
+                Ok(()) => show_info(&window, "Saved palette strip", &path.display().to_string()),
+                Err(e) => show_error(&window, "Could not save palette strip", e),
+            }
+        }
+    }));
+    chooser.show();
+}
+
+fn add_palette_actions(
+    window: &ApplicationWindow,
+    state: &Rc<RefCell<State>>,
+    settings: &ViewSettings,
+) {
+    let load_palette = gio::SimpleAction::new("load-palette", None);
+    load_palette.connect_activate(
+        clone!(@strong state, @strong settings, @weak window => move |_a, _p| {
+            choose_palette(&window, &state, &settings)
+        }),
+    );
+    window.add_action(&load_palette);
+    let save_palette = gio::SimpleAction::new("save-palette", None);
+    save_palette.connect_activate(clone!(@strong state, @weak window => move |_a, _p| {
+        choose_palette_file(&window, &state)
+    }));
+    window.add_action(&save_palette);
+    let save_strip = gio::SimpleAction::new("save-palette-strip", None);
+    save_strip.connect_activate(clone!(@strong state, @weak window => move |_a, _p| {
+        choose_strip_file(&window, &state)
+    }));
+    window.add_action(&save_strip);
+}
+
+// Measure the speed of the machine in the background and adapt the render settings to it
+fn calibrate(state: &Rc<RefCell<State>>) {
+    let state = state.clone();
+    glib::spawn_future_local(async move {
+        let speed = gio::spawn_blocking(|| measure_speed(&mut new_pool())).await;
+        if let Ok(speed) = speed {
+            eprintln!("Rendering speed is {:.0} pixels per second", speed);
+            state
+                .borrow_mut()
+                .set_calibration(Calibration::for_speed(speed));
+            let mut config = Config::load();
+            config.pixels_per_second = Some(speed);
+            if let Err(e) = config.save() {
+                eprintln!("Could not save settings: {}", e);
+            }
+        }
+    });
+}
+
+fn add_copy_link_action(window: &ApplicationWindow, state: &Rc<RefCell<State>>) {
+    let copy_link = gio::SimpleAction::new("copy-link", None);
+    copy_link.connect_activate(clone!(@strong state, @weak window => move |_a, _p| {
+        let uri = state.borrow().location().to_uri();
+        window.clipboard().set_text(&uri);
+    }));
+    window.add_action(&copy_link);
+}
+
+// Let the user choose a name for a workspace and export it as a folder with that name
+fn choose_workspace_export(window: &ApplicationWindow, state: &Rc<RefCell<State>>) {
+    let chooser = FileChooserNative::new(
+        Some("Export workspace"),
+        Some(window),
+        FileChooserAction::Save,
+        Some("Export"),
+        None,
+    );
+    chooser.set_current_name("lesson");
+    chooser.connect_response(clone!(@strong state, @weak window => move |chooser, response| {
+        if response != ResponseType::Accept {
+            return;
+        }
+        let Some(dir) = chooser.file().and_then(|f| f.path()) else {
+            return;
+        };
+        let name = dir
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let workspace = Workspace::from_state(&state.borrow(), &name);
+        glib::spawn_future_local(clone!(@weak window => async move {
+            let result = gio::spawn_blocking(clone!(@strong dir => move || workspace.write(&dir)))
+                .await
+                .unwrap_or_else(|_| Err(io::Error::other("the export failed")));
+            match result {
+                Ok(()) => show_info(&window, "Exported workspace", &dir.display().to_string()),
+                Err(e) => show_error(&window, "Could not export workspace", e),
+            }
+        }));
+    }));
+    chooser.show();
+}
+
+// Let the user choose the folder of a workspace and import it
+fn choose_workspace_import(window: &ApplicationWindow, state: &Rc<RefCell<State>>) {
+    let chooser = FileChooserNative::new(
+        Some("Import workspace"),
+        Some(window),
+        FileChooserAction::SelectFolder,
+        Some("Import"),
+        None,
+    );
+    chooser.connect_response(
+        clone!(@strong state, @weak window => move |chooser, response| {
+            if response != ResponseType::Accept {
+                return;
+            }
+            if let Some(dir) = chooser.file().and_then(|f| f.path()) {
+                let result = Workspace::read(&dir).and_then(|workspace| {
+                    let palette_errors = workspace.palette_errors.join("\n");
+                    let renders = workspace.apply(&dir, &mut state.borrow_mut())?;
+                    Ok((renders, palette_errors))
+                });
+                match result {
+                    Ok((_, palette_errors)) if !palette_errors.is_empty() => show_error(
+                        &window,
+                        "Imported workspace without some of its palettes",
+                        palette_errors,
+                    ),
+                    Ok((renders, _)) => show_info(
+                        &window,
+                        "Imported workspace",
+                        &format!("{}, with its images in {}", dir.display(), renders.display()),
+                    ),
+                    Err(e) => show_error(&window, "Could not import workspace", e),
+                }
+            }
+        }),
+    );
+    chooser.show();
+}
+
+fn add_print_action(app: &Application, window: &ApplicationWindow, state: &Rc<RefCell<State>>) {
+    // The parameter tells whether the view is rendered again at the resolution of the printer
+    let print = gio::SimpleAction::new("print", Some(glib::VariantTy::BOOLEAN));
+    print.connect_activate(clone!(@strong state, @weak window => move |_a, p| {
+        let rerender = p.and_then(|p| p.get::<bool>()).unwrap_or(false);
+        print_view(&window, &state, rerender);
+    }));
+    window.add_action(&print);
+    app.set_accels_for_action("win.print(false)", &["<Control>p"]);
+}
+
+fn add_workspace_actions(window: &ApplicationWindow, state: &Rc<RefCell<State>>) {
+    let export = gio::SimpleAction::new("export-workspace", None);
+    export.connect_activate(clone!(@strong state, @weak window => move |_a, _p| {
+        choose_workspace_export(&window, &state)
+    }));
+    window.add_action(&export);
+    let import = gio::SimpleAction::new("import-workspace", None);
+    import.connect_activate(clone!(@strong state, @weak window => move |_a, _p| {
+        choose_workspace_import(&window, &state)
+    }));
+    window.add_action(&import);
+}
+
+// The current image as a texture that can be put on the clipboard
+fn image_texture(img: &Image) -> gdk::MemoryTexture {
+    let (width, height) = (img.width(), img.height());
+    let mut rgb = Vec::with_capacity(3 * width);
+    let mut data = Vec::with_capacity(3 * width * height);
+    for y in 0..height {
+        rgb24_to_rgb(&img.data()[y * img.stride()..], width, &mut rgb);
+        data.extend_from_slice(&rgb);
+    }
+    gdk::MemoryTexture::new(
+        width as i32,
+        height as i32,
+        gdk::MemoryFormat::R8g8b8,
+        &glib::Bytes::from_owned(data),
+        3 * width,
+    )
+}
+
+fn add_copy_image_action(
+    app: &Application,
+    window: &ApplicationWindow,
+    state: &Rc<RefCell<State>>,
+) {
+    let copy_image = gio::SimpleAction::new("copy-image", None);
+    copy_image.connect_activate(clone!(@strong state, @weak window => move |_a, _p| {
+        match state.borrow().img() {
+            Some(img) => window.clipboard().set_texture(&image_texture(img)),
+            None => show_error(&window, "Could not copy the image", "there is no image yet"),
+        }
+    }));
+    window.add_action(&copy_image);
+    app.set_accels_for_action("win.copy-image", &["<Control>c"]);
+}
+
+// Copy the location as short text, and show the location of a text or link on the clipboard
+fn add_location_text_actions(
+    app: &Application,
+    window: &ApplicationWindow,
+    state: &Rc<RefCell<State>>,
+    controls: &Controls,
+) {
+    let copy_location = gio::SimpleAction::new("copy-location", None);
+    copy_location.connect_activate(clone!(@strong state, @weak window => move |_a, _p| {
+        let text = state.borrow().location().to_text();
+        window.clipboard().set_text(&text);
+    }));
+    window.add_action(&copy_location);
+    let paste_location = gio::SimpleAction::new("paste-location", None);
+    paste_location.connect_activate(
+        clone!(@strong state, @strong controls, @weak window => move |_a, _p| {
+            let clipboard = window.clipboard();
+            glib::spawn_future_local(clone!(@strong state, @strong controls, @weak window => async move {
+                let text = match clipboard.read_text_future().await {
+                    Ok(Some(text)) => text,
+                    Ok(None) => {
+                        show_error(&window, "Could not paste a location", "there is no text");
+                        return;
+                    }
+                    Err(e) => {
+                        show_error(&window, "Could not paste a location", e);
+                        return;
+                    }
+                };
+                let location = Location::from_text(&text).or_else(|| Location::from_uri(text.trim()));
+                match location {
+                    Some(location) => {
+                        state.borrow_mut().remember_location();
+                        controls.show_location(&state, &location);
+                    }
+                    None => show_error(&window, "Not a valid location", text),
+                }
+            }));
+        }),
+    );
+    window.add_action(&paste_location);
+    app.set_accels_for_action("win.copy-location", &["<Control><Shift>c"]);
+    app.set_accels_for_action("win.paste-location", &["<Control><Shift>v"]);
+}
+
+fn add_view_history_actions(
+    app: &Application,
+    window: &ApplicationWindow,
+    state: &Rc<RefCell<State>>,
+    controls: &Controls,
+) {
+    let undo = gio::SimpleAction::new("undo-view", None);
+    undo.connect_activate(clone!(@strong state, @strong controls => move |_a, _p| {
+        let location = state.borrow_mut().undo_view();
+        if let Some(location) = location {
+            controls.show_location(&state, &location);
+        }
+    }));
+    window.add_action(&undo);
+    let redo = gio::SimpleAction::new("redo-view", None);
+    redo.connect_activate(clone!(@strong state, @strong controls => move |_a, _p| {
+        let location = state.borrow_mut().redo_view();
+        if let Some(location) = location {
+            controls.show_location(&state, &location);
+        }
+    }));
+    window.add_action(&redo);
+    app.set_accels_for_action("win.undo-view", &["<Control>z"]);
+    app.set_accels_for_action("win.redo-view", &["<Control><Shift>z"]);
+}
+
+// Let the arrow keys move the view, + and - zoom and Page Up and Page Down change the
+// iteration depth. A focused widget that uses a key, e.g. an entry, gets it first.
+fn add_key_navigation(
+    window: &ApplicationWindow,
+    state: &Rc<RefCell<State>>,
+    controls: &Controls,
+    status: &StatusBar,
+) {
+    let keys = EventControllerKey::new();
+    keys.connect_key_pressed(
+        clone!(@strong state, @strong controls, @strong status => move |_c, key, _code, modifiers| {
+            if modifiers.intersects(gdk::ModifierType::CONTROL_MASK | gdk::ModifierType::ALT_MASK) {
+                return glib::Propagation::Proceed;
+            }
+            let settings = &controls.settings;
+            let step = if modifiers.contains(gdk::ModifierType::SHIFT_MASK) {
+                KEY_PAN_FINE
+            } else {
+                KEY_PAN
+            };
+            let scale = state.borrow().mapping().scale;
+            let depth = settings.iterations();
+            let deeper = ((depth as f64 * KEY_DEPTH_FACTOR) as u32).max(depth + 1);
+            let shallower = ((depth as f64 / KEY_DEPTH_FACTOR) as u32).max(1);
+            match key {
+                gdk::Key::Left => move_view(&state, settings, -step * scale, 0.0),
+                gdk::Key::Right => move_view(&state, settings, step * scale, 0.0),
+                gdk::Key::Up => move_view(&state, settings, 0.0, step * scale),
+                gdk::Key::Down => move_view(&state, settings, 0.0, -step * scale),
+                gdk::Key::plus | gdk::Key::equal | gdk::Key::KP_Add => {
+                    settings.set_zoom(settings.zoom() + KEY_ZOOM)
+                }
+                gdk::Key::minus | gdk::Key::KP_Subtract => {
+                    settings.set_zoom(settings.zoom() - KEY_ZOOM)
+                }
+                gdk::Key::Page_Up => settings.set_iterations(deeper),
+                gdk::Key::Page_Down => settings.set_iterations(shallower),
+                gdk::Key::Escape if state.borrow().orbit().is_some() => {
+                    state.borrow_mut().set_orbit(None);
+                    status.show_orbit(None);
+                }
+                _ => return glib::Propagation::Proceed,
+            }
+            controls.stop_motion(&state);
+            glib::Propagation::Stop
+        }),
+    );
+    window.add_controller(keys);
+}
+
+// Toggle fullscreen mode. The rows of controls then hide, and show again while the pointer is
+// at the top edge of the screen.
+fn add_fullscreen_action(
+    app: &Application,
+    window: &ApplicationWindow,
+    content_box: &gtk::Box,
+    rows: &gtk::Revealer,
+) {
+    let fullscreen = gio::SimpleAction::new_stateful("fullscreen", None, &false.to_variant());
+    fullscreen.connect_activate(clone!(@weak window => move |_a, _p| {
+        if window.is_fullscreen() {
+            window.unfullscreen();
+        } else {
+            window.fullscreen();
+        }
+    }));
+    window.add_action(&fullscreen);
+    app.set_accels_for_action("win.fullscreen", &["F11"]);
+    window.connect_fullscreened_notify(clone!(@weak content_box, @weak rows => move |window| {
+        let full = window.is_fullscreen();
+        fullscreen.set_state(&full.to_variant());
+        rows.set_reveal_child(!full);
+        let margin = if full { 0 } else { 10 };
+        content_box.set_margin_start(margin);
+        content_box.set_margin_end(margin);
+        content_box.set_margin_top(margin);
+        content_box.set_margin_bottom(margin);
+    }));
+    let motion = EventControllerMotion::new();
+    motion.connect_motion(clone!(@weak window, @weak rows => move |_m, _x, y| {
+        if !window.is_fullscreen() {
+            return;
+        }
+        if y <= FULLSCREEN_EDGE {
+            rows.set_reveal_child(true);
+        } else if rows.is_child_revealed() && y > (rows.height() as f64) + FULLSCREEN_EDGE {
+            rows.set_reveal_child(false);
+        }
+    }));
+    window.add_controller(motion);
+}
+
+fn add_preferences_action(
+    app: &Application,
+    window: &ApplicationWindow,
+    preferences_window: PreferencesWindow,
+) {
+    let preferences = gio::SimpleAction::new("preferences", None);
+    preferences.connect_activate(move |_a, _p| preferences_window.present());
+    window.add_action(&preferences);
+    app.set_accels_for_action("win.preferences", &["<Control>comma"]);
+}
+
+// The windows of the presets and of high resolution exports, which the menu opens as well
+fn add_window_actions(
+    app: &Application,
+    window: &ApplicationWindow,
+    preset_window: &Window,
+    hires_window: HiResWindow,
+) {
+    let presets = gio::SimpleAction::new("presets", None);
+    presets.connect_activate(clone!(@weak preset_window => move |_a, _p| preset_window.present()));
+    window.add_action(&presets);
+    app.set_accels_for_action("win.presets", &["<Control><Shift>p"]);
+    let hires = gio::SimpleAction::new("export-hires", None);
+    hires.connect_activate(move |_a, _p| hires_window.present());
+    window.add_action(&hires);
+    app.set_accels_for_action("win.export-hires", &["<Control><Shift>e"]);
+}
+
+// The title bar, with the primary menu that holds the actions of the main window
+fn build_header_bar() -> gtk::HeaderBar {
+    let export_menu = gio::Menu::new();
+    export_menu.append(Some("Save image…"), Some("win.save-image"));
+    export_menu.append(Some("Export with report…"), Some("win.export-report"));
+    export_menu.append(Some("Export 1-bit mask…"), Some("win.export-mask(true)"));
+    export_menu.append(Some("Export 8-bit mask…"), Some("win.export-mask(false)"));
+    export_menu.append(Some("High resolution…"), Some("win.export-hires"));
+    export_menu.append(Some("Print…"), Some("win.print(false)"));
+    let window_section = gio::Menu::new();
+    window_section.append(Some("New window"), Some("app.new-window"));
+    window_section.append(Some("Fullscreen"), Some("win.fullscreen"));
+    let settings_section = gio::Menu::new();
+    settings_section.append(Some("Presets…"), Some("win.presets"));
+    settings_section.append(Some("Preferences"), Some("win.preferences"));
+    let about_section = gio::Menu::new();
+    about_section.append(Some("About Mandelbrot"), Some("app.about"));
+    let menu = gio::Menu::new();
+    menu.append_submenu(Some("Export"), &export_menu);
+    menu.append_section(None, &window_section);
+    menu.append_section(None, &settings_section);
+    menu.append_section(None, &about_section);
+    let menu_btn = MenuButton::builder()
+        .icon_name("open-menu-symbolic")
+        .tooltip_text("Main menu (F10)")
+        .menu_model(&menu)
+        .primary(true)
+        .build();
+    let header = gtk::HeaderBar::new();
+    header.pack_end(&menu_btn);
+    header
+}
+
+// Show the name, version and purpose of the application
+fn show_about(app: &Application) {
+    let about = gtk::AboutDialog::builder()
+        .program_name("Mandelbrot")
+        .version(env!("CARGO_PKG_VERSION"))
+        .comments("Explore the Mandelbrot set and related fractals")
+        .website("https://webspace.science.uu.nl/~gieze101/gtk-rs-mandelbrot-tutorial/")
+        .website_label("The gtk-rs tutorial")
+        .logo_icon_name("applications-science")
+        .modal(true)
+        .build();
+    about.set_transient_for(app.active_window().as_ref());
+    about.present();
+}
+
+// The main window and what is needed to change its view
+struct MainView {
+    window: ApplicationWindow,
+    state: Rc<RefCell<State>>,
+    controls: Controls,
+}
+
+// Make a main window with its own view and renderer. The presets are shared by the windows.
+fn build_ui(app: &Application, presets: &Rc<RefCell<Presets>>) -> MainView {
+    let (req_sender, req_receiver) = channel(REQUEST_CAPACITY, Policy::LatestWins);
+    let (reply_sender, reply_receiver) = channel(REPLY_CAPACITY, Policy::Queue);
     gio::spawn_blocking(move || mandel_producer(req_receiver, reply_sender));
     let state = Rc::new(RefCell::new(State::new(req_sender)));
-    let colorings;
-    colorings = DropDown::from_strings(&state.borrow().coloring_names());
+    let colorings = DropDown::new(
+        Some(state.borrow().coloring_model().clone()),
+        None::<gtk::Expression>,
+    );
     colorings.set_width_request(120);
-    colorings.set_margin_end(15);
+    colorings.set_margin_end(5);
+    let palette_menu = gio::Menu::new();
+    palette_menu.append(Some("Load palette…"), Some("win.load-palette"));
+    palette_menu.append(Some("Save palette…"), Some("win.save-palette"));
+    palette_menu.append(Some("Save palette strip…"), Some("win.save-palette-strip"));
+    let palette_btn = MenuButton::builder()
+        .label("Palette")
+        .tooltip_text("Load a coloring from a .map, JSON or GIMP .ggr file, or save the colors of the coloring")
+        .menu_model(&palette_menu)
+        .margin_end(15)
+        .build();
     let iter_val = state.borrow().iter_depth();
     let iter_adj = Adjustment::new(iter_val, 10.0, 1000.0, 1.0, 0.0, 0.0);
     let iteration_button = SpinButton::builder().adjustment(&iter_adj).build();
     let preset_btn = Button::builder()
         .label("Choose Preset")
+        .tooltip_text("Show a preset view or add the current one (Ctrl+Shift+P)")
+        .action_name("win.presets")
         .margin_start(15)
         .build();
+    let curves_btn = Button::builder().label("Curves").margin_start(5).build();
+    let gradient_btn = Button::builder().label("Gradient").margin_start(5).build();
+    let inspector_btn = Button::builder().label("Inspector").margin_start(5).build();
+    let lighting_btn = Button::builder().label("Lighting").margin_start(5).build();
+    let depth_anim_btn = Button::builder()
+        .label("Depth animation")
+        .margin_start(5)
+        .build();
+    let zoom_anim_btn = Button::builder()
+        .label("Zoom animation")
+        .margin_start(5)
+        .build();
+    let linked_btn = Button::builder()
+        .label("Linked view")
+        .margin_start(5)
+        .build();
+    let fractal_names: Vec<&str> = FractalType::ALL.iter().map(|t| t.name()).collect();
+    let fractals = DropDown::from_strings(&fractal_names);
+    fractals.set_margin_end(15);
+    let power_adj = Adjustment::new(state.borrow().power(), 2.0, 16.0, 1.0, 0.0, 0.0);
+    let power_button = SpinButton::builder()
+        .adjustment(&power_adj)
+        .margin_end(15)
+        .build();
     let first_row = make_row_box();
+    first_row.append(&Label::new(Some("fractal:")));
+    first_row.append(&fractals);
+    first_row.append(&Label::new(Some("power:")));
+    first_row.append(&power_button);
     first_row.append(&Label::new(Some("coloring:")));
     first_row.append(&colorings);
+    first_row.append(&palette_btn);
     first_row.append(&Label::new(Some("max iterations:")));
     first_row.append(&iteration_button);
     first_row.append(&preset_btn);
-    let cx_value = gtk::Entry::builder()
-        .text(&state.borrow().cx().to_string())
-        .width_chars(15)
-        .margin_end(10)
-        .build();
-    let cy_value = gtk::Entry::builder()
-        .text(&state.borrow().cy().to_string())
-        .width_chars(15)
+    first_row.append(&curves_btn);
+    first_row.append(&gradient_btn);
+    first_row.append(&inspector_btn);
+    first_row.append(&lighting_btn);
+    first_row.append(&linked_btn);
+    first_row.append(&depth_anim_btn);
+    first_row.append(&zoom_anim_btn);
+    let julia_preview = JuliaPreview::new();
+    first_row.append(julia_preview.toggle());
+    let compare_check = gtk::CheckButton::builder()
+        .label("compare with")
+        .tooltip_text(
+            "Show the view next to it in another coloring, without offset, density and curves",
+        )
+        .margin_start(15)
         .build();
+    let compare_colorings = DropDown::new(
+        Some(state.borrow().coloring_model().clone()),
+        None::<gtk::Expression>,
+    );
+    first_row.append(&compare_check);
+    first_row.append(&compare_colorings);
+    let cx_value = gtk::Entry::builder().width_chars(15).margin_end(10).build();
+    let cy_value = gtk::Entry::builder().width_chars(15).build();
     let second_row = make_row_box();
     second_row.append(&Label::new(Some("center x:")));
     second_row.append(&cx_value);
     second_row.append(&Label::new(Some("center y:")));
     second_row.append(&cy_value);
-    let zoom_adj = Adjustment::new(0.0, 0.0, 1000.0, 1.0, 0.0, 0.0);
+    let interior_check = gtk::CheckButton::builder()
+        .label("interior:")
+        .tooltip_text("Give points in the set this color instead of the color of the coloring")
+        .margin_start(15)
+        .build();
+    let interior_btn = gtk::ColorButton::with_rgba(&to_rgba(0x000000));
+    interior_btn.set_use_alpha(false);
+    interior_btn.set_sensitive(false);
+    second_row.append(&interior_check);
+    second_row.append(&interior_btn);
+    let julia_cx_value = gtk::Entry::builder()
+        .text(state.borrow().julia_cx().to_string())
+        .width_chars(15)
+        .margin_start(15)
+        .build();
+    let julia_cy_value = gtk::Entry::builder()
+        .text(state.borrow().julia_cy().to_string())
+        .width_chars(15)
+        .build();
+    second_row.append(&Label::builder().label("julia c:").margin_start(15).build());
+    second_row.append(&julia_cx_value);
+    second_row.append(&julia_cy_value);
+    let new_window_btn = Button::builder()
+        .label("New window")
+        .tooltip_text("Explore in another window, with its own view (Ctrl+N)")
+        .action_name("app.new-window")
+        .margin_start(15)
+        .build();
+    second_row.append(&new_window_btn);
+    let open_btn = Button::builder()
+        .label("Open image…")
+        .tooltip_text("Show the view of a PNG file that was saved by this application (Ctrl+O)")
+        .action_name("win.open-image")
+        .margin_start(15)
+        .build();
+    second_row.append(&open_btn);
+    let save_btn = Button::builder()
+        .label("Save image…")
+        .tooltip_text("Save the image as a PNG file (Ctrl+S)")
+        .action_name("win.save-image")
+        .margin_start(5)
+        .build();
+    second_row.append(&save_btn);
+    let copy_btn = Button::builder()
+        .label("Copy image")
+        .tooltip_text("Put the image on the clipboard (Ctrl+C)")
+        .action_name("win.copy-image")
+        .margin_start(5)
+        .build();
+    second_row.append(&copy_btn);
+    let share_btn = Button::builder()
+        .label("Copy share link")
+        .action_name("win.copy-link")
+        .margin_start(5)
+        .build();
+    second_row.append(&share_btn);
+    let location_menu = gio::Menu::new();
+    location_menu.append(Some("Copy location"), Some("win.copy-location"));
+    location_menu.append(Some("Paste location"), Some("win.paste-location"));
+    let location_btn = MenuButton::builder()
+        .label("Location")
+        .tooltip_text(
+            "Copy the view as short text, or show a pasted one (Ctrl+Shift+C, Ctrl+Shift+V)",
+        )
+        .menu_model(&location_menu)
+        .margin_start(5)
+        .build();
+    second_row.append(&location_btn);
+    let report_btn = Button::builder()
+        .label("Export with report")
+        .tooltip_text("Save the image with a JSON record of how it was rendered")
+        .action_name("win.export-report")
+        .margin_start(5)
+        .build();
+    second_row.append(&report_btn);
+    let mask_menu = gio::Menu::new();
+    mask_menu.append(Some("1-bit mask"), Some("win.export-mask(true)"));
+    mask_menu.append(Some("8-bit mask"), Some("win.export-mask(false)"));
+    let mask_btn = MenuButton::builder()
+        .label("Export mask")
+        .tooltip_text("Save an image that is white in the set and black outside it")
+        .menu_model(&mask_menu)
+        .margin_start(5)
+        .build();
+    second_row.append(&mask_btn);
+    let hires_btn = Button::builder()
+        .label("High resolution…")
+        .tooltip_text("Export the view as an image of any size (Ctrl+Shift+E)")
+        .action_name("win.export-hires")
+        .margin_start(5)
+        .build();
+    second_row.append(&hires_btn);
+    let print_menu = gio::Menu::new();
+    print_menu.append(Some("Print image…"), Some("win.print(false)"));
+    print_menu.append(
+        Some("Print at printer resolution…"),
+        Some("win.print(true)"),
+    );
+    let print_btn = MenuButton::builder()
+        .label("Print")
+        .tooltip_text("Print the view or save it as PDF (Ctrl+P)")
+        .menu_model(&print_menu)
+        .margin_start(5)
+        .build();
+    second_row.append(&print_btn);
+    let workspace_menu = gio::Menu::new();
+    workspace_menu.append(Some("Export workspace…"), Some("win.export-workspace"));
+    workspace_menu.append(Some("Import workspace…"), Some("win.import-workspace"));
+    let workspace_btn = MenuButton::builder()
+        .label("Workspace")
+        .tooltip_text("Share the recent locations, palettes and settings, e.g. with a class")
+        .menu_model(&workspace_menu)
+        .margin_start(5)
+        .build();
+    second_row.append(&workspace_btn);
+    let named = named_formulas();
+    let formula_tooltip = if named.is_empty() {
+        "An expression in z and c".to_string()
+    } else {
+        format!("An expression in z and c, or one of {}", named.join(", "))
+    };
+    let formula_value = gtk::Entry::builder()
+        .text(state.borrow().formula().source())
+        .tooltip_text(formula_tooltip)
+        .width_chars(40)
+        .build();
+    let formula_error = Label::builder().margin_start(10).build();
+    let formula_row = make_row_box();
+    formula_row.append(&Label::new(Some("formula:")));
+    formula_row.append(&formula_value);
+    formula_row.append(&formula_error);
+    let polynomial_value = gtk::Entry::builder()
+        .text(state.borrow().polynomial().to_string())
+        .tooltip_text("The coefficients of the polynomial, highest power first")
+        .width_chars(20)
+        .margin_start(15)
+        .build();
+    let polynomial_error = Label::builder().margin_start(10).build();
+    formula_row.append(
+        &Label::builder()
+            .label("newton polynomial:")
+            .margin_start(15)
+            .build(),
+    );
+    formula_row.append(&polynomial_value);
+    formula_row.append(&polynomial_error);
+    let zoom_adj = Adjustment::new(0.0, 0.0, 2000.0, 1.0, 0.0, 0.0);
     let zoom_bar = Scale::new(Orientation::Horizontal, Some(&zoom_adj));
     zoom_bar.set_hexpand(true);
     let third_row = make_row_box();
+    let back_btn = Button::builder()
+        .icon_name("go-previous-symbolic")
+        .tooltip_text("Show the previous view again (Ctrl+Z)")
+        .action_name("win.undo-view")
+        .build();
+    let forward_btn = Button::builder()
+        .icon_name("go-next-symbolic")
+        .tooltip_text("Show the view that was left by going back (Ctrl+Shift+Z)")
+        .action_name("win.redo-view")
+        .margin_end(10)
+        .build();
+    third_row.append(&back_btn);
+    third_row.append(&forward_btn);
     third_row.append(&Label::new(Some("zoom:")));
     third_row.append(&zoom_bar);
+    let offset_adj = Adjustment::new(0.0, 0.0, 100.0, 0.5, 5.0, 0.0);
+    let density_adj = Adjustment::new(1.0, 0.1, 10.0, 0.05, 0.5, 0.0);
+    for (name, adj, digits) in [
+        ("color offset:", &offset_adj, 1),
+        ("density:", &density_adj, 2),
+    ] {
+        let slider = Scale::builder()
+            .orientation(Orientation::Horizontal)
+            .adjustment(adj)
+            .digits(digits)
+            .draw_value(true)
+            .width_request(150)
+            .build();
+        third_row.append(&Label::builder().label(name).margin_start(15).build());
+        third_row.append(&slider);
+    }
+    let cycle_btn = ToggleButton::builder()
+        .label("Cycle colors")
+        .tooltip_text("Shift the colors continuously")
+        .margin_start(15)
+        .build();
+    third_row.append(&cycle_btn);
+    let dive_btn = ToggleButton::builder()
+        .label("Dive")
+        .tooltip_text("Zoom in on the center until this is switched off")
+        .margin_start(15)
+        .build();
+    third_row.append(&dive_btn);
+    let breadcrumbs_check = gtk::CheckButton::builder()
+        .label("breadcrumbs")
+        .tooltip_text("Mark the centers of the views that were visited before")
+        .margin_start(15)
+        .build();
+    third_row.append(&breadcrumbs_check);
+    let crosshair_check = gtk::CheckButton::builder()
+        .label("crosshair")
+        .tooltip_text("Mark the center of the view with its coordinates")
+        .build();
+    third_row.append(&crosshair_check);
+    let grid_check = gtk::CheckButton::builder()
+        .label("grid")
+        .tooltip_text("Draw the axes and a grid with coordinates that adapts to the zoom")
+        .build();
+    third_row.append(&grid_check);
+    let stats_check = gtk::CheckButton::builder()
+        .label("statistics")
+        .tooltip_text("Show the render time, the iterations and the points at the iteration depth")
+        .build();
+    third_row.append(&stats_check);
+    let calibrate_btn = Button::builder()
+        .label("Calibrate")
+        .tooltip_text("Measure the speed of this computer and adapt the render settings")
+        .build();
+    third_row.append(&calibrate_btn);
+    let preferences_btn = Button::builder()
+        .label("Preferences")
+        .tooltip_text("Change the start view, the threads and the supersampling (Ctrl+,)")
+        .action_name("win.preferences")
+        .margin_start(5)
+        .build();
+    third_row.append(&preferences_btn);
+    let fullscreen_btn = ToggleButton::builder()
+        .label("Fullscreen")
+        .tooltip_text("Fill the screen with the image; the controls show at the top edge (F11)")
+        .action_name("win.fullscreen")
+        .margin_start(5)
+        .build();
+    third_row.append(&fullscreen_btn);
     let canvas = DrawingArea::builder()
         .content_height(WIN_SZ0 as i32)
         .content_width(WIN_SZ0 as i32)
@@ -214,57 +2052,545 @@ fn build_ui(app: &Application) {
         .margin_top(10)
         .margin_bottom(10)
         .build();
-    content_box.append(&first_row);
-    content_box.append(&second_row);
-    content_box.append(&third_row);
-    content_box.append(&canvas);
+    // The rows of controls, which hide in fullscreen mode
+    let rows_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+    rows_box.append(&first_row);
+    rows_box.append(&second_row);
+    rows_box.append(&formula_row);
+    rows_box.append(&third_row);
+    let rows = gtk::Revealer::builder()
+        .child(&rows_box)
+        .reveal_child(true)
+        .build();
+    content_box.append(&rows);
+    let tour = Tour::new();
+    content_box.append(tour.panel());
+    let view_row = make_row_box();
+    canvas.set_hexpand(true);
+    // The minimap is drawn in a corner of the image
+    let canvas_overlay = gtk::Overlay::builder().child(&canvas).build();
+    view_row.append(&canvas_overlay);
+    // The same view in another coloring, for comparing them
+    let compare_canvas = DrawingArea::builder().hexpand(true).visible(false).build();
+    compare_canvas.set_draw_func(
+        clone!(@strong state => move |_d, ctxt, w, h| compare_draw(&state.borrow(), ctxt, w, h)),
+    );
+    state
+        .borrow_mut()
+        .set_compare_canvas(compare_canvas.downgrade());
+    view_row.append(&compare_canvas);
+    view_row.append(julia_preview.panel());
+    let histogram = HistogramPanel::new();
+    view_row.append(histogram.panel());
+    content_box.append(&view_row);
+    let status = StatusBar::new();
+    content_box.append(status.panel());
     let window = ApplicationWindow::builder()
         .application(app)
         .title("Mandelbrot")
         .child(&content_box)
         .build();
+    window.set_titlebar(Some(&build_header_bar()));
+
+    let settings = ViewSettings::new(&state.borrow(), zoom_adj.value());
+    settings.bind_entry("cx", &cx_value);
+    settings.bind_entry("cy", &cy_value);
+    settings.bind_adjustment("zoom", &zoom_adj);
+    settings.bind_iterations(&iter_adj);
+    settings.bind_drop_down("coloring", &colorings);
+    settings.drive(&state);
 
-    let presets = Presets::new();
+    let curves_window = build_curves_window(&state);
+    curves_window.set_transient_for(Some(&window));
+    let region_window = RegionWindow::new(&window);
+    let inspector = Inspector::new(&window);
+    let depth_anim_window = DepthAnimationWindow::new(&window, &state);
+    let hires_window = HiResWindow::new(&window, &state);
+    let lighting_window = LightingWindow::new(&window, &state);
+    let gradient_window = GradientWindow::new(&window, &state, &settings);
+    add_palette_actions(&window, &state, &settings);
+
+    let controls = Controls {
+        settings: settings.clone(),
+        fractals: fractals.clone(),
+        power_adj: power_adj.clone(),
+        julia_cx_value: julia_cx_value.clone(),
+        julia_cy_value: julia_cy_value.clone(),
+        formula_value: formula_value.clone(),
+        polynomial_value: polynomial_value.clone(),
+        jump: Rc::new(RefCell::new(None)),
+        glide: Rc::new(Cell::new(0)),
+    };
+    let zoom_anim_window = ZoomAnimationWindow::new(&window, &state, &controls);
+    let presets = presets.clone();
     let preset_window = build_preset_window(&state, &presets);
     preset_window.set_transient_for(Some(&window));
-    preset_window.connect_hide(
-        clone!(@strong state, @weak zoom_adj, @weak iter_adj, @weak cx_value, @weak cy_value =>
-            move|_w| preset_ready(&state, &cx_value, &cy_value, &zoom_adj, &iter_adj, &presets)),
+    preset_window.connect_hide(clone!(@strong state, @strong controls =>
+            move|_w| preset_ready(&state, &controls, &presets.borrow())));
+    let recent_btn = build_recent_button(&state, &controls);
+    first_row.insert_child_after(&recent_btn, Some(&inspector_btn));
+    let random_btn = build_random_button(&state, &controls);
+    cycle_btn.connect_toggled(clone!(@strong state, @weak offset_adj => move |btn| {
+        if !btn.is_active() {
+            return;
+        }
+        if reduce_motion() {
+            offset_adj.set_value((offset_adj.value() + CYCLE_JUMP) % offset_adj.upper());
+            btn.set_active(false);
+        } else {
+            cycle_colors(&state, btn);
+        }
+    }));
+    breadcrumbs_check.connect_toggled(clone!(@strong state => move |check| {
+        state.borrow_mut().set_show_breadcrumbs(check.is_active());
+    }));
+    crosshair_check.connect_toggled(clone!(@strong state => move |check| {
+        state.borrow_mut().set_show_crosshair(check.is_active());
+    }));
+    grid_check.connect_toggled(clone!(@strong state => move |check| {
+        state.borrow_mut().set_show_grid(check.is_active());
+    }));
+    stats_check.connect_toggled(clone!(@strong state => move |check| {
+        state.borrow_mut().set_show_stats(check.is_active());
+    }));
+    let compare_changed = clone!(@strong state, @weak compare_check, @weak compare_colorings,
+            @weak compare_canvas => move || {
+        let active = compare_check.is_active();
+        compare_canvas.set_visible(active);
+        let col_idx = active.then(|| compare_colorings.selected() as usize);
+        state.borrow_mut().set_compare(col_idx);
+    });
+    compare_check.connect_toggled(clone!(@strong compare_changed => move |_| compare_changed()));
+    compare_colorings.connect_selected_notify(move |_| compare_changed());
+    dive_btn.connect_toggled(
+        clone!(@strong state, @strong controls, @weak zoom_adj => move |btn| {
+            if btn.is_active() && reduce_motion() {
+                let zoom = controls.settings.zoom() + DIVE_JUMP;
+                controls.settings.set_zoom(zoom.min(zoom_adj.upper()));
+                btn.set_active(false);
+            } else if btn.is_active() {
+                dive(&state, &controls, &zoom_adj, btn);
+            } else {
+                state.borrow_mut().set_accumulate(false);
+            }
+        }),
+    );
+    first_row.insert_child_after(&random_btn, Some(&recent_btn));
+    let linked_view = LinkedView::new(
+        &window,
+        clone!(@strong state, @strong controls => move |cx, cy, width| {
+            let win_width = state.borrow().mapping().win_width.max(1);
+            let _late_redraw = postpone_redraw(&state);
+            controls.settings.set_cx(cx);
+            controls.settings.set_cy(cy);
+            controls
+                .settings
+                .set_zoom(zoom_for_scale(width / win_width as f64));
+        }),
     );
+    let minimap = Minimap::new(&state, &controls);
+    canvas_overlay.add_overlay(minimap.area());
+    first_row.insert_child_after(minimap.toggle(), Some(julia_preview.toggle()));
+    first_row.insert_child_after(histogram.toggle(), Some(minimap.toggle()));
+    histogram
+        .toggle()
+        .connect_toggled(clone!(@strong state, @strong histogram => move |_t| {
+            histogram.update(&state.borrow());
+        }));
+    state.borrow_mut().set_view_listener(Box::new(
+        clone!(@strong linked_view, @strong minimap => move |mapping| {
+            linked_view.follow(mapping);
+            minimap.follow();
+        }),
+    ));
 
     // Set actions
-    canvas.set_draw_func(clone!(@strong state =>move |_d, ctxt, _w, _h| mandel_draw(&state, ctxt)));
-    iter_adj.connect_value_changed(clone!(@strong state => move |a| {
-        state.borrow_mut().set_iter_depth(a.value());
-    }));
-    preset_btn
-        .connect_clicked(clone!(@strong preset_window => move |_btn| preset_window.present();));
-    cx_value.connect_changed(
-        clone!(@strong state => move |e| { state.borrow_mut().set_cx(expect_float_value(e));}),
-    );
-    cy_value.connect_changed(
-        clone!(@strong state => move |e| { state.borrow_mut().set_cy(expect_float_value(e));}),
+    canvas.set_draw_func(
+        clone!(@strong state =>move |_d, ctxt, w, h| mandel_draw(&state, ctxt, w, h)),
     );
+    power_adj.connect_value_changed(clone!(@strong state => move |a| {
+        state.borrow_mut().set_power(a.value());
+    }));
+    curves_btn
+        .connect_clicked(clone!(@strong curves_window => move |_btn| curves_window.present();));
+    inspector_btn.connect_clicked(clone!(@strong inspector => move |_btn| inspector.present();));
+    linked_btn.connect_clicked(move |_btn| linked_view.present());
+    lighting_btn.connect_clicked(move |_btn| lighting_window.present());
+    gradient_btn.connect_clicked(move |_btn| gradient_window.present());
+    depth_anim_btn.connect_clicked(move |_btn| depth_anim_window.present());
+    zoom_anim_btn.connect_clicked(move |_btn| zoom_anim_window.present());
     let gesture = gtk::GestureClick::new();
     gesture.set_button(GDK_BUTTON_PRIMARY as u32);
-    gesture.connect_pressed(clone!(@strong state => move |gesture, _, wx, wy| on_clicked(&state, gesture, wx, wy, &cx_value, &cy_value)));
+    gesture.connect_pressed(clone!(@strong state, @strong controls => move |_g, _n, _x, _y| controls.stop_motion(&state)));
+    gesture.connect_released(
+        clone!(@strong state, @strong controls, @strong status => move |gesture, n_press, wx, wy| {
+            if gesture
+                .current_event_state()
+                .contains(gdk::ModifierType::CONTROL_MASK)
+            {
+                show_orbit(&state, &status, wx, wy);
+            } else {
+                on_clicked(&state, gesture, n_press, wx, wy, &controls);
+            }
+        }),
+    );
     canvas.add_controller(gesture);
-    colorings.connect_selected_notify(clone!(@strong state => move |dd| {
-        color_changed(&mut state.borrow_mut(), dd);
+    // Dragging moves the image at once, and the view when the button is released
+    let pan = GestureDrag::new();
+    pan.set_button(GDK_BUTTON_PRIMARY as u32);
+    pan.connect_drag_update(
+        clone!(@strong state => move |_g, dx, dy| state.borrow_mut().set_pan(dx, dy)),
+    );
+    pan.connect_drag_end(
+        clone!(@strong state => move |_g, dx, dy| on_pan_end(&state, dx, dy, &settings)),
+    );
+    canvas.add_controller(pan);
+    let swipe = gtk::GestureSwipe::new();
+    swipe.set_button(GDK_BUTTON_PRIMARY as u32);
+    swipe.connect_swipe(clone!(@strong state, @strong controls => move |_g, vx, vy| glide(&state, &controls, vx, vy)));
+    canvas.add_controller(swipe);
+    let select = GestureDrag::new();
+    select.set_button(GDK_BUTTON_SECONDARY as u32);
+    select.connect_drag_update(
+        clone!(@strong state => move |g, dx, dy| on_select_update(&state, g, dx, dy)),
+    );
+    select.connect_drag_end(
+        clone!(@strong state, @weak fractals, @weak julia_cx_value, @weak julia_cy_value => move |g, dx, dy| {
+            if let Some((wx, wy)) = on_select_end(&state, g, dx, dy, &region_window) {
+                pick_julia_c(&state, wx, wy, &fractals, &julia_cx_value, &julia_cy_value);
+            }
+        }),
+    );
+    canvas.add_controller(select);
+    let motion = EventControllerMotion::new();
+    motion.connect_motion(clone!(@strong state, @strong status => move |_m, wx, wy| {
+        inspector.update(&state.borrow(), wx, wy);
+        julia_preview.update(&state.borrow(), wx, wy);
+        status.update_pointer(&state.borrow(), wx, wy);
+    }));
+    motion.connect_leave(clone!(@strong status => move |_m| status.clear_pointer()));
+    canvas.add_controller(motion);
+    let interior_changed = clone!(@strong state, @weak interior_check, @weak interior_btn => move || {
+        let active = interior_check.is_active();
+        interior_btn.set_sensitive(active);
+        let interior = active.then(|| from_rgba(&interior_btn.rgba()));
+        state.borrow_mut().set_interior(interior);
+    });
+    interior_check.connect_toggled(clone!(@strong interior_changed => move |_| interior_changed()));
+    interior_btn.connect_color_set(move |_| interior_changed());
+    if let Some(interior) = start_interior(&Config::load()) {
+        interior_btn.set_rgba(&to_rgba(interior));
+        interior_check.set_active(true);
+    }
+    let params_changed = clone!(@strong state, @weak offset_adj, @weak density_adj => move || {
+        state.borrow_mut().set_coloring_params(ColoringParams {
+            offset: offset_adj.value(),
+            density: density_adj.value(),
+        });
+    });
+    offset_adj.connect_value_changed(clone!(@strong params_changed => move |_| params_changed()));
+    density_adj.connect_value_changed(move |_| params_changed());
+    fractals.connect_selected_notify(clone!(@strong state => move |dd| {
+        fractal_changed(&mut state.borrow_mut(), dd);
+    }));
+    julia_cx_value.connect_changed(
+        clone!(@strong state => move |e| { state.borrow_mut().set_julia_cx(expect_float_value(e));}),
+    );
+    julia_cy_value.connect_changed(
+        clone!(@strong state => move |e| { state.borrow_mut().set_julia_cy(expect_float_value(e));}),
+    );
+    formula_value.connect_changed(clone!(@strong state => move |e| {
+        formula_changed(&mut state.borrow_mut(), e, &formula_error);
     }));
-    zoom_adj.connect_value_changed(clone!(@strong state => move |adj| {
-        state.borrow_mut().set_zoom(adj.value());
+    polynomial_value.connect_changed(clone!(@strong state => move |e| {
+        polynomial_changed(&mut state.borrow_mut(), e, &polynomial_error);
     }));
     canvas.connect_resize(
         clone!(@strong state => move |_da, w, h| state.borrow_mut().on_resize(w, h)),
     );
-    glib::spawn_future_local(new_image_handler(reply_receiver, state));
+    add_screenshot_action(app, &window, &state);
+    add_open_image_action(app, &window, &state, &controls);
+    add_save_image_action(app, &window, &state);
+    add_copy_image_action(app, &window, &state);
+    add_copy_link_action(&window, &state);
+    add_location_text_actions(app, &window, &state, &controls);
+    add_view_history_actions(app, &window, &state, &controls);
+    add_key_navigation(&window, &state, &controls, &status);
+    add_report_action(&window, &state);
+    add_mask_action(&window, &state);
+    add_print_action(app, &window, &state);
+    add_workspace_actions(&window, &state);
+    calibrate_btn.connect_clicked(clone!(@strong state => move |_| calibrate(&state)));
+    let preferences_window = PreferencesWindow::new(
+        &window,
+        &state,
+        clone!(@strong state, @weak interior_check, @weak interior_btn => move |interior| {
+            if let Some(interior) = interior {
+                interior_btn.set_rgba(&to_rgba(interior));
+            }
+            interior_check.set_active(interior.is_some());
+            state.borrow_mut().set_interior(interior);
+        }),
+    );
+    add_preferences_action(app, &window, preferences_window);
+    add_window_actions(app, &window, &preset_window, hires_window);
+    add_fullscreen_action(app, &window, &content_box, &rows);
+    if Config::load().pixels_per_second.is_none() {
+        calibrate(&state);
+    }
+    // Continue where the previous session stopped
+    if let Some(session) = Session::load() {
+        window.set_default_size(session.width, session.height);
+        if session.maximized {
+            window.maximize();
+        }
+        controls.show_location(&state, &session.location);
+    }
+    window.connect_close_request(clone!(@strong state => move |window| {
+        let session = Session {
+            location: state.borrow().location(),
+            width: window.default_width(),
+            height: window.default_height(),
+            maximized: window.is_maximized(),
+        };
+        if let Err(e) = session.save() {
+            eprintln!("Could not save the session: {}", e);
+        }
+        glib::Propagation::Proceed
+    }));
+    if Config::load().show_tour {
+        tour.start(&state, &controls);
+    }
+    // A view that the user stays at is added to the recent locations
+    glib::timeout_add_seconds_local(
+        1,
+        clone!(@weak state => @default-return glib::ControlFlow::Break, move || {
+            state.borrow_mut().capture_dwelled_view(Instant::now());
+            glib::ControlFlow::Continue
+        }),
+    );
+    glib::spawn_future_local(new_image_handler(
+        reply_receiver,
+        state.clone(),
+        status,
+        histogram,
+    ));
 
     window.present();
+    MainView {
+        window,
+        state,
+        controls,
+    }
+}
+
+// The main windows of the application, most recently made last, and the presets they share
+#[derive(Clone)]
+struct Views {
+    views: Rc<RefCell<Vec<MainView>>>,
+    presets: Rc<RefCell<Presets>>,
+}
+
+impl Views {
+    // Make a new main window. It is forgotten when it is closed.
+    fn add(&self, app: &Application) {
+        let view = build_ui(app, &self.presets);
+        let views = Rc::downgrade(&self.views);
+        view.window.connect_destroy(move |window| {
+            if let Some(views) = views.upgrade() {
+                views.borrow_mut().retain(|v| v.window != *window);
+            }
+        });
+        self.views.borrow_mut().push(view);
+    }
+}
+
+// Show the locations of mandel:// links, or of PNG files saved by this application,
+// in the last main window
+fn open_uris(app: &Application, files: &[gio::File], views: &Views) {
+    if views.views.borrow().is_empty() {
+        views.add(app);
+    }
+    if let Some(view) = views.views.borrow().last() {
+        for file in files {
+            let uri = file.uri();
+            let location = Location::from_uri(&uri)
+                .or_else(|| file.path().and_then(|path| read_image_location(&path).ok()));
+            match location {
+                Some(location) => {
+                    view.state.borrow_mut().remember_location();
+                    view.controls.show_location(&view.state, &location);
+                }
+                None => eprintln!("Not a valid location: {}", uri),
+            }
+        }
+        view.window.present();
+    }
+}
+
+// The options of the command line, which set the view that the window starts with
+fn add_start_options(app: &Application) {
+    let options = [
+        (
+            "cx",
+            glib::OptionArg::Double,
+            "The real part of the center",
+            "X",
+        ),
+        (
+            "cy",
+            glib::OptionArg::Double,
+            "The imaginary part of the center",
+            "Y",
+        ),
+        (
+            "zoom",
+            glib::OptionArg::Double,
+            "The value of the zoom slider",
+            "ZOOM",
+        ),
+        (
+            "iter",
+            glib::OptionArg::Int,
+            "The maximum number of iterations",
+            "N",
+        ),
+        (
+            "coloring",
+            glib::OptionArg::String,
+            "The name of the coloring",
+            "NAME",
+        ),
+        (
+            "preset",
+            glib::OptionArg::String,
+            "Start at a preset, before the other options",
+            "NAME",
+        ),
+    ];
+    for (name, arg, description, arg_description) in options {
+        app.add_main_option(
+            name,
+            glib::Char::from(0),
+            glib::OptionFlags::NONE,
+            arg,
+            description,
+            Some(arg_description),
+        );
+    }
+    app.add_main_option(
+        glib::OPTION_REMAINING.as_str(),
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::StringArray,
+        "",
+        Some("[LINK|IMAGE…]"),
+    );
+}
+
+// Show the view that the options of the command line ask for: a preset, changed by the
+// other options. Without these options the view stays as it is.
+fn apply_start_options(view: &MainView, options: &glib::VariantDict) {
+    let preset = options.lookup::<String>("preset").ok().flatten();
+    let cx = options.lookup::<f64>("cx").ok().flatten();
+    let cy = options.lookup::<f64>("cy").ok().flatten();
+    let mut zoom = options.lookup::<f64>("zoom").ok().flatten();
+    let iter = options.lookup::<i32>("iter").ok().flatten();
+    let coloring = options.lookup::<String>("coloring").ok().flatten();
+    if preset.is_none()
+        && cx.is_none()
+        && cy.is_none()
+        && zoom.is_none()
+        && iter.is_none()
+        && coloring.is_none()
+    {
+        return;
+    }
+    let mut location = view.state.borrow().location();
+    if let Some(name) = preset {
+        let presets = load_presets();
+        match presets
+            .names()
+            .iter()
+            .position(|n| n.eq_ignore_ascii_case(&name))
+        {
+            Some(i) => {
+                let preset = presets.get(i);
+                location.cx = preset.cx();
+                location.cy = preset.cy();
+                location.iteration_depth = preset.iter_depth() as u32;
+                zoom = zoom.or(Some(preset.zoom()));
+            }
+            None => eprintln!("Unknown preset: {}", name),
+        }
+    }
+    location.cx = cx.unwrap_or(location.cx);
+    location.cy = cy.unwrap_or(location.cy);
+    if let Some(zoom) = zoom {
+        location.scale = scale_for_zoom(zoom);
+    }
+    if let Some(iter) = iter {
+        location.iteration_depth = iter.max(1) as u32;
+    }
+    if let Some(name) = coloring {
+        if view.state.borrow().col_idx_of(&name).is_some() {
+            location.coloring = name;
+        } else {
+            eprintln!("Unknown coloring: {}", name);
+        }
+    }
+    view.state.borrow_mut().remember_location();
+    view.controls.show_location(&view.state, &location);
 }
 
 pub fn run() -> glib::ExitCode {
-    let app = Application::builder().application_id(APP_ID).build();
-    app.connect_activate(build_ui);
+    // Plugins register their colorings and formulas before the first window is made
+    for message in load_plugins(&plugin_dir(&glib::user_data_dir())) {
+        eprintln!("{}", message);
+    }
+    let app = Application::builder()
+        .application_id(APP_ID)
+        .flags(gio::ApplicationFlags::HANDLES_OPEN | gio::ApplicationFlags::HANDLES_COMMAND_LINE)
+        .build();
+    add_start_options(&app);
+    let views = Views {
+        views: Rc::new(RefCell::new(Vec::new())),
+        presets: Rc::new(RefCell::new(load_presets())),
+    };
+    let new_window = gio::SimpleAction::new("new-window", None);
+    new_window.connect_activate(clone!(@strong views, @weak app => move |_a, _p| views.add(&app)));
+    app.add_action(&new_window);
+    app.set_accels_for_action("app.new-window", &["<Control>n"]);
+    let about = gio::SimpleAction::new("about", None);
+    about.connect_activate(clone!(@weak app => move |_a, _p| show_about(&app)));
+    app.add_action(&about);
+    app.connect_activate(clone!(@strong views => move |app| views.add(app)));
+    app.connect_open(clone!(@strong views => move |app, files, _hint| {
+        open_uris(app, files, &views);
+    }));
+    // The command line of every start, also when the application already runs
+    app.connect_command_line(clone!(@strong views => move |app, command_line| {
+        let options = command_line.options_dict();
+        let files: Vec<gio::File> = options
+            .lookup::<Vec<String>>(glib::OPTION_REMAINING.as_str())
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+            .iter()
+            .map(|arg| command_line.create_file_for_arg(arg))
+            .collect();
+        if files.is_empty() {
+            app.activate();
+        } else {
+            open_uris(app, &files, &views);
+        }
+        if let Some(view) = views.views.borrow().last() {
+            apply_start_options(view, &options);
+        }
+        0
+    }));
     app.run()
 }
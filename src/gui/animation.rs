@@ -0,0 +1,288 @@
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::thread;
+
+use gtk::glib::clone;
+use gtk::{
+    glib, prelude::*, Adjustment, Button, FileChooserAction, FileChooserNative, Grid, Label,
+    ResponseType, SpinButton, Window,
+};
+
+use crate::animation::{depth_schedule, export_depth_animation};
+use crate::channels::{channel, Policy, PolicySender, EXPORT_EVENT_CAPACITY, EXPORT_JOB_CAPACITY};
+use crate::colorings::Coloring;
+use crate::mandel_image::{CancelToken, Canceller, Mapping};
+
+use super::export::{confirm_render, export_dir, timestamp};
+use super::state::State;
+
+// An export that waits for the export thread
+struct ExportJob {
+    mapping: Mapping,
+    coloring: Box<dyn Coloring>,
+    depths: Vec<u32>,
+    dir: PathBuf,
+    cancel: CancelToken,
+}
+
+// What the export thread reports back
+enum ExportEvent {
+    Started(usize),
+    Progress(usize),
+    Done(io::Result<usize>),
+}
+
+// Export the jobs one after the other, until the window is gone
+fn export_jobs(
+    jobs: async_channel::Receiver<ExportJob>,
+    events: PolicySender<(usize, ExportEvent)>,
+) {
+    while let Ok(job) = jobs.recv_blocking() {
+        let n_frames = job.depths.len();
+        events.send_blocking((n_frames, ExportEvent::Started(jobs.len())));
+        let result = export_depth_animation(
+            &job.mapping,
+            job.coloring.as_ref(),
+            &job.depths,
+            &job.dir,
+            &job.cancel,
+            |n| {
+                events.send_blocking((n_frames, ExportEvent::Progress(n)));
+            },
+        );
+        events.send_blocking((n_frames, ExportEvent::Done(result)));
+    }
+}
+
+#[derive(Clone)]
+/// A window for exporting an animation of the current view with an increasing iteration depth
+pub struct DepthAnimationWindow {
+    state: Rc<RefCell<State>>,
+    win: Window,
+    first_adj: Adjustment,
+    last_adj: Adjustment,
+    frames_adj: Adjustment,
+    dir: Rc<RefCell<PathBuf>>,
+    dir_label: Label,
+    export_btn: Button,
+    stop_btn: Button,
+    status: Label,
+    canceller: Rc<Canceller>,
+    // The token of all exports since the last stop
+    cancel: Rc<RefCell<CancelToken>>,
+    jobs: PolicySender<ExportJob>,
+    // The number of exports that are queued or running
+    pending: Rc<Cell<usize>>,
+}
+
+fn spin_button(adj: &Adjustment) -> SpinButton {
+    SpinButton::builder().adjustment(adj).build()
+}
+
+impl DepthAnimationWindow {
+    pub fn new(parent: &impl IsA<Window>, state: &Rc<RefCell<State>>) -> DepthAnimationWindow {
+        let first_adj = Adjustment::new(1.0, 1.0, 100000.0, 1.0, 0.0, 0.0);
+        let last_adj = Adjustment::new(100.0, 1.0, 100000.0, 10.0, 0.0, 0.0);
+        let frames_adj = Adjustment::new(60.0, 1.0, 10000.0, 1.0, 0.0, 0.0);
+        let dir_label = Label::builder().xalign(0.0).width_chars(40).build();
+        let choose_btn = Button::builder().label("Choose...").build();
+        let export_btn = Button::builder().label("Export").build();
+        let stop_btn = Button::builder()
+            .label("Stop")
+            .sensitive(false)
+            .margin_start(10)
+            .build();
+        let status = Label::builder().xalign(0.0).build();
+        let grid = Grid::builder()
+            .row_spacing(5)
+            .column_spacing(10)
+            .margin_top(20)
+            .margin_start(20)
+            .margin_bottom(20)
+            .margin_end(20)
+            .build();
+        let rows: [(&str, gtk::Widget); 4] = [
+            ("first iteration depth:", spin_button(&first_adj).upcast()),
+            ("last iteration depth:", spin_button(&last_adj).upcast()),
+            ("frames:", spin_button(&frames_adj).upcast()),
+            ("folder:", dir_label.clone().upcast()),
+        ];
+        for (row, (name, widget)) in rows.iter().enumerate() {
+            grid.attach(
+                &Label::builder().label(*name).xalign(1.0).build(),
+                0,
+                row as i32,
+                1,
+                1,
+            );
+            grid.attach(widget, 1, row as i32, 1, 1);
+        }
+        grid.attach(&choose_btn, 2, 3, 1, 1);
+        let button_row = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .margin_top(10)
+            .build();
+        button_row.append(&export_btn);
+        button_row.append(&stop_btn);
+        grid.attach(&button_row, 1, 4, 2, 1);
+        grid.attach(&status, 1, 5, 2, 1);
+        let win = Window::builder()
+            .title("Depth animation")
+            .transient_for(parent)
+            .hide_on_close(true)
+            .resizable(false)
+            .child(&grid)
+            .build();
+        let (jobs, job_receiver) = channel(EXPORT_JOB_CAPACITY, Policy::Queue);
+        let (event_sender, event_receiver) = channel(EXPORT_EVENT_CAPACITY, Policy::Queue);
+        thread::spawn(move || export_jobs(job_receiver, event_sender));
+        let canceller = Rc::new(Canceller::new());
+        let cancel = Rc::new(RefCell::new(canceller.next_token()));
+        let anim_win = DepthAnimationWindow {
+            state: state.clone(),
+            win,
+            first_adj,
+            last_adj,
+            frames_adj,
+            dir: Rc::new(RefCell::new(PathBuf::new())),
+            dir_label,
+            export_btn,
+            stop_btn,
+            status,
+            canceller,
+            cancel,
+            jobs,
+            pending: Rc::new(Cell::new(0)),
+        };
+        glib::spawn_future_local(clone!(@strong anim_win => async move {
+            while let Ok((n_frames, event)) = event_receiver.recv().await {
+                anim_win.show_event(n_frames, event);
+            }
+        }));
+        anim_win
+            .export_btn
+            .connect_clicked(clone!(@strong anim_win => move |_| anim_win.confirm_export()));
+        choose_btn.connect_clicked(clone!(@strong anim_win => move |_| anim_win.choose_dir()));
+        anim_win
+            .stop_btn
+            .connect_clicked(clone!(@strong anim_win => move |_| {
+                // Handing out a new token cancels the running and the queued exports
+                *anim_win.cancel.borrow_mut() = anim_win.canceller.next_token();
+            }));
+        anim_win
+    }
+
+    /// Show the window, with the last depth set to the depth of the current view
+    pub fn present(&self) {
+        if self.pending.get() == 0 {
+            self.last_adj.set_value(self.state.borrow().iter_depth());
+            let name = format!("depth-{}", timestamp().unwrap_or_default());
+            self.set_dir(export_dir().join(name));
+        }
+        self.win.present();
+    }
+
+    fn set_dir(&self, dir: PathBuf) {
+        self.dir_label.set_text(&dir.display().to_string());
+        *self.dir.borrow_mut() = dir;
+    }
+
+    fn choose_dir(&self) {
+        let chooser = FileChooserNative::new(
+            Some("Folder for the frames"),
+            Some(&self.win),
+            FileChooserAction::SelectFolder,
+            Some("Select"),
+            None,
+        );
+        chooser.connect_response(
+            clone!(@strong self as anim_win => move |chooser, response| {
+                if response == ResponseType::Accept {
+                    if let Some(path) = chooser.file().and_then(|f| f.path()) {
+                        anim_win.set_dir(path);
+                    }
+                }
+            }),
+        );
+        chooser.show();
+    }
+
+    fn depths(&self) -> Vec<u32> {
+        depth_schedule(
+            self.first_adj.value() as u32,
+            self.last_adj.value() as u32,
+            self.frames_adj.value() as usize,
+        )
+    }
+
+    // Export, after a confirmation if all the frames together would take long
+    fn confirm_export(&self) {
+        let depths = self.depths();
+        let (pixels, calibration) = {
+            let state = self.state.borrow();
+            let mapping = state.mapping();
+            (
+                (mapping.win_width * mapping.win_height) as f64,
+                state.calibration().clone(),
+            )
+        };
+        // The time of a frame grows about linearly with its depth
+        let mean_depth = depths.iter().map(|&d| d as f64).sum::<f64>() / depths.len().max(1) as f64;
+        confirm_render(
+            &self.win,
+            &calibration,
+            pixels * depths.len() as f64,
+            mean_depth.round() as u32,
+            clone!(@strong self as anim_win => move || anim_win.export(depths.clone())),
+        );
+    }
+
+    fn export(&self, depths: Vec<u32>) {
+        let (mapping, coloring) = {
+            let state = self.state.borrow();
+            (state.mapping().clone(), state.coloring())
+        };
+        let job = ExportJob {
+            mapping,
+            coloring,
+            depths,
+            dir: self.dir.borrow().clone(),
+            cancel: self.cancel.borrow().clone(),
+        };
+        if self.jobs.try_send(job).is_err() {
+            self.status.set_text("the export queue is full");
+            return;
+        }
+        self.pending.set(self.pending.get() + 1);
+        self.stop_btn.set_sensitive(true);
+        if self.pending.get() > 1 {
+            self.status
+                .set_text(&format!("{} exports queued", self.pending.get() - 1));
+        }
+        // The next export goes to a new folder
+        let name = format!("depth-{}", timestamp().unwrap_or_default());
+        self.set_dir(export_dir().join(name));
+    }
+
+    fn show_event(&self, n_frames: usize, event: ExportEvent) {
+        let text = match event {
+            ExportEvent::Started(0) => format!("0 of {} frames", n_frames),
+            ExportEvent::Started(queued) => {
+                format!("0 of {} frames, {} exports queued", n_frames, queued)
+            }
+            ExportEvent::Progress(n) => format!("{} of {} frames", n, n_frames),
+            ExportEvent::Done(result) => {
+                self.pending.set(self.pending.get().saturating_sub(1));
+                self.stop_btn.set_sensitive(self.pending.get() > 0);
+                match result {
+                    Ok(n) if n == n_frames => format!("wrote {} frames", n),
+                    Ok(n) => format!("stopped after {} frames", n),
+                    Err(e) => format!("export failed: {}", e),
+                }
+            }
+        };
+        self.status.set_text(&text);
+    }
+}
@@ -0,0 +1,185 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use gtk::glib::{self, KeyFile, KeyFileFlags};
+
+use crate::mandel_image::DEFAULT_TILE_CACHE_MB;
+
+const CONFIG_DIR: &str = "mandelbrot";
+const CONFIG_FILE: &str = "settings.ini";
+/// The highest supersampling factor of the view
+pub const MAX_SUPERSAMPLING: usize = 4;
+/// The names of the supersampling factors, from 1 to MAX_SUPERSAMPLING
+pub const SUPERSAMPLING_NAMES: [&str; MAX_SUPERSAMPLING] = ["none", "2x2", "3x3", "4x4"];
+
+/// User settings, read from a key file in the user's configuration directory
+pub struct Config {
+    /// The accelerator for saving a screenshot, in the format of gtk::accelerator_parse
+    pub screenshot_accel: String,
+    /// Whether the guided tour is shown at startup
+    pub show_tour: bool,
+    /// The measured rendering speed, see benchmark::measure_speed
+    pub pixels_per_second: Option<f64>,
+    /// Whether clicks are converted at the exact pointer position instead of the pixel corner
+    pub subpixel_clicks: bool,
+    /// The color of the canvas where there is no image, in a format of gdk::RGBA::parse
+    pub background: String,
+    /// The color of lines and text over the image, and of their outline
+    pub overlay_color: String,
+    pub overlay_outline: String,
+    /// The width of lines over the image, in pixels
+    pub overlay_line_width: f64,
+    /// The size of text over the image, in pixels
+    pub overlay_font_size: f64,
+    /// The iteration depth and the name of the coloring of the start view
+    pub default_iterations: u32,
+    pub default_coloring: String,
+    /// The color of the points in the set at the start, empty for the color of the coloring
+    pub interior: String,
+    /// The number of threads that render, 0 for one per core
+    pub worker_threads: usize,
+    /// Every pixel of the view is the average of supersampling x supersampling samples
+    pub supersampling: usize,
+    /// Whether the pixels of high contrast of the view are sampled again after it is shown
+    pub adaptive_antialiasing: bool,
+    /// The name of the render backend, empty for the default
+    pub backend: String,
+    /// The megabytes of recently rendered tiles that are kept, to show their views again
+    pub tile_cache_mb: usize,
+}
+
+impl Config {
+    pub fn defaults() -> Config {
+        Config {
+            screenshot_accel: "F12".to_string(),
+            show_tour: true,
+            pixels_per_second: None,
+            subpixel_clicks: true,
+            background: "#202020".to_string(),
+            overlay_color: "#ffffff".to_string(),
+            overlay_outline: "#000000".to_string(),
+            overlay_line_width: 1.0,
+            overlay_font_size: 12.0,
+            default_iterations: 100,
+            default_coloring: String::new(),
+            interior: String::new(),
+            worker_threads: 0,
+            supersampling: 1,
+            adaptive_antialiasing: false,
+            backend: String::new(),
+            tile_cache_mb: DEFAULT_TILE_CACHE_MB,
+        }
+    }
+
+    pub fn path() -> PathBuf {
+        glib::user_config_dir().join(CONFIG_DIR).join(CONFIG_FILE)
+    }
+
+    /// Read the settings. Settings that are missing or can not be read get their default value.
+    pub fn load() -> Config {
+        Config::load_from(&Config::path())
+    }
+
+    /// Read the settings from another file than that of the user, like load
+    pub fn load_from(path: &Path) -> Config {
+        let mut config = Config::defaults();
+        let key_file = KeyFile::new();
+        if key_file.load_from_file(path, KeyFileFlags::NONE).is_err() {
+            return config;
+        }
+        if let Ok(accel) = key_file.string("shortcuts", "screenshot") {
+            config.screenshot_accel = accel.to_string();
+        }
+        if let Ok(show_tour) = key_file.boolean("startup", "tour") {
+            config.show_tour = show_tour;
+        }
+        if let Ok(speed) = key_file.double("calibration", "pixels_per_second") {
+            config.pixels_per_second = Some(speed);
+        }
+        if let Ok(subpixel) = key_file.boolean("input", "subpixel_clicks") {
+            config.subpixel_clicks = subpixel;
+        }
+        if let Ok(background) = key_file.string("view", "background") {
+            config.background = background.to_string();
+        }
+        if let Ok(color) = key_file.string("overlay", "color") {
+            config.overlay_color = color.to_string();
+        }
+        if let Ok(outline) = key_file.string("overlay", "outline") {
+            config.overlay_outline = outline.to_string();
+        }
+        if let Ok(width) = key_file.double("overlay", "line_width") {
+            config.overlay_line_width = width;
+        }
+        if let Ok(size) = key_file.double("overlay", "font_size") {
+            config.overlay_font_size = size;
+        }
+        if let Ok(iterations) = key_file.uint64("defaults", "iterations") {
+            config.default_iterations = iterations.clamp(1, u32::MAX as u64) as u32;
+        }
+        if let Ok(coloring) = key_file.string("defaults", "coloring") {
+            config.default_coloring = coloring.to_string();
+        }
+        if let Ok(interior) = key_file.string("defaults", "interior") {
+            config.interior = interior.to_string();
+        }
+        if let Ok(threads) = key_file.uint64("rendering", "worker_threads") {
+            config.worker_threads = threads as usize;
+        }
+        if let Ok(factor) = key_file.uint64("rendering", "supersampling") {
+            config.supersampling = (factor as usize).clamp(1, MAX_SUPERSAMPLING);
+        }
+        if let Ok(adaptive) = key_file.boolean("rendering", "adaptive_antialiasing") {
+            config.adaptive_antialiasing = adaptive;
+        }
+        if let Ok(backend) = key_file.string("rendering", "backend") {
+            config.backend = backend.to_string();
+        }
+        if let Ok(mb) = key_file.uint64("rendering", "tile_cache_mb") {
+            config.tile_cache_mb = mb as usize;
+        }
+        config
+    }
+
+    /// Write the settings, keeping the comments and unknown keys in the file
+    pub fn save(&self) -> io::Result<()> {
+        self.save_to(&Config::path())
+    }
+
+    /// Write the settings to another file than that of the user, like save
+    pub fn save_to(&self, path: &Path) -> io::Result<()> {
+        let key_file = KeyFile::new();
+        // A missing file is not a problem
+        let _ = key_file.load_from_file(path, KeyFileFlags::KEEP_COMMENTS);
+        key_file.set_string("shortcuts", "screenshot", &self.screenshot_accel);
+        key_file.set_boolean("startup", "tour", self.show_tour);
+        if let Some(speed) = self.pixels_per_second {
+            key_file.set_double("calibration", "pixels_per_second", speed);
+        }
+        key_file.set_boolean("input", "subpixel_clicks", self.subpixel_clicks);
+        key_file.set_string("view", "background", &self.background);
+        key_file.set_string("overlay", "color", &self.overlay_color);
+        key_file.set_string("overlay", "outline", &self.overlay_outline);
+        key_file.set_double("overlay", "line_width", self.overlay_line_width);
+        key_file.set_double("overlay", "font_size", self.overlay_font_size);
+        key_file.set_uint64("defaults", "iterations", self.default_iterations as u64);
+        key_file.set_string("defaults", "coloring", &self.default_coloring);
+        key_file.set_string("defaults", "interior", &self.interior);
+        key_file.set_uint64("rendering", "worker_threads", self.worker_threads as u64);
+        key_file.set_uint64("rendering", "supersampling", self.supersampling as u64);
+        key_file.set_boolean(
+            "rendering",
+            "adaptive_antialiasing",
+            self.adaptive_antialiasing,
+        );
+        key_file.set_string("rendering", "backend", &self.backend);
+        key_file.set_uint64("rendering", "tile_cache_mb", self.tile_cache_mb as u64);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        key_file
+            .save_to_file(path)
+            .map_err(|e| io::Error::other(e.to_string()))
+    }
+}
@@ -0,0 +1,243 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk::gdk::ffi::GDK_BUTTON_SECONDARY;
+use gtk::glib::clone;
+use gtk::{
+    glib, prelude::*, Button, DrawingArea, DropDown, GestureClick, GestureDrag, Label, Window,
+};
+
+use crate::curves::{Curve, TransferCurves};
+
+use super::state::State;
+
+const EDITOR_SZ: i32 = 256;
+// The distance in pixels within which a click grabs an existing control point
+const GRAB_DIST: f64 = 8.0;
+
+struct CurveEditor {
+    channel: usize,
+    dragged: Option<usize>,
+}
+
+// Convert widget coordinates to a (input, output) channel value pair
+fn to_channel_values(area: &DrawingArea, wx: f64, wy: f64) -> (u8, u8) {
+    let w = area.width().max(1) as f64;
+    let h = area.height().max(1) as f64;
+    let x = (wx / w * 255.0).round().clamp(0.0, 255.0) as u8;
+    let y = ((1.0 - wy / h) * 255.0).round().clamp(0.0, 255.0) as u8;
+    (x, y)
+}
+
+fn nearest_point(curves: &TransferCurves, channel: usize, x: u8, y: u8) -> Option<usize> {
+    let dist = |p: &(u8, u8)| {
+        let dx = p.0 as f64 - x as f64;
+        let dy = p.1 as f64 - y as f64;
+        (dx * dx + dy * dy).sqrt()
+    };
+    curves
+        .channel(channel)
+        .points()
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (i, dist(p)))
+        .filter(|(_, d)| *d <= GRAB_DIST)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+}
+
+fn curves_draw(
+    state: &Rc<RefCell<State>>,
+    editor: &Rc<RefCell<CurveEditor>>,
+    ctxt: &gtk::cairo::Context,
+    w: i32,
+    h: i32,
+) {
+    let (w, h) = (w as f64, h as f64);
+    ctxt.set_source_rgb(0.15, 0.15, 0.15);
+    let _ = ctxt.paint();
+    ctxt.set_source_rgb(0.35, 0.35, 0.35);
+    ctxt.set_line_width(1.0);
+    for i in 1..4 {
+        let f = i as f64 / 4.0;
+        ctxt.move_to(f * w, 0.0);
+        ctxt.line_to(f * w, h);
+        ctxt.move_to(0.0, f * h);
+        ctxt.line_to(w, f * h);
+    }
+    let _ = ctxt.stroke();
+    let channel = editor.borrow().channel;
+    let state = state.borrow();
+    let curve = state.curves().channel(channel);
+    let mut rgb = [0.0; 3];
+    rgb[channel] = 1.0;
+    ctxt.set_source_rgb(rgb[0], rgb[1], rgb[2]);
+    ctxt.set_line_width(2.0);
+    for x in 0..=255u8 {
+        let wx = x as f64 / 255.0 * w;
+        let wy = (1.0 - curve.apply(x) as f64 / 255.0) * h;
+        if x == 0 {
+            ctxt.move_to(wx, wy);
+        } else {
+            ctxt.line_to(wx, wy);
+        }
+    }
+    let _ = ctxt.stroke();
+    ctxt.set_source_rgb(1.0, 1.0, 1.0);
+    for &(x, y) in curve.points() {
+        let wx = x as f64 / 255.0 * w;
+        let wy = (1.0 - y as f64 / 255.0) * h;
+        ctxt.arc(wx, wy, 4.0, 0.0, 2.0 * std::f64::consts::PI);
+        let _ = ctxt.fill();
+    }
+}
+
+fn on_drag_begin(
+    state: &Rc<RefCell<State>>,
+    editor: &Rc<RefCell<CurveEditor>>,
+    area: &DrawingArea,
+    wx: f64,
+    wy: f64,
+) {
+    let (x, y) = to_channel_values(area, wx, wy);
+    let channel = editor.borrow().channel;
+    let mut curves = state.borrow().curves().clone();
+    let idx = match nearest_point(&curves, channel, x, y) {
+        Some(idx) => idx,
+        None => curves.channel_mut(channel).add_point(x, y),
+    };
+    editor.borrow_mut().dragged = Some(idx);
+    state.borrow_mut().set_curves(curves);
+    area.queue_draw();
+}
+
+fn on_drag_update(
+    state: &Rc<RefCell<State>>,
+    editor: &Rc<RefCell<CurveEditor>>,
+    area: &DrawingArea,
+    gesture: &GestureDrag,
+    dx: f64,
+    dy: f64,
+) {
+    let editor = editor.borrow();
+    if let (Some(idx), Some((sx, sy))) = (editor.dragged, gesture.start_point()) {
+        let (x, y) = to_channel_values(area, sx + dx, sy + dy);
+        let mut curves = state.borrow().curves().clone();
+        curves.channel_mut(editor.channel).move_point(idx, x, y);
+        state.borrow_mut().set_curves(curves);
+        area.queue_draw();
+    }
+}
+
+fn on_remove_click(
+    state: &Rc<RefCell<State>>,
+    editor: &Rc<RefCell<CurveEditor>>,
+    area: &DrawingArea,
+    wx: f64,
+    wy: f64,
+) {
+    let (x, y) = to_channel_values(area, wx, wy);
+    let channel = editor.borrow().channel;
+    let mut curves = state.borrow().curves().clone();
+    if let Some(idx) = nearest_point(&curves, channel, x, y) {
+        curves.channel_mut(channel).remove_point(idx);
+        state.borrow_mut().set_curves(curves);
+        area.queue_draw();
+    }
+}
+
+pub fn build_curves_window(state: &Rc<RefCell<State>>) -> Window {
+    let editor = Rc::new(RefCell::new(CurveEditor {
+        channel: 0,
+        dragged: None,
+    }));
+    let channels = DropDown::from_strings(&TransferCurves::CHANNEL_NAMES);
+    let area = DrawingArea::builder()
+        .content_width(EDITOR_SZ)
+        .content_height(EDITOR_SZ)
+        .margin_top(10)
+        .build();
+    let reset_btn = Button::builder().label("Reset channel").build();
+    let reset_all_btn = Button::builder()
+        .label("Reset all")
+        .margin_start(10)
+        .build();
+    let top_row = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+    top_row.append(&Label::new(Some("channel:")));
+    top_row.append(&channels);
+    let bottom_row = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .margin_top(10)
+        .build();
+    bottom_row.append(&reset_btn);
+    bottom_row.append(&reset_all_btn);
+    let content_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .margin_top(20)
+        .margin_start(20)
+        .margin_bottom(20)
+        .margin_end(20)
+        .build();
+    content_box.append(&top_row);
+    content_box.append(&area);
+    content_box.append(&Label::new(Some(
+        "drag to add or move a point, right-click to remove it",
+    )));
+    content_box.append(&bottom_row);
+    let win = Window::builder()
+        .title("Color curves")
+        .resizable(false)
+        .hide_on_close(true)
+        .child(&content_box)
+        .build();
+
+    area.set_draw_func(
+        clone!(@strong state, @strong editor => move |_d, ctxt, w, h| {
+            curves_draw(&state, &editor, ctxt, w, h)
+        }),
+    );
+    channels.connect_selected_notify(clone!(@strong editor, @weak area => move |dd| {
+        editor.borrow_mut().channel = dd.selected() as usize % TransferCurves::CHANNEL_NAMES.len();
+        area.queue_draw();
+    }));
+    let drag = GestureDrag::new();
+    drag.connect_drag_begin(
+        clone!(@strong state, @strong editor, @weak area => move |_g, wx, wy| {
+            on_drag_begin(&state, &editor, &area, wx, wy)
+        }),
+    );
+    drag.connect_drag_update(
+        clone!(@strong state, @strong editor, @weak area => move |g, dx, dy| {
+            on_drag_update(&state, &editor, &area, g, dx, dy)
+        }),
+    );
+    drag.connect_drag_end(clone!(@strong editor => move |_g, _dx, _dy| {
+        editor.borrow_mut().dragged = None;
+    }));
+    area.add_controller(drag);
+    let remove = GestureClick::new();
+    remove.set_button(GDK_BUTTON_SECONDARY as u32);
+    remove.connect_pressed(
+        clone!(@strong state, @strong editor, @weak area => move |_g, _n, wx, wy| {
+            on_remove_click(&state, &editor, &area, wx, wy)
+        }),
+    );
+    area.add_controller(remove);
+    reset_btn.connect_clicked(
+        clone!(@strong state, @strong editor, @weak area => move |_| {
+            let channel = editor.borrow().channel;
+            let mut curves = state.borrow().curves().clone();
+            *curves.channel_mut(channel) = Curve::identity();
+            state.borrow_mut().set_curves(curves);
+            area.queue_draw();
+        }),
+    );
+    reset_all_btn.connect_clicked(clone!(@strong state, @weak area => move |_| {
+        state.borrow_mut().set_curves(TransferCurves::identity());
+        area.queue_draw();
+    }));
+    win
+}
@@ -0,0 +1,215 @@
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+use gtk::glib::clone;
+use gtk::{gio, glib, prelude::*, ButtonsType, MessageDialog, MessageType, ResponseType, Window};
+
+use crate::benchmark::{format_duration, Calibration, CONFIRM_SECONDS};
+use crate::mandel_image::{
+    make_mask, new_pool, sample_histogram, worker_threads, CancelToken, Mapping,
+};
+use crate::perturbation::Precision;
+use crate::png::{write_mask_png, write_rgb24_png};
+use crate::report::{RenderReport, Statistics};
+
+use super::state::State;
+
+const SCREENSHOT_DIR: &str = "mandelbrot";
+
+/// Write the current image as a PNG file, with the view parameters as text chunks
+pub fn write_view_png(state: &State, path: &Path) -> io::Result<()> {
+    let img = match state.img() {
+        Some(img) => img,
+        None => return Err(io::Error::other("there is no image yet")),
+    };
+    let text = state.location().params();
+    let out = BufWriter::new(File::create(path)?);
+    write_rgb24_png(
+        out,
+        img.data(),
+        img.width(),
+        img.height(),
+        img.stride(),
+        &text,
+    )?;
+    Ok(())
+}
+
+/// The directory in which exported images are saved by default
+pub fn export_dir() -> PathBuf {
+    let pictures = glib::user_special_dir(glib::UserDirectory::Pictures)
+        .unwrap_or_else(|| glib::home_dir().join("Pictures"));
+    pictures.join(SCREENSHOT_DIR)
+}
+
+/// The current local time, for use in file names
+pub fn timestamp() -> io::Result<String> {
+    glib::DateTime::now_local()
+        .and_then(|t| t.format("%Y%m%d-%H%M%S"))
+        .map(|s| s.to_string())
+        .map_err(|e| io::Error::other(e.to_string()))
+}
+
+/// Save the current image with a timestamped name in the screenshot directory
+pub fn save_screenshot(state: &State) -> io::Result<PathBuf> {
+    let dir = export_dir();
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("mandelbrot-{}.png", timestamp()?));
+    write_view_png(state, &path)?;
+    Ok(path)
+}
+
+/// The render report of the current image, without the statistics, which take a while
+pub fn render_report(state: &State, image: Option<&Path>) -> RenderReport {
+    let mapping = state.mapping();
+    RenderReport {
+        location: state.location(),
+        width: mapping.win_width,
+        height: mapping.win_height,
+        lighting: state.lighting(),
+        curves: state.curves().clone(),
+        passes: state.passes().to_vec(),
+        statistics: None,
+        backend: state.backend().name(),
+        precision: Precision::for_mapping(mapping).name(),
+        threads: worker_threads(),
+        supersampling: state.supersampling(),
+        adaptive_antialiasing: state.adaptive_antialiasing(),
+        image: image
+            .and_then(|path| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned()),
+    }
+}
+
+// Add the statistics of the view of mapping to a report and write it next to the image
+fn write_report(mut report: RenderReport, mapping: &Mapping, image: &Path) -> io::Result<PathBuf> {
+    report.statistics = sample_histogram(mapping, &mut new_pool(), &CancelToken::never())
+        .map(|histogram| Statistics::from_histogram(&histogram));
+    let path = image.with_extension("json");
+    report.write(&path)?;
+    Ok(path)
+}
+
+/// Save a screenshot with its render report next to it. The statistics of the report are
+/// computed in the background, and the result is shown in a dialog over parent.
+pub fn save_with_report(parent: &impl IsA<Window>, state: &State) {
+    let parent: Window = parent.clone().upcast();
+    let image = match save_screenshot(state) {
+        Ok(image) => image,
+        Err(e) => return show_error(&parent, "Could not save image with report", e),
+    };
+    let report = render_report(state, Some(&image));
+    let mapping = state.mapping().clone();
+    glib::spawn_future_local(async move {
+        let result = gio::spawn_blocking(clone!(@strong image => move || {
+            write_report(report, &mapping, &image)
+        }))
+        .await
+        .unwrap_or_else(|_| Err(io::Error::other("the statistics could not be computed")));
+        match result {
+            Ok(path) => show_info(
+                &parent,
+                "Saved image with report",
+                &format!("{}\n{}", image.display(), path.display()),
+            ),
+            Err(e) => show_error(&parent, "Could not save the render report", e),
+        }
+    });
+}
+
+fn write_mask(mapping: &Mapping, bilevel: bool, text: &[(&str, String)]) -> io::Result<PathBuf> {
+    let mask = make_mask(mapping, &mut new_pool(), &CancelToken::never())
+        .ok_or_else(|| io::Error::other("the mask could not be rendered"))?;
+    let dir = export_dir();
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("mandelbrot-mask-{}.png", timestamp()?));
+    let out = BufWriter::new(File::create(&path)?);
+    write_mask_png(
+        out,
+        &mask,
+        mapping.win_width,
+        mapping.win_height,
+        bilevel,
+        text,
+    )?;
+    Ok(path)
+}
+
+/// Save a mask of the current view in the screenshot directory: white for points in the set,
+/// black for points that escape. A bilevel mask has one bit per pixel, otherwise a byte.
+/// The mask is rendered in the background at the size of the view, and the result is shown
+/// in a dialog over parent.
+pub fn save_mask(parent: &impl IsA<Window>, state: &State, bilevel: bool) {
+    let parent: Window = parent.clone().upcast();
+    let mapping = state.mapping().clone();
+    let text = state.location().params();
+    glib::spawn_future_local(async move {
+        match gio::spawn_blocking(move || write_mask(&mapping, bilevel, &text)).await {
+            Ok(Ok(path)) => show_info(&parent, "Saved mask", &path.display().to_string()),
+            Ok(Err(e)) => show_error(&parent, "Could not save mask", e),
+            Err(_) => show_error(&parent, "Could not save mask", "the render failed"),
+        }
+    });
+}
+
+/// Call start for a render of samples at iteration_depth, but ask first if the calibration
+/// estimates that it takes longer than CONFIRM_SECONDS. Without a measured speed there is
+/// no estimate and the render starts right away.
+pub fn confirm_render(
+    parent: &impl IsA<Window>,
+    calibration: &Calibration,
+    samples: f64,
+    iteration_depth: u32,
+    start: impl Fn() + 'static,
+) {
+    let seconds = match calibration.estimate_seconds(samples, iteration_depth) {
+        Some(seconds) if seconds > CONFIRM_SECONDS => seconds,
+        _ => return start(),
+    };
+    let dialog = MessageDialog::builder()
+        .transient_for(parent)
+        .modal(true)
+        .message_type(MessageType::Question)
+        .buttons(ButtonsType::OkCancel)
+        .text("This render takes long")
+        .secondary_text(format!(
+            "Rendering {:.0} megapixels at {} iterations is estimated to take {}. Start anyway?",
+            samples / 1e6,
+            iteration_depth,
+            format_duration(seconds)
+        ))
+        .build();
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Ok {
+            start();
+        }
+        dialog.destroy();
+    });
+    dialog.present();
+}
+
+// Show a message with the details under it in a dialog over parent, until it is closed
+fn show_message(parent: &impl IsA<Window>, message_type: MessageType, text: &str, detail: &str) {
+    let dialog = MessageDialog::builder()
+        .transient_for(parent)
+        .modal(true)
+        .message_type(message_type)
+        .buttons(ButtonsType::Close)
+        .text(text)
+        .secondary_text(detail)
+        .build();
+    dialog.connect_response(|dialog, _response| dialog.destroy());
+    dialog.present();
+}
+
+/// Tell the user what was done, like the file that was saved
+pub fn show_info(parent: &impl IsA<Window>, text: &str, detail: &str) {
+    show_message(parent, MessageType::Info, text, detail);
+}
+
+/// Tell the user that something failed, and why
+pub fn show_error(parent: &impl IsA<Window>, text: &str, error: impl Display) {
+    show_message(parent, MessageType::Error, text, &error.to_string());
+}
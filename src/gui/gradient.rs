@@ -0,0 +1,278 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk::gdk::ffi::GDK_BUTTON_SECONDARY;
+use gtk::gdk::RGBA;
+use gtk::glib::clone;
+use gtk::{
+    prelude::*, Button, ColorButton, DrawingArea, Entry, GestureClick, GestureDrag, Label,
+    Orientation, Window,
+};
+
+use crate::colorings::GradientColoring;
+
+use super::state::State;
+use super::view_settings::ViewSettings;
+
+const STRIP_WIDTH: i32 = 400;
+const STRIP_HEIGHT: f64 = 40.0;
+const MARKER_HEIGHT: f64 = 16.0;
+// The distance in pixels within which a click grabs an existing stop
+const GRAB_DIST: f64 = 8.0;
+// The number of stops that a gradient keeps at least
+const MIN_STOPS: usize = 2;
+
+const DEFAULT_STOPS: [(f64, u32); 4] = [
+    (0.0, 0x000764),
+    (0.25, 0x206bcb),
+    (0.5, 0xedffff),
+    (0.75, 0xffaa00),
+];
+
+struct GradientEditor {
+    // The stops in the order of their color buttons
+    stops: Vec<(f64, u32)>,
+    dragged: Option<usize>,
+}
+
+/// Convert a color in GTK RGB-format to an opaque RGBA
+pub fn to_rgba(color: u32) -> RGBA {
+    let channel = |shift: u32| (color >> shift & 0xff) as f32 / 255.0;
+    RGBA::new(channel(16), channel(8), channel(0), 1.0)
+}
+
+/// Convert an RGBA to GTK RGB-format, ignoring the alpha
+pub fn from_rgba(rgba: &RGBA) -> u32 {
+    let channel = |v: f32| (v * 255.0).round().clamp(0.0, 255.0) as u32;
+    channel(rgba.red()) << 16 | channel(rgba.green()) << 8 | channel(rgba.blue())
+}
+
+fn set_source_color(ctxt: &gtk::cairo::Context, color: u32) {
+    let rgba = to_rgba(color);
+    ctxt.set_source_rgb(rgba.red() as f64, rgba.green() as f64, rgba.blue() as f64);
+}
+
+// The position in the gradient of a widget x coordinate
+fn to_position(area: &DrawingArea, wx: f64) -> f64 {
+    (wx / area.width().max(1) as f64).clamp(0.0, 1.0)
+}
+
+fn nearest_stop(stops: &[(f64, u32)], area: &DrawingArea, wx: f64) -> Option<usize> {
+    let w = area.width().max(1) as f64;
+    stops
+        .iter()
+        .enumerate()
+        .map(|(i, (t, _))| (i, (t * w - wx).abs()))
+        .filter(|(_, d)| *d <= GRAB_DIST)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+}
+
+fn gradient_draw(editor: &Rc<RefCell<GradientEditor>>, ctxt: &gtk::cairo::Context, w: i32) {
+    let editor = editor.borrow();
+    let gradient = GradientColoring::new("", &editor.stops);
+    for x in 0..w {
+        set_source_color(ctxt, gradient.color_at(x as f64 / w as f64));
+        ctxt.rectangle(x as f64, 0.0, 1.0, STRIP_HEIGHT);
+        let _ = ctxt.fill();
+    }
+    ctxt.set_line_width(1.0);
+    for (i, &(t, color)) in editor.stops.iter().enumerate() {
+        let x = t * w as f64;
+        ctxt.move_to(x, STRIP_HEIGHT);
+        ctxt.line_to(x - MARKER_HEIGHT / 2.0, STRIP_HEIGHT + MARKER_HEIGHT);
+        ctxt.line_to(x + MARKER_HEIGHT / 2.0, STRIP_HEIGHT + MARKER_HEIGHT);
+        ctxt.close_path();
+        set_source_color(ctxt, color);
+        let _ = ctxt.fill_preserve();
+        if editor.dragged == Some(i) {
+            ctxt.set_source_rgb(1.0, 1.0, 1.0);
+        } else {
+            ctxt.set_source_rgb(0.0, 0.0, 0.0);
+        }
+        let _ = ctxt.stroke();
+    }
+}
+
+#[derive(Clone)]
+/// A window to make a coloring from a gradient with color stops
+pub struct GradientWindow {
+    state: Rc<RefCell<State>>,
+    settings: ViewSettings,
+    win: Window,
+    editor: Rc<RefCell<GradientEditor>>,
+    area: DrawingArea,
+    color_buttons: gtk::Box,
+    name_value: Entry,
+}
+
+impl GradientWindow {
+    pub fn new(
+        parent: &impl IsA<Window>,
+        state: &Rc<RefCell<State>>,
+        settings: &ViewSettings,
+    ) -> GradientWindow {
+        let editor = Rc::new(RefCell::new(GradientEditor {
+            stops: DEFAULT_STOPS.to_vec(),
+            dragged: None,
+        }));
+        let area = DrawingArea::builder()
+            .content_width(STRIP_WIDTH)
+            .content_height((STRIP_HEIGHT + MARKER_HEIGHT) as i32)
+            .build();
+        let color_buttons = gtk::Box::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(5)
+            .margin_top(10)
+            .build();
+        let name_value = Entry::builder().text("1").width_chars(15).build();
+        let use_btn = Button::builder()
+            .label("Use coloring")
+            .margin_start(15)
+            .build();
+        let name_row = gtk::Box::builder()
+            .orientation(Orientation::Horizontal)
+            .margin_top(10)
+            .build();
+        name_row.append(&Label::new(Some("name: gradient-")));
+        name_row.append(&name_value);
+        name_row.append(&use_btn);
+        let content_box = gtk::Box::builder()
+            .orientation(Orientation::Vertical)
+            .margin_top(20)
+            .margin_start(20)
+            .margin_bottom(20)
+            .margin_end(20)
+            .build();
+        content_box.append(&area);
+        content_box.append(&Label::new(Some(
+            "drag to add or move a stop, right-click to remove it",
+        )));
+        content_box.append(&color_buttons);
+        content_box.append(&name_row);
+        let win = Window::builder()
+            .title("Gradient")
+            .transient_for(parent)
+            .hide_on_close(true)
+            .resizable(false)
+            .child(&content_box)
+            .build();
+        let gradient_win = GradientWindow {
+            state: state.clone(),
+            settings: settings.clone(),
+            win,
+            editor,
+            area,
+            color_buttons,
+            name_value,
+        };
+        gradient_win.show_color_buttons();
+
+        let area = &gradient_win.area;
+        area.set_draw_func(clone!(@strong gradient_win => move |_d, ctxt, w, _h| {
+            gradient_draw(&gradient_win.editor, ctxt, w)
+        }));
+        let drag = GestureDrag::new();
+        drag.connect_drag_begin(clone!(@strong gradient_win => move |_g, wx, _wy| {
+            gradient_win.on_drag_begin(wx)
+        }));
+        drag.connect_drag_update(clone!(@strong gradient_win => move |g, dx, _dy| {
+            gradient_win.on_drag_update(g, dx)
+        }));
+        drag.connect_drag_end(clone!(@strong gradient_win => move |_g, _dx, _dy| {
+            gradient_win.on_drag_end()
+        }));
+        area.add_controller(drag);
+        let remove = GestureClick::new();
+        remove.set_button(GDK_BUTTON_SECONDARY as u32);
+        remove.connect_pressed(clone!(@strong gradient_win => move |_g, _n, wx, _wy| {
+            gradient_win.on_remove_click(wx)
+        }));
+        area.add_controller(remove);
+        use_btn
+            .connect_clicked(clone!(@strong gradient_win => move |_| gradient_win.use_coloring()));
+        gradient_win
+    }
+
+    pub fn present(&self) {
+        self.win.present();
+    }
+
+    // Show a color button for every stop
+    fn show_color_buttons(&self) {
+        while let Some(child) = self.color_buttons.first_child() {
+            self.color_buttons.remove(&child);
+        }
+        for (i, &(_, color)) in self.editor.borrow().stops.iter().enumerate() {
+            let btn = ColorButton::with_rgba(&to_rgba(color));
+            btn.set_use_alpha(false);
+            btn.connect_color_set(clone!(@strong self as gradient_win => move |btn| {
+                gradient_win.editor.borrow_mut().stops[i].1 = from_rgba(&btn.rgba());
+                gradient_win.area.queue_draw();
+            }));
+            self.color_buttons.append(&btn);
+        }
+    }
+
+    fn on_drag_begin(&self, wx: f64) {
+        let mut editor = self.editor.borrow_mut();
+        let (idx, added) = match nearest_stop(&editor.stops, &self.area, wx) {
+            Some(idx) => (idx, false),
+            None => {
+                // A new stop gets the color that the gradient has there
+                let t = to_position(&self.area, wx);
+                let color = GradientColoring::new("", &editor.stops).color_at(t);
+                editor.stops.push((t, color));
+                (editor.stops.len() - 1, true)
+            }
+        };
+        editor.dragged = Some(idx);
+        drop(editor);
+        if added {
+            self.show_color_buttons();
+        }
+        self.area.queue_draw();
+    }
+
+    fn on_drag_update(&self, gesture: &GestureDrag, dx: f64) {
+        let mut editor = self.editor.borrow_mut();
+        if let (Some(idx), Some((sx, _sy))) = (editor.dragged, gesture.start_point()) {
+            editor.stops[idx].0 = to_position(&self.area, sx + dx);
+            self.area.queue_draw();
+        }
+    }
+
+    fn on_drag_end(&self) {
+        // The color buttons are in the order of the stops
+        let mut editor = self.editor.borrow_mut();
+        editor.dragged = None;
+        editor.stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        drop(editor);
+        self.show_color_buttons();
+        self.area.queue_draw();
+    }
+
+    fn on_remove_click(&self, wx: f64) {
+        let mut editor = self.editor.borrow_mut();
+        if editor.stops.len() <= MIN_STOPS {
+            return;
+        }
+        if let Some(idx) = nearest_stop(&editor.stops, &self.area, wx) {
+            editor.stops.remove(idx);
+            drop(editor);
+            self.show_color_buttons();
+            self.area.queue_draw();
+        }
+    }
+
+    // Add the gradient to the colorings, or replace the one with the same name, and show it
+    fn use_coloring(&self) {
+        let name = match self.name_value.text().trim() {
+            "" => "gradient".to_string(),
+            name => format!("gradient-{}", name),
+        };
+        let coloring = GradientColoring::new(&name, &self.editor.borrow().stops);
+        let idx = self.state.borrow_mut().add_coloring(Box::new(coloring));
+        self.settings.set_coloring(idx as u32);
+    }
+}
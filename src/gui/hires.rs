@@ -0,0 +1,254 @@
+use std::cell::{Cell, RefCell};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use gtk::glib::clone;
+use gtk::{
+    gio, glib, prelude::*, Adjustment, Button, DropDown, FileChooserAction, FileChooserNative,
+    Grid, Label, ProgressBar, ResponseType, SpinButton, Window,
+};
+
+use crate::channels::{channel, Policy};
+use crate::export::{export_mapping, ExportJob};
+use crate::location::Location;
+use crate::mandel_image::{new_pool, CancelToken, Canceller};
+
+use super::config::{MAX_SUPERSAMPLING, SUPERSAMPLING_NAMES};
+use super::export::{confirm_render, export_dir, show_error, timestamp};
+use super::state::State;
+
+// The size of an exported image, until the user chooses another. This is 8K UHD.
+const EXPORT_WIDTH: f64 = 7680.0;
+const EXPORT_HEIGHT: f64 = 4320.0;
+const MAX_EXPORT_SZ: f64 = 100000.0;
+
+#[derive(Clone)]
+/// A window for exporting the current view as an image of any size, independent of the
+/// size of the main window
+pub struct HiResWindow {
+    state: Rc<RefCell<State>>,
+    win: Window,
+    width_adj: Adjustment,
+    height_adj: Adjustment,
+    iter_adj: Adjustment,
+    aa_choice: DropDown,
+    export_btn: Button,
+    cancel_btn: Button,
+    progress: ProgressBar,
+    status: Label,
+    canceller: Rc<Canceller>,
+    running: Rc<Cell<bool>>,
+}
+
+fn spin_button(adj: &Adjustment) -> SpinButton {
+    SpinButton::builder().adjustment(adj).build()
+}
+
+fn write_png(
+    job: &ExportJob,
+    path: &Path,
+    text: &[(&str, String)],
+    cancel: &CancelToken,
+    progress: impl FnMut(f64),
+) -> io::Result<()> {
+    let out = BufWriter::new(File::create(path)?);
+    job.write_png(out, text, &mut new_pool(), cancel, progress)?
+        .flush()
+}
+
+impl HiResWindow {
+    pub fn new(parent: &impl IsA<Window>, state: &Rc<RefCell<State>>) -> HiResWindow {
+        let width_adj = Adjustment::new(EXPORT_WIDTH, 1.0, MAX_EXPORT_SZ, 1.0, 0.0, 0.0);
+        let height_adj = Adjustment::new(EXPORT_HEIGHT, 1.0, MAX_EXPORT_SZ, 1.0, 0.0, 0.0);
+        let iter_adj = Adjustment::new(400.0, 10.0, 100000.0, 10.0, 0.0, 0.0);
+        // The export starts with the supersampling of the view
+        let aa_choice = DropDown::from_strings(&SUPERSAMPLING_NAMES);
+        aa_choice
+            .set_selected(state.borrow().supersampling().clamp(1, MAX_SUPERSAMPLING) as u32 - 1);
+        let export_btn = Button::builder().label("Export...").build();
+        let cancel_btn = Button::builder()
+            .label("Cancel")
+            .sensitive(false)
+            .margin_start(10)
+            .build();
+        let progress = ProgressBar::builder().show_text(true).build();
+        let status = Label::builder().xalign(0.0).build();
+        let grid = Grid::builder()
+            .row_spacing(5)
+            .column_spacing(10)
+            .margin_top(20)
+            .margin_start(20)
+            .margin_bottom(20)
+            .margin_end(20)
+            .build();
+        let rows: [(&str, gtk::Widget); 4] = [
+            ("width:", spin_button(&width_adj).upcast()),
+            ("height:", spin_button(&height_adj).upcast()),
+            ("max iterations:", spin_button(&iter_adj).upcast()),
+            ("anti-aliasing:", aa_choice.clone().upcast()),
+        ];
+        for (row, (name, widget)) in rows.iter().enumerate() {
+            grid.attach(
+                &Label::builder().label(*name).xalign(1.0).build(),
+                0,
+                row as i32,
+                1,
+                1,
+            );
+            grid.attach(widget, 1, row as i32, 1, 1);
+        }
+        let button_row = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .margin_top(10)
+            .build();
+        button_row.append(&export_btn);
+        button_row.append(&cancel_btn);
+        grid.attach(&button_row, 1, 4, 1, 1);
+        grid.attach(&progress, 0, 5, 2, 1);
+        grid.attach(&status, 0, 6, 2, 1);
+        let win = Window::builder()
+            .title("High resolution export")
+            .transient_for(parent)
+            .hide_on_close(true)
+            .resizable(false)
+            .child(&grid)
+            .build();
+        let hires_win = HiResWindow {
+            state: state.clone(),
+            win,
+            width_adj,
+            height_adj,
+            iter_adj,
+            aa_choice,
+            export_btn,
+            cancel_btn,
+            progress,
+            status,
+            canceller: Rc::new(Canceller::new()),
+            running: Rc::new(Cell::new(false)),
+        };
+        hires_win
+            .export_btn
+            .connect_clicked(clone!(@strong hires_win => move |_| hires_win.choose_file()));
+        hires_win
+            .cancel_btn
+            .connect_clicked(clone!(@strong hires_win => move |_| {
+                // Handing out a new token cancels the running export
+                hires_win.canceller.next_token();
+            }));
+        hires_win
+    }
+
+    /// Show the window, with the iteration depth set to that of the current view
+    pub fn present(&self) {
+        if !self.running.get() {
+            self.iter_adj.set_value(self.state.borrow().iter_depth());
+        }
+        self.win.present();
+    }
+
+    fn choose_file(&self) {
+        let chooser = FileChooserNative::new(
+            Some("Export image"),
+            Some(&self.win),
+            FileChooserAction::Save,
+            Some("Export"),
+            None,
+        );
+        let _ = chooser.set_current_folder(Some(&gio::File::for_path(export_dir())));
+        let name = format!("mandelbrot-{}.png", timestamp().unwrap_or_default());
+        chooser.set_current_name(&name);
+        chooser.connect_response(
+            clone!(@strong self as hires_win => move |chooser, response| {
+                if response == ResponseType::Accept {
+                    if let Some(path) = chooser.file().and_then(|f| f.path()) {
+                        hires_win.confirm_export(path);
+                    }
+                }
+            }),
+        );
+        chooser.show();
+    }
+
+    fn job(&self) -> ExportJob {
+        let state = self.state.borrow();
+        ExportJob {
+            mapping: export_mapping(
+                state.mapping(),
+                self.width_adj.value() as usize,
+                self.height_adj.value() as usize,
+                self.iter_adj.value() as u32,
+            ),
+            coloring: state.coloring(),
+            factor: (self.aa_choice.selected() as usize).min(MAX_SUPERSAMPLING - 1) + 1,
+            backend: state.backend(),
+        }
+    }
+
+    // Export to path, after a confirmation if the export would take long
+    fn confirm_export(&self, path: PathBuf) {
+        let job = self.job();
+        let mapping = &job.mapping;
+        let samples = (mapping.win_width * mapping.win_height * job.factor * job.factor) as f64;
+        let calibration = self.state.borrow().calibration().clone();
+        confirm_render(
+            &self.win,
+            &calibration,
+            samples,
+            mapping.iteration_depth,
+            clone!(@strong self as hires_win => move || hires_win.export(path.clone())),
+        );
+    }
+
+    fn export(&self, path: PathBuf) {
+        let job = self.job();
+        let text = Location::new(&job.mapping, job.coloring.name()).params();
+        let cancel = self.canceller.next_token();
+        self.running.set(true);
+        self.export_btn.set_sensitive(false);
+        self.cancel_btn.set_sensitive(true);
+        self.progress.set_fraction(0.0);
+        self.status
+            .set_text(&format!("exporting to {}", path.display()));
+        // Only the latest progress is of interest
+        let (progress, progress_receiver) = channel(1, Policy::LatestWins);
+        glib::spawn_future_local(clone!(@strong self as hires_win => async move {
+            while let Ok(fraction) = progress_receiver.recv().await {
+                hires_win.progress.set_fraction(fraction);
+            }
+        }));
+        let hires_win = self.clone();
+        glib::spawn_future_local(async move {
+            let result = gio::spawn_blocking(clone!(@strong path => move || {
+                write_png(&job, &path, &text, &cancel, |fraction| {
+                    progress.send_blocking(fraction);
+                })
+            }))
+            .await
+            .unwrap_or_else(|_| Err(io::Error::other("the export thread panicked")));
+            hires_win.running.set(false);
+            hires_win.export_btn.set_sensitive(true);
+            hires_win.cancel_btn.set_sensitive(false);
+            let text = match result {
+                Ok(()) => format!("wrote {}", path.display()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    // Don't leave a half written image behind
+                    let _ = fs::remove_file(&path);
+                    "cancelled".to_string()
+                }
+                Err(e) => {
+                    let text = format!("export failed: {}", e);
+                    show_error(
+                        &hires_win.win,
+                        &format!("Could not export {}", path.display()),
+                        e,
+                    );
+                    text
+                }
+            };
+            hires_win.status.set_text(&text);
+        });
+    }
+}
@@ -0,0 +1,132 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk::glib::clone;
+use gtk::{prelude::*, CheckButton, DrawingArea, Label};
+
+use super::gradient::to_rgba;
+use super::state::State;
+
+const HISTOGRAM_WIDTH: i32 = 256;
+const HISTOGRAM_HEIGHT: i32 = 150;
+
+// The bars of the histogram, with the number of pixels and the color of the first value of
+// every bar, and the share of the pixels that reached the iteration depth
+#[derive(Default)]
+struct Bars {
+    counts: Vec<u32>,
+    colors: Vec<u32>,
+    interior: f64,
+}
+
+#[derive(Clone)]
+/// A panel next to the image with the histogram of the values of the pixels of the view,
+/// in the colors of the coloring. The height of a bar is logarithmic in its number of pixels.
+pub struct HistogramPanel {
+    panel: gtk::Box,
+    area: DrawingArea,
+    info: Label,
+    bars: Rc<RefCell<Bars>>,
+    toggle: CheckButton,
+}
+
+fn draw_bars(bars: &Bars, ctxt: &gtk::cairo::Context, w: f64, h: f64) {
+    let highest = bars.counts.iter().copied().max().unwrap_or(0);
+    if highest == 0 {
+        return;
+    }
+    let bar_w = w / bars.counts.len() as f64;
+    let top = (highest as f64).ln_1p();
+    for (i, (&count, &color)) in bars.counts.iter().zip(&bars.colors).enumerate() {
+        let bar_h = h * (count as f64).ln_1p() / top;
+        let rgba = to_rgba(color);
+        ctxt.set_source_rgb(rgba.red() as f64, rgba.green() as f64, rgba.blue() as f64);
+        ctxt.rectangle(i as f64 * bar_w, h - bar_h, bar_w.max(1.0), bar_h);
+        let _ = ctxt.fill();
+    }
+}
+
+impl HistogramPanel {
+    pub fn new() -> HistogramPanel {
+        let bars = Rc::new(RefCell::new(Bars::default()));
+        let area = DrawingArea::builder()
+            .content_width(HISTOGRAM_WIDTH)
+            .content_height(HISTOGRAM_HEIGHT)
+            .build();
+        area.set_draw_func(clone!(@strong bars => move |_d, ctxt, w, h| {
+            draw_bars(&bars.borrow(), ctxt, w as f64, h as f64);
+        }));
+        let info = Label::builder().xalign(0.0).build();
+        let panel = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(5)
+            .visible(false)
+            .build();
+        panel.append(&Label::new(Some("iteration histogram")));
+        panel.append(&area);
+        panel.append(&info);
+        let toggle = CheckButton::builder()
+            .label("histogram")
+            .tooltip_text(
+                "Show how many pixels have every iteration value, for the depth and the density",
+            )
+            .margin_start(15)
+            .build();
+        toggle.connect_toggled(clone!(@strong panel => move |t| panel.set_visible(t.is_active())));
+        HistogramPanel {
+            panel,
+            area,
+            info,
+            bars,
+            toggle,
+        }
+    }
+
+    /// The widget that contains the histogram
+    pub fn panel(&self) -> &gtk::Box {
+        &self.panel
+    }
+
+    /// The check button that shows or hides the histogram
+    pub fn toggle(&self) -> &CheckButton {
+        &self.toggle
+    }
+
+    /// Show the histogram of the values of the current view, once its last pass is rendered.
+    /// The values are grouped in at most one bar per pixel of the width; the pixels at the
+    /// iteration depth are only counted in the text under it.
+    pub fn update(&self, state: &State) {
+        if !self.toggle.is_active() {
+            return;
+        }
+        let Some(buffer) = state.buffer() else {
+            return;
+        };
+        let histogram = buffer.histogram();
+        let max = buffer.mapping.iteration_depth;
+        let values = max as usize;
+        let per_bar = values.div_ceil(HISTOGRAM_WIDTH as usize).max(1);
+        let coloring = state.coloring();
+        let mut bars = self.bars.borrow_mut();
+        bars.counts = histogram[..values]
+            .chunks(per_bar)
+            .map(|chunk| chunk.iter().sum())
+            .collect();
+        bars.colors = (0..bars.counts.len())
+            .map(|i| coloring.get_color((i * per_bar) as u32, max))
+            .collect();
+        let pixels = buffer.values.len().max(1);
+        bars.interior = histogram[values] as f64 / pixels as f64;
+        let highest = histogram[..values]
+            .iter()
+            .rposition(|&count| count > 0)
+            .unwrap_or(0);
+        self.info.set_text(&format!(
+            "highest escape: {} of {}\nat the depth: {:.1}%",
+            highest,
+            max,
+            100.0 * bars.interior
+        ));
+        self.area.queue_draw();
+    }
+}
@@ -0,0 +1,134 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use gtk::glib::clone;
+use gtk::{prelude::*, DrawingArea, Grid, Label, Window};
+
+use crate::mandel_image::{fractal_color, probe};
+
+use super::state::State;
+
+#[derive(Clone)]
+/// A window showing the details of the computation for the pixel under the mouse
+pub struct Inspector {
+    win: Window,
+    position: Label,
+    iterations: Label,
+    smooth: Label,
+    z: Label,
+    distance: Label,
+    color: Label,
+    swatch: DrawingArea,
+    swatch_color: Rc<Cell<u32>>,
+}
+
+fn value_label() -> Label {
+    Label::builder()
+        .xalign(0.0)
+        .selectable(true)
+        .width_chars(40)
+        .build()
+}
+
+fn opt_to_string(v: Option<f64>) -> String {
+    match v {
+        Some(v) => format!("{:.6}", v),
+        None => "-".to_string(),
+    }
+}
+
+impl Inspector {
+    pub fn new(parent: &impl IsA<Window>) -> Inspector {
+        let swatch_color = Rc::new(Cell::new(0));
+        let swatch = DrawingArea::builder()
+            .content_width(40)
+            .content_height(20)
+            .build();
+        swatch.set_draw_func(clone!(@strong swatch_color => move |_d, ctxt, _w, _h| {
+            let color = swatch_color.get();
+            ctxt.set_source_rgb(
+                (color >> 16 & 0xff) as f64 / 255.0,
+                (color >> 8 & 0xff) as f64 / 255.0,
+                (color & 0xff) as f64 / 255.0,
+            );
+            let _ = ctxt.paint();
+        }));
+        let inspector = Inspector {
+            win: Window::builder()
+                .title("Inspector")
+                .transient_for(parent)
+                .hide_on_close(true)
+                .resizable(false)
+                .build(),
+            position: value_label(),
+            iterations: value_label(),
+            smooth: value_label(),
+            z: value_label(),
+            distance: value_label(),
+            color: value_label(),
+            swatch,
+            swatch_color,
+        };
+        let grid = Grid::builder()
+            .row_spacing(5)
+            .column_spacing(10)
+            .margin_top(20)
+            .margin_start(20)
+            .margin_bottom(20)
+            .margin_end(20)
+            .build();
+        let rows = [
+            ("position:", &inspector.position),
+            ("iterations:", &inspector.iterations),
+            ("smooth value:", &inspector.smooth),
+            ("final z:", &inspector.z),
+            ("distance estimate:", &inspector.distance),
+            ("color:", &inspector.color),
+        ];
+        let n_rows = rows.len() as i32;
+        for (row, (name, value)) in rows.into_iter().enumerate() {
+            grid.attach(
+                &Label::builder().label(name).xalign(1.0).build(),
+                0,
+                row as i32,
+                1,
+                1,
+            );
+            grid.attach(value, 1, row as i32, 1, 1);
+        }
+        grid.attach(&inspector.swatch, 1, n_rows, 1, 1);
+        inspector.win.set_child(Some(&grid));
+        inspector
+    }
+
+    pub fn present(&self) {
+        self.win.present();
+    }
+
+    /// Show the details for window position (wx, wy), if the inspector is visible
+    pub fn update(&self, state: &State, wx: f64, wy: f64) {
+        if !self.win.is_visible() {
+            return;
+        }
+        let (x, y) = state.win_to_mandel(wx, wy);
+        let max = state.mapping().iteration_depth;
+        let p = probe(&state.mapping().fractal, x, y, max);
+        let color = fractal_color(
+            &state.mapping().fractal,
+            x,
+            y,
+            max,
+            state.coloring().as_ref(),
+            state.mapping().scale,
+        );
+        self.position.set_text(&format!("{} {:+}i", x, y));
+        self.iterations
+            .set_text(&format!("{} of {}", p.iterations, max));
+        self.smooth.set_text(&opt_to_string(p.smooth));
+        self.z.set_text(&format!("{:.6} {:+.6}i", p.z.0, p.z.1));
+        self.distance.set_text(&opt_to_string(p.distance));
+        self.color.set_text(&format!("#{:06x}", color));
+        self.swatch_color.set(color);
+        self.swatch.queue_draw();
+    }
+}
@@ -0,0 +1,92 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk::glib::clone;
+use gtk::{prelude::*, CheckButton, DrawingArea, Label};
+
+use crate::fractal::FractalType;
+use crate::image::Image;
+use crate::mandel_image::{make_preview_image, Mapping};
+use crate::IMG_FMT;
+
+use super::state::State;
+
+const PREVIEW_SZ: usize = 150;
+
+#[derive(Clone)]
+/// A small view of the Julia set for the point under the mouse
+pub struct JuliaPreview {
+    panel: gtk::Box,
+    area: DrawingArea,
+    img: Rc<RefCell<Option<Image>>>,
+    toggle: CheckButton,
+}
+
+impl JuliaPreview {
+    pub fn new() -> JuliaPreview {
+        let img: Rc<RefCell<Option<Image>>> = Rc::new(RefCell::new(None));
+        let area = DrawingArea::builder()
+            .content_width(PREVIEW_SZ as i32)
+            .content_height(PREVIEW_SZ as i32)
+            .build();
+        area.set_draw_func(clone!(@strong img => move |_d, ctxt, _w, _h| {
+            if let Some(img) = &*img.borrow() {
+                ctxt.set_source_surface(img.surface(), 0.0, 0.0)
+                    .expect("Expected to be able to set source surface");
+                ctxt.paint().unwrap();
+            }
+        }));
+        let panel = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(5)
+            .visible(false)
+            .build();
+        panel.append(&Label::new(Some("julia set")));
+        panel.append(&area);
+        let toggle = CheckButton::builder()
+            .label("julia preview")
+            .margin_start(15)
+            .build();
+        toggle.connect_toggled(clone!(@strong panel => move |t| panel.set_visible(t.is_active())));
+        JuliaPreview {
+            panel,
+            area,
+            img,
+            toggle,
+        }
+    }
+
+    /// The widget that contains the preview
+    pub fn panel(&self) -> &gtk::Box {
+        &self.panel
+    }
+
+    /// The check button that shows or hides the preview
+    pub fn toggle(&self) -> &CheckButton {
+        &self.toggle
+    }
+
+    /// Show the Julia set for window position (wx, wy) of the mandelbrot view
+    pub fn update(&self, state: &State, wx: f64, wy: f64) {
+        if !self.toggle.is_active()
+            || state.mapping().fractal.fractal_type != FractalType::Mandelbrot
+        {
+            return;
+        }
+        let (x, y) = state.win_to_mandel(wx, wy);
+        let mut mapping = Mapping::new_for_size(PREVIEW_SZ);
+        mapping.fractal.fractal_type = FractalType::Julia;
+        mapping.fractal.power = state.mapping().fractal.power;
+        mapping.fractal.julia_cx = x;
+        mapping.fractal.julia_cy = y;
+        if let Some((data, stride)) = make_preview_image(
+            &mapping,
+            state.coloring().as_ref(),
+            state.calibration().preview_iter_depth,
+        ) {
+            let sz = PREVIEW_SZ as i32;
+            *self.img.borrow_mut() = Some(Image::new(data, IMG_FMT, sz, sz, stride));
+            self.area.queue_draw();
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk::glib::clone;
+use gtk::{prelude::*, Adjustment, CheckButton, Grid, Label, Orientation, Scale, Window};
+
+use crate::shading::Lighting;
+
+use super::state::State;
+
+#[derive(Clone)]
+/// A window with the settings of the slope shading of the main view
+pub struct LightingWindow {
+    state: Rc<RefCell<State>>,
+    win: Window,
+    enabled: CheckButton,
+    azimuth_adj: Adjustment,
+    elevation_adj: Adjustment,
+    relief_adj: Adjustment,
+}
+
+fn slider(adj: &Adjustment) -> Scale {
+    Scale::builder()
+        .orientation(Orientation::Horizontal)
+        .adjustment(adj)
+        .digits(0)
+        .draw_value(true)
+        .width_request(200)
+        .build()
+}
+
+impl LightingWindow {
+    pub fn new(parent: &impl IsA<Window>, state: &Rc<RefCell<State>>) -> LightingWindow {
+        let defaults = Lighting::defaults();
+        let enabled = CheckButton::builder().label("slope shading").build();
+        let azimuth_adj = Adjustment::new(defaults.azimuth, 0.0, 360.0, 1.0, 15.0, 0.0);
+        let elevation_adj = Adjustment::new(defaults.elevation, 5.0, 90.0, 1.0, 5.0, 0.0);
+        let relief_adj = Adjustment::new(defaults.relief, 1.0, 100.0, 1.0, 10.0, 0.0);
+        let grid = Grid::builder()
+            .row_spacing(5)
+            .column_spacing(10)
+            .margin_top(20)
+            .margin_start(20)
+            .margin_bottom(20)
+            .margin_end(20)
+            .build();
+        grid.attach(&enabled, 1, 0, 1, 1);
+        let rows = [
+            ("light direction:", &azimuth_adj),
+            ("light height:", &elevation_adj),
+            ("relief:", &relief_adj),
+        ];
+        for (row, (name, adj)) in rows.into_iter().enumerate() {
+            grid.attach(
+                &Label::builder().label(name).xalign(1.0).build(),
+                0,
+                row as i32 + 1,
+                1,
+                1,
+            );
+            grid.attach(&slider(adj), 1, row as i32 + 1, 1, 1);
+        }
+        let win = Window::builder()
+            .title("Lighting")
+            .transient_for(parent)
+            .hide_on_close(true)
+            .resizable(false)
+            .child(&grid)
+            .build();
+        let lighting_win = LightingWindow {
+            state: state.clone(),
+            win,
+            enabled,
+            azimuth_adj,
+            elevation_adj,
+            relief_adj,
+        };
+        lighting_win
+            .enabled
+            .connect_toggled(clone!(@strong lighting_win => move |_| lighting_win.apply()));
+        for adj in [
+            &lighting_win.azimuth_adj,
+            &lighting_win.elevation_adj,
+            &lighting_win.relief_adj,
+        ] {
+            adj.connect_value_changed(clone!(@strong lighting_win => move |_| {
+                if lighting_win.enabled.is_active() {
+                    lighting_win.apply();
+                }
+            }));
+        }
+        lighting_win
+    }
+
+    pub fn present(&self) {
+        self.win.present();
+    }
+
+    fn apply(&self) {
+        let lighting = self.enabled.is_active().then(|| Lighting {
+            azimuth: self.azimuth_adj.value(),
+            elevation: self.elevation_adj.value(),
+            relief: self.relief_adj.value(),
+        });
+        self.state.borrow_mut().set_lighting(lighting);
+    }
+}
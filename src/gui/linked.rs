@@ -0,0 +1,256 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk::gdk::ffi::GDK_BUTTON_PRIMARY;
+use gtk::glib::clone;
+use gtk::{
+    gio, glib, prelude::*, CheckButton, DrawingArea, DropDown, EventControllerScroll,
+    EventControllerScrollFlags, GestureClick, Label, Window,
+};
+
+use crate::colorings::ColorInfo;
+use crate::fractal::FractalType;
+use crate::image::Image;
+use crate::mandel_image::{make_mandel_image, new_pool, Canceller, Mapping, WinToMandel};
+use crate::IMG_FMT;
+
+const LINKED_SZ: usize = 400;
+// The zoom factor of one step of the scroll wheel
+const SCROLL_ZOOM: f64 = 1.2;
+
+struct LinkedState {
+    mapping: Mapping,
+    // The last mapping of the main view
+    leader: Option<Mapping>,
+    color_info: ColorInfo,
+    col_idx: usize,
+    img: Option<Image>,
+    canceller: Canceller,
+    generation: u32,
+}
+
+impl LinkedState {
+    // The width of the view in mandelbrot space
+    fn view_width(&self) -> f64 {
+        self.mapping.scale * self.mapping.win_width as f64
+    }
+    // Take the center, zoom and iteration depth of the main view, keeping the fractal
+    fn copy_leader(&mut self) {
+        if let Some(leader) = &self.leader {
+            self.mapping.cx = leader.cx;
+            self.mapping.cy = leader.cy;
+            // Show the same width as the main view
+            self.mapping.scale =
+                leader.scale * leader.win_width as f64 / self.mapping.win_width.max(1) as f64;
+            self.mapping.iteration_depth = leader.iteration_depth;
+        }
+    }
+}
+
+#[derive(Clone)]
+/// A second view with its own fractal and coloring. When linked, panning and zooming
+/// in either view is mirrored in the other one.
+pub struct LinkedView {
+    view: Rc<RefCell<LinkedState>>,
+    win: Window,
+    area: DrawingArea,
+    link: CheckButton,
+    // Called with the center and the width in mandelbrot space when a linked view is moved
+    on_navigate: Rc<dyn Fn(f64, f64, f64)>,
+}
+
+impl LinkedView {
+    pub fn new(
+        parent: &impl IsA<Window>,
+        on_navigate: impl Fn(f64, f64, f64) + 'static,
+    ) -> LinkedView {
+        let color_info = ColorInfo::new();
+        let colorings = DropDown::from_strings(&color_info.names_iter().collect::<Vec<_>>());
+        colorings.set_margin_end(15);
+        let fractal_names: Vec<&str> = FractalType::ALL.iter().map(|t| t.name()).collect();
+        let fractals = DropDown::from_strings(&fractal_names);
+        fractals.set_margin_end(15);
+        let link = CheckButton::builder()
+            .label("link views")
+            .active(true)
+            .build();
+        let row = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .spacing(5)
+            .build();
+        row.append(&Label::new(Some("fractal:")));
+        row.append(&fractals);
+        row.append(&Label::new(Some("coloring:")));
+        row.append(&colorings);
+        row.append(&link);
+        let area = DrawingArea::builder()
+            .content_width(LINKED_SZ as i32)
+            .content_height(LINKED_SZ as i32)
+            .vexpand(true)
+            .hexpand(true)
+            .build();
+        let content_box = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(5)
+            .margin_start(10)
+            .margin_end(10)
+            .margin_top(10)
+            .margin_bottom(10)
+            .build();
+        content_box.append(&row);
+        content_box.append(&area);
+        let win = Window::builder()
+            .title("Linked view")
+            .transient_for(parent)
+            .hide_on_close(true)
+            .child(&content_box)
+            .build();
+        let view = Rc::new(RefCell::new(LinkedState {
+            mapping: Mapping::new_for_size(LINKED_SZ),
+            leader: None,
+            color_info,
+            col_idx: 0,
+            img: None,
+            canceller: Canceller::new(),
+            generation: 0,
+        }));
+        area.set_draw_func(clone!(@strong view => move |_d, ctxt, _w, _h| {
+            if let Some(img) = &view.borrow().img {
+                ctxt.set_source_surface(img.surface(), 0.0, 0.0)
+                    .expect("Expected to be able to set source surface");
+                ctxt.paint().unwrap();
+            }
+        }));
+        let linked = LinkedView {
+            view,
+            win,
+            area,
+            link,
+            on_navigate: Rc::new(on_navigate),
+        };
+        fractals.connect_selected_notify(clone!(@strong linked => move |dd| {
+            if let Some(t) = FractalType::ALL.get(dd.selected() as usize) {
+                linked.view.borrow_mut().mapping.fractal.fractal_type = *t;
+                linked.render();
+            }
+        }));
+        colorings.connect_selected_notify(clone!(@strong linked => move |dd| {
+            let sel = dd.selected() as usize;
+            if sel < linked.view.borrow().color_info.len() {
+                linked.view.borrow_mut().col_idx = sel;
+                linked.render();
+            }
+        }));
+        linked
+            .link
+            .connect_toggled(clone!(@strong linked => move |t| {
+                if t.is_active() {
+                    linked.view.borrow_mut().copy_leader();
+                    linked.render();
+                }
+            }));
+        linked
+            .area
+            .connect_resize(clone!(@strong linked => move |_da, w, h| linked.on_resize(w, h)));
+        let click = GestureClick::new();
+        click.set_button(GDK_BUTTON_PRIMARY as u32);
+        click.connect_pressed(clone!(@strong linked => move |_g, _n, wx, wy| {
+            let (cx, cy) = {
+                let view = linked.view.borrow();
+                WinToMandel::from_mapping(&view.mapping).cvt_f(wx, wy)
+            };
+            linked.navigate(cx, cy, 1.0);
+        }));
+        linked.area.add_controller(click);
+        let scroll = EventControllerScroll::new(EventControllerScrollFlags::VERTICAL);
+        scroll.connect_scroll(clone!(@strong linked => move |_s, _dx, dy| {
+            let (cx, cy) = {
+                let view = linked.view.borrow();
+                (view.mapping.cx, view.mapping.cy)
+            };
+            linked.navigate(cx, cy, SCROLL_ZOOM.powf(dy));
+            glib::Propagation::Stop
+        }));
+        linked.area.add_controller(scroll);
+        linked
+    }
+
+    pub fn present(&self) {
+        self.win.present();
+        self.render();
+    }
+
+    /// Take the new mapping of the main view. A linked view follows it.
+    pub fn follow(&self, leader: &Mapping) {
+        let linked = self.link.is_active();
+        {
+            let mut view = self.view.borrow_mut();
+            view.leader = Some(leader.clone());
+            if !linked {
+                return;
+            }
+            view.copy_leader();
+        }
+        if self.win.is_visible() {
+            self.render();
+        }
+    }
+
+    // Move the center to (cx, cy) and multiply the width of the view by factor
+    fn navigate(&self, cx: f64, cy: f64, factor: f64) {
+        let width = self.view.borrow().view_width() * factor;
+        if self.link.is_active() {
+            // The main view sends its new mapping back through follow
+            (self.on_navigate)(cx, cy, width);
+        } else {
+            {
+                let mut view = self.view.borrow_mut();
+                view.mapping.cx = cx;
+                view.mapping.cy = cy;
+                view.mapping.scale = width / view.mapping.win_width.max(1) as f64;
+            }
+            self.render();
+        }
+    }
+
+    fn on_resize(&self, w: i32, h: i32) {
+        {
+            let mut view = self.view.borrow_mut();
+            let width = view.view_width();
+            view.mapping.win_width = w as usize;
+            view.mapping.win_height = h as usize;
+            view.mapping.scale = width / w.max(1) as f64;
+        }
+        self.render();
+    }
+
+    fn render(&self) {
+        let (mapping, coloring, cancel, generation) = {
+            let mut view = self.view.borrow_mut();
+            view.generation += 1;
+            (
+                view.mapping.clone(),
+                view.color_info.scheme(view.col_idx).clone(),
+                view.canceller.next_token(),
+                view.generation,
+            )
+        };
+        let linked = self.clone();
+        glib::spawn_future_local(async move {
+            let (w, h) = (mapping.win_width as i32, mapping.win_height as i32);
+            let result = gio::spawn_blocking(move || {
+                make_mandel_image(&mapping, &coloring, &mut new_pool(), 1, &cancel)
+            })
+            .await;
+            let mut view = linked.view.borrow_mut();
+            if view.generation != generation {
+                // A newer render was started
+                return;
+            }
+            if let Ok(Some((data, stride))) = result {
+                view.img = Some(Image::new(data, IMG_FMT, w, h, stride));
+                linked.area.queue_draw();
+            }
+        });
+    }
+}
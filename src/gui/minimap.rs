@@ -0,0 +1,128 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk::gdk::ffi::GDK_BUTTON_PRIMARY;
+use gtk::glib::clone;
+use gtk::{glib, prelude::*, CheckButton, DrawingArea, GestureClick};
+
+use crate::fractal::FractalParams;
+use crate::image::Image;
+use crate::mandel_image::{make_preview_image, MandelToWin, Mapping, WinToMandel};
+use crate::IMG_FMT;
+
+use super::state::State;
+use super::transition::ViewPoint;
+use super::Controls;
+
+const MINIMAP_SZ: usize = 150;
+
+// The image of the whole fractal, with the fractal and the coloring it was rendered for
+struct Overview {
+    img: Image,
+    fractal: FractalParams,
+    coloring: String,
+}
+
+#[derive(Clone)]
+/// A small view of the whole fractal in a corner of the image, with a rectangle where the
+/// view is. A click on it moves the view there. The image is only rendered again when the
+/// fractal or the coloring changes.
+pub struct Minimap {
+    area: DrawingArea,
+    toggle: CheckButton,
+}
+
+// The view of the minimap for the fractal of the main view
+fn overview_mapping(state: &State) -> Mapping {
+    let mut mapping = Mapping::new_for_size(MINIMAP_SZ);
+    mapping.fractal = state.mapping().fractal.clone();
+    mapping
+}
+
+fn draw_minimap(
+    state: &State,
+    overview: &mut Option<Overview>,
+    ctxt: &gtk::cairo::Context,
+) -> Option<()> {
+    let mapping = overview_mapping(state);
+    let coloring = state.coloring();
+    let stale = overview
+        .as_ref()
+        .is_none_or(|o| o.fractal != mapping.fractal || o.coloring != coloring.name());
+    if stale {
+        let max_iter = state.calibration().preview_iter_depth;
+        let (data, stride) = make_preview_image(&mapping, coloring.as_ref(), max_iter)?;
+        let sz = MINIMAP_SZ as i32;
+        *overview = Some(Overview {
+            img: Image::new(data, IMG_FMT, sz, sz, stride),
+            fractal: mapping.fractal.clone(),
+            coloring: coloring.name().to_string(),
+        });
+    }
+    ctxt.set_source_surface(overview.as_ref()?.img.surface(), 0.0, 0.0)
+        .ok()?;
+    ctxt.paint().ok()?;
+    // The rectangle of the view, which is at least a few pixels so it stays visible
+    let m = state.mapping();
+    let to_win = MandelToWin::from_mapping(&mapping);
+    let (x, y) = to_win.cvt(m.cx, m.cy);
+    let w = (m.win_width as f64 * m.scale / mapping.scale).max(4.0);
+    let h = (m.win_height as f64 * m.scale / mapping.scale).max(4.0);
+    ctxt.rectangle(x - w / 2.0, y - h / 2.0, w, h);
+    state.overlay_style().stroke(ctxt);
+    Some(())
+}
+
+impl Minimap {
+    pub fn new(state: &Rc<RefCell<State>>, controls: &Controls) -> Minimap {
+        let area = DrawingArea::builder()
+            .content_width(MINIMAP_SZ as i32)
+            .content_height(MINIMAP_SZ as i32)
+            .halign(gtk::Align::End)
+            .valign(gtk::Align::End)
+            .margin_end(10)
+            .margin_bottom(10)
+            .visible(false)
+            .build();
+        let overview: Rc<RefCell<Option<Overview>>> = Rc::new(RefCell::new(None));
+        area.set_draw_func(clone!(@strong state => move |_d, ctxt, _w, _h| {
+            draw_minimap(&state.borrow(), &mut overview.borrow_mut(), ctxt);
+        }));
+        let click = GestureClick::new();
+        click.set_button(GDK_BUTTON_PRIMARY as u32);
+        click.connect_released(clone!(@strong state, @strong controls =>
+                move |gesture, _n, wx, wy| {
+            gesture.set_state(gtk::EventSequenceState::Claimed);
+            let to_mandel = WinToMandel::from_mapping(&overview_mapping(&state.borrow()));
+            let (cx, cy) = to_mandel.cvt_f(wx, wy);
+            state.borrow_mut().remember_location();
+            let zoom = controls.settings.zoom();
+            controls.jump_to(&state, ViewPoint { cx, cy, zoom });
+        }));
+        area.add_controller(click);
+        let toggle = CheckButton::builder()
+            .label("minimap")
+            .tooltip_text("Show where the view is in the whole fractal")
+            .margin_start(15)
+            .build();
+        toggle.connect_toggled(clone!(@weak area => move |t| area.set_visible(t.is_active())));
+        Minimap { area, toggle }
+    }
+
+    /// The widget that shows the minimap, for an overlay over the image
+    pub fn area(&self) -> &DrawingArea {
+        &self.area
+    }
+
+    /// The check button that shows or hides the minimap
+    pub fn toggle(&self) -> &CheckButton {
+        &self.toggle
+    }
+
+    /// Draw the rectangle of the view anew, after the view changed
+    pub fn follow(&self) {
+        if self.area.is_visible() {
+            self.area.queue_draw();
+        }
+    }
+}
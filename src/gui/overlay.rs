@@ -0,0 +1,92 @@
+use gtk::cairo::Context;
+use gtk::gdk::RGBA;
+
+use super::config::Config;
+
+// The dashes of lines that mark something temporary, like a selection
+const DASHES: [f64; 2] = [4.0, 4.0];
+
+/// How lines and text over the image are drawn. They get an outline in a contrasting color,
+/// so they stay legible over dark and bright colorings alike.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OverlayStyle {
+    pub color: RGBA,
+    pub outline: RGBA,
+    pub line_width: f64,
+    pub font_size: f64,
+}
+
+/// A color in a format of gdk::RGBA::parse. An invalid color is reported and replaced by
+/// the default, which is in the same format.
+pub fn parse_color(value: &str, default: &str) -> RGBA {
+    RGBA::parse(value).unwrap_or_else(|_| {
+        eprintln!("Invalid color {}", value);
+        RGBA::parse(default).unwrap_or(RGBA::BLACK)
+    })
+}
+
+fn set_source(ctxt: &Context, color: &RGBA) {
+    ctxt.set_source_rgba(
+        color.red() as f64,
+        color.green() as f64,
+        color.blue() as f64,
+        color.alpha() as f64,
+    );
+}
+
+impl OverlayStyle {
+    pub fn from_config(config: &Config) -> OverlayStyle {
+        let defaults = Config::defaults();
+        OverlayStyle {
+            color: parse_color(&config.overlay_color, &defaults.overlay_color),
+            outline: parse_color(&config.overlay_outline, &defaults.overlay_outline),
+            line_width: config.overlay_line_width.max(0.5),
+            font_size: config.overlay_font_size.max(4.0),
+        }
+    }
+
+    /// Stroke the current path with a solid outline and a dashed line over it
+    pub fn stroke_dashed(&self, ctxt: &Context) {
+        ctxt.set_line_width(self.line_width);
+        set_source(ctxt, &self.outline);
+        let _ = ctxt.stroke_preserve();
+        ctxt.set_dash(&DASHES, 0.0);
+        set_source(ctxt, &self.color);
+        let _ = ctxt.stroke();
+        ctxt.set_dash(&[], 0.0);
+    }
+
+    /// Stroke the current path with a solid line over a wider outline
+    pub fn stroke(&self, ctxt: &Context) {
+        ctxt.set_line_width(3.0 * self.line_width);
+        set_source(ctxt, &self.outline);
+        let _ = ctxt.stroke_preserve();
+        ctxt.set_line_width(self.line_width);
+        set_source(ctxt, &self.color);
+        let _ = ctxt.stroke();
+    }
+
+    /// Draw a small round marker with its center at (x, y)
+    pub fn marker(&self, ctxt: &Context, x: f64, y: f64) {
+        let radius = 1.5 * self.line_width + 1.5;
+        ctxt.new_sub_path();
+        ctxt.arc(x, y, radius, 0.0, std::f64::consts::TAU);
+        ctxt.set_line_width(self.line_width);
+        set_source(ctxt, &self.color);
+        let _ = ctxt.fill_preserve();
+        set_source(ctxt, &self.outline);
+        let _ = ctxt.stroke();
+    }
+
+    /// Draw text with the left end of its baseline at (x, y)
+    pub fn text(&self, ctxt: &Context, x: f64, y: f64, text: &str) {
+        ctxt.set_font_size(self.font_size);
+        ctxt.move_to(x, y);
+        ctxt.text_path(text);
+        ctxt.set_line_width(self.font_size / 4.0);
+        set_source(ctxt, &self.outline);
+        let _ = ctxt.stroke_preserve();
+        set_source(ctxt, &self.color);
+        let _ = ctxt.fill();
+    }
+}
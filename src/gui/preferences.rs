@@ -0,0 +1,206 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk::glib::{self, clone};
+use gtk::{
+    gdk::RGBA, prelude::*, Adjustment, CheckButton, ColorButton, DropDown, Grid, Label, SpinButton,
+    Window,
+};
+
+use crate::backend::backend_names;
+
+use super::config::{Config, MAX_SUPERSAMPLING, SUPERSAMPLING_NAMES};
+use super::gradient::{from_rgba, to_rgba};
+use super::state::State;
+
+const MAX_DEFAULT_ITERATIONS: f64 = 100000.0;
+const MAX_WORKER_THREADS: f64 = 256.0;
+// The largest cache of rendered tiles, in megabytes
+const MAX_TILE_CACHE_MB: f64 = 4096.0;
+
+#[derive(Clone)]
+/// A window with the settings that were fixed before: the iteration depth and coloring of the
+/// start view, the interior color, the number of threads, the supersampling, the adaptive
+/// anti-aliasing and the render backend of the view.
+/// A change is stored in the settings file and applied at once.
+pub struct PreferencesWindow {
+    win: Window,
+}
+
+// The color of a setting, which is empty for none
+fn parse_interior(value: &str) -> Option<u32> {
+    RGBA::parse(value).ok().map(|rgba| from_rgba(&rgba))
+}
+
+impl PreferencesWindow {
+    /// interior_changed is called with a new interior color, which the main window shows
+    pub fn new(
+        parent: &impl IsA<Window>,
+        state: &Rc<RefCell<State>>,
+        interior_changed: impl Fn(Option<u32>) + 'static,
+    ) -> PreferencesWindow {
+        let config = Config::load();
+        let iter_adj = Adjustment::new(
+            config.default_iterations as f64,
+            10.0,
+            MAX_DEFAULT_ITERATIONS,
+            10.0,
+            0.0,
+            0.0,
+        );
+        let colorings = DropDown::new(
+            Some(state.borrow().coloring_model().clone()),
+            None::<gtk::Expression>,
+        );
+        if let Some(idx) = state.borrow().col_idx_of(&config.default_coloring) {
+            colorings.set_selected(idx as u32);
+        }
+        let interior = parse_interior(&config.interior);
+        let interior_check = CheckButton::builder()
+            .label("color:")
+            .active(interior.is_some())
+            .build();
+        let interior_btn = ColorButton::with_rgba(&to_rgba(interior.unwrap_or(0)));
+        interior_btn.set_use_alpha(false);
+        interior_btn.set_sensitive(interior.is_some());
+        let interior_box = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .build();
+        interior_box.append(&interior_check);
+        interior_box.append(&interior_btn);
+        let threads_adj = Adjustment::new(
+            config.worker_threads as f64,
+            0.0,
+            MAX_WORKER_THREADS,
+            1.0,
+            0.0,
+            0.0,
+        );
+        let threads_btn = SpinButton::builder()
+            .adjustment(&threads_adj)
+            .tooltip_text("0 is one thread per core")
+            .build();
+        let cache_adj = Adjustment::new(
+            config.tile_cache_mb as f64,
+            0.0,
+            MAX_TILE_CACHE_MB,
+            16.0,
+            0.0,
+            0.0,
+        );
+        let cache_btn = SpinButton::builder()
+            .adjustment(&cache_adj)
+            .tooltip_text(
+                "Megabytes of rendered tiles that are kept to show views again, 0 for none",
+            )
+            .build();
+        let supersampling = DropDown::from_strings(&SUPERSAMPLING_NAMES);
+        supersampling.set_selected(config.supersampling.clamp(1, MAX_SUPERSAMPLING) as u32 - 1);
+        let adaptive = CheckButton::builder()
+            .active(config.adaptive_antialiasing)
+            .tooltip_text("Sample the pixels at edges again after the view is shown")
+            .build();
+        let names = backend_names();
+        let backend = DropDown::from_strings(&names);
+        backend.set_tooltip_text(Some("How the view is computed; the fastest is the default"));
+        if let Some(idx) = names.iter().position(|name| *name == config.backend) {
+            backend.set_selected(idx as u32);
+        }
+        let grid = Grid::builder()
+            .row_spacing(5)
+            .column_spacing(10)
+            .margin_top(20)
+            .margin_start(20)
+            .margin_bottom(20)
+            .margin_end(20)
+            .build();
+        let rows: [(&str, gtk::Widget); 8] = [
+            (
+                "start iterations:",
+                SpinButton::builder().adjustment(&iter_adj).build().upcast(),
+            ),
+            ("start coloring:", colorings.clone().upcast()),
+            ("interior:", interior_box.upcast()),
+            ("worker threads:", threads_btn.upcast()),
+            ("tile cache (MB):", cache_btn.upcast()),
+            ("supersampling:", supersampling.clone().upcast()),
+            ("adaptive anti-aliasing:", adaptive.clone().upcast()),
+            ("render backend:", backend.clone().upcast()),
+        ];
+        for (row, (name, widget)) in rows.iter().enumerate() {
+            grid.attach(
+                &Label::builder().label(*name).xalign(1.0).build(),
+                0,
+                row as i32,
+                1,
+                1,
+            );
+            grid.attach(widget, 1, row as i32, 1, 1);
+        }
+        let win = Window::builder()
+            .title("Preferences")
+            .transient_for(parent)
+            .hide_on_close(true)
+            .resizable(false)
+            .child(&grid)
+            .build();
+        // Every change is stored at once
+        let changed = clone!(@strong state, @weak iter_adj, @weak colorings, @weak interior_check,
+                @weak interior_btn, @weak threads_adj, @weak cache_adj, @weak supersampling,
+                @weak adaptive, @weak backend => move || {
+            let mut config = Config::load();
+            config.default_iterations = iter_adj.value() as u32;
+            if let Some(name) = colorings
+                .selected_item()
+                .and_then(|item| item.downcast::<gtk::StringObject>().ok())
+            {
+                config.default_coloring = name.string().to_string();
+            }
+            interior_btn.set_sensitive(interior_check.is_active());
+            config.interior = if interior_check.is_active() {
+                interior_btn.rgba().to_str().to_string()
+            } else {
+                String::new()
+            };
+            config.worker_threads = threads_adj.value() as usize;
+            config.tile_cache_mb = cache_adj.value() as usize;
+            config.supersampling = supersampling.selected() as usize + 1;
+            config.adaptive_antialiasing = adaptive.is_active();
+            config.backend = backend_names()
+                .get(backend.selected() as usize)
+                .map_or(String::new(), |name| name.to_string());
+            if let Err(e) = config.save() {
+                eprintln!("Could not save the preferences: {}", e);
+            }
+            state.borrow_mut().apply_config(&config);
+        });
+        iter_adj.connect_value_changed(clone!(@strong changed => move |_| changed()));
+        colorings.connect_selected_notify(clone!(@strong changed => move |_| changed()));
+        threads_adj.connect_value_changed(clone!(@strong changed => move |_| changed()));
+        cache_adj.connect_value_changed(clone!(@strong changed => move |_| changed()));
+        supersampling.connect_selected_notify(clone!(@strong changed => move |_| changed()));
+        adaptive.connect_toggled(clone!(@strong changed => move |_| changed()));
+        backend.connect_selected_notify(clone!(@strong changed => move |_| changed()));
+        let interior_changed = Rc::new(interior_changed);
+        interior_check.connect_toggled(
+            clone!(@strong changed, @strong interior_changed, @weak interior_btn => move |check| {
+                changed();
+                interior_changed(check.is_active().then(|| from_rgba(&interior_btn.rgba())));
+            }),
+        );
+        interior_btn.connect_color_set(move |btn| {
+            changed();
+            interior_changed(Some(from_rgba(&btn.rgba())));
+        });
+        PreferencesWindow { win }
+    }
+
+    pub fn present(&self) {
+        self.win.present();
+    }
+}
+
+/// The interior color of the start view, from the settings
+pub fn start_interior(config: &Config) -> Option<u32> {
+    parse_interior(&config.interior)
+}
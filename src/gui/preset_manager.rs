@@ -0,0 +1,157 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk::gdk::{ContentProvider, DragAction};
+use gtk::glib::clone;
+use gtk::{
+    glib, prelude::*, Button, DragSource, DropTarget, Entry, Label, ListBox, ScrolledWindow,
+    SelectionMode, Window,
+};
+
+use crate::presets::Presets;
+
+use super::user_presets::{location_preset, preset_location, save_presets};
+
+#[derive(Clone)]
+/// A window to rename, duplicate, delete and reorder the presets. The built-in presets can
+/// only be duplicated. A user preset is moved by dragging it onto the place of another.
+pub struct PresetManager {
+    presets: Rc<RefCell<Presets>>,
+    win: Window,
+    list: ListBox,
+    changed: Rc<dyn Fn()>,
+}
+
+impl PresetManager {
+    /// changed is called after every change of the presets
+    pub fn new(
+        parent: &impl IsA<Window>,
+        presets: &Rc<RefCell<Presets>>,
+        changed: impl Fn() + 'static,
+    ) -> PresetManager {
+        let list = ListBox::builder()
+            .selection_mode(SelectionMode::None)
+            .build();
+        let scroller = ScrolledWindow::builder()
+            .child(&list)
+            .min_content_height(300)
+            .min_content_width(420)
+            .margin_top(20)
+            .margin_start(20)
+            .margin_bottom(20)
+            .margin_end(20)
+            .build();
+        let win = Window::builder()
+            .title("Manage presets")
+            .transient_for(parent)
+            .modal(true)
+            .hide_on_close(true)
+            .child(&scroller)
+            .build();
+        PresetManager {
+            presets: presets.clone(),
+            win,
+            list,
+            changed: Rc::new(changed),
+        }
+    }
+
+    pub fn present(&self) {
+        self.refresh();
+        self.win.present();
+    }
+
+    // Store a change of the presets and show it
+    fn edit(&self, change: impl FnOnce(&mut Presets) -> bool) {
+        if change(&mut self.presets.borrow_mut()) {
+            save_presets(&self.presets.borrow());
+            (self.changed)();
+            self.refresh();
+        }
+    }
+
+    fn refresh(&self) {
+        while let Some(row) = self.list.row_at_index(0) {
+            self.list.remove(&row);
+        }
+        let n = self.presets.borrow().len();
+        for i in 0..n {
+            let row = self.preset_row(i);
+            self.list.append(&row);
+        }
+    }
+
+    fn preset_row(&self, i: usize) -> gtk::Box {
+        let presets = self.presets.borrow();
+        let builtin = presets.is_builtin(i);
+        let row = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .spacing(5)
+            .build();
+        let duplicate_btn = Button::builder().label("Duplicate").build();
+        duplicate_btn.connect_clicked(clone!(@strong self as manager => move |_| {
+            manager.edit(|presets| {
+                let copy = location_preset(preset_location(presets.get(i)));
+                presets.add(&presets.copy_name(i), copy);
+                true
+            });
+        }));
+        if builtin {
+            row.append(
+                &Label::builder()
+                    .label(presets.name(i))
+                    .xalign(0.0)
+                    .hexpand(true)
+                    .build(),
+            );
+            row.append(&Label::new(Some("built-in")));
+            row.append(&duplicate_btn);
+            return row;
+        }
+        // The handle to drag the preset to another place
+        let handle = Label::new(Some("☰"));
+        let name_entry = Entry::builder().text(presets.name(i)).hexpand(true).build();
+        let delete_btn = Button::builder().label("Delete").build();
+        row.append(&handle);
+        row.append(&name_entry);
+        row.append(&duplicate_btn);
+        row.append(&delete_btn);
+        // A name is taken when the entry is left or Enter is pressed
+        let rename = clone!(@strong self as manager, @weak name_entry => move || {
+            let name = name_entry.text().trim().to_string();
+            let renamed = manager.presets.borrow_mut().rename(i, &name);
+            if renamed {
+                name_entry.remove_css_class("error");
+                save_presets(&manager.presets.borrow());
+                (manager.changed)();
+            } else if manager.presets.borrow().name(i) != name {
+                name_entry.add_css_class("error");
+            }
+        });
+        let focus = gtk::EventControllerFocus::new();
+        focus.connect_leave(clone!(@strong rename => move |_| rename()));
+        name_entry.add_controller(focus);
+        name_entry.connect_activate(move |_| rename());
+        delete_btn.connect_clicked(clone!(@strong self as manager => move |_| {
+            manager.edit(|presets| presets.remove(i));
+        }));
+        let drag = DragSource::builder().actions(DragAction::MOVE).build();
+        drag.connect_prepare(move |_, _, _| {
+            Some(ContentProvider::for_value(&(i as u32).to_value()))
+        });
+        handle.add_controller(drag);
+        let drop = DropTarget::new(glib::Type::U32, DragAction::MOVE);
+        drop.connect_drop(clone!(@strong self as manager => move |_, value, _, _| {
+            let Ok(from) = value.get::<u32>() else {
+                return false;
+            };
+            // The list is made anew, so it is changed after the drop has finished
+            glib::idle_add_local_once(clone!(@strong manager => move || {
+                manager.edit(|presets| presets.move_to(from as usize, i));
+            }));
+            true
+        }));
+        row.add_controller(drop);
+        row
+    }
+}
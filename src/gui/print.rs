@@ -0,0 +1,90 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk::glib::clone;
+use gtk::{prelude::*, PrintContext, PrintOperation, PrintOperationAction, Unit, Window};
+
+use crate::export::{export_mapping, tile_coloring};
+use crate::image::Image;
+use crate::mandel_image::{make_mandel_image, new_pool, tiles, CancelToken};
+use crate::IMG_FMT;
+
+use super::export::show_error;
+use super::state::State;
+
+// The highest resolution at which a view is rendered for a printer
+const MAX_PRINT_DPI: f64 = 300.0;
+const POINTS_PER_INCH: f64 = 72.0;
+
+// Draw the image that is shown, as large as fits on the page
+fn draw_image(state: &State, ctxt: &PrintContext) {
+    let Some(img) = state.img() else {
+        return;
+    };
+    let cr = ctxt.cairo_context();
+    let (iw, ih) = (img.width() as f64, img.height() as f64);
+    let scale = (ctxt.width() / iw).min(ctxt.height() / ih);
+    cr.translate(
+        (ctxt.width() - scale * iw) / 2.0,
+        (ctxt.height() - scale * ih) / 2.0,
+    );
+    cr.scale(scale, scale);
+    if cr.set_source_surface(img.surface(), 0.0, 0.0).is_ok() {
+        let _ = cr.paint();
+    }
+}
+
+// Render the view at the resolution of the printer and draw it on the whole page. The view
+// is rendered in tiles, so even a large page does not need one large image.
+fn draw_rendered(state: &State, ctxt: &PrintContext) {
+    let px_per_point = ctxt.dpi_x().min(MAX_PRINT_DPI) / POINTS_PER_INCH;
+    let width = (ctxt.width() * px_per_point) as usize;
+    let height = (ctxt.height() * px_per_point) as usize;
+    let view = state.mapping();
+    let mapping = export_mapping(view, width, height, view.iteration_depth);
+    let (pool, cancel) = (&mut new_pool(), CancelToken::never());
+    let Some(coloring) = tile_coloring(&mapping, state.coloring(), pool, &cancel) else {
+        return;
+    };
+    let cr = ctxt.cairo_context();
+    cr.scale(1.0 / px_per_point, 1.0 / px_per_point);
+    for (first_row, tile) in tiles(&mapping, 1) {
+        let Some((data, stride)) = make_mandel_image(&tile, &coloring, pool, 1, &cancel) else {
+            return;
+        };
+        let img = Image::new(
+            data,
+            IMG_FMT,
+            tile.win_width as i32,
+            tile.win_height as i32,
+            stride,
+        );
+        if cr
+            .set_source_surface(img.surface(), 0.0, first_row as f64)
+            .is_ok()
+        {
+            let _ = cr.paint();
+        }
+    }
+}
+
+/// Print the current view on one page, or export it as PDF through the print dialog. The
+/// image that is shown is scaled to the page, or with rerender the view is rendered again
+/// at the resolution of the printer, up to MAX_PRINT_DPI.
+pub fn print_view(window: &impl IsA<Window>, state: &Rc<RefCell<State>>, rerender: bool) {
+    let operation = PrintOperation::builder()
+        .n_pages(1)
+        .unit(Unit::Points)
+        .job_name("Mandelbrot")
+        .build();
+    operation.connect_draw_page(clone!(@strong state => move |_op, ctxt, _page| {
+        if rerender {
+            draw_rendered(&state.borrow(), ctxt);
+        } else {
+            draw_image(&state.borrow(), ctxt);
+        }
+    }));
+    if let Err(e) = operation.run(PrintOperationAction::PrintDialog, Some(window)) {
+        show_error(window, "Could not print", e);
+    }
+}
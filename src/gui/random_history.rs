@@ -0,0 +1,158 @@
+use std::fs;
+use std::path::PathBuf;
+
+use gtk::glib::{self, KeyFile, KeyFileFlags};
+
+use crate::colorings::GradientColoring;
+use crate::location::Location;
+use crate::random::palette_name;
+
+const DATA_DIR: &str = "mandelbrot";
+const HISTORY_FILE: &str = "random.ini";
+const MAX_HISTORY: usize = 100;
+
+#[derive(Clone)]
+/// What was randomized
+pub enum RandomResult {
+    Palette(Vec<(f64, u32)>),
+    Location(Location),
+}
+
+#[derive(Clone)]
+/// A randomized palette or location, with the seed that made it
+pub struct RandomEntry {
+    pub seed: u64,
+    pub result: RandomResult,
+}
+
+impl RandomEntry {
+    pub fn palette(seed: u64, gradient: &GradientColoring) -> RandomEntry {
+        RandomEntry {
+            seed,
+            result: RandomResult::Palette(gradient.stops().to_vec()),
+        }
+    }
+
+    pub fn location(seed: u64, location: &Location) -> RandomEntry {
+        RandomEntry {
+            seed,
+            result: RandomResult::Location(location.clone()),
+        }
+    }
+
+    /// The palette of the entry, as a coloring with the name of its seed
+    pub fn gradient(&self) -> Option<GradientColoring> {
+        match &self.result {
+            RandomResult::Palette(stops) => {
+                Some(GradientColoring::new(&palette_name(self.seed), stops))
+            }
+            RandomResult::Location(_) => None,
+        }
+    }
+}
+
+// The stops of a palette as "t:rrggbb;t:rrggbb;..."
+fn stops_to_string(stops: &[(f64, u32)]) -> String {
+    let stops: Vec<String> = stops
+        .iter()
+        .map(|(t, color)| format!("{}:{:06x}", t, color))
+        .collect();
+    stops.join(";")
+}
+
+fn stops_from_string(s: &str) -> Option<Vec<(f64, u32)>> {
+    s.split(';')
+        .map(|stop| {
+            let (t, color) = stop.split_once(':')?;
+            Some((t.parse().ok()?, u32::from_str_radix(color, 16).ok()?))
+        })
+        .collect()
+}
+
+/// Every palette and location that was randomized, most recent first, with its seed and
+/// parameters. The list is stored in the user's data directory, so a result that was
+/// clicked past can be found again later.
+pub struct RandomHistory {
+    entries: Vec<RandomEntry>,
+}
+
+fn history_path() -> PathBuf {
+    glib::user_data_dir().join(DATA_DIR).join(HISTORY_FILE)
+}
+
+impl RandomHistory {
+    /// Read the list from its file. An unreadable file gives an empty list.
+    pub fn load() -> RandomHistory {
+        let mut history = RandomHistory {
+            entries: Vec::new(),
+        };
+        let key_file = KeyFile::new();
+        if key_file
+            .load_from_file(history_path(), KeyFileFlags::NONE)
+            .is_err()
+        {
+            return history;
+        }
+        for group in key_file.groups() {
+            let lookup = |key: &str| key_file.string(&group, key).ok().map(|v| v.to_string());
+            let Some(seed) = lookup("seed").and_then(|s| u64::from_str_radix(&s, 16).ok()) else {
+                continue;
+            };
+            let result = match lookup("kind").as_deref() {
+                Some("palette") => lookup("stops")
+                    .and_then(|s| stops_from_string(&s))
+                    .map(RandomResult::Palette),
+                Some("location") => Location::from_params(lookup).map(RandomResult::Location),
+                _ => None,
+            };
+            if let Some(result) = result {
+                history.entries.push(RandomEntry { seed, result });
+            }
+        }
+        history.entries.truncate(MAX_HISTORY);
+        history
+    }
+
+    fn save(&self) {
+        let key_file = KeyFile::new();
+        for (i, entry) in self.entries.iter().enumerate() {
+            let group = format!("random{}", i);
+            key_file.set_string(&group, "seed", &format!("{:016x}", entry.seed));
+            match &entry.result {
+                RandomResult::Palette(stops) => {
+                    key_file.set_string(&group, "kind", "palette");
+                    key_file.set_string(&group, "stops", &stops_to_string(stops));
+                }
+                RandomResult::Location(location) => {
+                    key_file.set_string(&group, "kind", "location");
+                    for (key, value) in location.params() {
+                        key_file.set_string(&group, key, &value);
+                    }
+                }
+            }
+        }
+        let path = history_path();
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| key_file.save_to_file(&path).map_err(std::io::Error::other));
+        if let Err(e) = result {
+            eprintln!("Could not save random history: {}", e);
+        }
+    }
+
+    /// Put an entry in front of the list
+    pub fn add(&mut self, entry: RandomEntry) {
+        self.entries.insert(0, entry);
+        self.entries.truncate(MAX_HISTORY);
+        self.save();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn entry(&self, i: usize) -> &RandomEntry {
+        &self.entries[i]
+    }
+}
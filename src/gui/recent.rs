@@ -0,0 +1,99 @@
+use std::fs;
+use std::path::PathBuf;
+
+use gtk::gdk::{MemoryFormat, MemoryTexture, Texture};
+use gtk::glib::{self, KeyFile, KeyFileFlags};
+use gtk::prelude::*;
+
+use crate::location::Location;
+use crate::thumbnail::render_thumbnail;
+
+const DATA_DIR: &str = "mandelbrot";
+const RECENT_FILE: &str = "recent.ini";
+const MAX_RECENT: usize = 20;
+/// The size of the thumbnails in the recent locations menu
+pub const THUMB_SZ: usize = 48;
+
+/// The locations that were visited recently, most recent first.
+/// The list is stored in the user's data directory.
+pub struct RecentLocations {
+    locations: Vec<Location>,
+}
+
+fn recent_path() -> PathBuf {
+    glib::user_data_dir().join(DATA_DIR).join(RECENT_FILE)
+}
+
+/// Render a thumbnail of sz x sz pixels of a location as a texture
+pub fn thumbnail_texture(location: &Location, sz: usize) -> Option<Texture> {
+    let image = render_thumbnail(location, sz)?;
+    let bytes = glib::Bytes::from_owned(image.data);
+    Some(
+        MemoryTexture::new(
+            image.width as i32,
+            image.height as i32,
+            MemoryFormat::R8g8b8,
+            &bytes,
+            3 * image.width,
+        )
+        .upcast(),
+    )
+}
+
+impl RecentLocations {
+    /// Read the list from its file. An unreadable file gives an empty list.
+    pub fn load() -> RecentLocations {
+        let mut recent = RecentLocations {
+            locations: Vec::new(),
+        };
+        let key_file = KeyFile::new();
+        if key_file
+            .load_from_file(recent_path(), KeyFileFlags::NONE)
+            .is_err()
+        {
+            return recent;
+        }
+        for group in key_file.groups() {
+            let lookup = |key: &str| key_file.string(&group, key).ok().map(|v| v.to_string());
+            if let Some(location) = Location::from_params(lookup) {
+                recent.locations.push(location);
+            }
+        }
+        recent.locations.truncate(MAX_RECENT);
+        recent
+    }
+
+    fn save(&self) {
+        let key_file = KeyFile::new();
+        for (i, location) in self.locations.iter().enumerate() {
+            let group = format!("location{}", i);
+            for (key, value) in location.params() {
+                key_file.set_string(&group, key, &value);
+            }
+        }
+        let path = recent_path();
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| key_file.save_to_file(&path).map_err(std::io::Error::other));
+        if let Err(e) = result {
+            eprintln!("Could not save recent locations: {}", e);
+        }
+    }
+
+    /// Put a location in front of the list. An equal location that is already in the list is moved.
+    pub fn add(&mut self, location: Location) {
+        self.locations.retain(|l| *l != location);
+        self.locations.insert(0, location);
+        self.locations.truncate(MAX_RECENT);
+        self.save();
+    }
+
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    pub fn location(&self, i: usize) -> &Location {
+        &self.locations[i]
+    }
+}
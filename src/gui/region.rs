@@ -0,0 +1,158 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk::glib::clone;
+use gtk::{
+    gio, glib, prelude::*, Adjustment, Button, DrawingArea, DropDown, Label, SpinButton, Window,
+};
+
+use crate::colorings::Coloring;
+use crate::image::Image;
+use crate::mandel_image::{make_supersampled_image, new_pool, CancelToken, Mapping};
+use crate::IMG_FMT;
+
+use super::config::{MAX_SUPERSAMPLING, SUPERSAMPLING_NAMES};
+
+/// The number of pixels of the longest side of a re-rendered region
+pub const REGION_SZ: usize = 600;
+
+struct RegionView {
+    mapping: Option<Mapping>,
+    coloring: Option<Box<dyn Coloring>>,
+    img: Option<Image>,
+    generation: u32,
+}
+
+#[derive(Clone)]
+/// A window that shows a selected region of the main view, rendered at a higher quality
+pub struct RegionWindow {
+    view: Rc<RefCell<RegionView>>,
+    win: Window,
+    area: DrawingArea,
+    iter_adj: Adjustment,
+    aa_choice: DropDown,
+    status: Label,
+}
+
+impl RegionWindow {
+    pub fn new(parent: &impl IsA<Window>) -> RegionWindow {
+        let view = Rc::new(RefCell::new(RegionView {
+            mapping: None,
+            coloring: None,
+            img: None,
+            generation: 0,
+        }));
+        let iter_adj = Adjustment::new(400.0, 10.0, 100000.0, 10.0, 0.0, 0.0);
+        let iteration_button = SpinButton::builder().adjustment(&iter_adj).build();
+        let aa_choice = DropDown::from_strings(&SUPERSAMPLING_NAMES);
+        aa_choice.set_selected(1);
+        let render_btn = Button::builder().label("Render").margin_start(10).build();
+        let status = Label::builder().margin_start(10).build();
+        let row = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .spacing(5)
+            .build();
+        row.append(&Label::new(Some("max iterations:")));
+        row.append(&iteration_button);
+        row.append(&Label::new(Some("anti-aliasing:")));
+        row.append(&aa_choice);
+        row.append(&render_btn);
+        row.append(&status);
+        let area = DrawingArea::builder()
+            .content_width(REGION_SZ as i32)
+            .content_height(REGION_SZ as i32)
+            .build();
+        let content_box = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(5)
+            .margin_start(10)
+            .margin_end(10)
+            .margin_top(10)
+            .margin_bottom(10)
+            .build();
+        content_box.append(&row);
+        content_box.append(&area);
+        let win = Window::builder()
+            .title("Region")
+            .transient_for(parent)
+            .hide_on_close(true)
+            .child(&content_box)
+            .build();
+        area.set_draw_func(clone!(@strong view => move |_d, ctxt, _w, _h| {
+            if let Some(img) = &view.borrow().img {
+                ctxt.set_source_surface(img.surface(), 0.0, 0.0)
+                    .expect("Expected to be able to set source surface");
+                ctxt.paint().unwrap();
+            }
+        }));
+        let region_win = RegionWindow {
+            view,
+            win,
+            area,
+            iter_adj,
+            aa_choice,
+            status,
+        };
+        render_btn.connect_clicked(clone!(@strong region_win => move |_| region_win.render()));
+        region_win
+    }
+
+    /// Show the region given by mapping, using a higher iteration depth than the main view
+    pub fn show_region(&self, mapping: Mapping, coloring: Box<dyn Coloring>) {
+        self.iter_adj
+            .set_value((4 * mapping.iteration_depth) as f64);
+        self.area.set_content_width(mapping.win_width as i32);
+        self.area.set_content_height(mapping.win_height as i32);
+        {
+            let mut view = self.view.borrow_mut();
+            view.mapping = Some(mapping);
+            view.coloring = Some(coloring);
+            view.img = None;
+        }
+        self.win.present();
+        self.render();
+    }
+
+    fn render(&self) {
+        let (mut mapping, coloring, generation) = {
+            let mut view = self.view.borrow_mut();
+            view.generation += 1;
+            match (&view.mapping, &view.coloring) {
+                (Some(mapping), Some(coloring)) => {
+                    (mapping.clone(), coloring.clone(), view.generation)
+                }
+                _ => return,
+            }
+        };
+        mapping.iteration_depth = self.iter_adj.value() as u32;
+        let factor = (self.aa_choice.selected() as usize).min(MAX_SUPERSAMPLING - 1) + 1;
+        self.status.set_text("rendering...");
+        let region_win = self.clone();
+        glib::spawn_future_local(async move {
+            let (w, h) = (mapping.win_width as i32, mapping.win_height as i32);
+            let result = gio::spawn_blocking(move || {
+                make_supersampled_image(
+                    &mapping,
+                    coloring.as_ref(),
+                    &mut new_pool(),
+                    factor,
+                    &CancelToken::never(),
+                )
+            })
+            .await;
+            let mut view = region_win.view.borrow_mut();
+            if view.generation != generation {
+                // A newer render was started
+                return;
+            }
+            match result {
+                Ok(Some((data, stride))) => {
+                    view.img = Some(Image::new(data, IMG_FMT, w, h, stride));
+                    region_win.status.set_text("");
+                    region_win.area.queue_draw();
+                }
+                _ => region_win.status.set_text("rendering failed"),
+            }
+        });
+    }
+}
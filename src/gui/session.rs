@@ -0,0 +1,60 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use gtk::glib::{self, KeyFile, KeyFileFlags};
+
+use crate::location::Location;
+
+const CONFIG_DIR: &str = "mandelbrot";
+const SESSION_FILE: &str = "session.ini";
+
+/// What the main window showed when the application was closed, so the next start can
+/// continue there. It is kept apart from the settings, which are shared in workspaces.
+pub struct Session {
+    /// The view, with the coloring and the iteration depth
+    pub location: Location,
+    /// The size of the main window
+    pub width: i32,
+    pub height: i32,
+    pub maximized: bool,
+}
+
+fn session_path() -> PathBuf {
+    glib::user_config_dir().join(CONFIG_DIR).join(SESSION_FILE)
+}
+
+impl Session {
+    /// The session that was saved last, if there is one
+    pub fn load() -> Option<Session> {
+        let key_file = KeyFile::new();
+        key_file
+            .load_from_file(session_path(), KeyFileFlags::NONE)
+            .ok()?;
+        let location =
+            Location::from_params(|key| key_file.string("view", key).ok().map(|v| v.to_string()))?;
+        Some(Session {
+            location,
+            width: key_file.integer("window", "width").ok()?,
+            height: key_file.integer("window", "height").ok()?,
+            maximized: key_file.boolean("window", "maximized").unwrap_or(false),
+        })
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let key_file = KeyFile::new();
+        for (key, value) in self.location.params() {
+            key_file.set_string("view", key, &value);
+        }
+        key_file.set_integer("window", "width", self.width);
+        key_file.set_integer("window", "height", self.height);
+        key_file.set_boolean("window", "maximized", self.maximized);
+        let path = session_path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        key_file
+            .save_to_file(&path)
+            .map_err(|e| io::Error::other(e.to_string()))
+    }
+}
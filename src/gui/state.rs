@@ -1,46 +1,360 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use async_channel::Sender;
-use gtk::{glib::WeakRef, prelude::*, DrawingArea};
+use gtk::{gdk::RGBA, glib::WeakRef, prelude::*, DrawingArea, StringList};
 
 use crate::{
-    colorings::ColorInfo,
+    accumulate::{accumulate, Frame, HISTORY_WEIGHT},
+    animation::Keyframe,
+    backend::{backend_by_name, default_backend, RenderBackend},
+    benchmark::{Calibration, FrameTimes},
+    channels::PolicySender,
+    colorings::{ColorInfo, Coloring, ColoringParams, InteriorColoring, ParamColoring},
+    curves::{CurvedColoring, TransferCurves},
+    fixed::PreciseCenter,
+    formula::Formula,
+    fractal::FractalType,
     image::Image,
-    mandel_image::{Mapping, WinToMandel},
-    MandelReq,
+    location::Location,
+    mandel_image::{
+        color_buffer, set_tile_cache_size, set_worker_threads, worker_threads, Canceller,
+        IterationBuffer, MandelToWin, Mapping, Orbit, RenderStats, WinToMandel,
+    },
+    newton::Polynomial,
+    report::PassTiming,
+    shading::{apply_buffer_lighting, Lighting},
+    view_history::ViewHistory,
+    MandelReq, MandelTile, IMG_FMT,
 };
 
+use super::config::Config;
+use super::overlay::{parse_color, OverlayStyle};
+use super::recent::RecentLocations;
 use super::WIN_SZ0;
 
+// The zoom factor of one step of the zoom slider
+pub const ZOOM_STEP: f64 = 1.035;
+// The number of visited centers that are kept for the breadcrumbs
+const MAX_BREADCRUMBS: usize = 500;
+// A view that is shown this long is added to the recent locations
+const DWELL_TIME: Duration = Duration::from_secs(5);
+// The zoom slider value from which a view counts as a find, and not as the way to one
+const MIN_DWELL_ZOOM: f64 = 50.0;
+
+/// The scale for a value of the zoom slider
+pub fn scale_for_zoom(zoom: f64) -> f64 {
+    // The value is chosen such that floating point approximation becomes clear near zoom == 1000,
+    // from where the Mandelbrot set is rendered with perturbation from a precise center
+    4.0 * ZOOM_STEP.powf(-zoom) / WIN_SZ0 as f64
+}
+
+/// The zoom slider value that gives the scale
+pub fn zoom_for_scale(scale: f64) -> f64 {
+    -(scale * WIN_SZ0 as f64 / 4.0).ln() / ZOOM_STEP.ln()
+}
+
+type ViewListener = Box<dyn Fn(&Mapping)>;
+
 pub struct State {
     mapping: Mapping,
     img: Option<Image>,
     col_idx: usize,
     color_info: ColorInfo,
+    // The names of the colorings, as shown by the coloring drop downs
+    coloring_model: StringList,
+    curves: TransferCurves,
+    // The color of points in the set, if not that of the coloring
+    interior: Option<u32>,
+    // How the palette of the coloring is shifted and stretched
+    coloring_params: ColoringParams,
+    // The offset that is added to that of the parameters while the colors cycle
+    cycle_phase: Option<f64>,
+    // The values of the pixels of a view, for coloring it again without rendering
+    buffer: Option<Arc<IterationBuffer>>,
     preset: Option<u8>,
-    req_sender: Sender<MandelReq>,
+    req_sender: PolicySender<MandelReq>,
+    canceller: Canceller,
     canvas: WeakRef<DrawingArea>,
+    selection: Option<(f64, f64, f64, f64)>,
+    recent: RecentLocations,
+    // The centers of the views that were left, most recent last
+    breadcrumbs: Vec<(f64, f64)>,
+    show_breadcrumbs: bool,
+    // Whether the center of the view is marked, with its coordinates
+    show_crosshair: bool,
+    // Whether the axes and a grid with coordinates are drawn over the image
+    show_grid: bool,
+    // Whether the counts of the render are drawn over the image
+    show_stats: bool,
+    // The orbit of a point that is drawn over the image
+    orbit: Option<Orbit>,
+    // The coloring of the compare view, if it is shown, and its image. It is colored from
+    // the buffer of the view.
+    compare_col_idx: Option<usize>,
+    compare_img: Option<Image>,
+    compare_canvas: WeakRef<DrawingArea>,
+    // When the view changed last, until it was added to the recent locations
+    view_changed: Option<Instant>,
+    // The views before and after the current one, for undo and redo
+    view_history: ViewHistory,
+    // The keyframes of the zoom animation that is being edited
+    timeline: Vec<Keyframe>,
+    // Called with the new mapping whenever the view changes
+    view_listener: Option<ViewListener>,
+    calibration: Calibration,
+    // The recent render times, which choose the resolution of the first pass
+    frame_times: FrameTimes,
+    // Whether window positions are converted without rounding them to whole pixels
+    subpixel: bool,
+    // The color of the canvas around an image that does not cover it
+    background: RGBA,
+    // How lines and text over the image are drawn
+    overlay_style: OverlayStyle,
+    lighting: Option<Lighting>,
+    // The passes that rendered the image of the current view so far, and what they computed
+    passes: Vec<PassTiming>,
+    pass_stats: Vec<RenderStats>,
+    // The view of the image
+    img_mapping: Option<Mapping>,
+    // How far the image is dragged, in window coordinates, before the view follows
+    pan: (f64, f64),
+    // Whether frames are blended with the previous frame, e.g. during a dive
+    accumulate: bool,
+    // The last image of the previous view, with its stride and view
+    history: Option<(Vec<u8>, usize, Mapping)>,
     block: bool,
+    // The supersampling factor of the last pass
+    factor: usize,
+    // Whether the pixels of high contrast of the last pass are sampled again
+    adaptive: bool,
+    // The backend that renders the view
+    backend: &'static dyn RenderBackend,
+    // Whether only the first pass is rendered, e.g. for the frames of an animation
+    preview: bool,
 }
 
 impl State {
-    pub fn new(req_sender: Sender<MandelReq>) -> State {
+    pub fn new(req_sender: PolicySender<MandelReq>) -> State {
+        let config = Config::load();
+        set_worker_threads(config.worker_threads);
+        set_tile_cache_size(config.tile_cache_mb);
+        let color_info = ColorInfo::new();
+        let mut mapping = Mapping::new_for_size(WIN_SZ0);
+        mapping.iteration_depth = config.default_iterations;
+        let coloring_model = StringList::new(&color_info.names_iter().collect::<Vec<_>>());
+        let recent = RecentLocations::load();
+        let breadcrumbs = (0..recent.len())
+            .rev()
+            .map(|i| (recent.location(i).cx, recent.location(i).cy))
+            .collect();
         State {
-            mapping: Mapping::new_for_size(WIN_SZ0),
+            mapping,
             img: None,
-            col_idx: 0,
-            color_info: ColorInfo::new(),
+            col_idx: color_info.index_of(&config.default_coloring).unwrap_or(0),
+            color_info,
+            coloring_model,
+            curves: TransferCurves::identity(),
+            interior: None,
+            coloring_params: ColoringParams::IDENTITY,
+            cycle_phase: None,
+            buffer: None,
             preset: None,
             req_sender,
+            canceller: Canceller::new(),
             canvas: WeakRef::new(),
+            selection: None,
+            recent,
+            breadcrumbs,
+            show_breadcrumbs: false,
+            show_crosshair: false,
+            show_grid: false,
+            show_stats: false,
+            orbit: None,
+            compare_col_idx: None,
+            compare_img: None,
+            compare_canvas: WeakRef::new(),
+            view_changed: None,
+            view_history: ViewHistory::default(),
+            timeline: Vec::new(),
+            view_listener: None,
+            calibration: Calibration::for_speed(config.pixels_per_second.unwrap_or(0.0)),
+            frame_times: FrameTimes::default(),
+            subpixel: config.subpixel_clicks,
+            background: parse_color(&config.background, &Config::defaults().background),
+            overlay_style: OverlayStyle::from_config(&config),
+            lighting: None,
+            passes: Vec::new(),
+            pass_stats: Vec::new(),
+            img_mapping: None,
+            pan: (0.0, 0.0),
+            accumulate: false,
+            history: None,
             block: false,
+            factor: config.supersampling,
+            adaptive: config.adaptive_antialiasing,
+            backend: backend_by_name(&config.backend).unwrap_or_else(default_backend),
+            preview: false,
         }
     }
-    pub fn coloring_names(&self) -> Vec<&str> {
-        self.color_info.names_iter().collect()
+    /// The names of the colorings, which grow when a coloring is added
+    pub fn coloring_model(&self) -> &StringList {
+        &self.coloring_model
+    }
+    /// The colorings that were loaded or made after the start
+    pub fn added_colorings(&self) -> &[Box<dyn Coloring>] {
+        self.color_info.added()
+    }
+    /// Add a coloring, or replace the one with the same name, and return its index
+    pub fn add_coloring(&mut self, coloring: Box<dyn Coloring>) -> usize {
+        let name = coloring.name().to_string();
+        let idx = self.color_info.add(coloring);
+        if idx == self.coloring_model.n_items() as usize {
+            self.coloring_model.append(&name);
+        }
+        if idx == self.col_idx {
+            self.recolor_image();
+        }
+        idx
     }
+    /// The point in mandelbrot space at a window position. Unless subpixel clicks are
+    /// switched off in the settings, this is the exact position and not the corner of its pixel.
     pub fn win_to_mandel(&self, wx: f64, wy: f64) -> (f64, f64) {
-        WinToMandel::from_mapping(&self.mapping).cvt(wx as usize, wy as usize)
+        let converter = WinToMandel::from_mapping(&self.mapping);
+        if self.subpixel {
+            converter.cvt_f(wx, wy)
+        } else {
+            converter.cvt(wx as usize, wy as usize)
+        }
+    }
+    pub fn mapping(&self) -> &Mapping {
+        &self.mapping
+    }
+    /// The coloring of the current view, including its parameters, the transfer curves and
+    /// the interior color
+    pub fn coloring(&self) -> Box<dyn Coloring> {
+        let mut coloring = self.color_info.scheme(self.col_idx).clone();
+        let mut params = self.coloring_params;
+        params.offset += self.cycle_phase.unwrap_or(0.0);
+        if !params.is_identity() {
+            coloring = Box::new(ParamColoring::new(coloring, params));
+        }
+        if !self.curves.is_identity() {
+            coloring = Box::new(CurvedColoring::new(coloring, self.curves.clone()));
+        }
+        if let Some(interior) = self.interior {
+            coloring = Box::new(InteriorColoring::new(coloring, interior));
+        }
+        coloring
+    }
+    /// The current view, apart from the window size
+    pub fn location(&self) -> Location {
+        Location::new(&self.mapping, self.coloring().name())
+    }
+    /// Add the current view to the recent locations, e.g. before jumping elsewhere
+    pub fn remember_location(&mut self) {
+        let location = self.location();
+        self.breadcrumbs.push((location.cx, location.cy));
+        if self.breadcrumbs.len() > MAX_BREADCRUMBS {
+            self.breadcrumbs.remove(0);
+        }
+        self.recent.add(location);
+    }
+    /// Add the current view to the recent locations if it is zoomed in and has been shown
+    /// for DWELL_TIME without changes
+    pub fn capture_dwelled_view(&mut self, now: Instant) {
+        let Some(changed) = self.view_changed else {
+            return;
+        };
+        if now.saturating_duration_since(changed) < DWELL_TIME {
+            return;
+        }
+        self.view_changed = None;
+        if zoom_for_scale(self.mapping.scale) >= MIN_DWELL_ZOOM {
+            self.recent.add(self.location());
+        }
+    }
+    /// The view before the current one, to show it again
+    pub fn undo_view(&mut self) -> Option<Location> {
+        self.view_history.back()
+    }
+    /// The view that was left by undo_view
+    pub fn redo_view(&mut self) -> Option<Location> {
+        self.view_history.forward()
+    }
+    pub fn timeline(&self) -> &[Keyframe] {
+        &self.timeline
+    }
+    pub fn set_timeline(&mut self, timeline: Vec<Keyframe>) {
+        self.timeline = timeline;
+    }
+    pub fn show_breadcrumbs(&self) -> bool {
+        self.show_breadcrumbs
+    }
+    pub fn set_show_breadcrumbs(&mut self, show: bool) {
+        self.show_breadcrumbs = show;
+        if let Some(canvas) = self.canvas.upgrade() {
+            canvas.queue_draw();
+        }
+    }
+    pub fn show_crosshair(&self) -> bool {
+        self.show_crosshair
+    }
+    pub fn set_show_crosshair(&mut self, show: bool) {
+        self.show_crosshair = show;
+        if let Some(canvas) = self.canvas.upgrade() {
+            canvas.queue_draw();
+        }
+    }
+    pub fn show_grid(&self) -> bool {
+        self.show_grid
+    }
+    pub fn set_show_grid(&mut self, show: bool) {
+        self.show_grid = show;
+        if let Some(canvas) = self.canvas.upgrade() {
+            canvas.queue_draw();
+        }
+    }
+    pub fn show_stats(&self) -> bool {
+        self.show_stats
+    }
+    pub fn set_show_stats(&mut self, show: bool) {
+        self.show_stats = show;
+        if let Some(canvas) = self.canvas.upgrade() {
+            canvas.queue_draw();
+        }
+    }
+    pub fn orbit(&self) -> Option<&Orbit> {
+        self.orbit.as_ref()
+    }
+    pub fn set_orbit(&mut self, orbit: Option<Orbit>) {
+        self.orbit = orbit;
+        if let Some(canvas) = self.canvas.upgrade() {
+            canvas.queue_draw();
+        }
+    }
+    /// The window coordinates of the visited centers that are in the current view
+    pub fn breadcrumbs_in_view(&self) -> Vec<(f64, f64)> {
+        let m = &self.mapping;
+        let (w, h) = (m.win_width as f64, m.win_height as f64);
+        let converter = MandelToWin::from_mapping(m);
+        self.breadcrumbs
+            .iter()
+            .map(|&(x, y)| converter.cvt(x, y))
+            .filter(|&(wx, wy)| (0.0..w).contains(&wx) && (0.0..h).contains(&wy))
+            .collect()
+    }
+    pub fn recent(&self) -> &RecentLocations {
+        &self.recent
+    }
+    pub fn recent_mut(&mut self) -> &mut RecentLocations {
+        &mut self.recent
+    }
+    pub fn col_idx_of(&self, name: &str) -> Option<usize> {
+        self.color_info.index_of(name)
     }
     pub fn img(&self) -> &Option<Image> {
         &self.img
@@ -51,9 +365,156 @@ impl State {
             canvas.queue_draw();
         }
     }
+    /// Draw a finished tile of the last pass over the image of the pass before it, if that
+    /// image is of the same view. Accumulated frames wait for the whole pass, which is blended
+    /// at once.
+    pub fn blit_tile(&mut self, tile: &MandelTile) {
+        if self.accumulate || self.img_mapping.as_ref() != Some(&tile.mapping) {
+            return;
+        }
+        if let Some(img) = &mut self.img {
+            let stride = tile.stride as usize;
+            img.blit(&tile.data, stride, tile.x, tile.y, tile.width, tile.height);
+            if let Some(canvas) = self.canvas.upgrade() {
+                canvas.queue_draw();
+            }
+        }
+    }
+    /// Show the image moved by a distance while it is dragged
+    pub fn set_pan(&mut self, dx: f64, dy: f64) {
+        self.pan = (dx, dy);
+        if let Some(canvas) = self.canvas.upgrade() {
+            canvas.queue_draw();
+        }
+    }
+    /// Where the image is drawn relative to the view, such that it lines up with it until an
+    /// image of a moved view arrives
+    pub fn image_offset(&self) -> (f64, f64) {
+        let (dx, dy) = self.pan;
+        let m = &self.mapping;
+        match &self.img_mapping {
+            Some(img_m)
+                if img_m.scale == m.scale
+                    && (img_m.win_width, img_m.win_height) == (m.win_width, m.win_height) =>
+            {
+                (
+                    dx + (img_m.cx - m.cx) / m.scale,
+                    dy + (m.cy - img_m.cy) / m.scale,
+                )
+            }
+            _ => (dx, dy),
+        }
+    }
+    /// Blend the previous frame into the image data of a view before it is shown, if frames
+    /// are accumulated. Every pass of a new view is blended with the last image of the
+    /// view before it.
+    pub fn accumulate_frame(&mut self, data: &mut [u8], stride: usize, mapping: &Mapping) {
+        if !self.accumulate {
+            self.img_mapping = Some(mapping.clone());
+            return;
+        }
+        if self.img_mapping.as_ref() != Some(mapping) {
+            if let (Some(img), Some(img_mapping)) = (&self.img, self.img_mapping.take()) {
+                self.history = Some((img.data().to_vec(), img.stride(), img_mapping));
+            }
+        }
+        if let Some((prev, prev_stride, prev_mapping)) = &self.history {
+            let prev = Frame {
+                data: prev,
+                stride: *prev_stride,
+                mapping: prev_mapping,
+            };
+            accumulate(&prev, data, stride, mapping, HISTORY_WEIGHT);
+        }
+        self.img_mapping = Some(mapping.clone());
+    }
+    /// Start or stop accumulating frames. When it stops, the view is rendered again
+    /// without the previous frames.
+    pub fn set_accumulate(&mut self, accumulate: bool) {
+        self.accumulate = accumulate;
+        self.history = None;
+        if !accumulate {
+            self.recompute_image();
+        }
+    }
+    pub fn passes(&self) -> &[PassTiming] {
+        &self.passes
+    }
+    /// What the passes of the current view computed, in the order of passes
+    pub fn pass_stats(&self) -> &[RenderStats] {
+        &self.pass_stats
+    }
+    pub fn add_pass(&mut self, pass: PassTiming, stats: RenderStats) {
+        let pixels = self.mapping.win_width * self.mapping.win_height;
+        self.frame_times
+            .record(pixels / (pass.block * pass.block).max(1), pass.seconds);
+        self.passes.push(pass);
+        self.pass_stats.push(stats);
+    }
+    pub fn background(&self) -> &RGBA {
+        &self.background
+    }
+    pub fn overlay_style(&self) -> &OverlayStyle {
+        &self.overlay_style
+    }
+    /// Every pixel of the last pass is the average of supersampling x supersampling samples
+    pub fn supersampling(&self) -> usize {
+        self.factor
+    }
+    /// Whether pixels of high contrast are rendered again with more samples
+    pub fn adaptive_antialiasing(&self) -> bool {
+        self.adaptive
+    }
+    /// Use the settings that change how the view is shown, e.g. after they were imported
+    pub fn apply_config(&mut self, config: &Config) {
+        self.subpixel = config.subpixel_clicks;
+        self.background = parse_color(&config.background, &Config::defaults().background);
+        self.overlay_style = OverlayStyle::from_config(config);
+        if let Some(canvas) = self.canvas.upgrade() {
+            canvas.queue_draw();
+        }
+        let threads = worker_threads();
+        set_worker_threads(config.worker_threads);
+        set_tile_cache_size(config.tile_cache_mb);
+        let backend = backend_by_name(&config.backend).unwrap_or_else(default_backend);
+        if config.supersampling != self.factor
+            || config.adaptive_antialiasing != self.adaptive
+            || worker_threads() != threads
+            || backend.name() != self.backend.name()
+        {
+            self.factor = config.supersampling;
+            self.adaptive = config.adaptive_antialiasing;
+            self.backend = backend;
+            self.recompute_image();
+        }
+    }
+    /// The backend that renders the view, and the exports of it
+    pub fn backend(&self) -> &'static dyn RenderBackend {
+        self.backend
+    }
     pub fn set_canvas(&mut self, canvas: WeakRef<DrawingArea>) {
         self.canvas = canvas;
     }
+    /// The rectangle (x0, y0, x1, y1) in window coordinates that is being selected
+    pub fn selection(&self) -> Option<(f64, f64, f64, f64)> {
+        self.selection
+    }
+    pub fn set_selection(&mut self, selection: Option<(f64, f64, f64, f64)>) {
+        self.selection = selection;
+        if let Some(canvas) = self.canvas.upgrade() {
+            canvas.queue_draw();
+        }
+    }
+    pub fn calibration(&self) -> &Calibration {
+        &self.calibration
+    }
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+        self.recompute_image();
+    }
+    pub fn set_view_listener(&mut self, listener: ViewListener) {
+        self.view_listener = Some(listener);
+    }
     pub fn on_resize(&mut self, w: i32, h: i32) {
         self.mapping.win_width = w as usize;
         self.mapping.win_height = h as usize;
@@ -63,7 +524,8 @@ impl State {
         self.mapping.cx
     }
     pub fn set_cx(&mut self, v_opt: Option<f64>) {
-        if let Some(value) = v_opt {
+        if let Some(value) = v_opt.filter(|v| *v != self.mapping.cx) {
+            self.forget_far_precise_center(value - self.mapping.cx);
             self.mapping.cx = value;
             self.recompute_image();
         }
@@ -72,20 +534,204 @@ impl State {
         self.mapping.cy
     }
     pub fn set_cy(&mut self, v_opt: Option<f64>) {
-        if let Some(value) = v_opt {
+        if let Some(value) = v_opt.filter(|v| *v != self.mapping.cy) {
+            self.forget_far_precise_center(value - self.mapping.cy);
             self.mapping.cy = value;
             self.recompute_image();
         }
     }
+    /// Give the view the precise center of a location, after cx and cy were set, so that
+    /// it keeps the digits beyond f64
+    pub fn set_precise_center(&mut self, center: Option<PreciseCenter>) {
+        let old = self.mapping.precise_center.clone();
+        self.mapping.set_precise_center(center);
+        if self.mapping.precise_center != old {
+            self.recompute_image();
+        }
+    }
+    // A change of the center by more than the view is a jump to another place, which is
+    // where the f64 center is, without the digits of the precise center of the old place
+    fn forget_far_precise_center(&mut self, distance: f64) {
+        let m = &self.mapping;
+        if distance.abs() > m.scale * m.win_width.max(m.win_height) as f64 {
+            self.mapping.precise_center = None;
+        }
+    }
+    /// Move the center by (dx, dy), which adds up in deep views even where the moves are
+    /// smaller than the resolution of f64
+    pub fn move_center(&mut self, dx: f64, dy: f64) {
+        self.mapping.move_center(dx, dy);
+        self.recompute_image();
+    }
+    pub fn set_fractal_type(&mut self, fractal_type: FractalType) {
+        self.mapping.fractal.fractal_type = fractal_type;
+        self.recompute_image();
+    }
+    pub fn power(&self) -> f64 {
+        self.mapping.fractal.power as f64
+    }
+    pub fn set_power(&mut self, value: f64) {
+        self.mapping.fractal.power = (value as u32).max(2);
+        self.recompute_image();
+    }
+    pub fn formula(&self) -> &Formula {
+        &self.mapping.fractal.formula
+    }
+    pub fn set_formula(&mut self, formula: Formula) {
+        self.mapping.fractal.formula = formula;
+        self.recompute_image();
+    }
+    pub fn polynomial(&self) -> &Polynomial {
+        &self.mapping.fractal.polynomial
+    }
+    pub fn set_polynomial(&mut self, polynomial: Polynomial) {
+        self.mapping.fractal.polynomial = polynomial;
+        self.recompute_image();
+    }
+    pub fn julia_cx(&self) -> f64 {
+        self.mapping.fractal.julia_cx
+    }
+    pub fn set_julia_cx(&mut self, v_opt: Option<f64>) {
+        if let Some(value) = v_opt {
+            self.mapping.fractal.julia_cx = value;
+            self.recompute_image();
+        }
+    }
+    pub fn julia_cy(&self) -> f64 {
+        self.mapping.fractal.julia_cy
+    }
+    pub fn set_julia_cy(&mut self, v_opt: Option<f64>) {
+        if let Some(value) = v_opt {
+            self.mapping.fractal.julia_cy = value;
+            self.recompute_image();
+        }
+    }
+    pub fn lighting(&self) -> Option<Lighting> {
+        self.lighting
+    }
+    /// Set the slope shading of the image, None for no shading
+    pub fn set_lighting(&mut self, lighting: Option<Lighting>) {
+        self.lighting = lighting;
+        self.recolor_image();
+    }
+    pub fn col_idx(&self) -> usize {
+        self.col_idx
+    }
     pub fn set_col_idx(&mut self, col_idx: usize) {
         self.col_idx = col_idx;
-        self.recompute_image();
+        self.recolor_image();
+    }
+
+    pub fn interior(&self) -> Option<u32> {
+        self.interior
+    }
+    /// Set the color of points in the set, None for the color of the coloring
+    pub fn set_interior(&mut self, interior: Option<u32>) {
+        self.interior = interior;
+        self.recolor_image();
+    }
+    pub fn coloring_params(&self) -> ColoringParams {
+        self.coloring_params
+    }
+    /// Shift and stretch the palette of the coloring
+    pub fn set_coloring_params(&mut self, params: ColoringParams) {
+        self.coloring_params = params;
+        self.recolor_image();
+    }
+    /// Shift the colors by phase while they cycle, None when they stop cycling
+    pub fn set_cycle_phase(&mut self, phase: Option<f64>) {
+        self.cycle_phase = phase;
+        self.recolor_image();
+    }
+    /// Whether an iteration buffer of the current view is needed to color it again
+    pub fn needs_buffer(&self) -> bool {
+        self.buffer
+            .as_ref()
+            .is_none_or(|buffer| buffer.mapping != self.mapping)
+    }
+    /// The values of the pixels of the current view, once its last pass is rendered
+    pub fn buffer(&self) -> Option<&IterationBuffer> {
+        self.buffer
+            .as_deref()
+            .filter(|buffer| buffer.mapping == self.mapping)
+    }
+    /// Keep the values of the pixels of a view. A buffer of another view than the current is ignored.
+    pub fn set_buffer(&mut self, buffer: Arc<IterationBuffer>) {
+        if buffer.mapping == self.mapping {
+            self.buffer = Some(buffer);
+            self.recolor_compare();
+        }
+    }
+    pub fn set_compare_canvas(&mut self, canvas: WeakRef<DrawingArea>) {
+        self.compare_canvas = canvas;
+    }
+    pub fn compare_img(&self) -> Option<&Image> {
+        self.compare_img.as_ref()
+    }
+    /// Show the view in a second coloring next to the first one, or stop showing it
+    pub fn set_compare(&mut self, col_idx: Option<usize>) {
+        self.compare_col_idx = col_idx;
+        self.compare_img = None;
+        self.recolor_compare();
+    }
+    // Color the buffer of the view in the coloring of the compare view. This coloring is
+    // shown as it is, without the offset, density and curves, as a reference for them.
+    fn recolor_compare(&mut self) {
+        let (Some(col_idx), Some(buffer)) = (self.compare_col_idx, &self.buffer) else {
+            return;
+        };
+        let mut coloring = self.color_info.scheme(col_idx).clone();
+        if let Some(interior) = self.interior {
+            coloring = Box::new(InteriorColoring::new(coloring, interior));
+        }
+        if let Some((mut data, stride)) = color_buffer(buffer, coloring.as_ref(), &mut None) {
+            if let Some(lighting) = &self.lighting {
+                apply_buffer_lighting(&mut data, stride as usize, buffer, lighting);
+            }
+            let (w, h) = (buffer.mapping.win_width, buffer.mapping.win_height);
+            self.compare_img = Some(Image::new(data, IMG_FMT, w as i32, h as i32, stride));
+        }
+        if let Some(canvas) = self.compare_canvas.upgrade() {
+            canvas.queue_draw();
+        }
+    }
+    // Show the view in another coloring: from the buffer if possible, else by rendering it again
+    fn recolor_image(&mut self) {
+        if self.block || !self.recolor() {
+            self.recompute_image();
+        }
+        self.recolor_compare();
+    }
+    // Show the view in the current coloring, from the buffer. Returns false if there is
+    // no buffer of the view, or the coloring needs more than the values.
+    fn recolor(&mut self) -> bool {
+        let Some(buffer) = self.buffer.clone().filter(|b| b.mapping == self.mapping) else {
+            return false;
+        };
+        match color_buffer(&buffer, self.coloring().as_ref(), &mut None) {
+            Some((mut data, stride)) => {
+                // A refinement of the view that is still running would show the old coloring
+                self.canceller.next_token();
+                if let Some(lighting) = &self.lighting {
+                    apply_buffer_lighting(&mut data, stride as usize, &buffer, lighting);
+                }
+                let (w, h) = (buffer.mapping.win_width, buffer.mapping.win_height);
+                self.set_img(Image::new(data, IMG_FMT, w as i32, h as i32, stride));
+                true
+            }
+            None => false,
+        }
+    }
+    pub fn curves(&self) -> &TransferCurves {
+        &self.curves
+    }
+    pub fn set_curves(&mut self, curves: TransferCurves) {
+        self.curves = curves;
+        self.recolor_image();
     }
 
     pub fn set_zoom(&mut self, zoom: f64) {
-        // The value is chosen such that floating point approximation becomes clear near zoom == 1000
-        let scale = 1.035_f64.powf(-zoom);
-        self.mapping.scale = 4.0 * scale / WIN_SZ0 as f64;
+        self.mapping.scale = scale_for_zoom(zoom);
         self.recompute_image();
     }
     pub fn set_iter_depth(&mut self, value: f64) {
@@ -102,16 +748,49 @@ impl State {
     pub fn take_preset(&mut self) -> Option<u8> {
         self.preset.take()
     }
+    /// Render only the first pass of the views that follow, or render the view fully again
+    pub fn set_preview(&mut self, preview: bool) {
+        if preview != self.preview {
+            self.preview = preview;
+            if !preview {
+                self.recompute_image();
+            }
+        }
+    }
     fn recompute_image(&mut self) {
         if self.block {
             return;
         }
-        let coloring = self.color_info.scheme(self.col_idx).clone();
+        let mut blocks = self
+            .frame_times
+            .progressive_blocks(self.mapping.win_width * self.mapping.win_height)
+            .unwrap_or_else(|| self.calibration.progressive_blocks.clone());
+        if self.preview {
+            blocks.truncate(1);
+        }
         let request = MandelReq {
             mapping: self.mapping.clone(),
-            coloring,
+            coloring: self.coloring(),
+            // Cancels the render of the previous request
+            cancel: self.canceller.next_token(),
+            blocks,
+            lighting: self.lighting,
+            factor: if self.preview { 1 } else { self.factor },
+            backend: self.backend,
+            // A view that is rendered again as it is, e.g. with another backend, is computed again
+            previous: self.buffer.clone().filter(|b| b.mapping != self.mapping),
+            adaptive: self.adaptive && !self.preview,
         };
-        let _ = self.req_sender.send_blocking(request);
+        // The channel policy is latest-wins, so this replaces a request that is still waiting
+        self.req_sender.send_blocking(request);
+        self.passes.clear();
+        self.pass_stats.clear();
+        let now = Instant::now();
+        self.view_changed = Some(now);
+        self.view_history.record(self.location(), now);
+        if let Some(listener) = &self.view_listener {
+            listener(&self.mapping);
+        }
     }
 }
 
@@ -0,0 +1,110 @@
+use gtk::{prelude::*, Label};
+
+use crate::mandel_image::Orbit;
+use crate::perturbation::{precision_exhausted, Precision, PRECISION_WARNING};
+
+use super::state::State;
+
+#[derive(Clone)]
+/// A row under the image with the point under the pointer, the scale of the view and the
+/// time that rendering the view took
+pub struct StatusBar {
+    panel: gtk::Box,
+    pointer: Label,
+    scale: Label,
+    render: Label,
+    orbit: Label,
+    precision: Label,
+}
+
+fn status_label(width: i32) -> Label {
+    Label::builder()
+        .xalign(0.0)
+        .selectable(true)
+        .width_chars(width)
+        .build()
+}
+
+/// The number of decimals that a coordinate in a view of a scale needs to tell pixels apart
+pub fn coordinate_digits(scale: f64) -> usize {
+    (-scale.log10()).ceil().max(0.0) as usize + 1
+}
+
+impl StatusBar {
+    pub fn new() -> StatusBar {
+        let status = StatusBar {
+            panel: gtk::Box::builder()
+                .orientation(gtk::Orientation::Horizontal)
+                .spacing(20)
+                .build(),
+            pointer: status_label(50),
+            scale: status_label(35),
+            render: status_label(25),
+            orbit: status_label(30),
+            precision: status_label(0),
+        };
+        status.precision.add_css_class("warning");
+        status.precision.set_tooltip_text(Some(
+            "The view is deeper than f64 can tell pixels apart. Only the quadratic Mandelbrot \
+             set with colorings that do not use the distance is rendered with more precision.",
+        ));
+        status.panel.append(&status.pointer);
+        status.panel.append(&status.scale);
+        status.panel.append(&status.render);
+        status.panel.append(&status.orbit);
+        status.panel.append(&status.precision);
+        status
+    }
+
+    /// The widget that shows the status
+    pub fn panel(&self) -> &gtk::Box {
+        &self.panel
+    }
+
+    /// Show the point at window position (wx, wy), with as many digits as the scale makes
+    /// meaningful
+    pub fn update_pointer(&self, state: &State, wx: f64, wy: f64) {
+        let (x, y) = state.win_to_mandel(wx, wy);
+        let digits = coordinate_digits(state.mapping().scale);
+        self.pointer
+            .set_text(&format!("{:.*} {:+.*}i", digits, x, digits, y));
+    }
+
+    pub fn clear_pointer(&self) {
+        self.pointer.set_text("");
+    }
+
+    /// Show how the orbit that is drawn ends, if there is one
+    pub fn show_orbit(&self, orbit: Option<&Orbit>) {
+        let text = match orbit {
+            None => String::new(),
+            Some(o) if o.escaped => format!("orbit: escapes after {}", o.points.len() - 1),
+            Some(o) => match o.period {
+                Some(period) => format!("orbit: period {}", period),
+                None => format!("orbit: no cycle in {}", o.points.len() - 1),
+            },
+        };
+        self.orbit.set_text(&text);
+    }
+
+    /// Show the scale of the view, with the precision of deep views, and the time of its
+    /// passes so far
+    pub fn update_view(&self, state: &State) {
+        let mapping = state.mapping();
+        let scale = format!("scale: {:.3e} per pixel", mapping.scale);
+        self.scale.set_text(&match Precision::for_mapping(mapping) {
+            Precision::Double => scale,
+            precision => format!("{}, {}", scale, precision.name()),
+        });
+        let exhausted = precision_exhausted(mapping, state.coloring().as_ref());
+        self.precision
+            .set_text(if exhausted { PRECISION_WARNING } else { "" });
+        let passes = state.passes();
+        let seconds: f64 = passes.iter().map(|p| p.seconds).sum();
+        self.render.set_text(&format!(
+            "render: {:.3} s in {} passes",
+            seconds,
+            passes.len()
+        ));
+    }
+}
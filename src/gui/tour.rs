@@ -0,0 +1,147 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use gtk::glib::{self, clone};
+use gtk::{prelude::*, Button, Label};
+
+use crate::presets::Presets;
+
+use super::config::Config;
+use super::state::State;
+use super::transition::{Transition, ViewPoint};
+use super::Controls;
+
+// How long the view stays at a preset before moving on
+const HOLD: Duration = Duration::from_secs(5);
+
+// The preset that is visited and the caption that is shown there
+const STEPS: [(usize, &str); 4] = [
+    (
+        0,
+        "Click the image to center the view on that point. The zoom slider zooms in and out.",
+    ),
+    (
+        1,
+        "Choose Preset jumps to interesting places. Deep zooms need more max iterations.",
+    ),
+    (
+        2,
+        "Drag with the right mouse button to render a region in a separate window. \
+         A right click picks the constant of a Julia set.",
+    ),
+    (
+        0,
+        "Change the coloring, or adjust it with Curves. \
+         The tour can be switched off in the settings file.",
+    ),
+];
+
+struct TourState {
+    transition: Option<Transition>,
+    hold: Option<glib::SourceId>,
+}
+
+#[derive(Clone)]
+/// A guided tour that moves through the presets, with captions that explain the controls
+pub struct Tour {
+    panel: gtk::Box,
+    caption: Label,
+    tour_state: Rc<RefCell<TourState>>,
+    presets: Rc<Presets>,
+}
+
+impl Tour {
+    pub fn new() -> Tour {
+        let caption = Label::builder()
+            .xalign(0.0)
+            .hexpand(true)
+            .wrap(true)
+            .build();
+        let skip_btn = Button::builder().label("Skip tour").build();
+        let panel = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .spacing(10)
+            .visible(false)
+            .build();
+        panel.append(&caption);
+        panel.append(&skip_btn);
+        let tour = Tour {
+            panel,
+            caption,
+            tour_state: Rc::new(RefCell::new(TourState {
+                transition: None,
+                hold: None,
+            })),
+            presets: Rc::new(Presets::new()),
+        };
+        skip_btn.connect_clicked(clone!(@strong tour => move |_| tour.finish()));
+        tour
+    }
+
+    /// The widget that shows the captions
+    pub fn panel(&self) -> &gtk::Box {
+        &self.panel
+    }
+
+    pub fn start(&self, state: &Rc<RefCell<State>>, controls: &Controls) {
+        self.panel.set_visible(true);
+        self.run_step(0, state, controls);
+    }
+
+    fn run_step(&self, step: usize, state: &Rc<RefCell<State>>, controls: &Controls) {
+        let (preset_idx, caption) = match STEPS.get(step) {
+            Some(s) => *s,
+            None => return self.finish(),
+        };
+        self.caption.set_text(caption);
+        let preset = self.presets.get(preset_idx);
+        let from = controls.view_point(&state.borrow());
+        let to = ViewPoint {
+            cx: preset.cx(),
+            cy: preset.cy(),
+            zoom: preset.zoom(),
+        };
+        let iter_depth = preset.iter_depth();
+        let duration = Duration::from_secs_f64(2.0 + (to.zoom - from.zoom).abs() / 150.0);
+        let transition = Transition::start(
+            from,
+            to,
+            duration,
+            clone!(@strong state, @strong controls => move |vp| {
+                controls.show_view_point(&state, vp)
+            }),
+            clone!(@strong self as tour, @strong state, @strong controls => move || {
+                controls.settings.set_iterations(iter_depth as u32);
+                let hold = glib::timeout_add_local_once(
+                    HOLD,
+                    clone!(@strong tour, @strong state, @strong controls => move || {
+                        tour.tour_state.borrow_mut().hold = None;
+                        tour.run_step(step + 1, &state, &controls);
+                    }),
+                );
+                tour.tour_state.borrow_mut().hold = Some(hold);
+            }),
+        );
+        self.tour_state.borrow_mut().transition = Some(transition);
+    }
+
+    // Stop the tour and do not show it at the next start
+    fn finish(&self) {
+        let mut tour_state = self.tour_state.borrow_mut();
+        if let Some(transition) = tour_state.transition.take() {
+            transition.stop();
+        }
+        if let Some(hold) = tour_state.hold.take() {
+            hold.remove();
+        }
+        self.panel.set_visible(false);
+        let mut config = Config::load();
+        if config.show_tour {
+            config.show_tour = false;
+            if let Err(e) = config.save() {
+                eprintln!("Could not save settings: {}", e);
+            }
+        }
+    }
+}
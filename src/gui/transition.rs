@@ -0,0 +1,91 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use gtk::glib;
+
+use super::state::ZOOM_STEP;
+
+// The time between two frames of a transition
+const FRAME_INTERVAL: Duration = Duration::from_millis(40);
+
+#[derive(Clone, Copy)]
+/// The part of the view that changes during a transition: the center and the zoom slider value
+pub struct ViewPoint {
+    pub cx: f64,
+    pub cy: f64,
+    pub zoom: f64,
+}
+
+// The view at fraction t of the way from `from` to `to`. The zoom changes at a constant rate.
+// The center moves such that points move on the screen with a constant speed.
+fn interpolate(from: &ViewPoint, to: &ViewPoint, t: f64) -> ViewPoint {
+    // Start and end slowly
+    let t = t * t * (3.0 - 2.0 * t);
+    let zoom = from.zoom + (to.zoom - from.zoom) * t;
+    let ratio_end = ZOOM_STEP.powf(from.zoom - to.zoom);
+    let f = if (1.0 - ratio_end).abs() < 1e-9 {
+        t
+    } else {
+        (1.0 - ZOOM_STEP.powf(from.zoom - zoom)) / (1.0 - ratio_end)
+    };
+    ViewPoint {
+        cx: from.cx + (to.cx - from.cx) * f,
+        cy: from.cy + (to.cy - from.cy) * f,
+        zoom,
+    }
+}
+
+/// Whether the desktop asks for less motion. Animations then jump to their end at once.
+pub fn reduce_motion() -> bool {
+    gtk::Settings::default().is_some_and(|settings| !settings.is_gtk_enable_animations())
+}
+
+/// A running animation of the view
+pub struct Transition {
+    running: Rc<Cell<bool>>,
+}
+
+impl Transition {
+    /// Move the view from `from` to `to` in `duration`. show is called for every frame,
+    /// done after the last frame, unless the transition was stopped. With reduced motion
+    /// the only frame is the last one.
+    pub fn start(
+        from: ViewPoint,
+        to: ViewPoint,
+        duration: Duration,
+        show: impl Fn(&ViewPoint) + 'static,
+        done: impl FnOnce() + 'static,
+    ) -> Transition {
+        let running = Rc::new(Cell::new(true));
+        let duration = if reduce_motion() {
+            Duration::ZERO
+        } else {
+            duration
+        };
+        let start = Instant::now();
+        let mut done = Some(done);
+        let still_running = running.clone();
+        glib::timeout_add_local(FRAME_INTERVAL, move || {
+            if !still_running.get() {
+                return glib::ControlFlow::Break;
+            }
+            let t = start.elapsed().as_secs_f64() / duration.as_secs_f64().max(1e-3);
+            if t < 1.0 {
+                show(&interpolate(&from, &to, t));
+                return glib::ControlFlow::Continue;
+            }
+            show(&to);
+            still_running.set(false);
+            if let Some(done) = done.take() {
+                done();
+            }
+            glib::ControlFlow::Break
+        });
+        Transition { running }
+    }
+
+    pub fn stop(&self) {
+        self.running.set(false);
+    }
+}
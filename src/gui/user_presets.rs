@@ -0,0 +1,307 @@
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use gtk::gdk::Texture;
+use gtk::glib::{self, clone, BoxedAnyObject, KeyFile, KeyFileFlags};
+use gtk::{
+    gio, prelude::*, FileChooserAction, FileChooserNative, FileFilter, Label, ListItem,
+    ResponseType, Window,
+};
+
+use crate::location::Location;
+use crate::mandel_image::Mapping;
+use crate::png::{ColorType, PngEncoder};
+use crate::presets::{Preset, Presets};
+use crate::thumbnail::{render_thumbnail, RgbImage};
+
+use super::export::{show_error, show_info};
+use super::recent::THUMB_SZ;
+use super::state::{scale_for_zoom, zoom_for_scale};
+use super::WIN_SZ0;
+
+const DATA_DIR: &str = "mandelbrot";
+const PRESET_FILE: &str = "presets.toml";
+// The file in which earlier versions stored the presets, in the key file format
+const LEGACY_PRESET_FILE: &str = "presets.ini";
+const THUMBNAIL_DIR: &str = "preset-thumbnails";
+// The thumbnails are rendered with at most this iteration depth, so they are fast
+const THUMB_ITER_DEPTH: u32 = 250;
+// The number of thumbnails that were started, which makes the names of their temporary files
+static TEMP_THUMBNAILS: AtomicUsize = AtomicUsize::new(0);
+
+fn presets_path() -> PathBuf {
+    glib::user_data_dir().join(DATA_DIR).join(PRESET_FILE)
+}
+
+fn legacy_presets_path() -> PathBuf {
+    glib::user_data_dir()
+        .join(DATA_DIR)
+        .join(LEGACY_PRESET_FILE)
+}
+
+/// A preset of a location, with the zoom of the zoom slider
+pub fn location_preset(location: Location) -> Preset {
+    let zoom = zoom_for_scale(location.scale);
+    Preset::from_location(location, zoom)
+}
+
+/// Add the presets of a TOML file that write_presets made to presets, or of a key file of an
+/// earlier version if its name ends in .ini. A preset with the name of a user preset replaces
+/// it, and one with the name of a built-in preset gets another name. Returns the number of
+/// presets that were read.
+pub fn read_presets(path: &Path, presets: &mut Presets) -> io::Result<usize> {
+    if path.extension().is_some_and(|ext| ext == "ini") {
+        return read_ini_presets(path, presets);
+    }
+    let doc = fs::read_to_string(path)?;
+    presets
+        .add_toml(&doc, location_preset)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+// Add the presets of a key file with a group of the name and the location of every preset
+fn read_ini_presets(path: &Path, presets: &mut Presets) -> io::Result<usize> {
+    let key_file = KeyFile::new();
+    key_file
+        .load_from_file(path, KeyFileFlags::NONE)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let mut n = 0;
+    for group in key_file.groups() {
+        let Ok(name) = key_file.string(&group, "name") else {
+            continue;
+        };
+        let lookup = |key: &str| key_file.string(&group, key).ok().map(|v| v.to_string());
+        if let Some(location) = Location::from_params(lookup) {
+            presets.add(&name, location_preset(location));
+            n += 1;
+        }
+    }
+    Ok(n)
+}
+
+/// Write the presets that the user added to a TOML file, see Presets::to_toml
+pub fn write_presets(presets: &Presets, path: &Path) -> io::Result<()> {
+    path.parent().map_or(Ok(()), fs::create_dir_all)?;
+    fs::write(path, presets.to_toml())
+}
+
+/// The built-in presets and those that the user saved in the user's data directory, in the
+/// file of an earlier version if there is no other yet. An unreadable file gives only the
+/// built-in presets.
+pub fn load_presets() -> Presets {
+    let mut presets = Presets::new();
+    let path = presets_path();
+    let path = if path.exists() {
+        path
+    } else {
+        legacy_presets_path()
+    };
+    let _ = read_presets(&path, &mut presets);
+    presets
+}
+
+/// Store the presets that the user added
+pub fn save_presets(presets: &Presets) {
+    if let Err(e) = write_presets(presets, &presets_path()) {
+        eprintln!("Could not save presets: {}", e);
+    }
+}
+
+fn presets_filter() -> FileFilter {
+    let filter = FileFilter::new();
+    filter.set_name(Some("Presets"));
+    filter.add_pattern("*.toml");
+    // Files that earlier versions exported
+    filter.add_pattern("*.ini");
+    filter
+}
+
+/// Let the user choose a file of presets to add. refresh is called after they were added.
+pub fn choose_preset_import(
+    parent: &impl IsA<Window>,
+    presets: &Rc<RefCell<Presets>>,
+    refresh: impl Fn() + 'static,
+) {
+    let chooser = FileChooserNative::new(
+        Some("Import presets"),
+        Some(parent),
+        FileChooserAction::Open,
+        Some("Import"),
+        None,
+    );
+    chooser.add_filter(&presets_filter());
+    let parent: Window = parent.clone().upcast();
+    chooser.connect_response(
+        clone!(@strong presets, @weak parent => move |chooser, response| {
+            if response != ResponseType::Accept {
+                return;
+            }
+            if let Some(path) = chooser.file().and_then(|f| f.path()) {
+                let result = read_presets(&path, &mut presets.borrow_mut());
+                match result {
+                    Ok(n) => {
+                        save_presets(&presets.borrow());
+                        refresh();
+                        let detail = format!("{} presets from {}", n, path.display());
+                        show_info(&parent, "Imported presets", &detail);
+                    }
+                    Err(e) => show_error(&parent, "Could not import presets", e),
+                }
+            }
+        }),
+    );
+    chooser.show();
+}
+
+/// Let the user choose a file to export the user presets to
+pub fn choose_preset_export(parent: &impl IsA<Window>, presets: &Rc<RefCell<Presets>>) {
+    let chooser = FileChooserNative::new(
+        Some("Export presets"),
+        Some(parent),
+        FileChooserAction::Save,
+        Some("Export"),
+        None,
+    );
+    chooser.add_filter(&presets_filter());
+    chooser.set_current_name(PRESET_FILE);
+    let parent: Window = parent.clone().upcast();
+    chooser.connect_response(
+        clone!(@strong presets, @weak parent => move |chooser, response| {
+            if response != ResponseType::Accept {
+                return;
+            }
+            if let Some(path) = chooser.file().and_then(|f| f.path()) {
+                match write_presets(&presets.borrow(), &path) {
+                    Ok(()) => show_info(&parent, "Exported presets", &path.display().to_string()),
+                    Err(e) => show_error(&parent, "Could not export presets", e),
+                }
+            }
+        }),
+    );
+    chooser.show();
+}
+
+/// An entry of the preset list, with its thumbnail after that has been rendered
+pub struct PresetItem {
+    name: String,
+    location: Location,
+    thumbnail: RefCell<Option<Texture>>,
+    rendering: Cell<bool>,
+}
+
+/// The location that a preset shows. A built-in preset has the coloring and the fractal of
+/// the start view.
+pub fn preset_location(preset: &Preset) -> Location {
+    if let Some(location) = preset.location() {
+        return location.clone();
+    }
+    let mut location = Location::new(&Mapping::new_for_size(WIN_SZ0), "");
+    location.cx = preset.cx();
+    location.cy = preset.cy();
+    location.scale = scale_for_zoom(preset.zoom());
+    location.iteration_depth = preset.iter_depth() as u32;
+    location
+}
+
+/// The entries of the preset list, for a gio::ListStore
+pub fn preset_items(presets: &Presets) -> Vec<BoxedAnyObject> {
+    (0..presets.len())
+        .map(|i| {
+            BoxedAnyObject::new(PresetItem {
+                name: presets.name(i).to_string(),
+                location: preset_location(presets.get(i)),
+                thumbnail: RefCell::new(None),
+                rendering: Cell::new(false),
+            })
+        })
+        .collect()
+}
+
+// The file of the thumbnail of a location in the cache directory
+fn thumbnail_path(location: &Location) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    location.hash(&mut hasher);
+    THUMB_SZ.hash(&mut hasher);
+    glib::user_cache_dir()
+        .join(DATA_DIR)
+        .join(THUMBNAIL_DIR)
+        .join(format!("{:016x}.png", hasher.finish()))
+}
+
+// Render the thumbnail of a location into the cache directory, unless it is there already.
+// This takes a while, so it runs on a worker thread.
+fn cached_thumbnail(location: &Location) -> io::Result<PathBuf> {
+    let path = thumbnail_path(location);
+    if path.exists() {
+        return Ok(path);
+    }
+    let mut location = location.clone();
+    location.iteration_depth = location.iteration_depth.min(THUMB_ITER_DEPTH);
+    let image = render_thumbnail(&location, THUMB_SZ)
+        .ok_or_else(|| io::Error::other("the preset could not be rendered"))?;
+    path.parent().map_or(Ok(()), fs::create_dir_all)?;
+    // The thumbnail is written next to its file and renamed into place when it is complete, so
+    // that a thumbnail that is cut off is never taken for a cached one. Every render has a
+    // file of its own, as a location can be rendered for two entries at the same time.
+    let n = TEMP_THUMBNAILS.fetch_add(1, Ordering::Relaxed);
+    let temp_path = path.with_extension(format!("{}-{}.tmp", std::process::id(), n));
+    let written = write_thumbnail(&temp_path, &image).and_then(|_| fs::rename(&temp_path, &path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    written.map(|_| path)
+}
+
+fn write_thumbnail(path: &Path, image: &RgbImage) -> io::Result<()> {
+    let out = BufWriter::new(File::create(path)?);
+    let mut encoder = PngEncoder::new(out, image.width, image.height, ColorType::Rgb, &[])?;
+    for row in image.data.chunks(3 * image.width) {
+        encoder.write_row(row)?;
+    }
+    encoder.finish()?;
+    Ok(())
+}
+
+/// Show an entry of the preset list in the image and label of a row. A thumbnail that is not
+/// rendered yet is rendered in the background and shown if the row still shows the entry.
+pub fn show_preset_item(
+    list_item: &ListItem,
+    obj: &BoxedAnyObject,
+    image: &gtk::Image,
+    label: &Label,
+) {
+    let item = obj.borrow::<PresetItem>();
+    label.set_text(&item.name);
+    image.set_paintable(item.thumbnail.borrow().as_ref());
+    if item.thumbnail.borrow().is_some() || item.rendering.replace(true) {
+        return;
+    }
+    let location = item.location.clone();
+    glib::spawn_future_local(
+        clone!(@weak list_item, @strong obj, @weak image => async move {
+            let result = gio::spawn_blocking(move || cached_thumbnail(&location))
+                .await
+                .unwrap_or_else(|_| Err(io::Error::other("the thumbnail thread panicked")));
+            let result = result.and_then(|path| Texture::from_filename(path).map_err(io::Error::other));
+            let item = obj.borrow::<PresetItem>();
+            item.rendering.set(false);
+            let texture = match result {
+                Ok(texture) => texture,
+                Err(e) => {
+                    eprintln!("Could not make a preset thumbnail: {}", e);
+                    return;
+                }
+            };
+            if list_item.item().as_ref() == Some(obj.upcast_ref()) {
+                image.set_paintable(Some(&texture));
+            }
+            *item.thumbnail.borrow_mut() = Some(texture);
+        }),
+    );
+}
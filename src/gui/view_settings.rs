@@ -0,0 +1,118 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk::ffi::GTK_INVALID_LIST_POSITION;
+use gtk::glib::{self, clone, prelude::*};
+use gtk::{Adjustment, DropDown, Entry};
+
+use super::state::State;
+
+mod imp {
+    use std::cell::Cell;
+
+    use gtk::glib::{self, prelude::*, subclass::prelude::*, Properties};
+
+    #[derive(Properties, Default)]
+    #[properties(wrapper_type = super::ViewSettings)]
+    pub struct ViewSettings {
+        #[property(get, set)]
+        cx: Cell<f64>,
+        #[property(get, set)]
+        cy: Cell<f64>,
+        #[property(get, set)]
+        zoom: Cell<f64>,
+        #[property(get, set, minimum = 1)]
+        iterations: Cell<u32>,
+        // The index of the coloring in the coloring list
+        #[property(get, set)]
+        coloring: Cell<u32>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ViewSettings {
+        const NAME: &'static str = "MandelViewSettings";
+        type Type = super::ViewSettings;
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for ViewSettings {}
+}
+
+glib::wrapper! {
+    /// The parameters of the main view as properties. Widgets are bound to the
+    /// properties and the state follows them, so a control only needs a binding.
+    pub struct ViewSettings(ObjectSubclass<imp::ViewSettings>);
+}
+
+impl ViewSettings {
+    /// Make settings with the parameters of the state
+    pub fn new(state: &State, zoom: f64) -> ViewSettings {
+        glib::Object::builder()
+            .property("cx", state.cx())
+            .property("cy", state.cy())
+            .property("zoom", zoom)
+            .property("iterations", state.iter_depth() as u32)
+            .property("coloring", state.col_idx() as u32)
+            .build()
+    }
+
+    /// Let the state follow the settings
+    pub fn drive(&self, state: &Rc<RefCell<State>>) {
+        self.connect_notify_local(
+            None,
+            clone!(@strong state => move |settings, pspec| {
+                let mut state = state.borrow_mut();
+                match pspec.name() {
+                    "cx" => state.set_cx(Some(settings.cx())),
+                    "cy" => state.set_cy(Some(settings.cy())),
+                    "zoom" => state.set_zoom(settings.zoom()),
+                    "iterations" => state.set_iter_depth(settings.iterations() as f64),
+                    "coloring" => {
+                        let idx = settings.coloring();
+                        if idx != GTK_INVALID_LIST_POSITION {
+                            state.set_col_idx(idx as usize);
+                        }
+                    }
+                    _ => {}
+                }
+            }),
+        );
+    }
+
+    /// Bind a number property to the text of an entry. Text that is not a number
+    /// leaves the property unchanged.
+    pub fn bind_entry(&self, property: &str, entry: &Entry) {
+        self.bind_property(property, entry, "text")
+            .transform_to(|_, v: f64| Some(v.to_string()))
+            .transform_from(|_, text: String| text.trim().parse::<f64>().ok())
+            .bidirectional()
+            .sync_create()
+            .build();
+    }
+
+    /// Bind a number property to the value of an adjustment
+    pub fn bind_adjustment(&self, property: &str, adj: &Adjustment) {
+        self.bind_property(property, adj, "value")
+            .bidirectional()
+            .sync_create()
+            .build();
+    }
+
+    /// Bind the iteration depth to the value of an adjustment
+    pub fn bind_iterations(&self, adj: &Adjustment) {
+        self.bind_property("iterations", adj, "value")
+            .transform_to(|_, v: u32| Some(v as f64))
+            .transform_from(|_, v: f64| Some(v.round().max(1.0) as u32))
+            .bidirectional()
+            .sync_create()
+            .build();
+    }
+
+    /// Bind a property to the selected item of a drop down
+    pub fn bind_drop_down(&self, property: &str, dd: &DropDown) {
+        self.bind_property(property, dd, "selected")
+            .bidirectional()
+            .sync_create()
+            .build();
+    }
+}
@@ -0,0 +1,212 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+use gtk::glib::{KeyFile, KeyFileFlags};
+
+use crate::animation::Keyframe;
+use crate::colorings::{load_palette_as, save_palette, Coloring};
+use crate::location::Location;
+use crate::png::{ColorType, PngEncoder};
+use crate::thumbnail::render_thumbnail;
+
+use super::config::Config;
+use super::export::export_dir;
+use super::state::State;
+
+const WORKSPACE_FILE: &str = "workspace.ini";
+const SETTINGS_FILE: &str = "settings.ini";
+const PALETTE_DIR: &str = "palettes";
+const RENDER_DIR: &str = "renders";
+// The number of locations that are rendered into a workspace, and the size of the renders
+const RENDERS: usize = 4;
+const RENDER_SZ: usize = 300;
+
+/// A set of locations, palettes and settings that can be exported as a folder and imported
+/// elsewhere, e.g. to give every student in a class the same start. The folder holds:
+///
+/// - workspace.ini with the name, the locations and the keyframes of the zoom animation
+/// - settings.ini with the settings, apart from the measured speed and the threads of the machine
+/// - palettes/ with the palettes that were loaded or made, as .map files
+/// - renders/ with PNG images of the first locations, which "Open image…" can show
+pub struct Workspace {
+    pub name: String,
+    pub settings: Config,
+    /// The recent locations, most recent first
+    pub locations: Vec<Location>,
+    /// The timeline of the zoom animation
+    pub timeline: Vec<Keyframe>,
+    pub palettes: Vec<Box<dyn Coloring>>,
+    /// Why the palettes that could not be read were left out, one line each
+    pub palette_errors: Vec<String>,
+}
+
+// A name as far as a file name can hold it
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c == '/' || c == '\\' { '_' } else { c })
+        .collect()
+}
+
+fn write_render(location: &Location, path: &Path) -> io::Result<()> {
+    let image = render_thumbnail(location, RENDER_SZ)
+        .ok_or_else(|| io::Error::other("the location could not be rendered"))?;
+    let out = BufWriter::new(File::create(path)?);
+    let mut encoder = PngEncoder::new(
+        out,
+        image.width,
+        image.height,
+        ColorType::Rgb,
+        &location.params(),
+    )?;
+    for row in image.data.chunks(3 * image.width) {
+        encoder.write_row(row)?;
+    }
+    encoder.finish()?;
+    Ok(())
+}
+
+fn save_key_file(key_file: &KeyFile, path: &Path) -> io::Result<()> {
+    key_file.save_to_file(path).map_err(io::Error::other)
+}
+
+impl Workspace {
+    /// The workspace of the current session
+    pub fn from_state(state: &State, name: &str) -> Workspace {
+        let recent = state.recent();
+        let mut settings = Config::load();
+        // The speed and the threads belong to the machine, not to the workspace
+        settings.pixels_per_second = None;
+        settings.worker_threads = 0;
+        Workspace {
+            name: name.to_string(),
+            settings,
+            locations: (0..recent.len())
+                .map(|i| recent.location(i).clone())
+                .collect(),
+            timeline: state.timeline().to_vec(),
+            palettes: state.added_colorings().to_vec(),
+            palette_errors: Vec::new(),
+        }
+    }
+
+    /// Write the workspace into the folder dir, which is made if needed. Rendering the
+    /// locations takes a while, so this is best done in the background.
+    pub fn write(&self, dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(dir.join(PALETTE_DIR))?;
+        fs::create_dir_all(dir.join(RENDER_DIR))?;
+        let key_file = KeyFile::new();
+        key_file.set_string("workspace", "name", &self.name);
+        for (i, location) in self.locations.iter().enumerate() {
+            let group = format!("location{}", i);
+            for (key, value) in location.params() {
+                key_file.set_string(&group, key, &value);
+            }
+        }
+        for (i, keyframe) in self.timeline.iter().enumerate() {
+            let group = format!("keyframe{}", i);
+            for (key, value) in keyframe.location.params() {
+                key_file.set_string(&group, key, &value);
+            }
+            key_file.set_uint64(&group, "hold", keyframe.hold as u64);
+            key_file.set_uint64(&group, "frames", keyframe.frames as u64);
+        }
+        save_key_file(&key_file, &dir.join(WORKSPACE_FILE))?;
+        self.settings.save_to(&dir.join(SETTINGS_FILE))?;
+        for palette in &self.palettes {
+            let path = dir
+                .join(PALETTE_DIR)
+                .join(format!("{}.map", file_name(palette.name())));
+            save_palette(palette.as_ref(), &path)?;
+        }
+        for (i, location) in self.locations.iter().take(RENDERS).enumerate() {
+            let path = dir.join(RENDER_DIR).join(format!("location{}.png", i));
+            write_render(location, &path)?;
+        }
+        Ok(())
+    }
+
+    /// Read a workspace from a folder made by write
+    pub fn read(dir: &Path) -> io::Result<Workspace> {
+        let key_file = KeyFile::new();
+        key_file
+            .load_from_file(dir.join(WORKSPACE_FILE), KeyFileFlags::NONE)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        let name = key_file
+            .string("workspace", "name")
+            .map(|s| s.to_string())
+            .unwrap_or_default();
+        let locations = key_file
+            .groups()
+            .iter()
+            .filter(|group| group.starts_with("location"))
+            .filter_map(|group| {
+                Location::from_params(|key| key_file.string(group, key).ok().map(|v| v.to_string()))
+            })
+            .collect();
+        let timeline = key_file
+            .groups()
+            .iter()
+            .filter(|group| group.starts_with("keyframe"))
+            .filter_map(|group| {
+                let location = Location::from_params(|key| {
+                    key_file.string(group, key).ok().map(|v| v.to_string())
+                })?;
+                Some(Keyframe {
+                    location,
+                    hold: key_file.uint64(group, "hold").unwrap_or(0) as usize,
+                    frames: key_file.uint64(group, "frames").ok()?.max(1) as usize,
+                })
+            })
+            .collect();
+        let mut palettes: Vec<Box<dyn Coloring>> = Vec::new();
+        let mut palette_errors = Vec::new();
+        if let Ok(entries) = fs::read_dir(dir.join(PALETTE_DIR)) {
+            for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+                let Some(stem) = path.file_stem().map(|s| s.to_string_lossy().into_owned()) else {
+                    continue;
+                };
+                match load_palette_as(&path, &stem) {
+                    Ok(palette) => palettes.push(Box::new(palette)),
+                    Err(e) => palette_errors.push(format!("{}: {}", path.display(), e)),
+                }
+            }
+        }
+        Ok(Workspace {
+            name,
+            settings: Config::load_from(&dir.join(SETTINGS_FILE)),
+            locations,
+            timeline,
+            palettes,
+            palette_errors,
+        })
+    }
+
+    /// Make the workspace that was read from dir the current one: add its palettes, make its
+    /// locations the recent ones and its keyframes the timeline, use its settings and copy its renders to the folder of
+    /// exported images. Returns the folder with the renders.
+    pub fn apply(self, dir: &Path, state: &mut State) -> io::Result<PathBuf> {
+        for palette in self.palettes {
+            state.add_coloring(palette);
+        }
+        for location in self.locations.into_iter().rev() {
+            state.recent_mut().add(location);
+        }
+        state.set_timeline(self.timeline);
+        let mut settings = self.settings;
+        let own = Config::load();
+        settings.pixels_per_second = own.pixels_per_second;
+        settings.worker_threads = own.worker_threads;
+        settings.show_tour = own.show_tour;
+        settings.save()?;
+        state.apply_config(&settings);
+        let render_dir = export_dir().join(format!("workspace-{}", file_name(&self.name)));
+        fs::create_dir_all(&render_dir)?;
+        if let Ok(entries) = fs::read_dir(dir.join(RENDER_DIR)) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                fs::copy(entry.path(), render_dir.join(entry.file_name()))?;
+            }
+        }
+        Ok(render_dir)
+    }
+}
@@ -0,0 +1,412 @@
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+use gtk::glib::clone;
+use gtk::{
+    gio, glib, prelude::*, Adjustment, Button, DropDown, FileChooserAction, FileChooserNative,
+    Grid, Label, ListBox, ProgressBar, ResponseType, ScrolledWindow, SelectionMode, SpinButton,
+    ToggleButton, Window,
+};
+
+use crate::animation::{zoom_schedule, AnimationOutput, Keyframe, ZoomAnimation};
+use crate::channels::{channel, Policy};
+use crate::mandel_image::{CancelToken, Canceller};
+
+use super::export::{confirm_render, export_dir, show_error, timestamp};
+use super::state::State;
+use super::Controls;
+
+const OUTPUT_NAMES: [&str; 2] = ["PNG frames", "video (ffmpeg)"];
+const VIDEO_OUTPUT: u32 = 1;
+// The frames from a new keyframe to the next, and the frames that it holds
+const KEYFRAME_FRAMES: usize = 120;
+const KEYFRAME_HOLD: usize = 0;
+
+#[derive(Clone)]
+/// A window with the timeline of a zoom animation: a list of keyframes, each with the number
+/// of frames that it is held and the number of frames to the next keyframe. The timeline can
+/// be played in the main window and exported as PNG frames or as a video.
+pub struct ZoomAnimationWindow {
+    state: Rc<RefCell<State>>,
+    controls: Controls,
+    win: Window,
+    list: ListBox,
+    fps_adj: Adjustment,
+    width_adj: Adjustment,
+    height_adj: Adjustment,
+    output_choice: DropDown,
+    play_btn: ToggleButton,
+    export_btn: Button,
+    stop_btn: Button,
+    progress: ProgressBar,
+    status: Label,
+    canceller: Rc<Canceller>,
+    running: Rc<Cell<bool>>,
+}
+
+fn spin_button(adj: &Adjustment) -> SpinButton {
+    SpinButton::builder().adjustment(adj).build()
+}
+
+fn describe(i: usize, keyframe: &Keyframe) -> String {
+    let location = &keyframe.location;
+    format!(
+        "{}: {:.6}, {:.6}, scale {:.3e}",
+        i + 1,
+        location.cx,
+        location.cy,
+        location.scale
+    )
+}
+
+impl ZoomAnimationWindow {
+    pub fn new(
+        parent: &impl IsA<Window>,
+        state: &Rc<RefCell<State>>,
+        controls: &Controls,
+    ) -> ZoomAnimationWindow {
+        let add_btn = Button::builder().label("Add current view").build();
+        let list = ListBox::builder()
+            .selection_mode(SelectionMode::None)
+            .build();
+        let scroller = ScrolledWindow::builder()
+            .child(&list)
+            .min_content_height(200)
+            .min_content_width(560)
+            .build();
+        let fps_adj = Adjustment::new(30.0, 1.0, 120.0, 1.0, 0.0, 0.0);
+        let width_adj = Adjustment::new(1280.0, 16.0, 7680.0, 1.0, 0.0, 0.0);
+        let height_adj = Adjustment::new(720.0, 16.0, 4320.0, 1.0, 0.0, 0.0);
+        let output_choice = DropDown::from_strings(&OUTPUT_NAMES);
+        let play_btn = ToggleButton::builder().label("Play").build();
+        let export_btn = Button::builder()
+            .label("Export...")
+            .margin_start(10)
+            .build();
+        let stop_btn = Button::builder()
+            .label("Stop")
+            .sensitive(false)
+            .margin_start(10)
+            .build();
+        let progress = ProgressBar::builder().show_text(true).build();
+        let status = Label::builder().xalign(0.0).build();
+        let grid = Grid::builder()
+            .row_spacing(5)
+            .column_spacing(10)
+            .margin_top(20)
+            .margin_start(20)
+            .margin_bottom(20)
+            .margin_end(20)
+            .build();
+        grid.attach(&add_btn, 0, 0, 1, 1);
+        grid.attach(&scroller, 0, 1, 2, 1);
+        let rows: [(&str, gtk::Widget); 4] = [
+            ("frames per second:", spin_button(&fps_adj).upcast()),
+            ("width:", spin_button(&width_adj).upcast()),
+            ("height:", spin_button(&height_adj).upcast()),
+            ("output:", output_choice.clone().upcast()),
+        ];
+        for (row, (name, widget)) in rows.iter().enumerate() {
+            grid.attach(
+                &Label::builder().label(*name).xalign(1.0).build(),
+                0,
+                row as i32 + 2,
+                1,
+                1,
+            );
+            grid.attach(widget, 1, row as i32 + 2, 1, 1);
+        }
+        let button_row = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .margin_top(10)
+            .build();
+        button_row.append(&play_btn);
+        button_row.append(&export_btn);
+        button_row.append(&stop_btn);
+        grid.attach(&button_row, 1, 6, 1, 1);
+        grid.attach(&progress, 0, 7, 2, 1);
+        grid.attach(&status, 0, 8, 2, 1);
+        let win = Window::builder()
+            .title("Zoom animation")
+            .transient_for(parent)
+            .hide_on_close(true)
+            .child(&grid)
+            .build();
+        let zoom_win = ZoomAnimationWindow {
+            state: state.clone(),
+            controls: controls.clone(),
+            win,
+            list,
+            fps_adj,
+            width_adj,
+            height_adj,
+            output_choice,
+            play_btn,
+            export_btn,
+            stop_btn,
+            progress,
+            status,
+            canceller: Rc::new(Canceller::new()),
+            running: Rc::new(Cell::new(false)),
+        };
+        add_btn.connect_clicked(clone!(@strong zoom_win => move |_| zoom_win.add_keyframe()));
+        zoom_win
+            .play_btn
+            .connect_toggled(clone!(@strong zoom_win => move |btn| {
+                if btn.is_active() {
+                    zoom_win.play();
+                }
+            }));
+        zoom_win
+            .export_btn
+            .connect_clicked(clone!(@strong zoom_win => move |_| zoom_win.choose_output()));
+        zoom_win
+            .stop_btn
+            .connect_clicked(clone!(@strong zoom_win => move |_| {
+                // Handing out a new token cancels the running export
+                zoom_win.canceller.next_token();
+            }));
+        zoom_win
+    }
+
+    /// Show the window with the timeline of the state, e.g. after a workspace was imported
+    pub fn present(&self) {
+        self.refresh();
+        self.win.present();
+    }
+
+    fn add_keyframe(&self) {
+        let mut timeline = self.state.borrow().timeline().to_vec();
+        timeline.push(Keyframe {
+            location: self.state.borrow().location(),
+            hold: KEYFRAME_HOLD,
+            frames: KEYFRAME_FRAMES,
+        });
+        self.state.borrow_mut().set_timeline(timeline);
+        self.refresh();
+    }
+
+    // Change the timeline of the state and show it
+    fn edit(&self, change: impl FnOnce(&mut Vec<Keyframe>)) {
+        let mut timeline = self.state.borrow().timeline().to_vec();
+        change(&mut timeline);
+        self.state.borrow_mut().set_timeline(timeline);
+        self.refresh();
+    }
+
+    // A row of the list, for keyframe i of n
+    fn keyframe_row(&self, i: usize, n: usize, keyframe: &Keyframe) -> gtk::Box {
+        let row = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .spacing(5)
+            .build();
+        let label = Label::builder()
+            .label(describe(i, keyframe))
+            .xalign(0.0)
+            .hexpand(true)
+            .build();
+        let hold_adj = Adjustment::new(keyframe.hold as f64, 0.0, 10000.0, 1.0, 0.0, 0.0);
+        let frames_adj = Adjustment::new(keyframe.frames as f64, 1.0, 100000.0, 1.0, 0.0, 0.0);
+        let frames_btn = spin_button(&frames_adj);
+        // The last keyframe has no next one to move to
+        frames_btn.set_sensitive(i + 1 < n);
+        let show_btn = Button::builder().label("Show").build();
+        let up_btn = Button::builder().label("↑").sensitive(i > 0).build();
+        let down_btn = Button::builder().label("↓").sensitive(i + 1 < n).build();
+        let remove_btn = Button::builder().label("✕").build();
+        row.append(&label);
+        row.append(&Label::new(Some("hold:")));
+        row.append(&spin_button(&hold_adj));
+        row.append(&Label::new(Some("frames:")));
+        row.append(&frames_btn);
+        row.append(&show_btn);
+        row.append(&up_btn);
+        row.append(&down_btn);
+        row.append(&remove_btn);
+        // These do not refresh the list, so the spin button keeps the focus
+        hold_adj.connect_value_changed(clone!(@strong self.state as state => move |adj| {
+            let mut timeline = state.borrow().timeline().to_vec();
+            if let Some(keyframe) = timeline.get_mut(i) {
+                keyframe.hold = adj.value() as usize;
+            }
+            state.borrow_mut().set_timeline(timeline);
+        }));
+        frames_adj.connect_value_changed(clone!(@strong self.state as state => move |adj| {
+            let mut timeline = state.borrow().timeline().to_vec();
+            if let Some(keyframe) = timeline.get_mut(i) {
+                keyframe.frames = adj.value() as usize;
+            }
+            state.borrow_mut().set_timeline(timeline);
+        }));
+        let location = keyframe.location.clone();
+        show_btn.connect_clicked(clone!(@strong self as zoom_win => move |_| {
+            zoom_win.state.borrow_mut().remember_location();
+            zoom_win.controls.show_location(&zoom_win.state, &location);
+        }));
+        up_btn.connect_clicked(clone!(@strong self as zoom_win => move |_| {
+            zoom_win.edit(|timeline| timeline.swap(i - 1, i));
+        }));
+        down_btn.connect_clicked(clone!(@strong self as zoom_win => move |_| {
+            zoom_win.edit(|timeline| timeline.swap(i, i + 1));
+        }));
+        remove_btn.connect_clicked(clone!(@strong self as zoom_win => move |_| {
+            zoom_win.edit(|timeline| {
+                timeline.remove(i);
+            });
+        }));
+        row
+    }
+
+    fn refresh(&self) {
+        while let Some(row) = self.list.row_at_index(0) {
+            self.list.remove(&row);
+        }
+        let timeline = self.state.borrow().timeline().to_vec();
+        for (i, keyframe) in timeline.iter().enumerate() {
+            self.list
+                .append(&self.keyframe_row(i, timeline.len(), keyframe));
+        }
+        let ready = !self.running.get() && timeline.len() >= 2;
+        self.export_btn.set_sensitive(ready);
+        self.play_btn.set_sensitive(timeline.len() >= 2);
+    }
+
+    // Show the frames one after the other in the main window, at the frame rate of the
+    // export, until the last frame or until Play is switched off. The main window shows
+    // as much of every frame as it can render in time.
+    fn play(&self) {
+        let views = zoom_schedule(self.state.borrow().timeline());
+        let interval = Duration::from_secs_f64(1.0 / self.fps_adj.value().max(1.0));
+        let next = Cell::new(0);
+        glib::timeout_add_local(
+            interval,
+            clone!(@strong self as zoom_win => move || {
+                let i = next.get();
+                if !zoom_win.play_btn.is_active() || i >= views.len() {
+                    zoom_win.play_btn.set_active(false);
+                    return glib::ControlFlow::Break;
+                }
+                zoom_win.controls.show_location(&zoom_win.state, &views[i]);
+                next.set(i + 1);
+                glib::ControlFlow::Continue
+            }),
+        );
+    }
+
+    fn choose_output(&self) {
+        let video = self.output_choice.selected() == VIDEO_OUTPUT;
+        let (title, action) = if video {
+            ("Video file", FileChooserAction::Save)
+        } else {
+            ("Folder for the frames", FileChooserAction::SelectFolder)
+        };
+        let chooser =
+            FileChooserNative::new(Some(title), Some(&self.win), action, Some("Export"), None);
+        if video {
+            let _ = chooser.set_current_folder(Some(&gio::File::for_path(export_dir())));
+            let name = format!("zoom-{}.mp4", timestamp().unwrap_or_default());
+            chooser.set_current_name(&name);
+        }
+        chooser.connect_response(
+            clone!(@strong self as zoom_win => move |chooser, response| {
+                if response == ResponseType::Accept {
+                    if let Some(path) = chooser.file().and_then(|f| f.path()) {
+                        zoom_win.confirm_export(path, video);
+                    }
+                }
+            }),
+        );
+        chooser.show();
+    }
+
+    fn animation(&self) -> ZoomAnimation {
+        let state = self.state.borrow();
+        let mapping = state.mapping();
+        ZoomAnimation {
+            views: zoom_schedule(state.timeline()),
+            // The keyframes are shown as in the main window
+            view_size: (mapping.win_width, mapping.win_height),
+            size: (
+                self.width_adj.value() as usize,
+                self.height_adj.value() as usize,
+            ),
+            coloring: state.coloring(),
+        }
+    }
+
+    // Export, after a confirmation if all the frames together would take long
+    fn confirm_export(&self, path: PathBuf, video: bool) {
+        let animation = self.animation();
+        let n_frames = animation.views.len();
+        let pixels = (animation.size.0 * animation.size.1 * n_frames) as f64;
+        // The time of a frame grows about linearly with its depth
+        let depths: f64 = animation
+            .views
+            .iter()
+            .map(|v| v.iteration_depth as f64)
+            .sum();
+        let mean_depth = (depths / n_frames.max(1) as f64).round() as u32;
+        let calibration = self.state.borrow().calibration().clone();
+        let output = if video {
+            AnimationOutput::Video(path, self.fps_adj.value() as u32)
+        } else {
+            AnimationOutput::Frames(path)
+        };
+        let job = Rc::new(RefCell::new(Some((animation, output))));
+        confirm_render(
+            &self.win,
+            &calibration,
+            pixels,
+            mean_depth,
+            clone!(@strong self as zoom_win => move || {
+                if let Some((animation, output)) = job.borrow_mut().take() {
+                    zoom_win.export(animation, output);
+                }
+            }),
+        );
+    }
+
+    fn export(&self, animation: ZoomAnimation, output: AnimationOutput) {
+        let n_frames = animation.views.len();
+        let cancel: CancelToken = self.canceller.next_token();
+        self.running.set(true);
+        self.export_btn.set_sensitive(false);
+        self.stop_btn.set_sensitive(true);
+        self.progress.set_fraction(0.0);
+        self.status.set_text(&format!("0 of {} frames", n_frames));
+        // Only the latest progress is of interest
+        let (progress, progress_receiver) = channel(1, Policy::LatestWins);
+        glib::spawn_future_local(clone!(@strong self as zoom_win => async move {
+            while let Ok(n) = progress_receiver.recv().await {
+                zoom_win.progress.set_fraction(n as f64 / n_frames as f64);
+                zoom_win.status.set_text(&format!("{} of {} frames", n, n_frames));
+            }
+        }));
+        let zoom_win = self.clone();
+        glib::spawn_future_local(async move {
+            let result = gio::spawn_blocking(move || {
+                animation.export(&output, &cancel, |n| {
+                    progress.send_blocking(n);
+                })
+            })
+            .await
+            .unwrap_or_else(|_| Err(io::Error::other("the export thread panicked")));
+            zoom_win.running.set(false);
+            zoom_win.stop_btn.set_sensitive(false);
+            zoom_win.refresh();
+            let text = match result {
+                Ok(n) if n == n_frames => format!("wrote {} frames", n),
+                Ok(n) => format!("stopped after {} frames", n),
+                Err(e) => {
+                    let text = format!("export failed: {}", e);
+                    show_error(&zoom_win.win, "Could not export the animation", e);
+                    text
+                }
+            };
+            zoom_win.status.set_text(&text);
+        });
+    }
+}
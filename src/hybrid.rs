@@ -0,0 +1,293 @@
+// Hybrid scheduling: every frame is split between two backends that render at the same time,
+// one on the threads of the pool and one on a thread of its own, the way a GPU works next to
+// the processor. The rows of a frame are divided in proportion to the throughput that was
+// measured for each in the frames before it. A device that fails where the pooled backend does
+// not, like a GPU device that is lost, has its rows rendered by the pooled backend, and is
+// left out of the frames after it for a while. There is no GPU backend yet, so no hybrid
+// backend can be chosen; the tests use the scalar backend on its thread as the device.
+
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::thread;
+use std::time::Instant;
+
+use scoped_threadpool::Pool;
+
+use crate::backend::RenderBackend;
+use crate::colorings::Coloring;
+use crate::mandel_image::{
+    sample_histogram, tile_mapping, CancelToken, IterationBuffer, Mapping, RenderStats,
+};
+use crate::perturbation::uses_perturbation;
+
+// The share of the rows of a frame that the device gets before anything is measured
+const START_SHARE: f64 = 0.25;
+// How much a new measurement counts in the share of the device
+const SHARE_WEIGHT: f64 = 0.5;
+// The device always gets at least this share, so that its throughput is measured again
+const MIN_SHARE: f64 = 0.02;
+// The number of frames that the pooled backend renders alone after the device failed
+const LOST_FRAMES: u32 = 100;
+
+/// A backend that splits every frame between a backend on the pool and a device backend on a
+/// thread of its own, in proportion to their measured throughput
+pub struct HybridBackend {
+    name: &'static str,
+    pooled: &'static dyn RenderBackend,
+    device: &'static dyn RenderBackend,
+    // The share of the rows of a frame that the device renders, as the bits of an f64
+    device_share: AtomicU64,
+    // The number of frames that the pooled backend still renders alone since the device failed
+    lost_frames: AtomicU32,
+}
+
+impl HybridBackend {
+    pub const fn new(
+        name: &'static str,
+        pooled: &'static dyn RenderBackend,
+        device: &'static dyn RenderBackend,
+    ) -> HybridBackend {
+        HybridBackend {
+            name,
+            pooled,
+            device,
+            device_share: AtomicU64::new(START_SHARE.to_bits()),
+            lost_frames: AtomicU32::new(0),
+        }
+    }
+
+    /// The share of the rows of a frame that the device renders
+    pub fn device_share(&self) -> f64 {
+        f64::from_bits(self.device_share.load(Ordering::Relaxed))
+    }
+
+    /// Whether the device failed recently, so that the pooled backend renders alone
+    pub fn device_lost(&self) -> bool {
+        self.lost_frames.load(Ordering::Relaxed) > 0
+    }
+
+    // The number of rows at the top of a view that the device renders, a multiple of block, or
+    // 0 if the view is not split. Views without a pool are not split, and neither are deep
+    // views, for which both parts would iterate their own reference orbit.
+    fn device_rows(&self, mapping: &Mapping, pool: &Option<Pool>, block: usize) -> usize {
+        if pool.is_none() || self.device_lost() || uses_perturbation(mapping) {
+            return 0;
+        }
+        let blocks = mapping.win_height / block;
+        if blocks < 2 {
+            return 0;
+        }
+        let device_blocks = (self.device_share() * blocks as f64).round() as usize;
+        device_blocks.clamp(1, blocks - 1) * block
+    }
+
+    // Move the share of the device towards the share at which both parts take as long, from
+    // the rows and the seconds that each took
+    fn measure(&self, device: (usize, f64), pooled: (usize, f64)) {
+        let speed = |(rows, seconds): (usize, f64)| rows as f64 / seconds.max(1e-6);
+        let (device_speed, pooled_speed) = (speed(device), speed(pooled));
+        let share = device_speed / (device_speed + pooled_speed);
+        let share = self.device_share() * (1.0 - SHARE_WEIGHT) + share * SHARE_WEIGHT;
+        self.device_share.store(
+            share.clamp(MIN_SHARE, 1.0 - MIN_SHARE).to_bits(),
+            Ordering::Relaxed,
+        );
+    }
+
+    // Render the rows of a view with part, split between the device and the pooled backend,
+    // and join the parts, the device part on top. Returns None if cancelled.
+    fn split<T: Send>(
+        &self,
+        mapping: &Mapping,
+        pool: &mut Option<Pool>,
+        block: usize,
+        cancel: &CancelToken,
+        part: impl Fn(&dyn RenderBackend, &Mapping, &mut Option<Pool>) -> Option<T> + Sync,
+        join: impl FnOnce(T, T) -> T,
+    ) -> Option<T> {
+        let rows = self.device_rows(mapping, pool, block);
+        if rows == 0 {
+            // A device that failed is tried again after LOST_FRAMES frames
+            let count_down = |frames: u32| frames.checked_sub(1);
+            _ = self
+                .lost_frames
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, count_down);
+            return part(self.pooled, mapping, pool);
+        }
+        let top = tile_mapping(mapping, 0, rows);
+        let bottom = tile_mapping(mapping, rows, mapping.win_height - rows);
+        let (device_part, pooled_part) = thread::scope(|scope| {
+            let device = scope.spawn(|| {
+                let start = Instant::now();
+                let result = part(self.device, &top, &mut None);
+                (result, start.elapsed().as_secs_f64())
+            });
+            let start = Instant::now();
+            let pooled = part(self.pooled, &bottom, pool);
+            let pooled_seconds = start.elapsed().as_secs_f64();
+            let device = device.join().unwrap_or((None, 0.0));
+            (device, (pooled, pooled_seconds))
+        });
+        let pooled_rows = mapping.win_height - rows;
+        let pooled_result = pooled_part.0?;
+        let device_result = match device_part {
+            (Some(result), seconds) => {
+                self.measure((rows, seconds), (pooled_rows, pooled_part.1));
+                result
+            }
+            (None, _) if cancel.is_cancelled() => return None,
+            (None, _) => {
+                // The pooled backend rendered its part, so the view is fine and the device
+                // failed
+                let result = part(self.pooled, &top, pool)?;
+                self.lost_frames.store(LOST_FRAMES, Ordering::Relaxed);
+                result
+            }
+        };
+        Some(join(device_result, pooled_result))
+    }
+}
+
+impl RenderBackend for HybridBackend {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn render(
+        &self,
+        mapping: &Mapping,
+        coloring: &dyn Coloring,
+        pool: &mut Option<Pool>,
+        block: usize,
+        cancel: &CancelToken,
+    ) -> Option<(Vec<u8>, i32, RenderStats)> {
+        // Both parts are colored for the histogram of the whole view
+        let for_histogram;
+        let coloring = if coloring.needs_histogram() && self.device_rows(mapping, pool, block) > 0 {
+            let histogram = sample_histogram(mapping, pool, cancel)?;
+            for_histogram = coloring.for_histogram(&histogram);
+            for_histogram.as_deref().unwrap_or(coloring)
+        } else {
+            coloring
+        };
+        self.split(
+            mapping,
+            pool,
+            block,
+            cancel,
+            |backend, part, pool| backend.render(part, coloring, pool, block, cancel),
+            |(mut data, stride, mut stats), (bottom, _, bottom_stats)| {
+                data.extend_from_slice(&bottom);
+                stats.add(&bottom_stats);
+                (data, stride, stats)
+            },
+        )
+    }
+
+    fn values(
+        &self,
+        mapping: &Mapping,
+        pool: &mut Option<Pool>,
+        cancel: &CancelToken,
+    ) -> Option<IterationBuffer> {
+        self.split(
+            mapping,
+            pool,
+            1,
+            cancel,
+            |backend, part, pool| backend.values(part, pool, cancel),
+            |mut top, bottom| {
+                top.values.extend_from_slice(&bottom.values);
+                top.smooth.extend_from_slice(&bottom.smooth);
+                top.mapping = mapping.clone();
+                top
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{SCALAR, SIMD};
+    use crate::colorings::ColorInfo;
+    use crate::mandel_image::make_iteration_buffer;
+
+    // A backend that fails every render, like a device that is lost
+    struct LostDevice;
+
+    impl RenderBackend for LostDevice {
+        fn name(&self) -> &'static str {
+            "lost"
+        }
+        fn render(
+            &self,
+            _mapping: &Mapping,
+            _coloring: &dyn Coloring,
+            _pool: &mut Option<Pool>,
+            _block: usize,
+            _cancel: &CancelToken,
+        ) -> Option<(Vec<u8>, i32, RenderStats)> {
+            None
+        }
+        fn values(
+            &self,
+            _mapping: &Mapping,
+            _pool: &mut Option<Pool>,
+            _cancel: &CancelToken,
+        ) -> Option<IterationBuffer> {
+            None
+        }
+    }
+
+    static LOST: LostDevice = LostDevice;
+
+    #[test]
+    fn frames_are_split_by_throughput() {
+        let hybrid = HybridBackend::new("test", &SIMD, &SCALAR);
+        let mut mapping = Mapping::new_for_size(120);
+        mapping.iteration_depth = 200;
+        let (mut pool, cancel) = (Some(Pool::new(2)), CancelToken::never());
+        let view = make_iteration_buffer(&mapping, &mut None, &cancel).unwrap();
+        let split = hybrid.values(&mapping, &mut pool, &cancel).unwrap();
+        assert!(split.mapping == mapping);
+        assert_eq!((split.values, split.smooth), (view.values, view.smooth));
+        // A device that is as fast as the pool moves halfway to half of the rows
+        let hybrid = HybridBackend::new("test", &SIMD, &SCALAR);
+        hybrid.measure((30, 1.0), (30, 1.0));
+        let expected = START_SHARE * (1.0 - SHARE_WEIGHT) + 0.5 * SHARE_WEIGHT;
+        assert!((hybrid.device_share() - expected).abs() < 1e-12);
+        // Blocks are not split
+        assert_eq!(hybrid.device_rows(&mapping, &pool, 8) % 8, 0);
+        assert_eq!(hybrid.device_rows(&mapping, &None, 1), 0);
+    }
+
+    #[test]
+    fn a_lost_device_falls_back_to_the_pool() {
+        let hybrid = HybridBackend::new("test", &SIMD, &LOST);
+        let mapping = Mapping::new_for_size(40);
+        let coloring = ColorInfo::new().scheme(0).clone();
+        let (mut pool, cancel) = (Some(Pool::new(2)), CancelToken::never());
+        let (data, stride, _) = hybrid
+            .render(&mapping, coloring.as_ref(), &mut pool, 1, &cancel)
+            .unwrap();
+        assert!(hybrid.device_lost());
+        assert_eq!(data.len(), 40 * stride as usize);
+        // For the next frames the pooled backend renders the whole view
+        assert_eq!(hybrid.device_rows(&mapping, &pool, 1), 0);
+        let rendered = SIMD.render(&mapping, coloring.as_ref(), &mut pool, 1, &cancel);
+        let again = hybrid.render(&mapping, coloring.as_ref(), &mut pool, 1, &cancel);
+        assert_eq!(again.map(|image| image.0), rendered.map(|image| image.0));
+        for _ in 1..LOST_FRAMES {
+            assert!(hybrid.device_lost());
+            hybrid.values(&mapping, &mut pool, &cancel).unwrap();
+        }
+        assert!(!hybrid.device_lost());
+        // A view that no backend can render does not make the device lost
+        let invalid = Mapping {
+            iteration_depth: 0,
+            ..mapping.clone()
+        };
+        assert!(hybrid.values(&invalid, &mut pool, &cancel).is_none());
+        assert!(!hybrid.device_lost());
+    }
+}
@@ -1,7 +1,10 @@
 use gtk::cairo::{Format, ImageSurface};
 
 pub struct Image {
-    _data: Vec<u8>,
+    data: Vec<u8>,
+    width: i32,
+    height: i32,
+    stride: i32,
     surface: ImageSurface,
 }
 
@@ -20,7 +23,10 @@ impl Image {
             .unwrap();
         }
         Image {
-            _data: data,
+            data,
+            width,
+            height,
+            stride,
             surface,
         }
     }
@@ -28,6 +34,39 @@ impl Image {
     pub fn surface(&self) -> &ImageSurface {
         &self.surface
     }
+    pub fn data(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+    pub fn width(&self) -> usize {
+        self.width as usize
+    }
+    pub fn height(&self) -> usize {
+        self.height as usize
+    }
+    pub fn stride(&self) -> usize {
+        self.stride as usize
+    }
+    /// Copy an image of width x height pixels, with the given stride, into this one at (x, y)
+    pub fn blit(
+        &mut self,
+        data: &[u8],
+        stride: usize,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) {
+        self.surface.flush();
+        let own_stride = self.stride();
+        let width = width.min(self.width().saturating_sub(x));
+        let height = height.min(self.height().saturating_sub(y));
+        for row in 0..height {
+            let start = (y + row) * own_stride + 4 * x;
+            self.data[start..start + 4 * width]
+                .copy_from_slice(&data[row * stride..row * stride + 4 * width]);
+        }
+        self.surface.mark_dirty();
+    }
 }
 
 impl Drop for Image {
@@ -1,22 +1,108 @@
+use backend::RenderBackend;
 use colorings::Coloring;
-use mandel_image::Mapping;
+use mandel_image::{CancelToken, IterationBuffer, Mapping, RenderStats};
+use shading::Lighting;
+use std::sync::Arc;
 
+pub mod accumulate;
+pub mod animation;
+pub mod backend;
+pub mod benchmark;
+pub mod channels;
 pub mod colorings;
+pub mod curves;
+pub mod double_double;
+pub mod export;
+pub mod fixed;
+pub mod formula;
+pub mod fractal;
+pub mod ggr;
+#[cfg(feature = "gui")]
 pub mod gui;
+pub mod hybrid;
+#[cfg(feature = "gui")]
 pub mod image;
+pub mod location;
 pub mod mandel_image;
+pub mod mariani_silver;
+pub mod newton;
+pub mod perturbation;
+pub mod plugins;
+pub mod png;
 pub mod presets;
+pub mod random;
+pub mod report;
+pub mod shading;
+pub mod simd;
+pub mod thumbnail;
+pub mod view_history;
 
+#[cfg(feature = "gui")]
 const IMG_FMT: gtk::cairo::Format = gtk::cairo::Format::Rgb24;
 
+/// The number of bytes per row of an image of the given width. Images have the
+/// RGB24 format of cairo: 4 bytes per pixel, 0x00RRGGBB in native endianness.
+pub fn stride_for_width(width: usize) -> Option<i32> {
+    width
+        .checked_mul(4)
+        .and_then(|stride| i32::try_from(stride).ok())
+}
+
 pub struct MandelReq {
     mapping: Mapping,
     coloring: Box<dyn Coloring>,
+    cancel: CancelToken,
+    // The block sizes of the progressive passes
+    blocks: Vec<usize>,
+    // Slope shading that is applied to the image, if any
+    lighting: Option<Lighting>,
+    // Every pixel of the last pass is the average of factor x factor samples
+    factor: usize,
+    // The backend that renders the passes
+    backend: &'static dyn RenderBackend,
+    // The values of the view before this one. If the view only moved, the pixels that are
+    // still in view are reused.
+    previous: Option<Arc<IterationBuffer>>,
+    // Whether the pixels of high contrast of the last pass are sampled again, in a refinement
+    // pass after it is shown
+    adaptive: bool,
+}
+
+/// What the producer sends to the view: the image of a pass, or a finished tile of the last
+/// pass, which can be shown over the image of the pass before it
+pub enum MandelReply {
+    Pass(MandelPass),
+    Tile(MandelTile),
+}
+
+/// A tile of the last pass of a view, in the format of stride_for_width
+pub struct MandelTile {
+    pub data: Vec<u8>,
+    /// The column and the row of the view where the tile starts
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub stride: i32,
+    /// The view that the tile is part of
+    pub mapping: Mapping,
 }
 
-pub struct MandelReply {
-    data: Vec<u8>,
-    width: i32,
-    height: i32,
-    stride: i32,
+/// A rendered image, in the format of stride_for_width
+pub struct MandelPass {
+    pub data: Vec<u8>,
+    pub width: i32,
+    pub height: i32,
+    pub stride: i32,
+    /// The block size of the pass that rendered the image
+    pub block: usize,
+    /// The time in seconds that the pass took
+    pub seconds: f64,
+    /// The view that the image shows
+    pub mapping: Mapping,
+    /// The values of the pixels, only for the last pass, from which the view can be
+    /// colored again without rendering
+    pub buffer: Option<Arc<IterationBuffer>>,
+    /// What the pass computed
+    pub stats: RenderStats,
 }
@@ -0,0 +1,320 @@
+use std::hash::{Hash, Hasher};
+
+use crate::fixed::PreciseCenter;
+use crate::formula::Formula;
+use crate::fractal::{FractalParams, FractalType};
+use crate::mandel_image::Mapping;
+use crate::newton::Polynomial;
+use crate::perturbation::uses_perturbation;
+
+/// The start of the links that open the application at a location
+pub const URI_PREFIX: &str = "mandel://locate?";
+/// The start of the short text form of a location, e.g. for a chat message
+pub const TEXT_PREFIX: &str = "mandel:";
+// The short keys of the text form for the keys of params
+const TEXT_KEYS: [(&str, &str); 11] = [
+    ("cx", "cx"),
+    ("cy", "cy"),
+    ("scale", "s"),
+    ("iteration_depth", "it"),
+    ("fractal", "f"),
+    ("power", "p"),
+    ("formula", "fo"),
+    ("polynomial", "po"),
+    ("julia_cx", "jx"),
+    ("julia_cy", "jy"),
+    ("coloring", "col"),
+];
+
+// Encode all characters that are not unreserved in a URI
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = s.get(i + 1..i + 3)?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            b'+' => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            b => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[derive(Clone, PartialEq, Debug)]
+/// A view on a fractal: everything that is needed to render it again, apart from the window size
+pub struct Location {
+    /// The x coordinate of the center of the view
+    pub cx: f64,
+    /// The y coordinate of the center of the view
+    pub cy: f64,
+    /// The length in mandelbrot space of one pixel
+    pub scale: f64,
+    pub iteration_depth: u32,
+    pub fractal: FractalParams,
+    /// The name of the coloring
+    pub coloring: String,
+    /// The center with more precision than cx and cy, as in Mapping
+    pub precise_center: Option<PreciseCenter>,
+}
+
+impl Location {
+    pub fn new(mapping: &Mapping, coloring: &str) -> Location {
+        // The precise center is stored such that cx and cy are its f64 values, and only for
+        // views that need it
+        let precise_center = mapping
+            .precise_center
+            .as_ref()
+            .filter(|_| uses_perturbation(mapping))
+            .map(|center| center.at(mapping.cx, mapping.cy));
+        let (cx, cy) = precise_center
+            .as_ref()
+            .map_or((mapping.cx, mapping.cy), |center| center.approx());
+        Location {
+            cx,
+            cy,
+            scale: mapping.scale,
+            iteration_depth: mapping.iteration_depth,
+            fractal: mapping.fractal.clone(),
+            coloring: coloring.to_string(),
+            precise_center,
+        }
+    }
+
+    /// The mapping for showing this location in a window of the given size
+    pub fn mapping(&self, win_width: usize, win_height: usize) -> Mapping {
+        Mapping {
+            cx: self.cx,
+            cy: self.cy,
+            scale: self.scale,
+            iteration_depth: self.iteration_depth,
+            win_width,
+            win_height,
+            fractal: self.fractal.clone(),
+            precise_center: self.precise_center.clone(),
+            part: None,
+        }
+    }
+
+    /// The location as keyword/value pairs, e.g. for storing in a file
+    pub fn params(&self) -> Vec<(&'static str, String)> {
+        // A precise center is written with all its digits
+        let (cx, cy) = match &self.precise_center {
+            Some(center) => {
+                let center = center.at(self.cx, self.cy);
+                (center.x.to_string(), center.y.to_string())
+            }
+            None => (self.cx.to_string(), self.cy.to_string()),
+        };
+        let mut params = vec![
+            ("cx", cx),
+            ("cy", cy),
+            ("scale", self.scale.to_string()),
+            ("iteration_depth", self.iteration_depth.to_string()),
+            ("fractal", self.fractal.fractal_type.name().to_string()),
+        ];
+        if self.fractal.power != 2 {
+            params.push(("power", self.fractal.power.to_string()));
+        }
+        if self.fractal.fractal_type == FractalType::Formula {
+            params.push(("formula", self.fractal.formula.source().to_string()));
+        }
+        if self.fractal.fractal_type == FractalType::Newton {
+            params.push(("polynomial", self.fractal.polynomial.to_string()));
+        }
+        if self.fractal.fractal_type == FractalType::Julia {
+            params.push(("julia_cx", self.fractal.julia_cx.to_string()));
+            params.push(("julia_cy", self.fractal.julia_cy.to_string()));
+        }
+        params.push(("coloring", self.coloring.clone()));
+        params
+    }
+
+    /// A link like mandel://locate?cx=-0.5&cy=0&... that contains all parameters
+    pub fn to_uri(&self) -> String {
+        let query: Vec<String> = self
+            .params()
+            .into_iter()
+            .map(|(key, value)| format!("{}={}", key, percent_encode(&value)))
+            .collect();
+        format!("{}{}", URI_PREFIX, query.join("&"))
+    }
+
+    /// Read a location from a link made by to_uri
+    pub fn from_uri(uri: &str) -> Option<Location> {
+        let query = uri.strip_prefix(URI_PREFIX)?;
+        let mut params = Vec::new();
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=')?;
+            params.push((percent_decode(key)?, percent_decode(value)?));
+        }
+        Location::from_params(|key| {
+            params
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.clone())
+        })
+    }
+
+    /// A short text like mandel:cx=-0.5;cy=0;s=0.005;it=100;col=rgb18 that contains all
+    /// parameters. The numbers are written such that they are read back exactly. The fractal
+    /// is left out for the Mandelbrot set.
+    pub fn to_text(&self) -> String {
+        let fields: Vec<String> = self
+            .params()
+            .into_iter()
+            .filter(|(key, value)| !(*key == "fractal" && *value == FractalType::Mandelbrot.name()))
+            .filter_map(|(key, value)| {
+                let (_, short) = TEXT_KEYS.iter().find(|(k, _)| *k == key)?;
+                Some(format!("{}={}", short, percent_encode(&value)))
+            })
+            .collect();
+        format!("{}{}", TEXT_PREFIX, fields.join(";"))
+    }
+
+    /// Read a location from a text made by to_text. Space around the text is ignored.
+    pub fn from_text(text: &str) -> Option<Location> {
+        let fields = text.trim().strip_prefix(TEXT_PREFIX)?;
+        let mut params = Vec::new();
+        for field in fields.split(';').filter(|f| !f.is_empty()) {
+            let (short, value) = field.split_once('=')?;
+            let (key, _) = TEXT_KEYS.iter().find(|(_, s)| *s == short.trim())?;
+            params.push((*key, percent_decode(value)?));
+        }
+        Location::from_params(|key| {
+            params
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.clone())
+        })
+    }
+
+    /// Read a location from the text chunks of a PNG file that was saved with the params
+    pub fn from_png_text(text: &[(String, String)]) -> Option<Location> {
+        Location::from_params(|key| text.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone()))
+    }
+
+    /// Read a location from the keyword/value pairs made by params.
+    /// The lookup function returns the value for a keyword.
+    pub fn from_params(lookup: impl Fn(&str) -> Option<String>) -> Option<Location> {
+        let float = |key: &str| lookup(key).and_then(|v| v.trim().parse::<f64>().ok());
+        let mut fractal = FractalParams::mandelbrot();
+        if let Some(name) = lookup("fractal") {
+            fractal.fractal_type = FractalType::from_name(name.trim())?;
+        }
+        if let Some(power) = lookup("power") {
+            fractal.power = power.trim().parse().ok().filter(|p| *p >= 2)?;
+        }
+        if fractal.fractal_type == FractalType::Formula {
+            fractal.formula = Formula::parse(&lookup("formula")?).ok()?;
+        }
+        if fractal.fractal_type == FractalType::Newton {
+            fractal.polynomial = Polynomial::parse(&lookup("polynomial")?).ok()?;
+        }
+        if fractal.fractal_type == FractalType::Julia {
+            fractal.julia_cx = float("julia_cx")?;
+            fractal.julia_cy = float("julia_cy")?;
+        }
+        let precise_center = PreciseCenter::from_text(&lookup("cx")?, &lookup("cy")?);
+        let (cx, cy) = match &precise_center {
+            Some(center) => center.approx(),
+            None => (float("cx")?, float("cy")?),
+        };
+        let location = Location {
+            cx,
+            cy,
+            scale: float("scale")?,
+            iteration_depth: lookup("iteration_depth")?.trim().parse().ok()?,
+            fractal,
+            coloring: lookup("coloring").unwrap_or_default(),
+            precise_center,
+        };
+        if location.scale > 0.0 && location.iteration_depth > 0 {
+            Some(location)
+        } else {
+            None
+        }
+    }
+}
+
+// Adding 0.0 turns -0.0 into 0.0, so values that are equal give equal bits
+fn hash_f64<H: Hasher>(v: f64, state: &mut H) {
+    (v + 0.0).to_bits().hash(state);
+}
+
+impl Hash for Location {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_f64(self.cx, state);
+        hash_f64(self.cy, state);
+        hash_f64(self.scale, state);
+        self.iteration_depth.hash(state);
+        self.fractal.fractal_type.hash(state);
+        self.fractal.power.hash(state);
+        hash_f64(self.fractal.julia_cx, state);
+        hash_f64(self.fractal.julia_cy, state);
+        self.fractal.formula.hash(state);
+        self.fractal.polynomial.hash(state);
+        self.coloring.hash(state);
+        self.precise_center.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_gives_the_same_location() {
+        let mut location = Location::new(&Mapping::new_for_size(600), "rgb18");
+        location.cx = -0.7436438870371587;
+        location.cy = 0.1318259042053119;
+        location.scale = 1.0 / 3.0 * 1e-13;
+        assert!(location.to_text().starts_with("mandel:cx="));
+        assert_eq!(
+            Location::from_text(&location.to_text()),
+            Some(location.clone())
+        );
+        // A deep view keeps the digits of its center beyond those of f64
+        let mut deep = location.mapping(600, 600);
+        deep.scale = 1e-30;
+        deep.move_center(3e-29, -1e-28);
+        let deep = Location::new(&deep, "rgb18");
+        assert_eq!((deep.cx, deep.cy), (location.cx, location.cy));
+        assert!(deep.precise_center.is_some());
+        assert_eq!(Location::from_text(&deep.to_text()), Some(deep.clone()));
+        // and so does a view that is set to it like the main window does: cx and cy first,
+        // then the precise center
+        let mut shown = Mapping::new_for_size(600);
+        (shown.cx, shown.cy, shown.scale) = (deep.cx, deep.cy, deep.scale);
+        shown.set_precise_center(deep.precise_center.clone());
+        assert_eq!(Location::new(&shown, "rgb18"), deep);
+        location.fractal.fractal_type = FractalType::Julia;
+        location.fractal.julia_cx = -0.8;
+        location.fractal.julia_cy = 0.156;
+        let text = format!("  {}\n", location.to_text());
+        assert_eq!(Location::from_text(&text), Some(location));
+        assert_eq!(Location::from_text("mandel:cx=0;cy=0;zoom=2"), None);
+    }
+}
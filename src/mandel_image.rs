@@ -1,9 +1,32 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread;
+use std::time::Instant;
 
-use crate::{colorings::Coloring, MandelReply, MandelReq, IMG_FMT};
+use crate::{
+    backend::{default_backend, RenderBackend},
+    channels::PolicySender,
+    colorings::Coloring,
+    fixed::{Fixed, PreciseCenter},
+    formula::{Complex, Formula},
+    fractal::{FractalParams, FractalType},
+    newton::root_color,
+    perturbation::{pixel_offset, uses_perturbation, ReferenceOrbit},
+    shading::{apply_buffer_lighting, apply_lighting},
+    simd::{mandel_lanes, LANES},
+    stride_for_width, MandelPass, MandelReply, MandelReq, MandelTile,
+};
 use scoped_threadpool::Pool;
 
-#[derive(Clone)]
+/// The default maximum iteration depth of quick previews
+pub const PREVIEW_ITER_DEPTH: u32 = 64;
+
+/// The default block sizes of the successive passes of the progressive renderer.
+/// Each pass computes one pixel per block x block square, the last pass all pixels.
+pub const PROGRESSIVE_BLOCKS: [usize; 3] = [8, 4, 1];
+
+#[derive(Clone, PartialEq)]
 /// Parameters for mapping from mandelbrot space to a window
 pub struct Mapping {
     /// The x coordinate that is in the horizontal center of the window
@@ -20,6 +43,26 @@ pub struct Mapping {
     pub win_width: usize,
     /// The height of the window
     pub win_height: usize,
+    /// Which fractal to compute
+    pub fractal: FractalParams,
+    /// The center with more precision than cx and cy, for views deeper than f64 resolves
+    pub precise_center: Option<PreciseCenter>,
+    /// For a part of a view, e.g. a tile, where the window lies in the view. cx and cy are then
+    /// the center of the view, so that the pixels have exactly the coordinates of the view's.
+    pub part: Option<ViewPart>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Where the window of a mapping lies in a larger view
+pub struct ViewPart {
+    /// The column of the view that is the first column of the window
+    pub first_col: usize,
+    /// The row of the view that is the first row of the window
+    pub first_row: usize,
+    /// The width of the view
+    pub view_width: usize,
+    /// The height of the view
+    pub view_height: usize,
 }
 
 impl Mapping {
@@ -31,6 +74,102 @@ impl Mapping {
             iteration_depth: 100,
             win_width: win_sz,
             win_height: win_sz,
+            fractal: FractalParams::mandelbrot(),
+            precise_center: None,
+            part: None,
+        }
+    }
+    /// The mapping for the window rectangle with corners (x0, y0) and (x1, y1),
+    /// scaled such that the longest side of the result has `longest` pixels
+    pub fn for_region(&self, x0: f64, y0: f64, x1: f64, y1: f64, longest: usize) -> Mapping {
+        let (rw, rh) = ((x1 - x0).abs().max(1.0), (y1 - y0).abs().max(1.0));
+        let f = longest as f64 / rw.max(rh);
+        let mut mapping = Mapping {
+            scale: self.scale / f,
+            win_width: (rw * f).round() as usize,
+            win_height: (rh * f).round() as usize,
+            part: None,
+            ..self.clone()
+        };
+        let dx = ((x0 + x1) / 2.0 - self.win_width as f64 / 2.0) * self.scale;
+        let dy = (self.win_height as f64 / 2.0 - (y0 + y1) / 2.0) * self.scale;
+        mapping.move_center(dx, dy);
+        mapping
+    }
+
+    /// Give the view a precise center, e.g. that of a location, after cx and cy were set to its
+    /// f64 values. The precise center follows where cx and cy differ from them.
+    pub fn set_precise_center(&mut self, center: Option<PreciseCenter>) {
+        self.precise_center = center.map(|center| center.at(self.cx, self.cy));
+    }
+
+    /// Move the center by (dx, dy). A deep view keeps a precise center, so that moves smaller
+    /// than the resolution of f64 add up.
+    pub fn move_center(&mut self, dx: f64, dy: f64) {
+        if uses_perturbation(self) {
+            let fraction = crate::fixed::limbs_for_scale(self.scale);
+            let (x, y) = self.precise_center_at(fraction);
+            let center = PreciseCenter { x, y }.moved(dx, dy);
+            (self.cx, self.cy) = center.approx();
+            self.precise_center = Some(center);
+        } else {
+            self.cx += dx;
+            self.cy += dy;
+            self.precise_center = None;
+        }
+    }
+
+    /// The number of columns and rows by which the view moved from another view, if the views
+    /// differ only in their center and that moved by a whole number of pixels. A pixel of this
+    /// view is then the pixel of the other view that is that many columns to the right and
+    /// rows down.
+    pub fn pixel_shift(&self, from: &Mapping) -> Option<(isize, isize)> {
+        let moved = Mapping {
+            cx: self.cx,
+            cy: self.cy,
+            precise_center: self.precise_center.clone(),
+            ..from.clone()
+        };
+        if moved != *self {
+            return None;
+        }
+        let (dx, dy) = if self.precise_center.is_some() || from.precise_center.is_some() {
+            // The difference of the f64 centers of deep views has no precision left
+            let fraction = crate::fixed::limbs_for_scale(self.scale);
+            let ((x, y), (from_x, from_y)) = (
+                self.precise_center_at(fraction),
+                from.precise_center_at(fraction),
+            );
+            (x.sub(&from_x).to_f64(), y.sub(&from_y).to_f64())
+        } else {
+            (self.cx - from.cx, self.cy - from.cy)
+        };
+        let (cols, rows) = (dx / self.scale, -dy / self.scale);
+        let whole = |v: f64| (v - v.round()).abs() < 1e-6;
+        (whole(cols) && whole(rows)).then(|| (cols.round() as isize, rows.round() as isize))
+    }
+
+    /// Where the window lies in the view that it is a part of, or the whole view
+    pub fn view_part(&self) -> ViewPart {
+        self.part.unwrap_or(ViewPart {
+            first_col: 0,
+            first_row: 0,
+            view_width: self.win_width,
+            view_height: self.win_height,
+        })
+    }
+
+    /// The center with fraction limbs after the binary point
+    pub fn precise_center_at(&self, fraction: usize) -> (Fixed, Fixed) {
+        match &self.precise_center {
+            Some(center) => {
+                let center = center.at(self.cx, self.cy);
+                (center.x.with_limbs(fraction), center.y.with_limbs(fraction))
+            }
+            None => (
+                Fixed::from_f64(self.cx, fraction),
+                Fixed::from_f64(self.cy, fraction),
+            ),
         }
     }
     pub fn is_valid(&self) -> bool {
@@ -60,37 +199,180 @@ The solution is:
 f = s
 x0 = x_c - (f*w)/2
 y0 = y_c - (f*h)/2
+
+For a part of a view, w and h are those of the view, and window coordinates are first offset
+by the column and row at which the part starts, so that both give exactly the same coordinates.
  */
 pub struct WinToMandel {
     x0: f64,
     y0: f64,
     f: f64,
+    col: usize,
+    row: usize,
 }
 
 impl WinToMandel {
     pub fn from_mapping(mapping: &Mapping) -> WinToMandel {
+        let part = mapping.view_part();
         let f = mapping.scale;
-        let x0: f64 = mapping.cx - (f * mapping.win_width as f64) / 2.0;
-        let y0 = mapping.cy + (f * mapping.win_height as f64) / 2.0;
-        WinToMandel { x0, y0, f }
+        let x0: f64 = mapping.cx - (f * part.view_width as f64) / 2.0;
+        let y0 = mapping.cy + (f * part.view_height as f64) / 2.0;
+        WinToMandel {
+            x0,
+            y0,
+            f,
+            col: part.first_col,
+            row: part.first_row,
+        }
     }
     pub fn cvt(&self, wx: usize, wy: usize) -> (f64, f64) {
-        (self.x0 + wx as f64 * self.f, self.y0 - wy as f64 * self.f)
+        (self.cvt_x(wx), self.cvt_y(wy))
+    }
+    pub fn cvt_f(&self, wx: f64, wy: f64) -> (f64, f64) {
+        (
+            self.x0 + (wx + self.col as f64) * self.f,
+            self.y0 - (wy + self.row as f64) * self.f,
+        )
     }
     pub fn cvt_x(&self, wx: usize) -> f64 {
-        self.x0 + wx as f64 * self.f
+        self.x0 + (wx + self.col) as f64 * self.f
     }
     pub fn cvt_y(&self, wy: usize) -> f64 {
-        self.y0 - wy as f64 * self.f
+        self.y0 - (wy + self.row) as f64 * self.f
+    }
+}
+
+/// The inverse of WinToMandel: from mandelbrot coordinates to window coordinates, without
+/// rounding, e.g. for drawing over the image
+pub struct MandelToWin {
+    x0: f64,
+    y0: f64,
+    f: f64,
+}
+
+impl MandelToWin {
+    pub fn from_mapping(mapping: &Mapping) -> MandelToWin {
+        let WinToMandel {
+            x0,
+            y0,
+            f,
+            col,
+            row,
+        } = WinToMandel::from_mapping(mapping);
+        MandelToWin {
+            x0: x0 + col as f64 * f,
+            y0: y0 - row as f64 * f,
+            f,
+        }
     }
+    pub fn cvt(&self, mx: f64, my: f64) -> (f64, f64) {
+        ((mx - self.x0) / self.f, (self.y0 - my) / self.f)
+    }
+    pub fn cvt_x(&self, mx: f64) -> f64 {
+        (mx - self.x0) / self.f
+    }
+    pub fn cvt_y(&self, my: f64) -> f64 {
+        (self.y0 - my) / self.f
+    }
+}
+
+#[derive(Clone)]
+/// A token that tells a render whether it is still wanted
+pub struct CancelToken {
+    latest: Arc<AtomicU64>,
+    generation: u64,
+}
+
+impl CancelToken {
+    /// A token for a render that is never cancelled
+    pub fn never() -> CancelToken {
+        CancelToken {
+            latest: Arc::new(AtomicU64::new(0)),
+            generation: 0,
+        }
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.latest.load(Ordering::Relaxed) != self.generation
+    }
+}
+
+/// Hands out cancel tokens. Handing out a new token cancels all previous ones.
+#[derive(Default)]
+pub struct Canceller {
+    latest: Arc<AtomicU64>,
+}
+
+impl Canceller {
+    pub fn new() -> Canceller {
+        Canceller::default()
+    }
+    pub fn next_token(&self) -> CancelToken {
+        let generation = self.latest.fetch_add(1, Ordering::Relaxed) + 1;
+        CancelToken {
+            latest: self.latest.clone(),
+            generation,
+        }
+    }
+}
+
+// The first iteration after which z is saved for the periodicity check, and how close z must
+// come back to it
+pub(crate) const FIRST_CYCLE_CHECK: u32 = 16;
+pub(crate) const CYCLE_TOLERANCE: f64 = 1e-14;
+
+/// Brent's periodicity check for points in the set. The value of z is saved after a number of
+/// iterations that doubles every time, and an orbit that comes back to the saved value is in a
+/// cycle of at most that length: the point will never escape, so the iteration can stop
+/// instead of running up to the iteration depth. The iterations at which z is saved do not
+/// depend on where an iteration was continued, so every way of iterating finds the same cycles.
+pub(crate) struct CycleCheck {
+    saved: (f64, f64),
+    next_save: u32,
+}
+
+impl CycleCheck {
+    /// The check for an iteration that has done iter iterations
+    pub(crate) fn new(iter: u32) -> CycleCheck {
+        CycleCheck {
+            // Nothing is close to NaN, so there is no cycle before the first save
+            saved: (f64::NAN, f64::NAN),
+            next_save: (iter + 1).next_power_of_two().max(FIRST_CYCLE_CHECK),
+        }
+    }
+
+    /// Whether z, the value after iteration iter, is that of an earlier iteration
+    pub(crate) fn in_cycle(&mut self, z: (f64, f64), iter: u32) -> bool {
+        if (z.0 - self.saved.0).abs() < CYCLE_TOLERANCE
+            && (z.1 - self.saved.1).abs() < CYCLE_TOLERANCE
+        {
+            return true;
+        }
+        if iter == self.next_save {
+            self.saved = z;
+            self.next_save = self.next_save.saturating_mul(2);
+        }
+        false
+    }
+}
+
+/// Whether c = (x, y) is in the main cardioid or in the period-2 bulb of the Mandelbrot set,
+/// which together are most of its interior. Such points never escape, so they need not be
+/// iterated.
+pub fn in_main_bulbs(x: f64, y: f64) -> bool {
+    let q = (x - 0.25) * (x - 0.25) + y * y;
+    q * (q + (x - 0.25)) <= 0.25 * y * y || (x + 1.0) * (x + 1.0) + y * y <= 0.0625
 }
 
 // Return the number of iterations before we encounter the stop criterion
-fn mandel_value(x: f64, y: f64, max_iter: u32) -> u32 {
+pub fn mandel_value(x: f64, y: f64, max_iter: u32) -> u32 {
+    if in_main_bulbs(x, y) {
+        return max_iter;
+    }
     // The number of iterations
     let mut iter = 0;
     // The initial values of r and i.
     let (mut r, mut i) = (0.0, 0.0);
+    let mut cycle = CycleCheck::new(iter);
     while iter < max_iter {
         // Compute the new values for r and i
         (r, i) = (r * r - i * i + x, 2.0 * r * i + y);
@@ -99,30 +381,542 @@ fn mandel_value(x: f64, y: f64, max_iter: u32) -> u32 {
             break;
         }
         iter += 1;
+        // A point whose orbit cycles is in the set
+        if cycle.in_cycle((r, i), iter) {
+            return max_iter;
+        }
+    }
+    iter
+}
+
+// Return the number of iterations before we encounter the stop criterion,
+// for the Julia set of (cx, cy)
+pub fn julia_value(x: f64, y: f64, cx: f64, cy: f64, max_iter: u32) -> u32 {
+    let mut iter = 0;
+    // For Julia sets r and i start at the point
+    let (mut r, mut i) = (x, y);
+    let mut cycle = CycleCheck::new(iter);
+    while iter < max_iter {
+        (r, i) = (r * r - i * i + cx, 2.0 * r * i + cy);
+        if i * i + r * r >= 4.0 {
+            break;
+        }
+        iter += 1;
+        if cycle.in_cycle((r, i), iter) {
+            return max_iter;
+        }
     }
     iter
 }
 
+/// Compute the value of the fractal at point (x, y)
+pub fn fractal_value(fractal: &FractalParams, x: f64, y: f64, max_iter: u32) -> u32 {
+    match (fractal.fractal_type, fractal.power) {
+        (FractalType::Mandelbrot, 2) => mandel_value(x, y, max_iter),
+        (FractalType::Julia, 2) => julia_value(x, y, fractal.julia_cx, fractal.julia_cy, max_iter),
+        _ => {
+            let (mut z, c) = iteration_start(fractal, x, y);
+            let mut iter = 0;
+            continue_iteration(fractal, &mut z, c, &mut iter, max_iter);
+            iter
+        }
+    }
+}
+
+// Compute z^d by repeated multiplication
+fn complex_pow((r, i): (f64, f64), d: u32) -> (f64, f64) {
+    let (mut pr, mut pi) = (1.0, 0.0);
+    for _ in 0..d {
+        (pr, pi) = (pr * r - pi * i, pr * i + pi * r);
+    }
+    (pr, pi)
+}
+
+/// The initial z and the constant c of the iteration for point (x, y)
+pub fn iteration_start(fractal: &FractalParams, x: f64, y: f64) -> ((f64, f64), (f64, f64)) {
+    match fractal.fractal_type {
+        FractalType::Mandelbrot | FractalType::Tricorn | FractalType::Formula => {
+            ((0.0, 0.0), (x, y))
+        }
+        FractalType::Julia => ((x, y), (fractal.julia_cx, fractal.julia_cy)),
+        FractalType::Newton => ((x, y), (0.0, 0.0)),
+    }
+}
+
+/// Continue an iteration of the fractal that has done `iter` iterations without escaping,
+/// until it escapes or `iter` reaches max_iter. An orbit that is found to cycle sets `iter` to
+/// max_iter at once. Returns whether the point escaped.
+/// The result is the same as that of fractal_value with max_iter.
+pub fn continue_iteration(
+    fractal: &FractalParams,
+    z: &mut (f64, f64),
+    c: (f64, f64),
+    iter: &mut u32,
+    max_iter: u32,
+) -> bool {
+    if fractal.fractal_type == FractalType::Mandelbrot
+        && fractal.power == 2
+        && *iter == 0
+        && in_main_bulbs(c.0, c.1)
+    {
+        *iter = max_iter;
+        return false;
+    }
+    iterate(fractal, z, c, iter, max_iter)
+}
+
+// continue_iteration without skipping the points of the main cardioid and the period-2 bulb,
+// for when the values of z are needed
+fn iterate(
+    fractal: &FractalParams,
+    z: &mut (f64, f64),
+    c: (f64, f64),
+    iter: &mut u32,
+    max_iter: u32,
+) -> bool {
+    match fractal.fractal_type {
+        FractalType::Formula => return continue_formula(&fractal.formula, z, c, iter, max_iter),
+        FractalType::Newton => {
+            // For Newton's method converging to a root takes the place of escaping
+            let mut zc = Complex::new(z.0, z.1);
+            let root = fractal.polynomial.converge(&mut zc, iter, max_iter);
+            *z = (zc.re, zc.im);
+            return root.is_some();
+        }
+        _ => {}
+    }
+    let (mut r, mut i) = *z;
+    let mut escaped = false;
+    let power = fractal.power;
+    // The Tricorn uses the complex conjugate of z
+    let sign = if fractal.fractal_type == FractalType::Tricorn {
+        -1.0
+    } else {
+        1.0
+    };
+    let mut cycle = CycleCheck::new(*iter);
+    while *iter < max_iter {
+        i *= sign;
+        (r, i) = if power == 2 {
+            (r * r - i * i + c.0, 2.0 * r * i + c.1)
+        } else {
+            let (pr, pi) = complex_pow((r, i), power);
+            (pr + c.0, pi + c.1)
+        };
+        if i * i + r * r >= 4.0 {
+            escaped = true;
+            break;
+        }
+        *iter += 1;
+        if cycle.in_cycle((r, i), *iter) {
+            *iter = max_iter;
+            break;
+        }
+    }
+    *z = (r, i);
+    escaped
+}
+
+fn continue_formula(
+    formula: &Formula,
+    z: &mut (f64, f64),
+    c: (f64, f64),
+    iter: &mut u32,
+    max_iter: u32,
+) -> bool {
+    let c = Complex::new(c.0, c.1);
+    let mut zc = Complex::new(z.0, z.1);
+    let mut escaped = false;
+    while *iter < max_iter {
+        zc = formula.step(zc, c);
+        // NaN is also treated as escaped
+        let norm = zc.norm_sqr();
+        if norm >= 4.0 || norm.is_nan() {
+            escaped = true;
+            break;
+        }
+        *iter += 1;
+    }
+    *z = (zc.re, zc.im);
+    escaped
+}
+
+/// Compute the color of the fractal at point (x, y). Newton fractals have colors for their
+/// roots, the other fractals use the coloring. pixel_size is the width of a pixel, for
+/// colorings that use the distance to the set.
+pub fn fractal_color(
+    fractal: &FractalParams,
+    x: f64,
+    y: f64,
+    max_iter: u32,
+    col_producer: &dyn Coloring,
+    pixel_size: f64,
+) -> u32 {
+    counted_fractal_color(fractal, x, y, max_iter, col_producer, pixel_size).0
+}
+
+// Like fractal_color, but also returns the number of iterations that the color took
+fn counted_fractal_color(
+    fractal: &FractalParams,
+    x: f64,
+    y: f64,
+    max_iter: u32,
+    col_producer: &dyn Coloring,
+    pixel_size: f64,
+) -> (u32, u32) {
+    if fractal.fractal_type == FractalType::Newton {
+        let mut z = Complex::new(x, y);
+        let mut iter = 0;
+        let root = fractal.polynomial.converge(&mut z, &mut iter, max_iter);
+        return (
+            root_color(root, fractal.polynomial.roots().len(), iter),
+            iter,
+        );
+    }
+    if col_producer.uses_distance() {
+        let p = probe(fractal, x, y, max_iter);
+        let color = match p.distance {
+            Some(distance) => {
+                col_producer.get_distance_color(p.iterations, max_iter, distance / pixel_size)
+            }
+            None => col_producer.get_color(p.iterations, max_iter),
+        };
+        return (color, p.iterations);
+    }
+    if col_producer.is_smooth() {
+        let (mut z, c) = iteration_start(fractal, x, y);
+        let mut iter = 0;
+        let v = if continue_iteration(fractal, &mut z, c, &mut iter, max_iter) {
+            smooth_escape(fractal, iter, z)
+        } else {
+            max_iter as f64
+        };
+        return (col_producer.get_smooth_color(v, max_iter), iter);
+    }
+    let iter = fractal_value(fractal, x, y, max_iter);
+    (col_producer.get_color(iter, max_iter), iter)
+}
+
+/// The normalized iteration count of a point that escaped after `iter` iterations with
+/// final value z: iter + 1 - log_d(ln |z|), for power d. It grows continuously over the plane.
+pub fn smooth_escape(fractal: &FractalParams, iter: u32, z: (f64, f64)) -> f64 {
+    // The power of a formula is not known, most formulas are quadratic
+    let d = match fractal.fractal_type {
+        FractalType::Formula => 2.0,
+        _ => fractal.power.max(2) as f64,
+    };
+    let abs_z = (z.0 * z.0 + z.1 * z.1).sqrt();
+    if abs_z <= 1.0 || !abs_z.is_finite() {
+        return iter as f64;
+    }
+    (iter as f64 + 1.0 - abs_z.ln().ln() / d.ln()).max(iter as f64)
+}
+
+/// Detailed information about the iteration for one point
+pub struct Probe {
+    /// The value, as computed by fractal_value
+    pub iterations: u32,
+    /// The normalized iteration count, only for points that escape
+    pub smooth: Option<f64>,
+    /// The last value of z that was computed
+    pub z: (f64, f64),
+    /// An estimate of the distance to the set, only for points that escape
+    pub distance: Option<f64>,
+}
+
+// Do the same iteration as fractal_value, but keep track of the final z and
+// the derivative of z to the point
+pub fn probe(fractal: &FractalParams, x: f64, y: f64, max_iter: u32) -> Probe {
+    let mut iter = 0;
+    if matches!(
+        fractal.fractal_type,
+        FractalType::Formula | FractalType::Newton
+    ) {
+        // The derivative is not known
+        let (mut z, c) = iteration_start(fractal, x, y);
+        continue_iteration(fractal, &mut z, c, &mut iter, max_iter);
+        return Probe {
+            iterations: iter,
+            smooth: None,
+            z,
+            distance: None,
+        };
+    }
+    // For a Julia set z starts at the point, so its derivative starts at 1
+    let ((mut r, mut i), (mut dr, mut di), (cx, cy), dc) = match fractal.fractal_type {
+        FractalType::Mandelbrot | FractalType::Tricorn | FractalType::Formula => {
+            ((0.0, 0.0), (0.0, 0.0), (x, y), 1.0)
+        }
+        FractalType::Julia | FractalType::Newton => (
+            (x, y),
+            (1.0, 0.0),
+            (fractal.julia_cx, fractal.julia_cy),
+            0.0,
+        ),
+    };
+    let mut escaped = false;
+    let d = fractal.power as f64;
+    let tricorn = fractal.fractal_type == FractalType::Tricorn;
+    while iter < max_iter {
+        if tricorn {
+            i = -i;
+        }
+        // dz' = d * z^(d-1) * dz + dc
+        let (pr, pi) = complex_pow((r, i), fractal.power - 1);
+        (dr, di) = (d * (pr * dr - pi * di) + dc, d * (pr * di + pi * dr));
+        (r, i) = (pr * r - pi * i + cx, pr * i + pi * r + cy);
+        if i * i + r * r >= 4.0 {
+            escaped = true;
+            break;
+        }
+        iter += 1;
+    }
+    let (smooth, distance) = if escaped {
+        let abs_z = (r * r + i * i).sqrt();
+        let abs_dz = (dr * dr + di * di).sqrt();
+        let smooth = iter as f64 + 1.0 - abs_z.ln().ln() / d.ln();
+        // The conjugate is not differentiable, so there is no distance estimate for the Tricorn
+        let distance = if tricorn {
+            None
+        } else {
+            Some(0.5 * abs_z * abs_z.ln() / abs_dz)
+        };
+        (Some(smooth), distance)
+    } else {
+        (None, None)
+    };
+    Probe {
+        iterations: iter,
+        smooth,
+        z: (r, i),
+        distance,
+    }
+}
+
+// The longest cycle that orbit looks for, and how close points of a cycle must be
+const MAX_PERIOD: usize = 1000;
+const PERIOD_TOLERANCE: f64 = 1e-9;
+
+/// The values of z that the iteration for one point visits
+pub struct Orbit {
+    /// z before the first iteration and after every iteration
+    pub points: Vec<(f64, f64)>,
+    /// Whether the point escaped, or for Newton's method converged to a root
+    pub escaped: bool,
+    /// The length of the cycle that the orbit ends in, if it has one
+    pub period: Option<usize>,
+}
+
+/// Do the same iteration as fractal_value, one step at a time, and keep the values of z
+pub fn orbit(fractal: &FractalParams, x: f64, y: f64, max_iter: u32) -> Orbit {
+    let (mut z, c) = iteration_start(fractal, x, y);
+    let mut points = vec![z];
+    let mut iter = 0;
+    let mut escaped = false;
+    while iter < max_iter && !escaped {
+        let before = iter;
+        escaped = iterate(fractal, &mut z, c, &mut iter, before + 1);
+        points.push(z);
+        if iter == before && !escaped {
+            break;
+        }
+    }
+    let last = points[points.len() - 1];
+    let period = if escaped {
+        None
+    } else {
+        (1..points.len().min(MAX_PERIOD + 1)).find(|&p| {
+            let (r, i) = points[points.len() - 1 - p];
+            (r - last.0).hypot(i - last.1) < PERIOD_TOLERANCE
+        })
+    };
+    Orbit {
+        points,
+        escaped,
+        period,
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// What a render pass computed. The iterations for lighting and for the histogram of a
+/// coloring are not counted.
+pub struct RenderStats {
+    /// The number of points that were iterated: one per block x block square of the pass,
+    /// factor x factor per pixel when supersampled
+    pub points: u64,
+    /// The iterations of all points together
+    pub iterations: u64,
+    /// The number of points that reached the iteration depth, which are mostly in the set
+    pub max_depth: u64,
+}
+
+impl RenderStats {
+    pub(crate) fn add_point(&mut self, iterations: u32, max_iter: u32) {
+        self.points += 1;
+        self.iterations += iterations as u64;
+        if iterations >= max_iter {
+            self.max_depth += 1;
+        }
+    }
+    /// Add the counts of another pass
+    pub fn add(&mut self, other: &RenderStats) {
+        self.points += other.points;
+        self.iterations += other.iterations;
+        self.max_depth += other.max_depth;
+    }
+}
+
+// The counts of the parts of a render, which run on different threads
+#[derive(Default)]
+pub(crate) struct StatsCounter {
+    points: AtomicU64,
+    iterations: AtomicU64,
+    max_depth: AtomicU64,
+}
+
+impl StatsCounter {
+    pub(crate) fn add(&self, stats: &RenderStats) {
+        self.points.fetch_add(stats.points, Ordering::Relaxed);
+        self.iterations
+            .fetch_add(stats.iterations, Ordering::Relaxed);
+        self.max_depth.fetch_add(stats.max_depth, Ordering::Relaxed);
+    }
+    pub(crate) fn stats(&self) -> RenderStats {
+        RenderStats {
+            points: self.points.load(Ordering::Relaxed),
+            iterations: self.iterations.load(Ordering::Relaxed),
+            max_depth: self.max_depth.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// The parameters of a render that are shared by all parts of the image
+struct RenderParams<'a> {
+    col_producer: &'a dyn Coloring,
+    converter: WinToMandel,
+    mapping: &'a Mapping,
+    ustride: usize,
+    // With block > 1 only one pixel per block x block square is computed and
+    // its color is used for the whole square.
+    block: usize,
+    cancel: &'a CancelToken,
+    counter: &'a StatsCounter,
+    // Whether the quadratic Mandelbrot set is iterated LANES points at a time
+    lanes: bool,
+    // The orbit of the center of a deep view, which is rendered with perturbation
+    reference: Option<&'a ReferenceOrbit>,
+}
+
+// The color of a point of the quadratic Mandelbrot set that was iterated iterations times,
+// to z, for colorings that do not use the distance
+fn escape_color(params: &RenderParams, iterations: u32, z: (f64, f64), escaped: bool) -> u32 {
+    let (coloring, max) = (params.col_producer, params.mapping.iteration_depth);
+    if !coloring.is_smooth() {
+        coloring.get_color(iterations, max)
+    } else if escaped {
+        let v = smooth_escape(&params.mapping.fractal, iterations, z);
+        coloring.get_smooth_color(v, max)
+    } else {
+        coloring.get_smooth_color(max as f64, max)
+    }
+}
+
+// The colors of the computed pixels of row wy, at y, for the quadratic Mandelbrot set and
+// colorings that do not use the distance. A deep view is rendered with perturbation, other
+// views LANES pixels at a time. The colors of the last pixels, which do not fill all lanes,
+// are left out, as are the colors of the other fractals.
+fn lane_colors(params: &RenderParams, wy: usize, y: f64, stats: &mut RenderStats) -> Vec<u32> {
+    let (mapping, coloring) = (params.mapping, params.col_producer);
+    let fractal = &mapping.fractal;
+    if fractal.fractal_type != FractalType::Mandelbrot
+        || fractal.power != 2
+        || coloring.uses_distance()
+    {
+        return Vec::new();
+    }
+    let max = mapping.iteration_depth;
+    let samples = mapping.win_width.div_ceil(params.block);
+    let mut colors = Vec::with_capacity(samples);
+    if let Some(reference) = params.reference {
+        for sample in 0..samples {
+            let dc = pixel_offset(mapping, sample * params.block, wy);
+            let (iterations, z, escaped) = reference.iterate(dc, max);
+            stats.add_point(iterations, max);
+            colors.push(escape_color(params, iterations, z, escaped));
+        }
+        return colors;
+    }
+    if !params.lanes {
+        return colors;
+    }
+    for first in (0..samples - samples % LANES).step_by(LANES) {
+        let xs = [0, 1, 2, 3].map(|k| params.converter.cvt_x((first + k) * params.block));
+        let lanes = mandel_lanes(xs, y, max);
+        for k in 0..LANES {
+            let iterations = lanes.iterations[k];
+            stats.add_point(iterations, max);
+            colors.push(escape_color(
+                params,
+                iterations,
+                lanes.z[k],
+                lanes.escaped[k],
+            ));
+        }
+    }
+    colors
+}
+
+// Fill the rows h_start..h_end. Returns false if the render failed or was cancelled.
 fn fill_mandel_image_partial(
     data: &mut [u8],
-    col_producer: &Box<dyn Coloring>,
-    converter: &WinToMandel,
-    w: usize,
+    params: &RenderParams,
     h_start: usize,
     h_end: usize,
-    max: u32,
-    ustride: usize,
 ) -> bool {
     {
+        let (ustride, block) = (params.ustride, params.block);
+        let w = params.mapping.win_width;
+        let max = params.mapping.iteration_depth;
         let mut ok = true;
+        let mut stats = RenderStats::default();
         for dy in 0..(h_end - h_start) {
-            let y = converter.cvt_y(h_start + dy);
+            if params.cancel.is_cancelled() {
+                params.counter.add(&stats);
+                return false;
+            }
+            let wy = h_start + dy;
+            if dy > 0 && !wy.is_multiple_of(block) {
+                // Same block row as the previous line, so copy it
+                let (prev, line) =
+                    data[(dy - 1) * ustride..(dy + 1) * ustride].split_at_mut(ustride);
+                line.copy_from_slice(prev);
+                continue;
+            }
+            let y = params.converter.cvt_y(wy - wy % block);
+            let lane_colors = lane_colors(params, wy - wy % block, y, &mut stats);
             let line = &mut data[dy * ustride..(dy + 1) * ustride];
             let mut iter = line.iter_mut();
+            let mut bytes = [0; 4];
             for wx in 0..w {
-                let x = converter.cvt_x(wx);
-                let mv = mandel_value(x, y, max);
-                let bytes = col_producer.get_color(mv, max).to_ne_bytes();
+                if wx.is_multiple_of(block) {
+                    let color = match lane_colors.get(wx / block) {
+                        Some(&color) => color,
+                        None => {
+                            let x = params.converter.cvt_x(wx);
+                            let (color, iterations) = counted_fractal_color(
+                                &params.mapping.fractal,
+                                x,
+                                y,
+                                max,
+                                params.col_producer,
+                                params.mapping.scale,
+                            );
+                            stats.add_point(iterations, max);
+                            color
+                        }
+                    };
+                    bytes = color.to_ne_bytes();
+                }
                 for i in 0..bytes.len() {
                     if let Some(v) = iter.next() {
                         *v = bytes[i];
@@ -132,6 +926,7 @@ fn fill_mandel_image_partial(
                 }
             }
         }
+        params.counter.add(&stats);
         return ok;
     }
 }
@@ -155,41 +950,30 @@ fn compute_splits(h: usize, par_count: usize) -> Vec<usize> {
     splits
 }
 
+// Fill rows first..end of the image, of which data holds the pixels
 fn fill_mandel_image_parallel(
     pool: &mut Pool,
     data: &mut [u8],
-    col_producer: &Box<dyn Coloring>,
-    ustride: usize,
-    mapping: &Mapping,
+    params: &RenderParams,
+    first: usize,
+    end: usize,
 ) -> bool {
-    let converter = WinToMandel::from_mapping(mapping);
-    let w = mapping.win_width;
-    let h = mapping.win_height;
-    let max = mapping.iteration_depth;
+    let ustride = params.ustride;
     let par_count = pool.thread_count() as usize;
-    let mut splits = compute_splits(h, par_count);
-    let mut end = h;
+    let mut splits = compute_splits(end - first, par_count);
+    let mut end = end;
     let mut statuses = vec![true; par_count];
     debug_assert_eq!(statuses.len(), splits.len());
     pool.scoped(|scope| {
         let mut rest_of_data = data;
         let mut rest_of_statuses = &mut statuses[..];
         while let Some(s) = splits.pop() {
+            let s = first + s;
             let (cur_status, cur_data);
-            (rest_of_data, cur_data) = rest_of_data.split_at_mut(ustride * s);
+            (rest_of_data, cur_data) = rest_of_data.split_at_mut(ustride * (s - first));
             (cur_status, rest_of_statuses) = rest_of_statuses.split_at_mut(1);
-            let converter_ref = &converter;
             scope.execute(move || {
-                cur_status[0] = fill_mandel_image_partial(
-                    cur_data,
-                    col_producer,
-                    converter_ref,
-                    w,
-                    s,
-                    end,
-                    max,
-                    ustride,
-                );
+                cur_status[0] = fill_mandel_image_partial(cur_data, params, s, end);
             });
             end = s;
         }
@@ -197,44 +981,477 @@ fn fill_mandel_image_parallel(
     statuses.into_iter().fold(true, |a, b| a && b)
 }
 
-fn fill_mandel_image(
+// Fill rows first..end of the image, of which data holds the pixels
+fn fill_rows(
     pool: &mut Option<Pool>,
     data: &mut [u8],
-    col_producer: &Box<dyn Coloring>,
-    ustride: usize,
-    mapping: &Mapping,
+    params: &RenderParams,
+    first: usize,
+    end: usize,
 ) -> bool {
     match pool {
-        None => fill_mandel_image_partial(
-            data,
-            col_producer,
-            &WinToMandel::from_mapping(mapping),
-            mapping.win_width,
+        None => fill_mandel_image_partial(data, params, first, end),
+        Some(pool) => fill_mandel_image_parallel(pool, data, params, first, end),
+    }
+}
+
+// The sum k of the rows of the view that are each other's mirror image in the real axis, row
+// wy and row k - wy, if the fractal is symmetric in the real axis and the rows of the two
+// halves fall on each other. Only for the last pass, which is the one that takes time.
+fn mirror_rows(params: &RenderParams) -> Option<usize> {
+    let (mapping, fractal) = (params.mapping, &params.mapping.fractal);
+    let symmetric = match fractal.fractal_type {
+        FractalType::Mandelbrot | FractalType::Tricorn => true,
+        FractalType::Julia => fractal.julia_cy == 0.0,
+        FractalType::Formula | FractalType::Newton => false,
+    };
+    if !symmetric || params.block != 1 || mapping.precise_center.is_some() {
+        return None;
+    }
+    // Row wy is at cy + (h / 2 - wy) * scale, so the row at -y is 2 * cy / scale + h - wy. In
+    // a part of a view, row wy is row first_row + wy of the view.
+    let (h, part) = (mapping.win_height, mapping.view_part());
+    let k =
+        2.0 * mapping.cy / mapping.scale + part.view_height as f64 - 2.0 * part.first_row as f64;
+    let rounded = k.round();
+    // Without a pair of rows in the view there is nothing to mirror
+    let in_view = 1.0 <= rounded && rounded < (2 * h - 1) as f64;
+    ((k - rounded).abs() < 1e-6 && in_view).then_some(rounded as usize)
+}
+
+// Fill the image. A view across the real axis of a symmetric fractal is rendered down to the
+// axis and below the mirror image of the top row; the rows in between are copied from above
+// the axis, and the points of the rows they are copied from count twice in the statistics.
+fn fill_mandel_image(pool: &mut Option<Pool>, data: &mut [u8], params: &RenderParams) -> bool {
+    let (h, ustride) = (params.mapping.win_height, params.ustride);
+    let Some(k) = mirror_rows(params) else {
+        return fill_rows(pool, data, params, 0, h);
+    };
+    // Rows top..bottom are the mirror images of rows sources.0..sources.1
+    let (top, bottom) = (k / 2 + 1, k.min(h - 1) + 1);
+    let sources = (k + 1 - bottom, k + 1 - top);
+    let mut fill = |first: usize, end: usize| {
+        let rows = &mut data[first * ustride..end * ustride];
+        fill_rows(pool, rows, params, first, end)
+    };
+    let mut ok = fill(0, sources.0);
+    let before = params.counter.stats();
+    ok = ok && fill(sources.0, sources.1);
+    let after = params.counter.stats();
+    ok = ok && fill(sources.1, top) && fill(bottom, h);
+    if !ok {
+        return false;
+    }
+    params.counter.add(&RenderStats {
+        points: after.points - before.points,
+        iterations: after.iterations - before.iterations,
+        max_depth: after.max_depth - before.max_depth,
+    });
+    for wy in top..bottom {
+        let source = (k - wy) * ustride;
+        data.copy_within(source..source + ustride, wy * ustride);
+    }
+    true
+}
+
+// The maximum number of samples per side of the image for the histogram of a coloring
+const HISTOGRAM_SAMPLES: usize = 256;
+
+fn add_row_to_histogram(
+    histogram: &mut [u32],
+    fractal: &FractalParams,
+    xs: &[f64],
+    y: f64,
+    max_iter: u32,
+) {
+    for &x in xs {
+        histogram[fractal_value(fractal, x, y, max_iter) as usize] += 1;
+    }
+}
+
+/// Collect the histogram of the values of the fractal at a grid of sample points of the view.
+/// Entry v is the number of samples with value v, for v up to and including the iteration depth.
+pub fn sample_histogram(
+    mapping: &Mapping,
+    pool: &mut Option<Pool>,
+    cancel: &CancelToken,
+) -> Option<Vec<u32>> {
+    let step = mapping
+        .win_width
+        .max(mapping.win_height)
+        .div_ceil(HISTOGRAM_SAMPLES)
+        .max(1);
+    let converter = WinToMandel::from_mapping(mapping);
+    let xs: Vec<f64> = (0..mapping.win_width)
+        .step_by(step)
+        .map(|wx| converter.cvt_x(wx))
+        .collect();
+    let ys: Vec<f64> = (0..mapping.win_height)
+        .step_by(step)
+        .map(|wy| converter.cvt_y(wy))
+        .collect();
+    let (fractal, max) = (&mapping.fractal, mapping.iteration_depth);
+    let len = max as usize + 1;
+    let mut histogram = vec![0; len];
+    match pool {
+        None => {
+            for &y in &ys {
+                if cancel.is_cancelled() {
+                    return None;
+                }
+                add_row_to_histogram(&mut histogram, fractal, &xs, y, max);
+            }
+        }
+        Some(pool) => {
+            // Every thread counts its part of the rows in its own histogram
+            let n_parts = pool.thread_count() as usize;
+            let rows_per_part = ys.len().div_ceil(n_parts).max(1);
+            let mut parts = vec![vec![0; len]; n_parts];
+            pool.scoped(|scope| {
+                for (part_ys, part) in ys.chunks(rows_per_part).zip(parts.iter_mut()) {
+                    let xs = &xs;
+                    scope.execute(move || {
+                        for &y in part_ys {
+                            if cancel.is_cancelled() {
+                                return;
+                            }
+                            add_row_to_histogram(part, fractal, xs, y, max);
+                        }
+                    });
+                }
+            });
+            if cancel.is_cancelled() {
+                return None;
+            }
+            for part in parts {
+                for (n, p) in histogram.iter_mut().zip(part) {
+                    *n += p;
+                }
+            }
+        }
+    }
+    Some(histogram)
+}
+
+/// The value of a mask pixel of a point in the set
+pub const MASK_INSIDE: u8 = 0xff;
+
+fn fill_mask(mask: &mut [u8], mapping: &Mapping, first_row: usize, cancel: &CancelToken) -> bool {
+    let converter = WinToMandel::from_mapping(mapping);
+    let (fractal, max) = (&mapping.fractal, mapping.iteration_depth);
+    for (r, row) in mask.chunks_mut(mapping.win_width).enumerate() {
+        if cancel.is_cancelled() {
+            return false;
+        }
+        let y = converter.cvt_y(first_row + r);
+        for (wx, px) in row.iter_mut().enumerate() {
+            if fractal_value(fractal, converter.cvt_x(wx), y, max) >= max {
+                *px = MASK_INSIDE;
+            }
+        }
+    }
+    true
+}
+
+/// Make a mask of the view with a byte per pixel: MASK_INSIDE for points that do not
+/// escape within the iteration depth, 0 for the others. Returns None if cancelled.
+pub fn make_mask(
+    mapping: &Mapping,
+    pool: &mut Option<Pool>,
+    cancel: &CancelToken,
+) -> Option<Vec<u8>> {
+    if !mapping.is_valid() {
+        return None;
+    }
+    let width = mapping.win_width;
+    let mut mask = vec![0; width * mapping.win_height];
+    let complete = match pool {
+        None => fill_mask(&mut mask, mapping, 0, cancel),
+        Some(pool) => {
+            let rows_per_part = mapping
+                .win_height
+                .div_ceil(pool.thread_count() as usize)
+                .max(1);
+            pool.scoped(|scope| {
+                for (i, part) in mask.chunks_mut(width * rows_per_part).enumerate() {
+                    scope.execute(move || {
+                        fill_mask(part, mapping, i * rows_per_part, cancel);
+                    });
+                }
+            });
+            !cancel.is_cancelled()
+        }
+    };
+    complete.then_some(mask)
+}
+
+/// The values of every pixel of a view, from which the image can be colored again without
+/// iterating. Newton fractals and colorings that use the distance to the set need more than
+/// the values, so they cannot be colored from a buffer.
+pub struct IterationBuffer {
+    pub mapping: Mapping,
+    /// The value of every pixel, row by row, as computed by fractal_value
+    pub values: Vec<u32>,
+    /// The normalized iteration count of every pixel, the iteration depth for points in the set
+    pub smooth: Vec<f64>,
+}
+
+impl IterationBuffer {
+    /// Entry v is the number of pixels with value v, for v up to and including the iteration depth
+    pub fn histogram(&self) -> Vec<u32> {
+        let mut histogram = vec![0; self.mapping.iteration_depth as usize + 1];
+        for &v in &self.values {
+            histogram[v as usize] += 1;
+        }
+        histogram
+    }
+    /// The counts of computing the values
+    pub fn stats(&self) -> RenderStats {
+        let mut stats = RenderStats::default();
+        for &v in &self.values {
+            stats.add_point(v, self.mapping.iteration_depth);
+        }
+        stats
+    }
+}
+
+fn fill_buffer(
+    values: &mut [u32],
+    smooth: &mut [f64],
+    mapping: &Mapping,
+    first_row: usize,
+    cancel: &CancelToken,
+    lanes: bool,
+    reference: Option<&ReferenceOrbit>,
+) -> bool {
+    let converter = WinToMandel::from_mapping(mapping);
+    let (fractal, max) = (&mapping.fractal, mapping.iteration_depth);
+    let w = mapping.win_width;
+    let quadratic = fractal.fractal_type == FractalType::Mandelbrot && fractal.power == 2;
+    for (r, (row, smooth_row)) in values.chunks_mut(w).zip(smooth.chunks_mut(w)).enumerate() {
+        if cancel.is_cancelled() {
+            return false;
+        }
+        if let Some(reference) = reference {
+            for (wx, (v, s)) in row.iter_mut().zip(smooth_row.iter_mut()).enumerate() {
+                let dc = pixel_offset(mapping, wx, first_row + r);
+                let (iter, z, escaped) = reference.iterate(dc, max);
+                *s = if escaped {
+                    smooth_escape(fractal, iter, z)
+                } else {
+                    max as f64
+                };
+                *v = iter;
+            }
+            continue;
+        }
+        let y = converter.cvt_y(first_row + r);
+        // The quadratic Mandelbrot set is iterated LANES pixels at a time, the rest of the
+        // row and the other fractals one pixel at a time
+        let vectorized = if lanes && quadratic { w - w % LANES } else { 0 };
+        for wx in (0..vectorized).step_by(LANES) {
+            let xs = [0, 1, 2, 3].map(|k| converter.cvt_x(wx + k));
+            let lanes = mandel_lanes(xs, y, max);
+            for k in 0..LANES {
+                row[wx + k] = lanes.iterations[k];
+                smooth_row[wx + k] = if lanes.escaped[k] {
+                    smooth_escape(fractal, lanes.iterations[k], lanes.z[k])
+                } else {
+                    max as f64
+                };
+            }
+        }
+        let rest = row[vectorized..]
+            .iter_mut()
+            .zip(smooth_row[vectorized..].iter_mut());
+        for (wx, (v, s)) in (vectorized..).zip(rest) {
+            let (mut z, c) = iteration_start(fractal, converter.cvt_x(wx), y);
+            let mut iter = 0;
+            *s = if continue_iteration(fractal, &mut z, c, &mut iter, max) {
+                smooth_escape(fractal, iter, z)
+            } else {
+                max as f64
+            };
+            *v = iter;
+        }
+    }
+    true
+}
+
+/// Compute the values of every pixel of a view. Returns None for Newton fractals, which
+/// are colored by their roots, and if cancelled.
+pub fn make_iteration_buffer(
+    mapping: &Mapping,
+    pool: &mut Option<Pool>,
+    cancel: &CancelToken,
+) -> Option<IterationBuffer> {
+    default_backend().values(mapping, pool, cancel)
+}
+
+// Like make_iteration_buffer, with or without the vector kernel
+pub(crate) fn iteration_buffer(
+    mapping: &Mapping,
+    pool: &mut Option<Pool>,
+    cancel: &CancelToken,
+    lanes: bool,
+) -> Option<IterationBuffer> {
+    if !mapping.is_valid() || mapping.fractal.fractal_type == FractalType::Newton {
+        return None;
+    }
+    let (w, n) = (mapping.win_width, mapping.win_width * mapping.win_height);
+    let (mut values, mut smooth) = (vec![0; n], vec![0.0; n]);
+    let reference = ReferenceOrbit::for_mapping(mapping);
+    let reference = reference.as_ref();
+    let complete = match pool {
+        None => fill_buffer(
+            &mut values,
+            &mut smooth,
+            mapping,
             0,
-            mapping.win_height,
-            mapping.iteration_depth,
-            ustride,
+            cancel,
+            lanes,
+            reference,
         ),
-        Some(pool) => fill_mandel_image_parallel(pool, data, col_producer, ustride, mapping),
+        Some(pool) => {
+            let rows_per_part = mapping
+                .win_height
+                .div_ceil(pool.thread_count() as usize)
+                .max(1);
+            pool.scoped(|scope| {
+                let parts = values
+                    .chunks_mut(w * rows_per_part)
+                    .zip(smooth.chunks_mut(w * rows_per_part));
+                for (i, (values, smooth)) in parts.enumerate() {
+                    scope.execute(move || {
+                        let first_row = i * rows_per_part;
+                        fill_buffer(values, smooth, mapping, first_row, cancel, lanes, reference);
+                    });
+                }
+            });
+            !cancel.is_cancelled()
+        }
+    };
+    complete.then(|| IterationBuffer {
+        mapping: mapping.clone(),
+        values,
+        smooth,
+    })
+}
+
+fn color_buffer_rows(
+    data: &mut [u8],
+    ustride: usize,
+    buffer: &IterationBuffer,
+    col_producer: &dyn Coloring,
+    first_row: usize,
+) {
+    let (w, max) = (buffer.mapping.win_width, buffer.mapping.iteration_depth);
+    let smooth = col_producer.is_smooth();
+    for (r, line) in data.chunks_mut(ustride).enumerate() {
+        let start = (first_row + r) * w;
+        for (wx, px) in line[..4 * w].chunks_mut(4).enumerate() {
+            let color = if smooth {
+                col_producer.get_smooth_color(buffer.smooth[start + wx], max)
+            } else {
+                col_producer.get_color(buffer.values[start + wx], max)
+            };
+            px.copy_from_slice(&color.to_ne_bytes());
+        }
+    }
+}
+
+/// Color the pixels of a buffer, which gives the same image as make_mandel_image without
+/// iterating again. Returns None for colorings that use the distance to the set.
+pub fn color_buffer(
+    buffer: &IterationBuffer,
+    col_producer: &dyn Coloring,
+    pool: &mut Option<Pool>,
+) -> Option<(Vec<u8>, i32)> {
+    if col_producer.uses_distance() {
+        return None;
+    }
+    let for_histogram;
+    let col_producer = if col_producer.needs_histogram() {
+        for_histogram = col_producer.for_histogram(&buffer.histogram());
+        for_histogram.as_deref().unwrap_or(col_producer)
+    } else {
+        col_producer
+    };
+    let stride = stride_for_width(buffer.mapping.win_width)?;
+    let ustride = stride as usize;
+    let h = buffer.mapping.win_height;
+    let mut data = vec![0; h * ustride];
+    match pool {
+        None => color_buffer_rows(&mut data, ustride, buffer, col_producer, 0),
+        Some(pool) => {
+            let rows_per_part = h.div_ceil(pool.thread_count() as usize).max(1);
+            pool.scoped(|scope| {
+                for (i, part) in data.chunks_mut(ustride * rows_per_part).enumerate() {
+                    scope.execute(move || {
+                        color_buffer_rows(part, ustride, buffer, col_producer, i * rows_per_part);
+                    });
+                }
+            });
+        }
     }
+    Some((data, stride))
 }
 
 // Make an Vec<u8> and fill it with a mandelbrot image, according to the parameters.
+// Only one pixel per block x block square is computed, so block 1 gives the full image.
 pub fn make_mandel_image(
     mapping: &Mapping,
     col_producer: &Box<dyn Coloring>,
     pool: &mut Option<Pool>,
+    block: usize,
+    cancel: &CancelToken,
+) -> Option<(Vec<u8>, i32)> {
+    let (data, stride, _) =
+        default_backend().render(mapping, col_producer.as_ref(), pool, block, cancel)?;
+    Some((data, stride))
+}
+
+// Like make_mandel_image, with or without the vector kernel, and add what was computed to counter
+pub(crate) fn counted_mandel_image(
+    mapping: &Mapping,
+    col_producer: &dyn Coloring,
+    pool: &mut Option<Pool>,
+    block: usize,
+    cancel: &CancelToken,
+    counter: &StatsCounter,
+    lanes: bool,
 ) -> Option<(Vec<u8>, i32)> {
     if !mapping.is_valid() {
         return None;
     }
-    match IMG_FMT.stride_for_width(mapping.win_width as u32) {
-        Err(_) => None,
-        Ok(stride) => {
+    // A coloring that depends on the histogram is replaced by one for the histogram of this view
+    let for_histogram;
+    let col_producer = if col_producer.needs_histogram() {
+        let histogram = sample_histogram(mapping, pool, cancel)?;
+        for_histogram = col_producer.for_histogram(&histogram);
+        for_histogram.as_deref().unwrap_or(col_producer)
+    } else {
+        col_producer
+    };
+    match stride_for_width(mapping.win_width) {
+        None => None,
+        Some(stride) => {
             let h = mapping.win_height as usize;
             let ustride = stride as usize;
             let mut surface: Vec<u8> = vec![0; h * ustride];
-            if fill_mandel_image(pool, surface.as_mut(), col_producer, ustride, mapping) {
+            let reference = ReferenceOrbit::for_mapping(mapping);
+            let params = RenderParams {
+                col_producer,
+                converter: WinToMandel::from_mapping(mapping),
+                mapping,
+                ustride,
+                block,
+                cancel,
+                counter,
+                lanes,
+                reference: reference.as_ref(),
+            };
+            if fill_mandel_image(pool, surface.as_mut(), &params) {
                 Some((surface, stride))
             } else {
                 None
@@ -243,6 +1460,273 @@ pub fn make_mandel_image(
     }
 }
 
+// Make a small image quickly: on the calling thread and with an iteration depth of at most max_iter
+pub fn make_preview_image(
+    mapping: &Mapping,
+    col_producer: &dyn Coloring,
+    max_iter: u32,
+) -> Option<(Vec<u8>, i32)> {
+    let mut mapping = mapping.clone();
+    mapping.iteration_depth = mapping.iteration_depth.min(max_iter);
+    let (data, stride, _) =
+        default_backend().render(&mapping, col_producer, &mut None, 1, &CancelToken::never())?;
+    Some((data, stride))
+}
+
+// Like make_mandel_image, but every pixel is the average of factor x factor samples
+pub fn make_supersampled_image(
+    mapping: &Mapping,
+    col_producer: &dyn Coloring,
+    pool: &mut Option<Pool>,
+    factor: usize,
+    cancel: &CancelToken,
+) -> Option<(Vec<u8>, i32)> {
+    let (data, stride, _) = supersampled_image(
+        default_backend(),
+        mapping,
+        col_producer,
+        pool,
+        factor,
+        cancel,
+    )?;
+    Some((data, stride))
+}
+
+/// Like make_supersampled_image, rendered by a backend, and what it computed
+pub fn supersampled_image(
+    backend: &dyn RenderBackend,
+    mapping: &Mapping,
+    col_producer: &dyn Coloring,
+    pool: &mut Option<Pool>,
+    factor: usize,
+    cancel: &CancelToken,
+) -> Option<(Vec<u8>, i32, RenderStats)> {
+    if factor <= 1 {
+        return backend.render(mapping, col_producer, pool, 1, cancel);
+    }
+    let mut big_mapping = mapping.clone();
+    big_mapping.win_width *= factor;
+    big_mapping.win_height *= factor;
+    big_mapping.scale /= factor as f64;
+    let (big_data, big_stride, stats) =
+        backend.render(&big_mapping, col_producer, pool, 1, cancel)?;
+    let stride = stride_for_width(mapping.win_width)?;
+    let (ustride, big_ustride) = (stride as usize, big_stride as usize);
+    let mut data = vec![0; mapping.win_height * ustride];
+    let samples = (factor * factor) as u32;
+    for y in 0..mapping.win_height {
+        for x in 0..mapping.win_width {
+            let mut sums = [0u32; 4];
+            for sy in 0..factor {
+                let row = (y * factor + sy) * big_ustride;
+                for sx in 0..factor {
+                    let pos = row + (x * factor + sx) * 4;
+                    for (sum, v) in sums.iter_mut().zip(&big_data[pos..pos + 4]) {
+                        *sum += *v as u32;
+                    }
+                }
+            }
+            let pos = y * ustride + x * 4;
+            for (v, sum) in data[pos..pos + 4].iter_mut().zip(sums) {
+                *v = (sum / samples) as u8;
+            }
+        }
+    }
+    Some((data, stride, stats))
+}
+
+/// The number of samples per row and per column of a pixel that adaptive anti-aliasing takes
+pub const ADAPTIVE_FACTOR: usize = 3;
+// Neighboring pixels are sampled again if their smooth values differ by more than this, or if
+// one of them is in the set and the other is not
+const ADAPTIVE_CONTRAST: f64 = 1.0;
+
+/// Whether each pixel of a buffer differs strongly in value from one of its four neighbors,
+/// row by row. These are the pixels that adaptive anti-aliasing samples again.
+pub fn high_contrast_pixels(buffer: &IterationBuffer) -> Vec<bool> {
+    let (w, h) = (buffer.mapping.win_width, buffer.mapping.win_height);
+    let max = buffer.mapping.iteration_depth;
+    let differ = |a: usize, b: usize| {
+        (buffer.values[a] >= max) != (buffer.values[b] >= max)
+            || (buffer.smooth[a] - buffer.smooth[b]).abs() > ADAPTIVE_CONTRAST
+    };
+    let mut marks = vec![false; w * h];
+    for k in 0..w * h {
+        // Comparing with the right and the lower neighbor marks both pixels of a pair
+        for (next, beside) in [(k + 1, (k + 1) % w != 0), (k + w, k + w < w * h)] {
+            if beside && differ(k, next) {
+                marks[k] = true;
+                marks[next] = true;
+            }
+        }
+    }
+    marks
+}
+
+// Replace the colors of the marked pixels of the rows of an image that start at row first_row
+// by the average color of ADAPTIVE_FACTOR x ADAPTIVE_FACTOR samples, of which the first is the
+// pixel itself. Returns what the other samples computed, or None if cancelled.
+fn refine_rows(
+    data: &mut [u8],
+    ustride: usize,
+    buffer: &IterationBuffer,
+    col_producer: &dyn Coloring,
+    marks: &[bool],
+    first_row: usize,
+    cancel: &CancelToken,
+) -> Option<RenderStats> {
+    let mapping = &buffer.mapping;
+    let (fractal, max, w) = (&mapping.fractal, mapping.iteration_depth, mapping.win_width);
+    let converter = WinToMandel::from_mapping(mapping);
+    let step = 1.0 / ADAPTIVE_FACTOR as f64;
+    let samples = (ADAPTIVE_FACTOR * ADAPTIVE_FACTOR) as u32;
+    let mut stats = RenderStats::default();
+    for (r, line) in data.chunks_mut(ustride).enumerate() {
+        if cancel.is_cancelled() {
+            return None;
+        }
+        let wy = first_row + r;
+        for wx in (0..w).filter(|&wx| marks[wy * w + wx]) {
+            let mut sums = [0u32; 4];
+            for sy in 0..ADAPTIVE_FACTOR {
+                for sx in 0..ADAPTIVE_FACTOR {
+                    let (value, smooth) = if sx == 0 && sy == 0 {
+                        (buffer.values[wy * w + wx], buffer.smooth[wy * w + wx])
+                    } else {
+                        let (x, y) = converter
+                            .cvt_f(wx as f64 + sx as f64 * step, wy as f64 + sy as f64 * step);
+                        let (mut z, c) = iteration_start(fractal, x, y);
+                        let mut iter = 0;
+                        let escaped = continue_iteration(fractal, &mut z, c, &mut iter, max);
+                        stats.add_point(iter, max);
+                        let smooth = if escaped {
+                            smooth_escape(fractal, iter, z)
+                        } else {
+                            max as f64
+                        };
+                        (iter, smooth)
+                    };
+                    let color = if col_producer.is_smooth() {
+                        col_producer.get_smooth_color(smooth, max)
+                    } else {
+                        col_producer.get_color(value, max)
+                    };
+                    for (sum, v) in sums.iter_mut().zip(color.to_ne_bytes()) {
+                        *sum += v as u32;
+                    }
+                }
+            }
+            for (v, sum) in line[4 * wx..4 * wx + 4].iter_mut().zip(sums) {
+                *v = (sum / samples) as u8;
+            }
+        }
+    }
+    Some(stats)
+}
+
+/// Anti-alias an image that was colored from a buffer by color_buffer, only at the pixels of
+/// high_contrast_pixels, which get the average color of ADAPTIVE_FACTOR x ADAPTIVE_FACTOR
+/// samples. Deep views, which are rendered with perturbation, are left as they are. Returns
+/// what the new samples computed, or None if cancelled.
+pub fn refine_image(
+    data: &mut [u8],
+    stride: i32,
+    buffer: &IterationBuffer,
+    col_producer: &dyn Coloring,
+    pool: &mut Option<Pool>,
+    cancel: &CancelToken,
+) -> Option<RenderStats> {
+    if uses_perturbation(&buffer.mapping) {
+        return Some(RenderStats::default());
+    }
+    let for_histogram;
+    let col_producer = if col_producer.needs_histogram() {
+        for_histogram = col_producer.for_histogram(&buffer.histogram());
+        for_histogram.as_deref().unwrap_or(col_producer)
+    } else {
+        col_producer
+    };
+    let marks = high_contrast_pixels(buffer);
+    let ustride = stride as usize;
+    match pool {
+        None => refine_rows(data, ustride, buffer, col_producer, &marks, 0, cancel),
+        Some(pool) => {
+            let rows_per_part = buffer
+                .mapping
+                .win_height
+                .div_ceil(pool.thread_count() as usize)
+                .max(1);
+            let counter = StatsCounter::default();
+            pool.scoped(|scope| {
+                for (i, part) in data.chunks_mut(ustride * rows_per_part).enumerate() {
+                    let (marks, counter) = (&marks, &counter);
+                    scope.execute(move || {
+                        let first_row = i * rows_per_part;
+                        let refined = refine_rows(
+                            part,
+                            ustride,
+                            buffer,
+                            col_producer,
+                            marks,
+                            first_row,
+                            cancel,
+                        );
+                        if let Some(stats) = refined {
+                            counter.add(&stats);
+                        }
+                    });
+                }
+            });
+            (!cancel.is_cancelled()).then(|| counter.stats())
+        }
+    }
+}
+
+/// The largest number of bytes of the samples of a tile of a tiled image
+pub const TILE_BYTES: usize = 32 << 20;
+
+/// The part of mapping with rows rows, starting at row first_row
+pub fn tile_mapping(mapping: &Mapping, first_row: usize, rows: usize) -> Mapping {
+    region_mapping(mapping, 0, first_row, mapping.win_width, rows)
+}
+
+/// The part of mapping with cols columns and rows rows, starting at column first_col and row
+/// first_row. Its pixels have the coordinates of those of mapping.
+pub fn region_mapping(
+    mapping: &Mapping,
+    first_col: usize,
+    first_row: usize,
+    cols: usize,
+    rows: usize,
+) -> Mapping {
+    let view = mapping.view_part();
+    Mapping {
+        win_width: cols,
+        win_height: rows,
+        part: Some(ViewPart {
+            first_col: view.first_col + first_col,
+            first_row: view.first_row + first_row,
+            ..view
+        }),
+        ..mapping.clone()
+    }
+}
+
+/// The tiles, from top to bottom, in which an image that is too large for one buffer is
+/// rendered, as the first row of every tile and its mapping. A tile has the whole width of
+/// the image, because a PNG file is written by rows, and as many rows as fit in TILE_BYTES
+/// when it is rendered with factor x factor samples per pixel.
+pub fn tiles(mapping: &Mapping, factor: usize) -> impl Iterator<Item = (usize, Mapping)> + '_ {
+    let row_bytes = 4 * mapping.win_width * factor * factor;
+    let tile_rows = (TILE_BYTES / row_bytes.max(1)).max(1);
+    (0..mapping.win_height)
+        .step_by(tile_rows)
+        .map(move |first_row| {
+            let rows = tile_rows.min(mapping.win_height - first_row);
+            (first_row, tile_mapping(mapping, first_row, rows))
+        })
+}
+
 fn last_request(
     mut request: MandelReq,
     req_receiver: &async_channel::Receiver<MandelReq>,
@@ -258,21 +1742,405 @@ fn last_request(
     }
 }
 
-pub fn mandel_producer(
-    req_receiver: async_channel::Receiver<MandelReq>,
-    reply_sender: async_channel::Sender<MandelReply>,
-) {
-    let par_count: usize;
-    match thread::available_parallelism() {
-        Ok(pc) => par_count = pc.into(),
-        Err(_) => par_count = 8,
+// The number of threads of the pools that new_pool makes, 0 for one per core
+static WORKER_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Let the pools that are made after this have n threads, or one per core for 0
+pub fn set_worker_threads(n: usize) {
+    WORKER_THREADS.store(n, Ordering::Relaxed);
+}
+
+/// The number of threads of the pools that new_pool makes
+pub fn worker_threads() -> usize {
+    match WORKER_THREADS.load(Ordering::Relaxed) {
+        0 => thread::available_parallelism().map_or(8, |pc| pc.into()),
+        n => n,
     }
+}
+
+// Make a thread pool with worker_threads threads, or None if that is only one
+pub fn new_pool() -> Option<Pool> {
+    let par_count = worker_threads();
     eprintln!("Parallelism is {}", par_count);
-    let mut pool = if par_count <= 1 {
+    if par_count <= 1 {
         None
     } else {
         Some(Pool::new(par_count as u32))
+    }
+}
+
+type Pass = (Vec<u8>, i32, Option<Arc<IterationBuffer>>, RenderStats);
+
+// The side of the tiles of the last pass, which are shown one by one as they are done
+const DISPLAY_TILE: usize = 128;
+
+// Whether the last pass of a request, if it is rendered on a pool, is computed in tiles that
+// are shown as they are done. Not for colorings that depend on the histogram of the whole view or on the distance to the
+// set, which need more than the values of a tile, nor for deep views, for which every tile
+// would iterate its own reference orbit.
+fn shows_tiles(request: &MandelReq) -> bool {
+    let (mapping, coloring) = (&request.mapping, request.coloring.as_ref());
+    (mapping.win_width > DISPLAY_TILE || mapping.win_height > DISPLAY_TILE)
+        && mapping.fractal.fractal_type != FractalType::Newton
+        && !coloring.needs_histogram()
+        && !coloring.uses_distance()
+        && !uses_perturbation(mapping)
+}
+
+// Whether the last pass of a request is computed from the values of the view before it, which
+// only moved, and by how many columns and rows. Only the pixels that came into view are
+// iterated then.
+fn reused_shift(request: &MandelReq) -> Option<(isize, isize)> {
+    let (mapping, previous) = (&request.mapping, request.previous.as_ref()?);
+    if request.factor > 1 || request.coloring.uses_distance() {
+        return None;
+    }
+    let (cols, rows) = mapping.pixel_shift(&previous.mapping)?;
+    let (w, h) = (mapping.win_width as isize, mapping.win_height as isize);
+    (cols.abs() < w && rows.abs() < h).then_some((cols, rows))
+}
+
+// The values of the last pass of a request that moved by (cols, rows) pixels from the view of
+// its previous values: the pixels that are still in view are copied, and the strips that came
+// into view are computed. Returns them with what was computed, or None if cancelled.
+fn shifted_values(
+    request: &MandelReq,
+    (cols, rows): (isize, isize),
+    pool: &mut Option<Pool>,
+) -> Option<(IterationBuffer, RenderStats)> {
+    let (mapping, cancel) = (&request.mapping, &request.cancel);
+    let previous = request.previous.as_ref()?;
+    let (w, h) = (mapping.win_width, mapping.win_height);
+    let (mut values, mut smooth) = (vec![0; w * h], vec![0.0; w * h]);
+    // The columns and rows of this view that the previous view has too
+    let kept = |shift: isize, n: usize| {
+        let start = (-shift).max(0) as usize;
+        start..(n as isize - shift).min(n as isize) as usize
+    };
+    let (kept_cols, kept_rows) = (kept(cols, w), kept(rows, h));
+    for wy in kept_rows.clone() {
+        let from = (wy as isize + rows) as usize * w + (kept_cols.start as isize + cols) as usize;
+        let to = wy * w + kept_cols.start;
+        let n = kept_cols.len();
+        values[to..to + n].copy_from_slice(&previous.values[from..from + n]);
+        smooth[to..to + n].copy_from_slice(&previous.smooth[from..from + n]);
+    }
+    // The rows above or below the kept ones, and the columns beside them
+    let other = |kept: &std::ops::Range<usize>, n: usize| {
+        if kept.start > 0 {
+            0..kept.start
+        } else {
+            kept.end..n
+        }
+    };
+    let (new_rows, new_cols) = (other(&kept_rows, h), other(&kept_cols, w));
+    let strips = [(0..w, new_rows), (new_cols, kept_rows)];
+    let mut stats = RenderStats::default();
+    for (strip_cols, strip_rows) in strips {
+        if strip_cols.is_empty() || strip_rows.is_empty() {
+            continue;
+        }
+        let (x, y, n) = (strip_cols.start, strip_rows.start, strip_cols.len());
+        let strip = region_mapping(mapping, x, y, n, strip_rows.len());
+        let buffer = request.backend.values(&strip, pool, cancel)?;
+        stats.add(&buffer.stats());
+        let parts = buffer.values.chunks(n).zip(buffer.smooth.chunks(n));
+        for (r, (strip_values, strip_smooth)) in parts.enumerate() {
+            let to = (y + r) * w + x;
+            values[to..to + n].copy_from_slice(strip_values);
+            smooth[to..to + n].copy_from_slice(strip_smooth);
+        }
+    }
+    let buffer = IterationBuffer {
+        mapping: mapping.clone(),
+        values,
+        smooth,
+    };
+    Some((buffer, stats))
+}
+
+/// The number of megabytes that the cache of rendered tiles has at the start
+pub const DEFAULT_TILE_CACHE_MB: usize = 64;
+
+// The recently rendered tiles of the last passes, the most recently used first, so that views
+// that are shown again, e.g. after zooming out and in again, are not computed again. A tile
+// is found by its mapping, which has its position, its scale and its iteration depth.
+struct TileCache {
+    tiles: VecDeque<Arc<IterationBuffer>>,
+    bytes: usize,
+    // The backend that rendered the tiles
+    backend: &'static str,
+}
+
+impl TileCache {
+    fn tile_bytes(tile: &IterationBuffer) -> usize {
+        tile.values.len() * (size_of::<u32>() + size_of::<f64>())
+    }
+
+    // Forget the tiles of other fractals or parameters than those of mapping, or of another
+    // backend, which will not be shown again soon
+    fn keep_only(&mut self, mapping: &Mapping, backend: &'static str) {
+        let same = |tile: &Arc<IterationBuffer>| tile.mapping.fractal == mapping.fractal;
+        if self.backend != backend || !self.tiles.front().is_none_or(same) {
+            self.tiles.clear();
+            self.bytes = 0;
+            self.backend = backend;
+        }
+    }
+
+    fn get(&mut self, mapping: &Mapping) -> Option<Arc<IterationBuffer>> {
+        let k = self
+            .tiles
+            .iter()
+            .position(|tile| tile.mapping == *mapping)?;
+        let tile = self.tiles.remove(k)?;
+        self.tiles.push_front(tile.clone());
+        Some(tile)
+    }
+
+    fn insert(&mut self, tile: Arc<IterationBuffer>) {
+        self.bytes += TileCache::tile_bytes(&tile);
+        self.tiles.push_front(tile);
+        self.trim();
+    }
+
+    // Forget the least recently used tiles that do not fit
+    fn trim(&mut self) {
+        let capacity = TILE_CACHE_MB.load(Ordering::Relaxed) << 20;
+        while self.bytes > capacity {
+            let Some(tile) = self.tiles.pop_back() else {
+                break;
+            };
+            self.bytes -= TileCache::tile_bytes(&tile);
+        }
+    }
+}
+
+static TILE_CACHE: Mutex<TileCache> = Mutex::new(TileCache {
+    tiles: VecDeque::new(),
+    bytes: 0,
+    backend: "",
+});
+
+// The size of the cache of rendered tiles in megabytes
+static TILE_CACHE_MB: AtomicUsize = AtomicUsize::new(DEFAULT_TILE_CACHE_MB);
+
+/// Let the cache of rendered tiles hold mb megabytes, or nothing for 0
+pub fn set_tile_cache_size(mb: usize) {
+    TILE_CACHE_MB.store(mb, Ordering::Relaxed);
+    TILE_CACHE.lock().unwrap_or_else(|e| e.into_inner()).trim();
+}
+
+// Compute the values of the last pass of a request in tiles of DISPLAY_TILE x DISPLAY_TILE
+// pixels on the threads of the pool, which each take the next tile when they are done with
+// one, and send every tile to the view, colored, as soon as it is done. Tiles that are in the
+// cache are not computed again. Returns the values with what was computed, or None if the
+// render was cancelled.
+fn tiled_values(
+    request: &MandelReq,
+    pool: &mut Pool,
+    reply_sender: &PolicySender<MandelReply>,
+) -> Option<(IterationBuffer, RenderStats)> {
+    let (mapping, cancel) = (&request.mapping, &request.cancel);
+    let (w, h) = (mapping.win_width, mapping.win_height);
+    let cache = || TILE_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    cache().keep_only(mapping, request.backend.name());
+    let done = Mutex::new(Vec::new());
+    let counter = StatsCounter::default();
+    pool.scoped(|scope| {
+        for y in (0..h).step_by(DISPLAY_TILE) {
+            for x in (0..w).step_by(DISPLAY_TILE) {
+                let (done, counter) = (&done, &counter);
+                scope.execute(move || {
+                    let (cols, rows) = (DISPLAY_TILE.min(w - x), DISPLAY_TILE.min(h - y));
+                    let tile = region_mapping(mapping, x, y, cols, rows);
+                    let cached = cache().get(&tile);
+                    let buffer = match cached {
+                        Some(buffer) => buffer,
+                        None => {
+                            let Some(buffer) = request.backend.values(&tile, &mut None, cancel)
+                            else {
+                                return;
+                            };
+                            counter.add(&buffer.stats());
+                            let buffer = Arc::new(buffer);
+                            cache().insert(buffer.clone());
+                            buffer
+                        }
+                    };
+                    let coloring = request.coloring.as_ref();
+                    if let Some((mut data, stride)) = color_buffer(&buffer, coloring, &mut None) {
+                        if let Some(lighting) = &request.lighting {
+                            apply_buffer_lighting(&mut data, stride as usize, &buffer, lighting);
+                        }
+                        reply_sender.send_blocking(MandelReply::Tile(MandelTile {
+                            data,
+                            x,
+                            y,
+                            width: cols,
+                            height: rows,
+                            stride,
+                            mapping: mapping.clone(),
+                        }));
+                    }
+                    done.lock().unwrap().push((x, y, buffer));
+                });
+            }
+        }
+    });
+    if cancel.is_cancelled() {
+        return None;
+    }
+    let (mut values, mut smooth) = (vec![0; w * h], vec![0.0; w * h]);
+    for (x, y, tile) in done.into_inner().unwrap() {
+        let cols = tile.mapping.win_width;
+        let rows = tile.values.chunks(cols).zip(tile.smooth.chunks(cols));
+        for (r, (tile_values, tile_smooth)) in rows.enumerate() {
+            let start = (y + r) * w + x;
+            values[start..start + cols].copy_from_slice(tile_values);
+            smooth[start..start + cols].copy_from_slice(tile_smooth);
+        }
+    }
+    let buffer = IterationBuffer {
+        mapping: mapping.clone(),
+        values,
+        smooth,
     };
+    Some((buffer, counter.stats()))
+}
+
+// Render one pass of a request. The last pass, at full resolution, computes the values of
+// the pixels first and colors them in a separate step, so the values can be kept for coloring
+// the view again. A supersampled last pass keeps no values. A large last pass is computed in
+// tiles, which are sent to the view as they are done. Returns None if the render failed or
+// was cancelled.
+fn render_pass(
+    request: &MandelReq,
+    block: usize,
+    pool: &mut Option<Pool>,
+    reply_sender: &PolicySender<MandelReply>,
+) -> Option<Pass> {
+    let (mapping, cancel) = (&request.mapping, &request.cancel);
+    let supersampled = block == 1 && request.factor > 1;
+    let values = if block != 1 || supersampled {
+        None
+    } else if let Some(shift) = reused_shift(request) {
+        shifted_values(request, shift, pool)
+    } else {
+        match pool {
+            Some(tile_pool) if shows_tiles(request) => {
+                tiled_values(request, tile_pool, reply_sender)
+            }
+            _ => request.backend.values(mapping, pool, cancel).map(|buffer| {
+                let stats = buffer.stats();
+                (buffer, stats)
+            }),
+        }
+    };
+    if cancel.is_cancelled() {
+        return None;
+    }
+    let (buffer, mut stats) = match values {
+        Some((buffer, stats)) => (Some(Arc::new(buffer)), stats),
+        None => (None, RenderStats::default()),
+    };
+    let colored = buffer
+        .as_ref()
+        .and_then(|buffer| color_buffer(buffer, request.coloring.as_ref(), pool));
+    let coloring = request.coloring.as_ref();
+    let (mut data, stride) = match colored {
+        Some(image) => image,
+        None => {
+            let (data, stride, rendered) = if supersampled {
+                supersampled_image(
+                    request.backend,
+                    mapping,
+                    coloring,
+                    pool,
+                    request.factor,
+                    cancel,
+                )?
+            } else {
+                // Newton fractals and colorings that use the distance to the set
+                request
+                    .backend
+                    .render(mapping, coloring, pool, block, cancel)?
+            };
+            stats.add(&rendered);
+            (data, stride)
+        }
+    };
+    if let Some(lighting) = &request.lighting {
+        match &buffer {
+            Some(buffer) => apply_buffer_lighting(&mut data, stride as usize, buffer, lighting),
+            None => {
+                if !apply_lighting(
+                    &mut data,
+                    stride as usize,
+                    mapping,
+                    lighting,
+                    block,
+                    pool,
+                    cancel,
+                ) {
+                    return None;
+                }
+            }
+        }
+    }
+    Some((data, stride, buffer, stats))
+}
+
+// Sample the pixels of high contrast of the last pass of a request again, see refine_image,
+// and send the image to the view as a tile of the whole view. The replies are queued, see
+// REPLY_CAPACITY, so the tile does not take the place of the last pass.
+fn refine_pass(
+    request: &MandelReq,
+    buffer: &IterationBuffer,
+    pool: &mut Option<Pool>,
+    reply_sender: &PolicySender<MandelReply>,
+) {
+    let coloring = request.coloring.as_ref();
+    let Some((mut data, stride)) = color_buffer(buffer, coloring, pool) else {
+        return;
+    };
+    match refine_image(&mut data, stride, buffer, coloring, pool, &request.cancel) {
+        Some(stats) if stats.points > 0 => {}
+        _ => return,
+    }
+    if let Some(lighting) = &request.lighting {
+        apply_buffer_lighting(&mut data, stride as usize, buffer, lighting);
+    }
+    reply_sender.send_blocking(MandelReply::Tile(MandelTile {
+        data,
+        x: 0,
+        y: 0,
+        width: buffer.mapping.win_width,
+        height: buffer.mapping.win_height,
+        stride,
+        mapping: request.mapping.clone(),
+    }));
+}
+
+// The pool of the producers of all windows, with its number of threads. The producers take
+// turns using it, a pass at a time.
+static SHARED_POOL: Mutex<Option<(usize, Option<Pool>)>> = Mutex::new(None);
+
+// Lock the shared pool, which is made again when the number of threads was changed while the
+// application runs
+fn lock_shared_pool() -> MutexGuard<'static, Option<(usize, Option<Pool>)>> {
+    let mut shared = SHARED_POOL.lock().unwrap_or_else(|e| e.into_inner());
+    let threads = worker_threads();
+    if shared.as_ref().is_none_or(|(n, _)| *n != threads) {
+        *shared = Some((threads, new_pool()));
+    }
+    shared
+}
+
+pub fn mandel_producer(
+    req_receiver: async_channel::Receiver<MandelReq>,
+    reply_sender: PolicySender<MandelReply>,
+) {
     loop {
         let mut request;
         match req_receiver.recv_blocking() {
@@ -282,15 +2150,297 @@ pub fn mandel_producer(
             }
         }
         request = last_request(request, &req_receiver);
-        if let Some((data, stride)) =
-            make_mandel_image(&request.mapping, &request.coloring, &mut pool)
-        {
-            let _ = reply_sender.send_blocking(MandelReply {
-                data,
-                width: request.mapping.win_width as i32,
-                height: request.mapping.win_height as i32,
-                stride,
-            });
+        // A view that only moved needs no coarse passes, because its last pass is quick
+        let blocks = match reused_shift(&request) {
+            Some(_) if request.blocks.last() == Some(&1) => vec![1],
+            _ => request.blocks.clone(),
+        };
+        for (pass, &block) in blocks.iter().enumerate() {
+            // Stop refining when a newer request is waiting
+            if pass > 0 && !req_receiver.is_empty() {
+                break;
+            }
+            let mut shared = lock_shared_pool();
+            let Some((_, pool)) = shared.as_mut() else {
+                break;
+            };
+            let start = Instant::now();
+            let result = render_pass(&request, block, pool, &reply_sender);
+            drop(shared);
+            match result {
+                Some((data, stride, buffer, stats)) => {
+                    let refine = buffer.clone().filter(|_| request.adaptive);
+                    reply_sender.send_blocking(MandelReply::Pass(MandelPass {
+                        data,
+                        width: request.mapping.win_width as i32,
+                        height: request.mapping.win_height as i32,
+                        stride,
+                        block,
+                        seconds: start.elapsed().as_secs_f64(),
+                        mapping: request.mapping.clone(),
+                        buffer,
+                        stats,
+                    }));
+                    // The image of the last pass is shown before its edges are refined
+                    if let Some(buffer) = refine.filter(|_| req_receiver.is_empty()) {
+                        if let Some((_, pool)) = lock_shared_pool().as_mut() {
+                            refine_pass(&request, &buffer, pool, &reply_sender);
+                        }
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The coloring of the tests, the first of the color schemes
+    fn test_coloring() -> Box<dyn Coloring> {
+        crate::colorings::ColorInfo::new().scheme(0).clone()
+    }
+
+    // A request that renders only the last pass of a view, in the coloring of the tests
+    fn last_pass_request(mapping: &Mapping, previous: Option<IterationBuffer>) -> MandelReq {
+        MandelReq {
+            mapping: mapping.clone(),
+            coloring: test_coloring(),
+            cancel: CancelToken::never(),
+            blocks: vec![1],
+            lighting: None,
+            factor: 1,
+            backend: default_backend(),
+            previous: previous.map(Arc::new),
+            adaptive: false,
         }
     }
+
+    // The number of places at which a and b differ
+    fn differences<T: PartialEq>(
+        a: impl IntoIterator<Item = T>,
+        b: impl IntoIterator<Item = T>,
+    ) -> usize {
+        a.into_iter().zip(b).filter(|(x, y)| x != y).count()
+    }
+
+    #[test]
+    fn orbits_end_in_a_cycle_or_escape() {
+        let fractal = Mapping::new_for_size(100).fractal;
+        assert_eq!(orbit(&fractal, 0.0, 0.0, 100).period, Some(1));
+        assert_eq!(orbit(&fractal, -1.0, 0.0, 100).period, Some(2));
+        // Points in the main cardioid are iterated like any other
+        let inside = orbit(&fractal, -0.1, 0.1, 3);
+        assert_eq!(
+            inside.points[..3],
+            [(0.0, 0.0), (-0.1, 0.1), (-0.1, 0.1 - 2.0 * 0.1 * 0.1)]
+        );
+        assert_eq!(inside.points.len(), 4);
+        let escaping = orbit(&fractal, 1.0, 0.0, 100);
+        assert!(escaping.escaped);
+        assert_eq!(
+            escaping.points.len() as u32,
+            fractal_value(&fractal, 1.0, 0.0, 100) + 2
+        );
+    }
+
+    #[test]
+    fn cycles_stop_the_iteration_of_interior_points() {
+        // Without the periodicity check these would take a billion iterations each
+        let depth = 1 << 30;
+        assert_eq!(mandel_value(-0.1, 0.1, depth), depth);
+        let fractal = Mapping::new_for_size(100).fractal;
+        let (mut z, mut iter) = ((0.0, 0.0), 0);
+        assert!(!continue_iteration(
+            &fractal,
+            &mut z,
+            (-1.0, 0.2),
+            &mut iter,
+            depth
+        ));
+        assert_eq!(iter, depth);
+        let lanes = mandel_lanes([-0.1, -1.0, 0.3, -1.3], 0.0, depth);
+        assert_eq!(
+            lanes.iterations,
+            [depth, depth, mandel_value(0.3, 0.0, 100), depth]
+        );
+        assert_eq!(lanes.escaped, [false, false, true, false]);
+        // The main cardioid and the period-2 bulb are known without iterating
+        assert!(in_main_bulbs(-0.1, 0.1) && in_main_bulbs(-1.0, 0.2));
+        assert!(!in_main_bulbs(-0.75, 0.1) && !in_main_bulbs(-1.3, 0.0));
+    }
+
+    #[test]
+    fn mirrored_rows_are_those_of_the_fractal() {
+        let mut mapping = Mapping::new_for_size(40);
+        (mapping.win_width, mapping.iteration_depth) = (60, 80);
+        // The axis is 7 rows below the middle, so rows 28 to 39 are mirrored
+        mapping.cy = 7.0 * mapping.scale;
+        let coloring = test_coloring();
+        let cancel = CancelToken::never();
+        let (mirrored, stride) =
+            make_mandel_image(&mapping, &coloring, &mut None, 1, &cancel).unwrap();
+        // A tiny shift makes the rows fall between each other, so none are mirrored
+        mapping.cy += 1e-9 * mapping.scale;
+        let (rendered, _) = make_mandel_image(&mapping, &coloring, &mut None, 1, &cancel).unwrap();
+        let row = |data: &[u8], wy: usize| data[wy * stride as usize..][..stride as usize].to_vec();
+        assert_eq!(stride, 4 * 60);
+        assert_eq!(row(&mirrored, 28), row(&mirrored, 26));
+        // Except for the points on the edge that the shift moves out of the set, 6 of the
+        // 2400 here
+        let pixel = |data: &[u8], k: usize| data[4 * k..][..4].to_vec();
+        let different = differences(
+            (0..40 * 60).map(|k| pixel(&mirrored, k)),
+            (0..40 * 60).map(|k| pixel(&rendered, k)),
+        );
+        assert!(different <= 8, "{} pixels differ", different);
+    }
+
+    #[test]
+    fn tiles_have_the_values_of_the_view() {
+        let mut mapping = Mapping::new_for_size(200);
+        (mapping.win_width, mapping.iteration_depth) = (300, 100);
+        let request = last_pass_request(&mapping, None);
+        assert!(shows_tiles(&request));
+        let (sender, receiver) = crate::channels::channel(8, crate::channels::Policy::Queue);
+        let (tiled, stats) = tiled_values(&request, &mut Pool::new(2), &sender).unwrap();
+        assert_eq!(receiver.len(), 3 * 2);
+        assert_eq!(stats.points, 300 * 200);
+        // The tiles are in the cache when the view is shown again
+        receiver.close();
+        let (again, stats) = tiled_values(&request, &mut Pool::new(2), &sender).unwrap();
+        assert_eq!((again.values, stats.points), (tiled.values.clone(), 0));
+        let view = make_iteration_buffer(&mapping, &mut None, &CancelToken::never()).unwrap();
+        assert_eq!(tiled.values, view.values);
+        assert_eq!(tiled.smooth, view.smooth);
+    }
+
+    #[test]
+    fn a_moved_view_reuses_the_pixels_in_view() {
+        // A scale of 1/32 makes the coordinates of all pixels exact. The kept values were
+        // computed for the previous center, and are otherwise only within a rounding of those
+        // of the moved view.
+        let mut mapping = Mapping::new_for_size(128);
+        (mapping.win_width, mapping.win_height) = (120, 100);
+        let cancel = CancelToken::never();
+        let previous = make_iteration_buffer(&mapping, &mut None, &cancel).unwrap();
+        mapping.move_center(7.0 * mapping.scale, 5.0 * mapping.scale);
+        assert_eq!(mapping.pixel_shift(&previous.mapping), Some((7, -5)));
+        let request = last_pass_request(&mapping, Some(previous));
+        let shift = reused_shift(&request).unwrap();
+        let (shifted, stats) = shifted_values(&request, shift, &mut None).unwrap();
+        // Only the strip of 5 rows at the top and of 7 columns at the right are computed
+        assert_eq!(stats.points, 5 * 120 + 7 * 95);
+        let view = make_iteration_buffer(&mapping, &mut None, &cancel).unwrap();
+        assert_eq!(shifted.values, view.values);
+        assert_eq!(shifted.smooth, view.smooth);
+        // A view with other parameters is computed again
+        mapping.iteration_depth += 1;
+        assert_eq!(mapping.pixel_shift(&view.mapping), None);
+    }
+
+    #[test]
+    fn refined_pixels_are_supersampled() {
+        let mut mapping = Mapping::new_for_size(60);
+        mapping.iteration_depth = 100;
+        let coloring = test_coloring();
+        let cancel = CancelToken::never();
+        let buffer = make_iteration_buffer(&mapping, &mut None, &cancel).unwrap();
+        let marks = high_contrast_pixels(&buffer);
+        let marked = marks.iter().filter(|&&m| m).count();
+        assert!(marked > 0 && marked < 60 * 60, "{} pixels marked", marked);
+        let (colored, stride) = color_buffer(&buffer, coloring.as_ref(), &mut None).unwrap();
+        let mut refined = colored.clone();
+        let stats = refine_image(
+            &mut refined,
+            stride,
+            &buffer,
+            coloring.as_ref(),
+            &mut Some(Pool::new(2)),
+            &cancel,
+        )
+        .unwrap();
+        let extra = (ADAPTIVE_FACTOR * ADAPTIVE_FACTOR - 1) as u64;
+        assert_eq!(stats.points, marked as u64 * extra);
+        let (supersampled, _, _) = supersampled_image(
+            default_backend(),
+            &mapping,
+            coloring.as_ref(),
+            &mut None,
+            ADAPTIVE_FACTOR,
+            &cancel,
+        )
+        .unwrap();
+        let pixel = |data: &[u8], k: usize| {
+            let pos = (k / 60) * stride as usize + (k % 60) * 4;
+            data[pos..pos + 4].to_vec()
+        };
+        for (k, &m) in marks.iter().enumerate() {
+            if !m {
+                assert_eq!(pixel(&refined, k), pixel(&colored, k));
+            }
+        }
+        // The samples are at the same points as those of supersampling
+        let marked_pixels = |data: &[u8]| {
+            (0..60 * 60)
+                .filter(|&k| marks[k])
+                .map(|k| pixel(data, k))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(marked_pixels(&refined), marked_pixels(&supersampled));
+    }
+
+    #[test]
+    fn the_last_pass_arrives_when_replies_are_read_late() {
+        use crate::channels::{channel, Policy, REPLY_CAPACITY, REQUEST_CAPACITY};
+        let mut mapping = Mapping::new_for_size(200);
+        (mapping.win_width, mapping.iteration_depth) = (300, 100);
+        let request = MandelReq {
+            blocks: vec![8, 1],
+            adaptive: true,
+            ..last_pass_request(&mapping, None)
+        };
+        let (req_sender, req_receiver) = channel(REQUEST_CAPACITY, Policy::LatestWins);
+        let (reply_sender, replies) = channel(REPLY_CAPACITY, Policy::Queue);
+        let producer = thread::spawn(move || mandel_producer(req_receiver, reply_sender));
+        req_sender.send_blocking(request);
+        // The view reads nothing until the producer is done
+        drop(req_sender);
+        producer.join().unwrap();
+        let replies: Vec<MandelReply> = std::iter::from_fn(|| replies.try_recv().ok()).collect();
+        let block = |reply: &MandelReply| match reply {
+            MandelReply::Pass(pass) => Some(pass.block),
+            MandelReply::Tile(_) => None,
+        };
+        // The coarse pass, the tiles of the last pass if there is a pool, the last pass with
+        // its values and the refinement of the whole view
+        let n = replies.len();
+        assert_eq!(block(&replies[0]), Some(8));
+        assert!(replies[1..n - 2].iter().all(|reply| block(reply).is_none()));
+        match &replies[n - 2] {
+            MandelReply::Pass(pass) => assert!(pass.block == 1 && pass.buffer.is_some()),
+            MandelReply::Tile(_) => panic!("the last pass is missing"),
+        }
+        match &replies[n - 1] {
+            MandelReply::Tile(tile) => assert_eq!((tile.width, tile.height), (300, 200)),
+            MandelReply::Pass(_) => panic!("the refinement is missing"),
+        }
+    }
+
+    #[test]
+    fn render_stats_count_every_pixel() {
+        let mut mapping = Mapping::new_for_size(40);
+        mapping.iteration_depth = 50;
+        let coloring = test_coloring();
+        let cancel = CancelToken::never();
+        let (_, _, stats) = default_backend()
+            .render(&mapping, coloring.as_ref(), &mut None, 1, &cancel)
+            .unwrap();
+        let buffer = make_iteration_buffer(&mapping, &mut None, &cancel).unwrap();
+        assert_eq!(stats.points, 40 * 40);
+        assert!(stats.max_depth > 0 && stats.max_depth < stats.points);
+        assert_eq!(stats, buffer.stats());
+    }
 }
@@ -0,0 +1,249 @@
+// The Mariani-Silver algorithm: the image is divided in rectangles, and of every rectangle only
+// the border is iterated at first. If all pixels of the border have the same value, the
+// interior has it too and is filled without iterating; otherwise the rectangle is divided in
+// four, which share the lines through its middle, and those are done the same way. This relies
+// on the set being connected: a border in the set encloses only points of the set, and a border
+// of one iteration count lies within one band of the count. A part of the set that is smaller
+// than the rectangles can stay unseen, which is why the rectangles start small.
+// Only the interior of the set is filled for smooth colorings, because within a band of the
+// iteration count the smooth value still changes from pixel to pixel.
+
+use scoped_threadpool::Pool;
+
+use crate::backend::{RenderBackend, SIMD};
+use crate::colorings::Coloring;
+use crate::fractal::FractalType;
+use crate::mandel_image::{
+    color_buffer, continue_iteration, iteration_start, smooth_escape, CancelToken, IterationBuffer,
+    Mapping, RenderStats, StatsCounter, WinToMandel,
+};
+use crate::perturbation::{pixel_offset, ReferenceOrbit};
+
+// The size of the rectangles that the image is divided in at first
+const TILE: usize = 32;
+// Rectangles with a side of at most this many pixels are iterated completely
+const MIN_SIDE: usize = 4;
+
+/// A backend that iterates only the borders of the uniform parts of the image, on the threads
+/// of the pool. Views that it cannot subdivide, of other fractals, with colorings that use the
+/// distance to the set or with blocks of more than one pixel, are rendered like SIMD does.
+pub struct MarianiSilverBackend;
+
+/// The Mariani-Silver backend
+pub static MARIANI_SILVER: MarianiSilverBackend = MarianiSilverBackend;
+
+// Whether the fractal is connected, so that borders of one value enclose only that value
+fn is_connected(mapping: &Mapping) -> bool {
+    match mapping.fractal.fractal_type {
+        FractalType::Mandelbrot | FractalType::Julia | FractalType::Tricorn => true,
+        FractalType::Formula | FractalType::Newton => false,
+    }
+}
+
+// The values of a band of rows of the image, which are filled in rectangle by rectangle
+struct Band<'a> {
+    mapping: &'a Mapping,
+    converter: WinToMandel,
+    reference: Option<&'a ReferenceOrbit>,
+    first_row: usize,
+    values: &'a mut [u32],
+    smooth: &'a mut [f64],
+    known: Vec<bool>,
+    // Whether interiors of one iteration count that escaped are filled too
+    fill_escaped: bool,
+    stats: RenderStats,
+}
+
+impl Band<'_> {
+    fn index(&self, wx: usize, wy: usize) -> usize {
+        (wy - self.first_row) * self.mapping.win_width + wx
+    }
+
+    // Iterate pixel (wx, wy) if it was not yet, and return its value
+    fn value(&mut self, wx: usize, wy: usize) -> (u32, f64) {
+        let k = self.index(wx, wy);
+        if !self.known[k] {
+            let (fractal, max) = (&self.mapping.fractal, self.mapping.iteration_depth);
+            let (iter, z, escaped) = match self.reference {
+                Some(reference) => reference.iterate(pixel_offset(self.mapping, wx, wy), max),
+                None => {
+                    let (x, y) = self.converter.cvt(wx, wy);
+                    let (mut z, c) = iteration_start(fractal, x, y);
+                    let mut iter = 0;
+                    let escaped = continue_iteration(fractal, &mut z, c, &mut iter, max);
+                    (iter, z, escaped)
+                }
+            };
+            self.values[k] = iter;
+            self.smooth[k] = if escaped {
+                smooth_escape(fractal, iter, z)
+            } else {
+                max as f64
+            };
+            self.known[k] = true;
+            self.stats.add_point(iter, max);
+        }
+        (self.values[k], self.smooth[k])
+    }
+
+    // Fill the rectangle with columns x0..=x1 and rows y0..=y1
+    fn fill_rectangle(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        let first = self.value(x0, y0);
+        let mut uniform = true;
+        for wx in x0..=x1 {
+            uniform &= self.value(wx, y0).0 == first.0;
+            uniform &= self.value(wx, y1).0 == first.0;
+        }
+        for wy in y0..=y1 {
+            uniform &= self.value(x0, wy).0 == first.0;
+            uniform &= self.value(x1, wy).0 == first.0;
+        }
+        // A filled interior that escaped gets the smooth value of the corner, which only the
+        // colorings that are not smooth are given
+        let in_set = first.0 >= self.mapping.iteration_depth;
+        if uniform && (in_set || self.fill_escaped) {
+            for wy in y0 + 1..y1 {
+                for wx in x0 + 1..x1 {
+                    let k = self.index(wx, wy);
+                    (self.values[k], self.smooth[k]) = first;
+                    self.known[k] = true;
+                }
+            }
+        } else if x1 - x0 <= MIN_SIDE || y1 - y0 <= MIN_SIDE {
+            for wy in y0 + 1..y1 {
+                for wx in x0 + 1..x1 {
+                    self.value(wx, wy);
+                }
+            }
+        } else {
+            let (xm, ym) = ((x0 + x1) / 2, (y0 + y1) / 2);
+            self.fill_rectangle(x0, y0, xm, ym);
+            self.fill_rectangle(xm, y0, x1, ym);
+            self.fill_rectangle(x0, ym, xm, y1);
+            self.fill_rectangle(xm, ym, x1, y1);
+        }
+    }
+
+    // Fill the whole band, unless cancelled
+    fn fill(&mut self, cancel: &CancelToken) {
+        let w = self.mapping.win_width;
+        let end = self.first_row + self.values.len() / w;
+        for x0 in (0..w).step_by(TILE) {
+            if cancel.is_cancelled() {
+                return;
+            }
+            let x1 = (x0 + TILE).min(w) - 1;
+            self.fill_rectangle(x0, self.first_row, x1, end - 1);
+        }
+    }
+}
+
+// The values of every pixel of a view, computed with subdivision, and what was iterated
+fn subdivided_values(
+    mapping: &Mapping,
+    pool: &mut Option<Pool>,
+    cancel: &CancelToken,
+    fill_escaped: bool,
+    counter: &StatsCounter,
+) -> Option<IterationBuffer> {
+    let (w, n) = (mapping.win_width, mapping.win_width * mapping.win_height);
+    let (mut values, mut smooth) = (vec![0; n], vec![0.0; n]);
+    let reference = ReferenceOrbit::for_mapping(mapping);
+    let reference = reference.as_ref();
+    let bands = values.chunks_mut(w * TILE).zip(smooth.chunks_mut(w * TILE));
+    let fill = |i: usize, (values, smooth): (&mut [u32], &mut [f64])| {
+        let mut band = Band {
+            mapping,
+            converter: WinToMandel::from_mapping(mapping),
+            reference,
+            first_row: i * TILE,
+            known: vec![false; values.len()],
+            values,
+            smooth,
+            fill_escaped,
+            stats: RenderStats::default(),
+        };
+        band.fill(cancel);
+        counter.add(&band.stats);
+    };
+    match pool {
+        None => bands.enumerate().for_each(|(i, band)| fill(i, band)),
+        Some(pool) => pool.scoped(|scope| {
+            for (i, band) in bands.enumerate() {
+                scope.execute(move || fill(i, band));
+            }
+        }),
+    }
+    (!cancel.is_cancelled()).then(|| IterationBuffer {
+        mapping: mapping.clone(),
+        values,
+        smooth,
+    })
+}
+
+impl RenderBackend for MarianiSilverBackend {
+    fn name(&self) -> &'static str {
+        "mariani-silver"
+    }
+
+    fn render(
+        &self,
+        mapping: &Mapping,
+        coloring: &dyn Coloring,
+        pool: &mut Option<Pool>,
+        block: usize,
+        cancel: &CancelToken,
+    ) -> Option<(Vec<u8>, i32, RenderStats)> {
+        if block != 1 || coloring.uses_distance() || !is_connected(mapping) {
+            return SIMD.render(mapping, coloring, pool, block, cancel);
+        }
+        if !mapping.is_valid() {
+            return None;
+        }
+        let counter = StatsCounter::default();
+        let buffer = subdivided_values(mapping, pool, cancel, !coloring.is_smooth(), &counter)?;
+        let (data, stride) = color_buffer(&buffer, coloring, pool)?;
+        Some((data, stride, counter.stats()))
+    }
+
+    fn values(
+        &self,
+        mapping: &Mapping,
+        pool: &mut Option<Pool>,
+        cancel: &CancelToken,
+    ) -> Option<IterationBuffer> {
+        if !is_connected(mapping) {
+            return SIMD.values(mapping, pool, cancel);
+        }
+        if !mapping.is_valid() {
+            return None;
+        }
+        // The buffer can be colored with any coloring, so only the interior is filled
+        subdivided_values(mapping, pool, cancel, false, &StatsCounter::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mandel_image::iteration_buffer;
+
+    #[test]
+    fn subdivision_iterates_less_for_the_same_values() {
+        let mut mapping = Mapping::new_for_size(200);
+        (mapping.win_width, mapping.iteration_depth) = (300, 200);
+        let cancel = CancelToken::never();
+        let plain = iteration_buffer(&mapping, &mut None, &cancel, false).unwrap();
+        // Filling only the interior of the set keeps the smooth values
+        let counter = StatsCounter::default();
+        let interior = subdivided_values(&mapping, &mut None, &cancel, false, &counter).unwrap();
+        assert_eq!(interior.values, plain.values);
+        assert_eq!(interior.smooth, plain.smooth);
+        assert!(counter.stats().points < plain.values.len() as u64);
+        let counter = StatsCounter::default();
+        let filled = subdivided_values(&mapping, &mut None, &cancel, true, &counter).unwrap();
+        assert_eq!(filled.values, plain.values);
+        let points = counter.stats().points;
+        assert!(points < plain.values.len() as u64 / 2, "{} points", points);
+    }
+}
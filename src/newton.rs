@@ -0,0 +1,224 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use crate::formula::Complex;
+
+/// The polynomial that the Newton type starts with
+pub const DEFAULT_POLYNOMIAL: &str = "1 0 0 -1";
+// A point has converged when it is this close to a root
+const ROOT_EPS: f64 = 1e-6;
+// The maximum degree of a polynomial
+const MAX_DEGREE: usize = 12;
+
+#[derive(Clone)]
+/// A polynomial with real coefficients and its roots, for the Newton fractal
+pub struct Polynomial {
+    // The coefficients, starting with the highest power
+    coeffs: Vec<f64>,
+    roots: Vec<Complex>,
+}
+
+impl Polynomial {
+    /// Read the coefficients separated by spaces or commas, highest power first.
+    /// For example "1 0 0 -1" is z^3 - 1.
+    pub fn parse(text: &str) -> Result<Polynomial, String> {
+        let mut coeffs = Vec::new();
+        for word in text.split([' ', ',']).filter(|w| !w.is_empty()) {
+            match word.parse::<f64>() {
+                Ok(v) if v.is_finite() => coeffs.push(v),
+                _ => return Err(format!("invalid coefficient {}", word)),
+            }
+        }
+        // Leading zeros do not change the polynomial
+        while coeffs.first() == Some(&0.0) {
+            coeffs.remove(0);
+        }
+        if coeffs.len() < 3 {
+            return Err("the degree must be at least 2".to_string());
+        }
+        if coeffs.len() > MAX_DEGREE + 1 {
+            return Err(format!("the degree can be at most {}", MAX_DEGREE));
+        }
+        let roots = find_roots(&coeffs);
+        Ok(Polynomial { coeffs, roots })
+    }
+
+    pub fn roots(&self) -> &[Complex] {
+        &self.roots
+    }
+
+    // The value of the polynomial and its derivative at z, with Horner's scheme
+    fn eval(&self, z: Complex) -> (Complex, Complex) {
+        let mut p = Complex::new(0.0, 0.0);
+        let mut dp = Complex::new(0.0, 0.0);
+        for &a in &self.coeffs {
+            dp = dp * z + p;
+            p = p * z + Complex::new(a, 0.0);
+        }
+        (p, dp)
+    }
+
+    /// Do Newton steps z -> z - p(z)/p'(z) from z, until z is close to a root or iter reaches
+    /// max_iter. Returns the index of the root that was reached.
+    pub fn converge(&self, z: &mut Complex, iter: &mut u32, max_iter: u32) -> Option<usize> {
+        while *iter < max_iter {
+            if let Some(root) = self.near_root(*z) {
+                return Some(root);
+            }
+            let (p, dp) = self.eval(*z);
+            if dp.norm_sqr() == 0.0 {
+                return None;
+            }
+            *z = *z - p / dp;
+            *iter += 1;
+        }
+        self.near_root(*z)
+    }
+
+    fn near_root(&self, z: Complex) -> Option<usize> {
+        self.roots
+            .iter()
+            .position(|r| (z - *r).norm_sqr() < ROOT_EPS * ROOT_EPS)
+    }
+}
+
+// Find all roots of the polynomial with the Durand-Kerner method
+fn find_roots(coeffs: &[f64]) -> Vec<Complex> {
+    let n = coeffs.len() - 1;
+    let lead = coeffs[0];
+    let monic = |z: Complex| {
+        let mut p = Complex::new(0.0, 0.0);
+        for &a in coeffs {
+            p = p * z + Complex::new(a / lead, 0.0);
+        }
+        p
+    };
+    // The usual starting points: powers of a number that is not a root of unity
+    let seed = Complex::new(0.4, 0.9);
+    let mut roots: Vec<Complex> = Vec::with_capacity(n);
+    let mut power = Complex::new(1.0, 0.0);
+    for _ in 0..n {
+        roots.push(power);
+        power = power * seed;
+    }
+    for _ in 0..500 {
+        let mut change: f64 = 0.0;
+        for i in 0..n {
+            let mut denom = Complex::new(1.0, 0.0);
+            for j in 0..n {
+                if i != j {
+                    denom = denom * (roots[i] - roots[j]);
+                }
+            }
+            let delta = monic(roots[i]) / denom;
+            roots[i] = roots[i] - delta;
+            change = change.max(delta.norm_sqr().sqrt());
+        }
+        if change < 1e-14 {
+            break;
+        }
+    }
+    roots
+}
+
+impl fmt::Display for Polynomial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words: Vec<String> = self.coeffs.iter().map(|a| a.to_string()).collect();
+        write!(f, "{}", words.join(" "))
+    }
+}
+
+impl fmt::Debug for Polynomial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Polynomial({})", self)
+    }
+}
+
+impl PartialEq for Polynomial {
+    fn eq(&self, other: &Polynomial) -> bool {
+        self.coeffs == other.coeffs
+    }
+}
+
+impl Hash for Polynomial {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for a in &self.coeffs {
+            // Adding 0.0 turns -0.0 into 0.0, so values that are equal give equal bits
+            (a + 0.0).to_bits().hash(state);
+        }
+    }
+}
+
+/// The color of a point that reached root `root` of `n_roots` after `iter` Newton steps.
+/// Every root has its own hue, and points that converge slowly are darker.
+pub fn root_color(root: Option<usize>, n_roots: usize, iter: u32) -> u32 {
+    let root = match root {
+        Some(root) => root,
+        None => return 0,
+    };
+    let hue = root as f64 / n_roots.max(1) as f64 * 6.0;
+    let brightness = 1.0 / (1.0 + 0.08 * iter as f64);
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    let channel = |v: f64| (v * brightness * 255.0).round() as u32;
+    (channel(r) << 16) | (channel(g) << 8) | channel(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            Polynomial::parse("1 x -1"),
+            Err("invalid coefficient x".to_string())
+        );
+        assert_eq!(
+            Polynomial::parse("1 0 inf"),
+            Err("invalid coefficient inf".to_string())
+        );
+        let low = Err("the degree must be at least 2".to_string());
+        assert_eq!(Polynomial::parse(""), low);
+        assert_eq!(Polynomial::parse("1 -1"), low);
+        // Leading zeros do not count for the degree
+        assert_eq!(Polynomial::parse("0 0 1 -1"), low);
+        let high = Err(format!("the degree can be at most {}", MAX_DEGREE));
+        assert_eq!(Polynomial::parse(&"1 ".repeat(MAX_DEGREE + 2)), high);
+        // Commas separate as well as spaces
+        let p = Polynomial::parse("0, 1,0, 0 -1").unwrap();
+        assert_eq!(p, Polynomial::parse(DEFAULT_POLYNOMIAL).unwrap());
+        assert_eq!(p.to_string(), "1 0 0 -1");
+    }
+
+    #[test]
+    fn the_roots_of_z3_minus_1_are_the_roots_of_unity() {
+        let p = Polynomial::parse("1 0 0 -1").unwrap();
+        assert_eq!(p.roots().len(), 3);
+        for k in 0..3 {
+            let angle = k as f64 * 2.0 * std::f64::consts::PI / 3.0;
+            let expected = Complex::new(angle.cos(), angle.sin());
+            let found = p
+                .roots()
+                .iter()
+                .filter(|&&r| (r - expected).norm_sqr() < 1e-24);
+            assert_eq!(found.count(), 1, "root {:?}", expected);
+        }
+        // Newton steps from near a root end at that root
+        for (i, &root) in p.roots().iter().enumerate() {
+            let (mut z, mut iter) = (root * Complex::new(1.1, 0.05), 0);
+            assert_eq!(p.converge(&mut z, &mut iter, 50), Some(i));
+            assert!(iter < 10);
+        }
+        // Where the derivative is 0 there is no Newton step
+        let (mut z, mut iter) = (Complex::new(0.0, 0.0), 0);
+        assert_eq!(p.converge(&mut z, &mut iter, 50), None);
+    }
+}
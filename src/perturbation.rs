@@ -0,0 +1,271 @@
+// Perturbation rendering of deep views of the quadratic Mandelbrot set. In a deep view the
+// coordinates of neighbouring pixels differ in fewer bits than f64 has, so iterating every
+// pixel on its own coordinates gives blocks of equal values. Instead the center of the view
+// is iterated once, as the reference orbit Z, and every pixel iterates only its difference dz
+// with the reference, from the difference dc of its coordinates with the center:
+//     dz' = 2 Z dz + dz^2 + dc
+// The differences are small numbers that f64 holds with full precision, down to scales far
+// below those of plain iteration. Where the value z = Z + dz of a pixel gets smaller than dz,
+// the difference would lose its precision (a glitch); the pixel then continues with z as its
+// difference against the start of the reference orbit (rebasing). The same happens at the end
+// of the reference, so one reference per frame is enough and no pixel is rendered twice.
+// The reference itself is iterated with the precision that the scale needs, from the precise
+// center of the view, and only then rounded to f64, so the views stay correct far below the
+// resolution of f64 coordinates. That is double-double while its 106 bits are enough, and
+// fixed point numbers with more limbs below that.
+
+use crate::colorings::Coloring;
+use crate::double_double::DoubleDouble;
+use crate::fixed::{limbs_for_scale, Fixed};
+use crate::fractal::FractalType;
+use crate::mandel_image::Mapping;
+
+// Views whose pixels are smaller than this, relative to the size of their coordinates, are
+// rendered with perturbation. Plain iteration shows blocks from about 1e-14.
+const PERTURBATION_SCALE: f64 = 1e-12;
+// Views whose pixels are larger than this, relative to the size of their coordinates, get a
+// reference orbit in double-double, which then has about 30 bits more than the pixels need
+const DOUBLE_DOUBLE_SCALE: f64 = 1e-22;
+// Views iterated in f64 whose pixels are smaller than this many units in the last place of
+// their coordinates show blocks of pixels with the same coordinates
+const BLOCKY_ULPS: f64 = 8.0;
+
+/// The text that tells that a view is deeper than its precision
+pub const PRECISION_WARNING: &str = "pixelation due to precision";
+
+// The size of the pixels of a view relative to the size of its coordinates, which sets the
+// precision that it needs
+fn relative_scale(mapping: &Mapping) -> f64 {
+    mapping.scale / mapping.cx.abs().max(mapping.cy.abs()).max(1.0)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+/// The precision with which a view is iterated, which follows from its scale
+pub enum Precision {
+    /// Every pixel on its own f64 coordinates
+    Double,
+    /// Perturbation from a reference orbit in double-double
+    DoubleDouble,
+    /// Perturbation from a reference orbit in fixed point numbers
+    Fixed,
+}
+
+impl Precision {
+    /// The precision of a view. Only the quadratic Mandelbrot set, which is by far the most
+    /// explored, is rendered with perturbation; other fractals are iterated in f64 at any
+    /// depth.
+    pub fn for_mapping(mapping: &Mapping) -> Precision {
+        let fractal = &mapping.fractal;
+        let relative = relative_scale(mapping);
+        if fractal.fractal_type != FractalType::Mandelbrot
+            || fractal.power != 2
+            || relative >= PERTURBATION_SCALE
+        {
+            Precision::Double
+        } else if relative >= DOUBLE_DOUBLE_SCALE {
+            Precision::DoubleDouble
+        } else {
+            Precision::Fixed
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Precision::Double => "double",
+            Precision::DoubleDouble => "double-double",
+            Precision::Fixed => "fixed point",
+        }
+    }
+}
+
+/// The orbit of the center of a view, from z = 0 until it escapes or reaches the iteration
+/// depth, which the pixels of the view follow
+pub struct ReferenceOrbit {
+    points: Vec<(f64, f64)>,
+}
+
+/// Whether a view is deep enough to be rendered with perturbation
+pub fn uses_perturbation(mapping: &Mapping) -> bool {
+    Precision::for_mapping(mapping) != Precision::Double
+}
+
+/// Whether a view is too deep for f64 and is not rendered with perturbation, so that it shows
+/// blocks of pixels with the same coordinates. That is the case for the fractals other than
+/// the quadratic Mandelbrot set, and for colorings that use the distance to the set, which is
+/// computed from the coordinates of every pixel.
+pub fn precision_exhausted(mapping: &Mapping, coloring: &dyn Coloring) -> bool {
+    let perturbed = uses_perturbation(mapping) && !coloring.uses_distance();
+    !perturbed && relative_scale(mapping) < BLOCKY_ULPS * f64::EPSILON
+}
+
+/// The difference of the point of pixel (wx, wy) with the center of the view, which keeps its
+/// precision in views of any depth. The pixels of a part of a view are relative to the center of
+/// the view.
+pub fn pixel_offset(mapping: &Mapping, wx: usize, wy: usize) -> (f64, f64) {
+    let part = mapping.view_part();
+    let dx = (part.first_col + wx) as f64 - part.view_width as f64 / 2.0;
+    let dy = part.view_height as f64 / 2.0 - (part.first_row + wy) as f64;
+    (dx * mapping.scale, dy * mapping.scale)
+}
+
+impl ReferenceOrbit {
+    /// The orbit of the center of a view, if the view is rendered with perturbation
+    pub fn for_mapping(mapping: &Mapping) -> Option<ReferenceOrbit> {
+        let (cx, cy) = mapping.precise_center_at(limbs_for_scale(mapping.scale));
+        let max_iter = mapping.iteration_depth;
+        match Precision::for_mapping(mapping) {
+            Precision::Double => None,
+            Precision::DoubleDouble => Some(ReferenceOrbit::double_double(
+                DoubleDouble::from_fixed(&cx),
+                DoubleDouble::from_fixed(&cy),
+                max_iter,
+            )),
+            Precision::Fixed => Some(ReferenceOrbit::precise(&cx, &cy, max_iter)),
+        }
+    }
+
+    /// The orbit of c = (cx, cy), iterated in double-double
+    pub fn double_double(cx: DoubleDouble, cy: DoubleDouble, max_iter: u32) -> ReferenceOrbit {
+        let mut points = Vec::with_capacity(max_iter as usize + 1);
+        let (mut r, mut i) = (DoubleDouble::ZERO, DoubleDouble::ZERO);
+        points.push((0.0, 0.0));
+        for _ in 0..max_iter {
+            (r, i) = (r * r - i * i + cx, (r * i).double() + cy);
+            let (fr, fi) = (r.to_f64(), i.to_f64());
+            points.push((fr, fi));
+            if fr * fr + fi * fi >= 4.0 {
+                break;
+            }
+        }
+        ReferenceOrbit { points }
+    }
+
+    /// The orbit of c = (cx, cy), iterated with the precision of cx and cy
+    pub fn precise(cx: &Fixed, cy: &Fixed, max_iter: u32) -> ReferenceOrbit {
+        let mut points = Vec::with_capacity(max_iter as usize + 1);
+        let fraction = cx.fraction_limbs().max(cy.fraction_limbs());
+        let (mut r, mut i) = (Fixed::zero(fraction), Fixed::zero(fraction));
+        points.push((0.0, 0.0));
+        for _ in 0..max_iter {
+            let ri = r.mul(&i);
+            (r, i) = (r.mul(&r).sub(&i.mul(&i)).add(cx), ri.add(&ri).add(cy));
+            let (fr, fi) = (r.to_f64(), i.to_f64());
+            points.push((fr, fi));
+            if fr * fr + fi * fi >= 4.0 {
+                break;
+            }
+        }
+        ReferenceOrbit { points }
+    }
+
+    /// The orbit of c = (cx, cy), including the first value that escapes
+    pub fn new(cx: f64, cy: f64, max_iter: u32) -> ReferenceOrbit {
+        let mut points = Vec::with_capacity(max_iter as usize + 1);
+        let (mut r, mut i) = (0.0, 0.0);
+        points.push((r, i));
+        for _ in 0..max_iter {
+            (r, i) = (r * r - i * i + cx, 2.0 * r * i + cy);
+            points.push((r, i));
+            if r * r + i * i >= 4.0 {
+                break;
+            }
+        }
+        ReferenceOrbit { points }
+    }
+
+    /// Iterate the point at difference dc from the center, like continue_iteration from z = 0.
+    /// Returns the number of iterations, the final value of z and whether it escaped.
+    pub fn iterate(&self, dc: (f64, f64), max_iter: u32) -> (u32, (f64, f64), bool) {
+        let points = &self.points;
+        let (mut dr, mut di) = (0.0, 0.0);
+        // The index of the value of the reference that dz is the difference with
+        let mut n = 0;
+        let mut iter = 0;
+        while iter < max_iter {
+            let (zr, zi) = points[n];
+            (dr, di) = (
+                2.0 * (zr * dr - zi * di) + dr * dr - di * di + dc.0,
+                2.0 * (zr * di + zi * dr) + 2.0 * dr * di + dc.1,
+            );
+            n += 1;
+            let (r, i) = (points[n].0 + dr, points[n].1 + di);
+            let norm = r * r + i * i;
+            if norm >= 4.0 {
+                return (iter, (r, i), true);
+            }
+            iter += 1;
+            if norm < dr * dr + di * di || n == points.len() - 1 {
+                (dr, di) = (r, i);
+                n = 0;
+            }
+        }
+        (iter, (points[n].0 + dr, points[n].1 + di), false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colorings::ColorInfo;
+    use crate::mandel_image::mandel_value;
+
+    #[test]
+    fn perturbation_follows_plain_iteration() {
+        // In a shallow view both ways of iterating compute the same points
+        let (cx, cy) = (-0.7453, 0.1127);
+        let reference = ReferenceOrbit::new(cx, cy, 2000);
+        let precise =
+            ReferenceOrbit::precise(&Fixed::from_f64(cx, 4), &Fixed::from_f64(cy, 4), 2000);
+        // The rounding of the f64 orbit grows, but only slowly in the first iterations
+        let close = |a: (f64, f64), b: (f64, f64)| (a.0 - b.0).hypot(a.1 - b.1) < 1e-12;
+        assert!(precise.points[..50]
+            .iter()
+            .zip(&reference.points)
+            .all(|(a, b)| close(*a, *b)));
+        let mut differences = 0;
+        for k in 0..100 {
+            let dc = ((k % 10) as f64 * 1e-4, (k / 10) as f64 * -1e-4);
+            let (iter, _, _) = reference.iterate(dc, 2000);
+            let plain = mandel_value(cx + dc.0, cy + dc.1, 2000);
+            // Rounding differs, which can change the value of a point on a chaotic edge
+            if iter != plain {
+                differences += 1;
+            }
+        }
+        assert!(differences <= 5, "{} differences", differences);
+    }
+
+    #[test]
+    fn deep_views_are_not_blocky() {
+        let mut mapping = Mapping::new_for_size(100);
+        (mapping.cx, mapping.cy) = (-0.743643887037151, 0.131825904205330);
+        mapping.scale = 1e-20;
+        mapping.iteration_depth = 5000;
+        assert_eq!(Precision::for_mapping(&mapping), Precision::DoubleDouble);
+        let reference = ReferenceOrbit::for_mapping(&mapping).unwrap();
+        // The double-double orbit is that of the fixed point numbers, rounded to f64
+        let (cx, cy) = mapping.precise_center_at(limbs_for_scale(mapping.scale));
+        let precise = ReferenceOrbit::precise(&cx, &cy, 5000);
+        assert_eq!(reference.points.len(), precise.points.len());
+        let close = |a: &(f64, f64), b: &(f64, f64)| (a.0 - b.0).hypot(a.1 - b.1) < 1e-14;
+        assert!(reference
+            .points
+            .iter()
+            .zip(&precise.points)
+            .all(|(a, b)| close(a, b)));
+        // Plain iteration gives one value for these points, because their coordinates are
+        // the same in f64, perturbation gives the smooth change of the escape
+        assert_eq!(mapping.cx + 9e-18, mapping.cx);
+        let escapes: Vec<(u32, (f64, f64), bool)> = (0..10)
+            .map(|k| reference.iterate((k as f64 * 1e-18, 0.0), 5000))
+            .collect();
+        assert!(escapes.iter().all(|e| e.2));
+        assert!(escapes.windows(2).all(|w| w[0].1 != w[1].1));
+        // Other fractals are still iterated in f64, which gives blocks
+        let color_info = ColorInfo::new();
+        let coloring = color_info.scheme(0).as_ref();
+        assert!(!precision_exhausted(&mapping, coloring));
+        mapping.fractal.fractal_type = FractalType::Tricorn;
+        assert!(precision_exhausted(&mapping, coloring));
+    }
+}
@@ -0,0 +1,216 @@
+// Plugins are shared libraries (cdylibs) in a plugin directory that add colorings and
+// formulas. A plugin exports these C functions; the coloring and formula functions are
+// only needed for what the plugin provides:
+//
+//     uint32_t mandel_plugin_abi_version(void);   // returns PLUGIN_ABI_VERSION
+//     uint32_t mandel_plugin_coloring_count(void);
+//     const char *mandel_plugin_coloring_name(uint32_t i);
+//     uint32_t mandel_plugin_coloring_color(uint32_t i, uint32_t v, uint32_t max);
+//     uint32_t mandel_plugin_formula_count(void);
+//     const char *mandel_plugin_formula_name(uint32_t i);
+//     void mandel_plugin_formula_step(uint32_t i, double zr, double zi,
+//                                     double cr, double ci, double *out);
+//
+// Names are UTF-8 strings that stay valid while the program runs. A color is 0xRRGGBB,
+// for value v of at most max. A formula step writes the real and imaginary part of the
+// next z to out[0] and out[1]. All functions must be safe to call from several threads.
+
+use std::ffi::{c_char, CStr, CString};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::colorings::{register_coloring, Coloring};
+use crate::formula::{register_formula, Complex};
+
+/// The version of the plugin interface that is described above
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+type VersionFn = extern "C" fn() -> u32;
+type CountFn = extern "C" fn() -> u32;
+type NameFn = extern "C" fn(u32) -> *const c_char;
+type ColorFn = extern "C" fn(u32, u32, u32) -> u32;
+type StepFn = extern "C" fn(u32, f64, f64, f64, f64, *mut f64);
+
+#[cfg(unix)]
+mod dl {
+    use std::ffi::{c_char, c_int, c_void};
+
+    pub const RTLD_NOW: c_int = 2;
+
+    #[cfg_attr(target_os = "linux", link(name = "dl"))]
+    extern "C" {
+        pub fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+        pub fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+        pub fn dlerror() -> *mut c_char;
+    }
+}
+
+// A loaded library. It is never unloaded, because its colorings and formulas stay registered.
+struct Library {
+    #[cfg(unix)]
+    handle: *mut std::ffi::c_void,
+}
+
+#[cfg(unix)]
+fn last_error() -> String {
+    // SAFETY: dlerror returns null or a valid C string
+    unsafe {
+        let err = dl::dlerror();
+        if err.is_null() {
+            "unknown error".to_string()
+        } else {
+            CStr::from_ptr(err).to_string_lossy().into_owned()
+        }
+    }
+}
+
+impl Library {
+    #[cfg(unix)]
+    fn open(path: &Path) -> Result<Library, String> {
+        use std::os::unix::ffi::OsStrExt;
+        let c_path = CString::new(path.as_os_str().as_bytes()).map_err(|e| e.to_string())?;
+        // SAFETY: the path is a valid C string. Loading runs the initializers of the library,
+        // which is what installing a plugin trusts.
+        let handle = unsafe { dl::dlopen(c_path.as_ptr(), dl::RTLD_NOW) };
+        if handle.is_null() {
+            return Err(last_error());
+        }
+        Ok(Library { handle })
+    }
+
+    #[cfg(not(unix))]
+    fn open(_path: &Path) -> Result<Library, String> {
+        Err("plugins are not supported on this platform".to_string())
+    }
+
+    // The address of a symbol, or None if the library does not export it
+    #[cfg(unix)]
+    fn symbol(&self, name: &str) -> Option<*mut std::ffi::c_void> {
+        let c_name = CString::new(name).ok()?;
+        // SAFETY: the handle is a loaded library and the name a valid C string
+        let sym = unsafe { dl::dlsym(self.handle, c_name.as_ptr()) };
+        (!sym.is_null()).then_some(sym)
+    }
+
+    #[cfg(not(unix))]
+    fn symbol(&self, _name: &str) -> Option<*mut std::ffi::c_void> {
+        None
+    }
+}
+
+// Look up a function of the plugin interface. The type must match the description above.
+macro_rules! plugin_fn {
+    ($lib:expr, $name:literal, $ty:ty) => {
+        // SAFETY: a plugin that exports the symbol exports it with this signature
+        $lib.symbol($name)
+            .map(|sym| unsafe { std::mem::transmute::<*mut std::ffi::c_void, $ty>(sym) })
+    };
+}
+
+fn plugin_name(name_fn: NameFn, i: u32) -> Option<String> {
+    let name = name_fn(i);
+    if name.is_null() {
+        return None;
+    }
+    // SAFETY: a plugin returns a valid C string that is never freed
+    let name = unsafe { CStr::from_ptr(name) };
+    Some(name.to_str().ok()?.to_string())
+}
+
+#[derive(Clone)]
+/// A coloring of a plugin
+struct PluginColoring {
+    name: String,
+    index: u32,
+    color_fn: ColorFn,
+}
+
+impl Coloring for PluginColoring {
+    fn get_color(&self, v: u32, max: u32) -> u32 {
+        (self.color_fn)(self.index, v, max) & 0xffffff
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+// Register the colorings and formulas of one library. Returns their names.
+fn load_plugin(path: &Path) -> Result<Vec<String>, String> {
+    let lib = Library::open(path)?;
+    let version = plugin_fn!(lib, "mandel_plugin_abi_version", VersionFn)
+        .ok_or("mandel_plugin_abi_version is missing")?;
+    if version() != PLUGIN_ABI_VERSION {
+        return Err(format!(
+            "plugin interface version {} is not supported, only {}",
+            version(),
+            PLUGIN_ABI_VERSION
+        ));
+    }
+    let mut names = Vec::new();
+    if let (Some(count), Some(name_fn), Some(color_fn)) = (
+        plugin_fn!(lib, "mandel_plugin_coloring_count", CountFn),
+        plugin_fn!(lib, "mandel_plugin_coloring_name", NameFn),
+        plugin_fn!(lib, "mandel_plugin_coloring_color", ColorFn),
+    ) {
+        for index in 0..count() {
+            if let Some(name) = plugin_name(name_fn, index) {
+                names.push(name.clone());
+                register_coloring(Box::new(PluginColoring {
+                    name,
+                    index,
+                    color_fn,
+                }));
+            }
+        }
+    }
+    if let (Some(count), Some(name_fn), Some(step_fn)) = (
+        plugin_fn!(lib, "mandel_plugin_formula_count", CountFn),
+        plugin_fn!(lib, "mandel_plugin_formula_name", NameFn),
+        plugin_fn!(lib, "mandel_plugin_formula_step", StepFn),
+    ) {
+        for index in 0..count() {
+            if let Some(name) = plugin_name(name_fn, index) {
+                register_formula(&name, move |z: Complex, c: Complex| {
+                    let mut out = [f64::NAN; 2];
+                    step_fn(index, z.re, z.im, c.re, c.im, out.as_mut_ptr());
+                    Complex::new(out[0], out[1])
+                });
+                names.push(name);
+            }
+        }
+    }
+    Ok(names)
+}
+
+fn is_library(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("so") | Some("dylib") | Some("dll")
+    )
+}
+
+/// The plugin directory in a data directory, e.g. ~/.local/share/mandelbrot/plugins
+pub fn plugin_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("mandelbrot").join("plugins")
+}
+
+/// Load all plugins in a directory, in the order of their file names. The result has a
+/// message for every plugin that was loaded or could not be loaded.
+pub fn load_plugins(dir: &Path) -> Vec<String> {
+    let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| is_library(p))
+            .collect(),
+        Err(_) => return Vec::new(),
+    };
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| match load_plugin(path) {
+            Ok(names) => format!("Loaded plugin {}: {}", path.display(), names.join(", ")),
+            Err(e) => format!("Could not load plugin {}: {}", path.display(), e),
+        })
+        .collect()
+}
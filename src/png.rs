@@ -0,0 +1,461 @@
+// A small PNG encoder, so images can be written without depending on GTK.
+// The image data is compressed with LZ77 and the fixed Huffman codes of deflate.
+
+use std::io::{self, Read, Write};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+// The amount of raw image data that is compressed and written at once
+const CHUNK_SZ: usize = 1 << 18;
+const WINDOW_SZ: usize = 1 << 15;
+const HASH_SZ: usize = 1 << 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// The maximum number of earlier positions that are tried for a match
+const MAX_CHAIN: usize = 32;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+/// The pixel formats that can be written
+pub enum ColorType {
+    /// Three bytes per pixel: red, green, blue
+    Rgb,
+    /// One byte per pixel
+    Gray,
+    /// One bit per pixel, black or white, with the leftmost pixel in the highest bit
+    Bilevel,
+}
+
+impl ColorType {
+    fn code(&self) -> u8 {
+        match self {
+            ColorType::Rgb => 2,
+            ColorType::Gray | ColorType::Bilevel => 0,
+        }
+    }
+    fn bit_depth(&self) -> u8 {
+        match self {
+            ColorType::Bilevel => 1,
+            _ => 8,
+        }
+    }
+    /// The number of bytes of a row of pixels
+    pub fn row_len(&self, width: usize) -> usize {
+        match self {
+            ColorType::Rgb => 3 * width,
+            ColorType::Gray => width,
+            ColorType::Bilevel => width.div_ceil(8),
+        }
+    }
+}
+
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn write_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let crc = crc32(crc32(0, kind), data);
+    out.write_all(&crc.to_be_bytes())
+}
+
+// Writes bits, least significant bit first, as deflate requires
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u32,
+    n_bits: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, value: u32, n_bits: u32) {
+        self.acc |= value << self.n_bits;
+        self.n_bits += n_bits;
+        while self.n_bits >= 8 {
+            self.bytes.push(self.acc as u8);
+            self.acc >>= 8;
+            self.n_bits -= 8;
+        }
+    }
+    // Huffman codes are stored most significant bit first
+    fn put_code(&mut self, code: u32, len: u32) {
+        let reversed = code.reverse_bits() >> (32 - len);
+        self.put(reversed, len);
+    }
+    fn flush_byte(&mut self) {
+        if self.n_bits > 0 {
+            self.bytes.push(self.acc as u8);
+            self.acc = 0;
+            self.n_bits = 0;
+        }
+    }
+}
+
+// Write a literal or length symbol with the fixed Huffman code
+fn put_lit_len(bits: &mut BitWriter, sym: u32) {
+    match sym {
+        0..=143 => bits.put_code(0x30 + sym, 8),
+        144..=255 => bits.put_code(0x190 + sym - 144, 9),
+        256..=279 => bits.put_code(sym - 256, 7),
+        _ => bits.put_code(0xc0 + sym - 280, 8),
+    }
+}
+
+const LEN_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LEN_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+fn put_match(bits: &mut BitWriter, len: usize, dist: usize) {
+    let li = LEN_BASE.iter().rposition(|&b| b as usize <= len).unwrap();
+    put_lit_len(bits, 257 + li as u32);
+    bits.put((len - LEN_BASE[li] as usize) as u32, LEN_EXTRA[li] as u32);
+    let di = DIST_BASE.iter().rposition(|&b| b as usize <= dist).unwrap();
+    bits.put_code(di as u32, 5);
+    bits.put(
+        (dist - DIST_BASE[di] as usize) as u32,
+        DIST_EXTRA[di] as u32,
+    );
+}
+
+fn hash(data: &[u8]) -> usize {
+    let v = (data[0] as usize) << 16 | (data[1] as usize) << 8 | data[2] as usize;
+    (v.wrapping_mul(2654435761) >> 7) % HASH_SZ
+}
+
+// Compress data as one fixed Huffman block that is not the final block
+fn deflate_block(bits: &mut BitWriter, data: &[u8]) {
+    bits.put(0b010, 3);
+    // heads[h] is the last position with hash h, prev[p] the position before p with the same hash
+    let mut heads = vec![usize::MAX; HASH_SZ];
+    let mut prev = vec![usize::MAX; data.len()];
+    let insert = |heads: &mut [usize], prev: &mut [usize], p: usize| {
+        if p + MIN_MATCH <= data.len() {
+            let h = hash(&data[p..]);
+            prev[p] = heads[h];
+            heads[h] = p;
+        }
+    };
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut best = (0, 0);
+        if pos + MIN_MATCH <= data.len() {
+            let max = MAX_MATCH.min(data.len() - pos);
+            let mut cand = heads[hash(&data[pos..])];
+            let mut tries = 0;
+            while cand != usize::MAX && pos - cand <= WINDOW_SZ && tries < MAX_CHAIN {
+                let len = (0..max)
+                    .take_while(|&k| data[cand + k] == data[pos + k])
+                    .count();
+                if len > best.0 {
+                    best = (len, pos - cand);
+                    if len == max {
+                        break;
+                    }
+                }
+                cand = prev[cand];
+                tries += 1;
+            }
+        }
+        if best.0 >= MIN_MATCH {
+            put_match(bits, best.0, best.1);
+            for p in pos..pos + best.0 {
+                insert(&mut heads, &mut prev, p);
+            }
+            pos += best.0;
+        } else {
+            insert(&mut heads, &mut prev, pos);
+            put_lit_len(bits, data[pos] as u32);
+            pos += 1;
+        }
+    }
+    put_lit_len(bits, 256);
+}
+
+/// Writes a PNG file row by row, so that the whole image never has to be in memory
+pub struct PngEncoder<W: Write> {
+    out: W,
+    row_len: usize,
+    rows_left: usize,
+    raw: Vec<u8>,
+    bits: BitWriter,
+    adler: (u32, u32),
+}
+
+impl<W: Write> PngEncoder<W> {
+    /// Start a PNG file. `text` contains keyword/value pairs that are stored as tEXt chunks.
+    pub fn new(
+        mut out: W,
+        width: usize,
+        height: usize,
+        color: ColorType,
+        text: &[(&str, String)],
+    ) -> io::Result<PngEncoder<W>> {
+        out.write_all(&SIGNATURE)?;
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+        ihdr.extend_from_slice(&[color.bit_depth(), color.code(), 0, 0, 0]);
+        write_chunk(&mut out, b"IHDR", &ihdr)?;
+        for (key, value) in text {
+            let mut chunk = key.as_bytes().to_vec();
+            chunk.push(0);
+            chunk.extend_from_slice(value.as_bytes());
+            write_chunk(&mut out, b"tEXt", &chunk)?;
+        }
+        let mut bits = BitWriter {
+            bytes: Vec::new(),
+            acc: 0,
+            n_bits: 0,
+        };
+        // The zlib header: deflate with a 32K window, no dictionary
+        bits.put(0x78, 8);
+        bits.put(0x01, 8);
+        Ok(PngEncoder {
+            out,
+            row_len: color.row_len(width),
+            rows_left: height,
+            raw: Vec::with_capacity(CHUNK_SZ + width * 3 + 1),
+            bits,
+            adler: (1, 0),
+        })
+    }
+
+    /// Add the next row of pixels
+    pub fn write_row(&mut self, row: &[u8]) -> io::Result<()> {
+        assert_eq!(row.len(), self.row_len);
+        assert!(self.rows_left > 0, "too many rows");
+        self.rows_left -= 1;
+        // Filter type 0: none
+        self.raw.push(0);
+        self.raw.extend_from_slice(row);
+        if self.raw.len() >= CHUNK_SZ {
+            self.compress_raw()?;
+        }
+        Ok(())
+    }
+
+    fn compress_raw(&mut self) -> io::Result<()> {
+        let (mut a, mut b) = self.adler;
+        for &v in &self.raw {
+            a = (a + v as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        self.adler = (a, b);
+        deflate_block(&mut self.bits, &self.raw);
+        self.raw.clear();
+        if !self.bits.bytes.is_empty() {
+            write_chunk(&mut self.out, b"IDAT", &self.bits.bytes)?;
+            self.bits.bytes.clear();
+        }
+        Ok(())
+    }
+
+    /// Complete the file, after all rows have been written
+    pub fn finish(mut self) -> io::Result<W> {
+        assert_eq!(self.rows_left, 0, "not all rows were written");
+        self.compress_raw()?;
+        // An empty final stored block
+        self.bits.put(0b001, 3);
+        self.bits.flush_byte();
+        self.bits.put(0x0000, 16);
+        self.bits.put(0xffff, 16);
+        let (a, b) = self.adler;
+        self.bits
+            .bytes
+            .extend_from_slice(&((b << 16) | a).to_be_bytes());
+        write_chunk(&mut self.out, b"IDAT", &self.bits.bytes)?;
+        write_chunk(&mut self.out, b"IEND", &[])?;
+        Ok(self.out)
+    }
+}
+
+/// Convert one line of an image in the native RGB24 format (0x00RRGGBB in native
+/// endianness) to red, green and blue bytes
+pub fn rgb24_to_rgb(line: &[u8], width: usize, rgb: &mut Vec<u8>) {
+    rgb.clear();
+    for px in line[..4 * width].chunks_exact(4) {
+        let v = u32::from_ne_bytes([px[0], px[1], px[2], px[3]]);
+        rgb.extend_from_slice(&[(v >> 16) as u8, (v >> 8) as u8, v as u8]);
+    }
+}
+
+/// Write an image in the native RGB24 format as an RGB PNG file
+pub fn write_rgb24_png<W: Write>(
+    out: W,
+    data: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    text: &[(&str, String)],
+) -> io::Result<W> {
+    let mut encoder = PngEncoder::new(out, width, height, ColorType::Rgb, text)?;
+    let mut rgb = Vec::with_capacity(3 * width);
+    for y in 0..height {
+        rgb24_to_rgb(&data[y * stride..], width, &mut rgb);
+        encoder.write_row(&rgb)?;
+    }
+    encoder.finish()
+}
+
+/// Write a mask with a byte per pixel as a PNG file: a grayscale image, or if bilevel
+/// an image with one bit per pixel in which pixels of at least 0x80 are white
+pub fn write_mask_png<W: Write>(
+    out: W,
+    mask: &[u8],
+    width: usize,
+    height: usize,
+    bilevel: bool,
+    text: &[(&str, String)],
+) -> io::Result<W> {
+    let color = if bilevel {
+        ColorType::Bilevel
+    } else {
+        ColorType::Gray
+    };
+    let mut encoder = PngEncoder::new(out, width, height, color, text)?;
+    let mut bits = vec![0; color.row_len(width)];
+    for row in mask.chunks_exact(width.max(1)).take(height) {
+        if bilevel {
+            bits.fill(0);
+            for (x, &v) in row.iter().enumerate() {
+                if v >= 0x80 {
+                    bits[x / 8] |= 0x80 >> (x % 8);
+                }
+            }
+            encoder.write_row(&bits)?;
+        } else {
+            encoder.write_row(row)?;
+        }
+    }
+    encoder.finish()
+}
+
+/// Read the keyword/value pairs of the tEXt chunks of a PNG file
+pub fn read_png_text<R: Read>(mut input: R) -> io::Result<Vec<(String, String)>> {
+    let mut signature = [0; 8];
+    input.read_exact(&mut signature)?;
+    if signature != SIGNATURE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a PNG file"));
+    }
+    let mut text = Vec::new();
+    loop {
+        let mut header = [0; 8];
+        input.read_exact(&mut header)?;
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let kind = &header[4..8];
+        if kind == b"IEND" {
+            return Ok(text);
+        }
+        // The data and the CRC
+        let mut data = vec![0; len + 4];
+        input.read_exact(&mut data)?;
+        if kind == b"tEXt" {
+            let data = &data[..len];
+            if let Some(sep) = data.iter().position(|&b| b == 0) {
+                text.push((
+                    String::from_utf8_lossy(&data[..sep]).into_owned(),
+                    String::from_utf8_lossy(&data[sep + 1..]).into_owned(),
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The Adler-32 checksum of data, computed without the encoder
+    fn adler32(data: &[u8]) -> u32 {
+        let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &v| {
+            let a = (a + v as u32) % 65521;
+            (a, (b + a) % 65521)
+        });
+        (b << 16) | a
+    }
+
+    #[test]
+    fn a_large_image_is_written_in_chunks() {
+        // Known checksums: the CRC of every IEND chunk and the Adler-32 of "Wikipedia"
+        assert_eq!(crc32(crc32(0, b"IEND"), &[]), 0xae426082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        let (width, height) = (300, 400);
+        let mut raw = Vec::new();
+        let mut encoder = PngEncoder::new(
+            Vec::new(),
+            width,
+            height,
+            ColorType::Rgb,
+            &[
+                ("Title", "multi chunk".to_string()),
+                ("cx", "-0.5".to_string()),
+            ],
+        )
+        .unwrap();
+        for y in 0..height {
+            let row: Vec<u8> = (0..3 * width).map(|x| ((x * y) ^ (x / 7)) as u8).collect();
+            encoder.write_row(&row).unwrap();
+            raw.push(0);
+            raw.extend_from_slice(&row);
+        }
+        let png = encoder.finish().unwrap();
+        assert!(raw.len() > CHUNK_SZ);
+
+        let text = read_png_text(&png[..]).unwrap();
+        assert_eq!(
+            text,
+            [
+                ("Title".to_string(), "multi chunk".to_string()),
+                ("cx".to_string(), "-0.5".to_string())
+            ]
+        );
+
+        // Every chunk has the CRC of its type and data
+        assert_eq!(png[..8], SIGNATURE);
+        let (mut pos, mut kinds, mut zlib) = (8, Vec::new(), Vec::new());
+        while pos < png.len() {
+            let len = u32::from_be_bytes(png[pos..pos + 4].try_into().unwrap()) as usize;
+            let (kind, data) = (&png[pos + 4..pos + 8], &png[pos + 8..pos + 8 + len]);
+            let crc = u32::from_be_bytes(png[pos + 8 + len..pos + 12 + len].try_into().unwrap());
+            assert_eq!(crc, crc32(crc32(0, kind), data));
+            if kind == b"IDAT" {
+                zlib.extend_from_slice(data);
+            }
+            kinds.push(String::from_utf8_lossy(kind).into_owned());
+            pos += 12 + len;
+        }
+        assert_eq!(pos, png.len());
+        // A chunk for every CHUNK_SZ of raw data and one with the end of the stream
+        let idats = kinds.iter().filter(|k| *k == "IDAT").count();
+        assert_eq!(idats, raw.len() / CHUNK_SZ + 2);
+        assert_eq!(kinds.first().unwrap(), "IHDR");
+        assert_eq!(kinds.last().unwrap(), "IEND");
+
+        // The zlib stream ends with the Adler-32 of the filtered rows
+        assert_eq!(zlib[..2], [0x78, 0x01]);
+        let adler = u32::from_be_bytes(zlib[zlib.len() - 4..].try_into().unwrap());
+        assert_eq!(adler, adler32(&raw));
+    }
+}
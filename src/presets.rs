@@ -1,8 +1,17 @@
+// The user presets are shared as TOML documents. Only parsing is enabled in the toml crate,
+// so the documents are written by hand. A JSON string is also a TOML basic string.
+
+use crate::location::Location;
+use crate::report::json_string;
+
 pub struct Preset {
     cx: f64,
     cy: f64,
-    zoom: i32,
+    zoom: f64,
     iter_depth: i32,
+    // The whole location of a preset that the user saved. The built-in presets keep the
+    // coloring and the fractal of the view.
+    location: Option<Location>,
 }
 
 impl Preset {
@@ -10,8 +19,19 @@ impl Preset {
         Preset {
             cx,
             cy,
-            zoom,
+            zoom: zoom as f64,
             iter_depth,
+            location: None,
+        }
+    }
+    /// A preset with a location and the value of the zoom slider that shows it
+    pub fn from_location(location: Location, zoom: f64) -> Preset {
+        Preset {
+            cx: location.cx,
+            cy: location.cy,
+            zoom,
+            iter_depth: location.iteration_depth as i32,
+            location: Some(location),
         }
     }
     pub fn cx(&self) -> f64 {
@@ -21,16 +41,22 @@ impl Preset {
         self.cy
     }
     pub fn zoom(&self) -> f64 {
-        self.zoom as f64
+        self.zoom
     }
     pub fn iter_depth(&self) -> f64 {
         self.iter_depth as f64
     }
+    /// The location of a preset that the user saved
+    pub fn location(&self) -> Option<&Location> {
+        self.location.as_ref()
+    }
 }
 
+/// The built-in presets, followed by those that the user added
 pub struct Presets {
-    names: Vec<&'static str>,
+    names: Vec<String>,
     values: Vec<Preset>,
+    builtin: usize,
 }
 
 impl Presets {
@@ -42,10 +68,14 @@ impl Presets {
             Preset::new(-0.8099833738092991, 0.17004289101216644, 500, 1000),
         ];
         assert_eq!(names.len(), values.len());
-        Presets { names, values }
+        Presets {
+            names: names.into_iter().map(String::from).collect(),
+            builtin: values.len(),
+            values,
+        }
     }
-    pub fn names(&self) -> &[&str] {
-        self.names.as_slice()
+    pub fn names(&self) -> Vec<&str> {
+        self.names.iter().map(|n| n.as_str()).collect()
     }
     pub fn len(&self) -> usize {
         self.names.len()
@@ -54,4 +84,193 @@ impl Presets {
         assert!(i < self.len());
         &self.values[i]
     }
+    pub fn name(&self, i: usize) -> &str {
+        &self.names[i]
+    }
+    /// Whether preset i is built in
+    pub fn is_builtin(&self, i: usize) -> bool {
+        i < self.builtin
+    }
+    // The name of a user preset that is added as name: name itself, or if a built-in preset
+    // has that name, the first of "name 2", "name 3", ... that no built-in preset has. The same
+    // name always gives the same user name, so adding it again replaces the preset.
+    fn user_name(&self, name: &str) -> String {
+        let builtin = &self.names[..self.builtin];
+        let mut user_name = name.to_string();
+        let mut n = 1;
+        while builtin.contains(&user_name) {
+            n += 1;
+            user_name = format!("{} {}", name, n);
+        }
+        user_name
+    }
+    /// Add a user preset. One that has the same name is replaced, in its place. A preset with
+    /// the name of a built-in preset gets a name that the built-in presets do not have.
+    /// Returns the index of the preset.
+    pub fn add(&mut self, name: &str, preset: Preset) -> usize {
+        let name = self.user_name(name);
+        let same = (self.builtin..self.len()).find(|&i| self.names[i] == name);
+        match same {
+            Some(i) => {
+                self.values[i] = preset;
+                i
+            }
+            None => {
+                self.names.push(name);
+                self.values.push(preset);
+                self.len() - 1
+            }
+        }
+    }
+    /// Give user preset i another name. A name that is empty or that another preset has is
+    /// refused, as are the built-in presets.
+    pub fn rename(&mut self, i: usize, name: &str) -> bool {
+        let taken = self
+            .names
+            .iter()
+            .enumerate()
+            .any(|(j, n)| j != i && n == name);
+        if self.is_builtin(i) || i >= self.len() || name.is_empty() || taken {
+            return false;
+        }
+        self.names[i] = name.to_string();
+        true
+    }
+    /// Remove user preset i
+    pub fn remove(&mut self, i: usize) -> bool {
+        if self.is_builtin(i) || i >= self.len() {
+            return false;
+        }
+        self.names.remove(i);
+        self.values.remove(i);
+        true
+    }
+    /// A name for a copy of preset i that no preset has yet
+    pub fn copy_name(&self, i: usize) -> String {
+        let base = format!("{} copy", self.names[i]);
+        let mut name = base.clone();
+        let mut n = 1;
+        while self.names.contains(&name) {
+            n += 1;
+            name = format!("{} {}", base, n);
+        }
+        name
+    }
+    /// Move user preset from to place to, which is also that of a user preset
+    pub fn move_to(&mut self, from: usize, to: usize) -> bool {
+        if self.is_builtin(from) || self.is_builtin(to) || from >= self.len() || to >= self.len() {
+            return false;
+        }
+        let name = self.names.remove(from);
+        let preset = self.values.remove(from);
+        self.names.insert(to, name);
+        self.values.insert(to, preset);
+        true
+    }
+    /// The presets that the user added, with their names
+    pub fn user_presets(&self) -> impl Iterator<Item = (&str, &Preset)> {
+        (self.builtin..self.len()).map(|i| (self.name(i), &self.values[i]))
+    }
+    /// The user presets as a TOML document: an array of tables "preset", each with the name
+    /// and the parameters of the location of a preset, as strings
+    pub fn to_toml(&self) -> String {
+        let mut doc = String::new();
+        for (name, preset) in self.user_presets() {
+            let Some(location) = preset.location() else {
+                continue;
+            };
+            doc.push_str("[[preset]]\n");
+            doc.push_str(&format!("name = {}\n", json_string(name)));
+            for (key, value) in location.params() {
+                doc.push_str(&format!("{} = {}\n", key, json_string(&value)));
+            }
+            doc.push('\n');
+        }
+        doc
+    }
+    /// Add the presets of a TOML document that to_toml made, with the preset that preset makes
+    /// of every location, like add. Presets without a name or with a location that can not be
+    /// read are left out. Returns the number of presets that were added.
+    pub fn add_toml(
+        &mut self,
+        doc: &str,
+        preset: impl Fn(Location) -> Preset,
+    ) -> Result<usize, toml::de::Error> {
+        let doc: toml::Table = doc.parse()?;
+        let entries = doc.get("preset").and_then(|entries| entries.as_array());
+        let mut n = 0;
+        for entry in entries.into_iter().flatten().filter_map(|e| e.as_table()) {
+            let Some(name) = entry.get("name").and_then(|name| name.as_str()) else {
+                continue;
+            };
+            let lookup = |key: &str| entry.get(key).and_then(|v| v.as_str()).map(String::from);
+            if let Some(location) = Location::from_params(lookup) {
+                self.add(name, preset(location));
+                n += 1;
+            }
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_user_presets_change() {
+        let mut presets = Presets::new();
+        let n = presets.len();
+        assert!(!presets.rename(0, "Start"));
+        assert!(!presets.remove(1));
+        let spiral = || Preset::new(-0.8, 0.17, 500, 1000);
+        let a = presets.add(&presets.copy_name(1), spiral());
+        let b = presets.add(&presets.copy_name(1), spiral());
+        assert_eq!(presets.names()[a..], ["Flamenco copy", "Flamenco copy 2"]);
+        assert!(!presets.rename(b, "Spiral"));
+        assert!(presets.rename(b, "Dancer"));
+        assert!(!presets.move_to(b, 0));
+        assert!(presets.move_to(b, a));
+        assert_eq!(presets.names()[n..], ["Dancer", "Flamenco copy"]);
+        assert!(presets.remove(a));
+        assert_eq!(presets.user_presets().count(), 1);
+        // A user preset does not take the name of a built-in preset, and adding it again
+        // replaces it
+        let initial = presets.add("Initial", spiral());
+        assert_eq!(presets.name(initial), "Initial 2");
+        assert_eq!(presets.add("Initial", spiral()), initial);
+        assert_eq!(
+            presets.names(),
+            [
+                "Initial",
+                "Flamenco",
+                "Spiral",
+                "Flamenco copy",
+                "Initial 2"
+            ]
+        );
+    }
+
+    #[test]
+    fn user_presets_are_shared_as_toml() {
+        let mut presets = Presets::new();
+        let mut mapping = crate::mandel_image::Mapping::new_for_size(100);
+        (mapping.cx, mapping.cy) = (-0.75, 0.1);
+        let location = Location::new(&mapping, "a \"quoted\"\tname\u{7f}\\");
+        let preset = |location: Location| Preset::from_location(location, 10.0);
+        presets.add("Dancer", preset(location.clone()));
+        presets.add("Initial", preset(location.clone()));
+        let doc = presets.to_toml();
+        let mut read = Presets::new();
+        assert_eq!(read.add_toml(&doc, preset).unwrap(), 2);
+        assert_eq!(read.names(), presets.names());
+        let params = |presets: &Presets, i: usize| presets.get(i).location().unwrap().params();
+        assert_eq!(params(&read, 3), location.params());
+        assert_eq!(params(&read, 4), location.params());
+        // Reading it again replaces the presets
+        assert_eq!(read.add_toml(&doc, preset).unwrap(), 2);
+        assert_eq!(read.len(), 5);
+        assert!(read.add_toml("[[preset]\nname = ", preset).is_err());
+        assert_eq!(read.add_toml("[other]\nname = \"x\"\n", preset).unwrap(), 0);
+    }
 }
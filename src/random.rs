@@ -0,0 +1,119 @@
+// Random palettes and locations. They are made from a seed, so the same seed gives
+// the same result again.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::colorings::GradientColoring;
+use crate::fractal::FractalParams;
+use crate::location::Location;
+use crate::mandel_image::mandel_value;
+
+// The number of stops of a random palette
+const MIN_STOPS: u64 = 3;
+const MAX_STOPS: u64 = 7;
+// A random location is centered on a point that escapes after at least MIN_ESCAPE and less
+// than MAX_ESCAPE iterations, so it is near the boundary of the set
+const MIN_ESCAPE: u32 = 40;
+const MAX_ESCAPE: u32 = 1000;
+const LOCATION_TRIES: usize = 1000;
+// The width of the view of a random location is 10^-zoom, with zoom in this range
+const MIN_ZOOM: f64 = 0.5;
+const MAX_ZOOM: f64 = 5.0;
+
+/// A small pseudo random number generator (SplitMix64)
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number from 0 up to but not including 1
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn range(&mut self, lo: f64, hi: f64) -> f64 {
+        lo + (hi - lo) * self.next_f64()
+    }
+}
+
+/// A seed that differs every time
+pub fn new_seed() -> u64 {
+    static COUNT: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    Rng::new(nanos ^ COUNT.fetch_add(1, Ordering::Relaxed)).next_u64()
+}
+
+/// The name of the random palette of a seed
+pub fn palette_name(seed: u64) -> String {
+    format!("random-{:016x}", seed)
+}
+
+/// A gradient with random colors at random positions
+pub fn random_palette(seed: u64) -> GradientColoring {
+    let mut rng = Rng::new(seed);
+    let n = MIN_STOPS + rng.next_u64() % (MAX_STOPS - MIN_STOPS + 1);
+    let stops: Vec<(f64, u32)> = (0..n)
+        .map(|i| {
+            // Spread the stops, so no two colors are very close
+            let t = (i as f64 + rng.range(0.0, 0.8)) / n as f64;
+            (t, rng.next_u64() as u32 & 0xffffff)
+        })
+        .collect();
+    GradientColoring::new(&palette_name(seed), &stops)
+}
+
+/// A view on the boundary of the mandelbrot set, for a window of win_sz pixels wide.
+/// The coloring is left empty.
+pub fn random_location(seed: u64, win_sz: usize) -> Location {
+    let mut rng = Rng::new(seed);
+    let (mut cx, mut cy) = (-0.75, 0.1);
+    for _ in 0..LOCATION_TRIES {
+        let (x, y) = (rng.range(-2.0, 0.5), rng.range(-1.2, 1.2));
+        let v = mandel_value(x, y, MAX_ESCAPE);
+        if (MIN_ESCAPE..MAX_ESCAPE).contains(&v) {
+            (cx, cy) = (x, y);
+            break;
+        }
+    }
+    let zoom = rng.range(MIN_ZOOM, MAX_ZOOM);
+    Location {
+        cx,
+        cy,
+        scale: 10f64.powf(-zoom) / win_sz.max(1) as f64,
+        // Deeper views need more iterations
+        iteration_depth: 200 + (100.0 * zoom) as u32,
+        fractal: FractalParams::mandelbrot(),
+        coloring: String::new(),
+        precise_center: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeds_give_the_same_result_again() {
+        let seed = 0x1234_5678_9abc_def0;
+        assert_eq!(random_palette(seed).stops(), random_palette(seed).stops());
+        assert_eq!(random_location(seed, 600), random_location(seed, 600));
+        let location = random_location(seed, 600);
+        let v = mandel_value(location.cx, location.cy, MAX_ESCAPE);
+        assert!((MIN_ESCAPE..MAX_ESCAPE).contains(&v));
+    }
+}
@@ -0,0 +1,251 @@
+// A render report records everything about an exported image that is needed to reproduce
+// it, as a JSON document. There is no JSON library, so the document is written by hand.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::curves::TransferCurves;
+use crate::location::Location;
+use crate::shading::Lighting;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The time that a pass of the progressive renderer took
+pub struct PassTiming {
+    /// The block size of the pass, 1 for the full resolution
+    pub block: usize,
+    pub seconds: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Statistics of the values of a view
+pub struct Statistics {
+    pub samples: u64,
+    /// The number of samples that did not escape
+    pub interior: u64,
+    /// The lowest and highest value of the samples that escaped
+    pub min_value: Option<u32>,
+    pub max_value: Option<u32>,
+    /// The mean value of the samples that escaped
+    pub mean_value: f64,
+}
+
+impl Statistics {
+    /// The statistics of a histogram made by sample_histogram, in which the last
+    /// entry counts the samples that did not escape
+    pub fn from_histogram(histogram: &[u32]) -> Statistics {
+        let escaped = &histogram[..histogram.len().saturating_sub(1)];
+        let interior = histogram.last().map_or(0, |&n| n as u64);
+        let n_escaped: u64 = escaped.iter().map(|&n| n as u64).sum();
+        let sum: f64 = escaped
+            .iter()
+            .enumerate()
+            .map(|(v, &n)| v as f64 * n as f64)
+            .sum();
+        let values = || escaped.iter().enumerate().filter(|(_, &n)| n > 0);
+        Statistics {
+            samples: n_escaped + interior,
+            interior,
+            min_value: values().next().map(|(v, _)| v as u32),
+            max_value: values().next_back().map(|(v, _)| v as u32),
+            mean_value: if n_escaped == 0 {
+                0.0
+            } else {
+                sum / n_escaped as f64
+            },
+        }
+    }
+}
+
+/// What is recorded about an exported image
+pub struct RenderReport {
+    pub location: Location,
+    pub width: usize,
+    pub height: usize,
+    pub lighting: Option<Lighting>,
+    pub curves: TransferCurves,
+    /// The passes that rendered the image, in order
+    pub passes: Vec<PassTiming>,
+    pub statistics: Option<Statistics>,
+    /// The name of the backend that rendered the image
+    pub backend: &'static str,
+    /// The precision of the arithmetic of the view, see Precision::name
+    pub precision: &'static str,
+    /// The number of threads that rendered the image
+    pub threads: usize,
+    /// Every pixel is the average of supersampling x supersampling samples
+    pub supersampling: usize,
+    /// Whether pixels of high contrast were rendered again with more samples
+    pub adaptive_antialiasing: bool,
+    /// The file name of the exported image
+    pub image: Option<String>,
+}
+
+// A JSON string with the characters of s. Control characters and DEL are escaped, so that it
+// is also a TOML basic string.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 || c == '\u{7f}' => {
+                quoted.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// JSON has no infinity or NaN
+fn json_number(v: f64) -> String {
+    if v.is_finite() {
+        format!("{:?}", v)
+    } else {
+        "null".to_string()
+    }
+}
+
+fn json_option<T: ToString>(v: Option<T>) -> String {
+    v.map_or("null".to_string(), |v| v.to_string())
+}
+
+// An object with one member per line, indented by the given level
+fn json_object(members: &[(&str, String)], level: usize) -> String {
+    let indent = "  ".repeat(level + 1);
+    let lines: Vec<String> = members
+        .iter()
+        .map(|(key, value)| format!("{}{}: {}", indent, json_string(key), value))
+        .collect();
+    format!("{{\n{}\n{}}}", lines.join(",\n"), "  ".repeat(level))
+}
+
+impl RenderReport {
+    /// The report as a JSON document
+    pub fn to_json(&self) -> String {
+        let location = &self.location;
+        let params: Vec<(&str, String)> = location
+            .params()
+            .into_iter()
+            .map(|(key, value)| (key, json_string(&value)))
+            .collect();
+        let view = json_object(
+            &[
+                ("cx", json_number(location.cx)),
+                ("cy", json_number(location.cy)),
+                ("scale", json_number(location.scale)),
+                ("iteration_depth", location.iteration_depth.to_string()),
+                ("fractal", json_string(location.fractal.fractal_type.name())),
+                ("coloring", json_string(&location.coloring)),
+                ("parameters", json_object(&params, 2)),
+                ("link", json_string(&location.to_uri())),
+            ],
+            1,
+        );
+        let lighting = match &self.lighting {
+            None => "null".to_string(),
+            Some(l) => json_object(
+                &[
+                    ("azimuth", json_number(l.azimuth)),
+                    ("elevation", json_number(l.elevation)),
+                    ("relief", json_number(l.relief)),
+                ],
+                1,
+            ),
+        };
+        let curves: Vec<(&str, String)> = TransferCurves::CHANNEL_NAMES
+            .iter()
+            .enumerate()
+            .map(|(i, &name)| {
+                let points: Vec<String> = self
+                    .curves
+                    .channel(i)
+                    .points()
+                    .iter()
+                    .map(|(x, y)| format!("[{}, {}]", x, y))
+                    .collect();
+                (name, format!("[{}]", points.join(", ")))
+            })
+            .collect();
+        let backend = json_object(
+            &[
+                ("name", json_string(self.backend)),
+                ("precision", json_string(self.precision)),
+                ("threads", self.threads.to_string()),
+                ("supersampling", self.supersampling.to_string()),
+                (
+                    "adaptive_antialiasing",
+                    self.adaptive_antialiasing.to_string(),
+                ),
+            ],
+            1,
+        );
+        let passes: Vec<String> = self
+            .passes
+            .iter()
+            .map(|p| {
+                format!(
+                    "    {{\"block\": {}, \"seconds\": {}}}",
+                    p.block,
+                    json_number(p.seconds)
+                )
+            })
+            .collect();
+        let passes = if passes.is_empty() {
+            "[]".to_string()
+        } else {
+            format!("[\n{}\n  ]", passes.join(",\n"))
+        };
+        let statistics = match &self.statistics {
+            None => "null".to_string(),
+            Some(s) => json_object(
+                &[
+                    ("samples", s.samples.to_string()),
+                    ("interior", s.interior.to_string()),
+                    ("min_value", json_option(s.min_value)),
+                    ("max_value", json_option(s.max_value)),
+                    ("mean_value", json_number(s.mean_value)),
+                ],
+                1,
+            ),
+        };
+        let software = json_object(
+            &[
+                ("name", json_string(env!("CARGO_PKG_NAME"))),
+                ("version", json_string(env!("CARGO_PKG_VERSION"))),
+            ],
+            1,
+        );
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let mut json = json_object(
+            &[
+                ("software", software),
+                ("created_unix", created.to_string()),
+                ("image", json_option(self.image.as_deref().map(json_string))),
+                ("width", self.width.to_string()),
+                ("height", self.height.to_string()),
+                ("view", view),
+                ("lighting", lighting),
+                ("curves", json_object(&curves, 1)),
+                ("backend", backend),
+                ("passes", passes),
+                ("statistics", statistics),
+            ],
+            0,
+        );
+        json.push('\n');
+        json
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+}
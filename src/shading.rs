@@ -0,0 +1,199 @@
+use scoped_threadpool::Pool;
+
+use crate::mandel_image::{
+    continue_iteration, iteration_start, smooth_escape, CancelToken, IterationBuffer, Mapping,
+    WinToMandel,
+};
+
+// The fraction of the light that also reaches surfaces that face away from the light
+const AMBIENT: f64 = 0.35;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// A light that shines on the iteration field as if it were a landscape
+pub struct Lighting {
+    /// The direction the light comes from, in degrees counterclockwise from the right
+    pub azimuth: f64,
+    /// The height of the light above the horizon, in degrees
+    pub elevation: f64,
+    /// How steep the landscape is
+    pub relief: f64,
+}
+
+impl Lighting {
+    pub fn defaults() -> Lighting {
+        Lighting {
+            azimuth: 135.0,
+            elevation: 45.0,
+            relief: 20.0,
+        }
+    }
+
+    // The unit vector towards the light. The y axis points down, like window coordinates.
+    fn direction(&self) -> (f64, f64, f64) {
+        let (az, el) = (self.azimuth.to_radians(), self.elevation.to_radians());
+        (el.cos() * az.cos(), -el.cos() * az.sin(), el.sin())
+    }
+}
+
+// The height of the landscape at a point: the logarithm of the smooth iteration count,
+// or NaN for points that do not escape
+fn height(mapping: &Mapping, x: f64, y: f64) -> f64 {
+    let fractal = &mapping.fractal;
+    let (mut z, c) = iteration_start(fractal, x, y);
+    let mut iter = 0;
+    if continue_iteration(fractal, &mut z, c, &mut iter, mapping.iteration_depth) {
+        (1.0 + smooth_escape(fractal, iter, z)).ln()
+    } else {
+        f64::NAN
+    }
+}
+
+fn fill_heights(field: &mut [f64], mapping: &Mapping, block: usize, rows: usize, first_row: usize) {
+    let converter = WinToMandel::from_mapping(mapping);
+    let cols = mapping.win_width.div_ceil(block);
+    for (r, row) in field.chunks_mut(cols).take(rows).enumerate() {
+        let y = converter.cvt_y((first_row + r) * block);
+        for (col, h) in row.iter_mut().enumerate() {
+            *h = height(mapping, converter.cvt_x(col * block), y);
+        }
+    }
+}
+
+// The heights at one point per block x block square. Returns None if cancelled.
+fn height_field(
+    mapping: &Mapping,
+    block: usize,
+    pool: &mut Option<Pool>,
+    cancel: &CancelToken,
+) -> Option<Vec<f64>> {
+    let cols = mapping.win_width.div_ceil(block);
+    let rows = mapping.win_height.div_ceil(block);
+    let mut field = vec![0.0; cols * rows];
+    match pool {
+        None => fill_heights(&mut field, mapping, block, rows, 0),
+        Some(pool) => {
+            let rows_per_part = rows.div_ceil(pool.thread_count() as usize).max(1);
+            pool.scoped(|scope| {
+                for (i, part) in field.chunks_mut(cols * rows_per_part).enumerate() {
+                    scope.execute(move || {
+                        if !cancel.is_cancelled() {
+                            let n_rows = part.len() / cols.max(1);
+                            fill_heights(part, mapping, block, n_rows, i * rows_per_part);
+                        }
+                    });
+                }
+            });
+        }
+    }
+    if cancel.is_cancelled() {
+        None
+    } else {
+        Some(field)
+    }
+}
+
+// Multiply the channels of a color in GTK RGB-format
+fn scale_color(color: u32, factor: f64) -> u32 {
+    let channel = |shift: u32| {
+        let v = (color >> shift & 0xff) as f64 * factor;
+        (v.round().clamp(0.0, 255.0) as u32) << shift
+    };
+    channel(16) | channel(8) | channel(0)
+}
+
+/// Light an image of the view as if the iteration count were a landscape: slopes towards the
+/// light get lighter, slopes away from it darker. The image was rendered with one pixel per
+/// block x block square, and the shading uses the same resolution.
+/// Returns false if the computation was cancelled.
+pub fn apply_lighting(
+    data: &mut [u8],
+    stride: usize,
+    mapping: &Mapping,
+    lighting: &Lighting,
+    block: usize,
+    pool: &mut Option<Pool>,
+    cancel: &CancelToken,
+) -> bool {
+    let block = block.max(1);
+    let field = match height_field(mapping, block, pool, cancel) {
+        Some(field) => field,
+        None => return false,
+    };
+    shade(data, stride, mapping, lighting, block, &field);
+    true
+}
+
+/// Light a full resolution image of a buffer, like apply_lighting, with the heights of
+/// the values in the buffer instead of iterating again
+pub fn apply_buffer_lighting(
+    data: &mut [u8],
+    stride: usize,
+    buffer: &IterationBuffer,
+    lighting: &Lighting,
+) {
+    let max = buffer.mapping.iteration_depth;
+    let field: Vec<f64> = buffer
+        .values
+        .iter()
+        .zip(&buffer.smooth)
+        .map(|(&v, &smooth)| {
+            if v < max {
+                (1.0 + smooth).ln()
+            } else {
+                f64::NAN
+            }
+        })
+        .collect();
+    shade(data, stride, &buffer.mapping, lighting, 1, &field);
+}
+
+// Shade the image with the heights at one point per block x block square
+fn shade(
+    data: &mut [u8],
+    stride: usize,
+    mapping: &Mapping,
+    lighting: &Lighting,
+    block: usize,
+    field: &[f64],
+) {
+    let cols = mapping.win_width.div_ceil(block);
+    let rows = mapping.win_height.div_ceil(block);
+    let (lx, ly, lz) = lighting.direction();
+    // The height at (col, row), with the center height for neighbours outside the field or the set
+    let at = |col: usize, row: usize, dc: isize, dr: isize, center: f64| {
+        let (c, r) = (col as isize + dc, row as isize + dr);
+        if c < 0 || r < 0 || c >= cols as isize || r >= rows as isize {
+            return center;
+        }
+        let h = field[r as usize * cols + c as usize];
+        if h.is_nan() {
+            center
+        } else {
+            h
+        }
+    };
+    // The slopes are per pixel, so a block is `block` pixels wide
+    let scale = lighting.relief / (2.0 * block as f64);
+    for row in 0..rows {
+        for col in 0..cols {
+            let center = field[row * cols + col];
+            if center.is_nan() {
+                continue;
+            }
+            let dx = (at(col, row, 1, 0, center) - at(col, row, -1, 0, center)) * scale;
+            let dy = (at(col, row, 0, 1, center) - at(col, row, 0, -1, center)) * scale;
+            // The normal of the surface is (-dx, -dy, 1), normalized
+            let len = (dx * dx + dy * dy + 1.0).sqrt();
+            let diffuse = ((-dx * lx - dy * ly + lz) / len).max(0.0);
+            let factor = AMBIENT + (1.0 - AMBIENT) * diffuse / lz.max(0.1);
+            for wy in row * block..((row + 1) * block).min(mapping.win_height) {
+                let line = &mut data[wy * stride..];
+                for wx in col * block..((col + 1) * block).min(mapping.win_width) {
+                    let px = &mut line[wx * 4..wx * 4 + 4];
+                    let color = u32::from_ne_bytes([px[0], px[1], px[2], px[3]]);
+                    px.copy_from_slice(&scale_color(color, factor).to_ne_bytes());
+                }
+            }
+        }
+    }
+}
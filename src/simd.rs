@@ -0,0 +1,171 @@
+// The escape-time iteration of the quadratic Mandelbrot set for several points at once.
+// With AVX the points are iterated in the lanes of one vector, and a lane that escapes is
+// masked until all lanes escaped or the iteration depth is reached. Without AVX the same
+// arithmetic is done point by point, so both give the same values as continue_iteration.
+// Both do the periodicity check of CycleCheck, which in the vector saves z of all lanes at
+// once, so lanes that are found in a cycle are masked like those that escaped. Points in the
+// main cardioid or the period-2 bulb are not iterated at all, and keep z = 0.
+
+use crate::mandel_image::{in_main_bulbs, CycleCheck, CYCLE_TOLERANCE, FIRST_CYCLE_CHECK};
+
+/// The number of points that mandel_lanes iterates together
+pub const LANES: usize = 4;
+
+/// The result of iterating LANES points
+pub struct Lanes {
+    /// The number of iterations before the point escaped, the iteration depth if it did not
+    pub iterations: [u32; LANES],
+    /// The value of z at the escape, or after the last iteration
+    pub z: [(f64, f64); LANES],
+    pub escaped: [bool; LANES],
+}
+
+/// Iterate z * z + c from z = 0 for the points c = (xs[k], y), like continue_iteration
+pub fn mandel_lanes(xs: [f64; LANES], y: f64, max_iter: u32) -> Lanes {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx") {
+        let inside = xs.map(|x| in_main_bulbs(x, y));
+        if inside.iter().all(|&b| b) {
+            return mandel_lanes_scalar(xs, y, max_iter);
+        }
+        // Safety: the CPU has been checked for AVX
+        let mut lanes = unsafe { mandel_lanes_avx(xs, y, max_iter) };
+        for k in (0..LANES).filter(|&k| inside[k]) {
+            (lanes.iterations[k], lanes.z[k]) = (max_iter, (0.0, 0.0));
+            lanes.escaped[k] = false;
+        }
+        return lanes;
+    }
+    mandel_lanes_scalar(xs, y, max_iter)
+}
+
+// The fallback for processors without AVX, one point at a time
+pub fn mandel_lanes_scalar(xs: [f64; LANES], y: f64, max_iter: u32) -> Lanes {
+    let mut lanes = Lanes {
+        iterations: [0; LANES],
+        z: [(0.0, 0.0); LANES],
+        escaped: [false; LANES],
+    };
+    for (k, &x) in xs.iter().enumerate() {
+        if in_main_bulbs(x, y) {
+            lanes.iterations[k] = max_iter;
+            continue;
+        }
+        let (mut r, mut i) = (0.0, 0.0);
+        let mut iter = 0;
+        let mut cycle = CycleCheck::new(iter);
+        while iter < max_iter {
+            (r, i) = (r * r - i * i + x, 2.0 * r * i + y);
+            if i * i + r * r >= 4.0 {
+                lanes.escaped[k] = true;
+                break;
+            }
+            iter += 1;
+            if cycle.in_cycle((r, i), iter) {
+                iter = max_iter;
+                break;
+            }
+        }
+        lanes.iterations[k] = iter;
+        lanes.z[k] = (r, i);
+    }
+    lanes
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+unsafe fn mandel_lanes_avx(xs: [f64; LANES], y: f64, max_iter: u32) -> Lanes {
+    use std::arch::x86_64::*;
+
+    let cr = _mm256_loadu_pd(xs.as_ptr());
+    let ci = _mm256_set1_pd(y);
+    let four = _mm256_set1_pd(4.0);
+    let one = _mm256_set1_pd(1.0);
+    let tolerance = _mm256_set1_pd(CYCLE_TOLERANCE);
+    // Clearing the sign bit gives the absolute value
+    let sign = _mm256_set1_pd(-0.0);
+    let (mut r, mut i) = (_mm256_setzero_pd(), _mm256_setzero_pd());
+    let (mut escape_r, mut escape_i) = (_mm256_setzero_pd(), _mm256_setzero_pd());
+    // The iterations are counted in doubles, which are exact far beyond any iteration depth
+    let mut iterations = _mm256_setzero_pd();
+    // All bits are set in the lanes that did not escape yet
+    let mut active = _mm256_castsi256_pd(_mm256_set1_epi64x(-1));
+    // And in the lanes that were found in a cycle
+    let mut cycling = _mm256_setzero_pd();
+    let (mut saved_r, mut saved_i) = (_mm256_set1_pd(f64::NAN), _mm256_set1_pd(f64::NAN));
+    let mut next_save = FIRST_CYCLE_CHECK;
+    for n in 1..=max_iter {
+        let new_r = _mm256_add_pd(_mm256_sub_pd(_mm256_mul_pd(r, r), _mm256_mul_pd(i, i)), cr);
+        let ri = _mm256_mul_pd(r, i);
+        let new_i = _mm256_add_pd(_mm256_add_pd(ri, ri), ci);
+        let norm = _mm256_add_pd(_mm256_mul_pd(new_i, new_i), _mm256_mul_pd(new_r, new_r));
+        let escaping = _mm256_and_pd(_mm256_cmp_pd::<_CMP_GE_OQ>(norm, four), active);
+        escape_r = _mm256_blendv_pd(escape_r, new_r, escaping);
+        escape_i = _mm256_blendv_pd(escape_i, new_i, escaping);
+        active = _mm256_andnot_pd(escaping, active);
+        iterations = _mm256_add_pd(iterations, _mm256_and_pd(active, one));
+        (r, i) = (new_r, new_i);
+        // The lanes that came back to the saved z stop with their z, like escaping lanes
+        let close_r = _mm256_andnot_pd(sign, _mm256_sub_pd(r, saved_r));
+        let close_i = _mm256_andnot_pd(sign, _mm256_sub_pd(i, saved_i));
+        let cycled = _mm256_and_pd(
+            _mm256_and_pd(
+                _mm256_cmp_pd::<_CMP_LT_OQ>(close_r, tolerance),
+                _mm256_cmp_pd::<_CMP_LT_OQ>(close_i, tolerance),
+            ),
+            active,
+        );
+        escape_r = _mm256_blendv_pd(escape_r, r, cycled);
+        escape_i = _mm256_blendv_pd(escape_i, i, cycled);
+        cycling = _mm256_or_pd(cycling, cycled);
+        active = _mm256_andnot_pd(cycled, active);
+        if n == next_save {
+            (saved_r, saved_i) = (r, i);
+            next_save = next_save.saturating_mul(2);
+        }
+        if _mm256_movemask_pd(active) == 0 {
+            break;
+        }
+    }
+    let r = _mm256_blendv_pd(escape_r, r, active);
+    let i = _mm256_blendv_pd(escape_i, i, active);
+    let (mut rs, mut is, mut counts) = ([0.0; LANES], [0.0; LANES], [0.0; LANES]);
+    _mm256_storeu_pd(rs.as_mut_ptr(), r);
+    _mm256_storeu_pd(is.as_mut_ptr(), i);
+    _mm256_storeu_pd(counts.as_mut_ptr(), iterations);
+    let (active, cycling) = (_mm256_movemask_pd(active), _mm256_movemask_pd(cycling));
+    Lanes {
+        iterations: [0, 1, 2, 3].map(|k| {
+            if cycling & (1 << k) != 0 {
+                max_iter
+            } else {
+                counts[k] as u32
+            }
+        }),
+        z: [0, 1, 2, 3].map(|k| (rs[k], is[k])),
+        escaped: [0, 1, 2, 3].map(|k| (active | cycling) & (1 << k) == 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lanes_agree_with_scalar_iteration() {
+        let xs = [-2.5, -0.75, 0.25, 0.3];
+        for y in [-1.0, 0.0, 0.01, 0.5] {
+            let vector = mandel_lanes(xs, y, 500);
+            let scalar = mandel_lanes_scalar(xs, y, 500);
+            assert_eq!(vector.iterations, scalar.iterations);
+            assert_eq!(vector.z, scalar.z);
+            assert_eq!(vector.escaped, scalar.escaped);
+            for (k, &x) in xs.iter().enumerate() {
+                assert_eq!(
+                    vector.iterations[k],
+                    crate::mandel_image::mandel_value(x, y, 500)
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+use crate::colorings::ColorInfo;
+use crate::location::Location;
+use crate::mandel_image::{make_mandel_image, CancelToken};
+use crate::png::rgb24_to_rgb;
+
+/// The size in pixels of the view that a thumbnail shows, at the scale of its location
+pub const THUMBNAIL_VIEW_SZ: usize = 600;
+// The number of thumbnails that are kept in the cache
+const CACHE_SZ: usize = 64;
+
+#[derive(Clone)]
+/// An image with three bytes per pixel: red, green, blue, without padding between rows
+pub struct RgbImage {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+struct CacheEntry {
+    hash: u64,
+    location: Location,
+    max_px: usize,
+    image: RgbImage,
+}
+
+// The most recently used thumbnails, least recently used first
+static CACHE: Mutex<Vec<CacheEntry>> = Mutex::new(Vec::new());
+
+fn location_hash(location: &Location, max_px: usize) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    location.hash(&mut hasher);
+    max_px.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn cache_lookup(hash: u64, location: &Location, max_px: usize) -> Option<RgbImage> {
+    let mut cache = CACHE.lock().ok()?;
+    let pos = cache
+        .iter()
+        .position(|e| e.hash == hash && e.max_px == max_px && e.location == *location)?;
+    let entry = cache.remove(pos);
+    let image = entry.image.clone();
+    cache.push(entry);
+    Some(image)
+}
+
+fn cache_store(hash: u64, location: &Location, max_px: usize, image: &RgbImage) {
+    if let Ok(mut cache) = CACHE.lock() {
+        if cache.len() >= CACHE_SZ {
+            cache.remove(0);
+        }
+        cache.push(CacheEntry {
+            hash,
+            location: location.clone(),
+            max_px,
+            image: image.clone(),
+        });
+    }
+}
+
+/// Render a square thumbnail of max_px x max_px pixels of a location, on the calling thread.
+/// The thumbnail shows the area of a view of THUMBNAIL_VIEW_SZ pixels. Recently rendered
+/// thumbnails are taken from a cache.
+pub fn render_thumbnail(location: &Location, max_px: usize) -> Option<RgbImage> {
+    let hash = location_hash(location, max_px);
+    if let Some(image) = cache_lookup(hash, location, max_px) {
+        return Some(image);
+    }
+    let mut mapping = location.mapping(max_px, max_px);
+    mapping.scale *= THUMBNAIL_VIEW_SZ as f64 / max_px.max(1) as f64;
+    let color_info = ColorInfo::new();
+    let col_idx = color_info.index_of(&location.coloring).unwrap_or(0);
+    let (data, stride) = make_mandel_image(
+        &mapping,
+        color_info.scheme(col_idx),
+        &mut None,
+        1,
+        &CancelToken::never(),
+    )?;
+    let mut image = RgbImage {
+        width: max_px,
+        height: max_px,
+        data: Vec::with_capacity(3 * max_px * max_px),
+    };
+    let mut line = Vec::with_capacity(3 * max_px);
+    for y in 0..max_px {
+        rgb24_to_rgb(&data[y * stride as usize..], max_px, &mut line);
+        image.data.extend_from_slice(&line);
+    }
+    cache_store(hash, location, max_px, &image);
+    Some(image)
+}
@@ -0,0 +1,92 @@
+use std::time::{Duration, Instant};
+
+use crate::location::Location;
+
+// Changes that follow each other faster than this, e.g. while a slider is dragged, are one step
+const COALESCE_TIME: Duration = Duration::from_millis(500);
+const MAX_STEPS: usize = 200;
+
+/// The views that were shown before and after the current one, for going back and forth.
+/// Only a change of the center or the zoom makes a new step.
+#[derive(Default)]
+pub struct ViewHistory {
+    back: Vec<Location>,
+    forward: Vec<Location>,
+    current: Option<Location>,
+    last_change: Option<Instant>,
+}
+
+fn same_view(a: &Location, b: &Location) -> bool {
+    a.cx == b.cx && a.cy == b.cy && a.scale == b.scale
+}
+
+impl ViewHistory {
+    /// Note the view that is shown at time now
+    pub fn record(&mut self, location: Location, now: Instant) {
+        let Some(current) = self.current.take() else {
+            self.current = Some(location);
+            return;
+        };
+        if same_view(&current, &location) {
+            // Other settings, such as the coloring, are kept for when the view is shown again
+            self.current = Some(location);
+            return;
+        }
+        let coalesced = self
+            .last_change
+            .is_some_and(|t| now.saturating_duration_since(t) < COALESCE_TIME);
+        if !coalesced {
+            self.back.push(current);
+            if self.back.len() > MAX_STEPS {
+                self.back.remove(0);
+            }
+            self.forward.clear();
+        }
+        self.current = Some(location);
+        self.last_change = Some(now);
+    }
+    /// The previous view, which becomes the current one
+    pub fn back(&mut self) -> Option<Location> {
+        let previous = self.back.pop()?;
+        self.forward.extend(self.current.replace(previous.clone()));
+        self.last_change = None;
+        Some(previous)
+    }
+    /// The view that was left by going back, which becomes the current one
+    pub fn forward(&mut self) -> Option<Location> {
+        let next = self.forward.pop()?;
+        self.back.extend(self.current.replace(next.clone()));
+        self.last_change = None;
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mandel_image::Mapping;
+
+    fn at(cx: f64) -> Location {
+        let mut location = Location::new(&Mapping::new_for_size(100), "");
+        location.cx = cx;
+        location
+    }
+
+    #[test]
+    fn fast_changes_are_one_step() {
+        let mut history = ViewHistory::default();
+        let t = Instant::now();
+        history.record(at(0.0), t);
+        history.record(at(1.0), t + Duration::from_secs(1));
+        history.record(at(1.5), t + Duration::from_millis(1100));
+        history.record(at(2.0), t + Duration::from_millis(1200));
+        assert_eq!(history.back().map(|l| l.cx), Some(0.0));
+        // Showing the view again is not a new step
+        history.record(at(0.0), t + Duration::from_secs(5));
+        assert!(history.back().is_none());
+        assert_eq!(history.forward().map(|l| l.cx), Some(2.0));
+        history.record(at(3.0), t + Duration::from_secs(10));
+        assert!(history.forward().is_none());
+        assert_eq!(history.back().map(|l| l.cx), Some(2.0));
+    }
+}